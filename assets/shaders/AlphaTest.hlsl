@@ -0,0 +1,24 @@
+#ifndef ALPHA_TEST_HLSL
+#define ALPHA_TEST_HLSL
+
+#include "Pbr.hlsl"
+
+SamplerState alpha_test_s : register(s0);
+
+Texture2D alpha_base_color_t : register(t0, space2);
+
+cbuffer AlphaMaterialBuffer : register(b0, space2) {
+    Material alpha_material;
+}
+
+// Blended materials cast shadows through the same path, glTF leaves their
+// cutoff undefined so they are cut at half coverage.
+void alpha_test(float4 uv) {
+    float2 tex_coord = alpha_material.base_color_uv == 0 ? uv.xy : uv.zw;
+    float alpha = alpha_base_color_t.Sample(alpha_test_s, tex_coord).a * alpha_material.base_color.a;
+    float cutoff = alpha_material.alpha_mode == ALPHA_MODE_MASK ? alpha_material.alpha_cutoff : 0.5f;
+
+    clip(alpha - cutoff);
+}
+
+#endif
@@ -1,6 +1,11 @@
 #ifndef COMMON_HLSL
 #define COMMON_HLSL
 
+// Must match `engine::MAX_INSTANCES`.
+#ifndef MAX_INSTANCES
+#define MAX_INSTANCES 1024
+#endif
+
 struct Globals {
     matrix view;
     matrix proj;
@@ -32,6 +37,19 @@ float4 screen_to_world(float4 screen, float2 screen_dim, matrix inv_proj_view) {
     return clip_to_world(clip, inv_proj_view);
 }
 
+// Normals are transformed by the cofactor matrix of the upper 3x3, which is
+// the inverse transpose up to scale and keeps non-uniformly scaled normals
+// perpendicular to the surface. Mirrored transforms flip it back outwards.
+float3 transform_normal(matrix transform, float3 normal) {
+    float3 c0 = transform._m00_m10_m20;
+    float3 c1 = transform._m01_m11_m21;
+    float3 c2 = transform._m02_m12_m22;
+
+    float3 result = cross(c1, c2) * normal.x + cross(c2, c0) * normal.y + cross(c0, c1) * normal.z;
+
+    return dot(c0, cross(c1, c2)) < 0.0f ? -result : result;
+}
+
 float4 pack_normal_to_texture(float3 normal) {
     normal = normalize(normal);
     float3 packedNormal = normal * 0.5 + 0.5;
@@ -1,3 +1,9 @@
+#include "Common.hlsl"
+
+#ifdef ALPHA_TEST
+#include "AlphaTest.hlsl"
+#endif
+
 cbuffer CsmMatrix : register(b0, space0)
 {
     matrix proj_view;
@@ -5,28 +11,42 @@ cbuffer CsmMatrix : register(b0, space0)
 
 cbuffer ObjectTransform : register(b0, space1)
 {
-    matrix transform;
+    matrix transforms[MAX_INSTANCES];
 }
 
 struct VertexInput
 {
     float3 pos : POSITION;
+#ifdef ALPHA_TEST
+    float4 uv : TEXCOORD;
+#endif
 };
 
 struct PixelInput
 {
     float4 pos : SV_POSITION;
+#ifdef ALPHA_TEST
+    float4 uv : TEXCOORD;
+#endif
 };
 
-PixelInput VSMain(VertexInput input)
+PixelInput VSMain(VertexInput input, uint instance : SV_InstanceID)
 {
     PixelInput output;
+    matrix transform = transforms[instance];
     output.pos = mul(proj_view, mul(transform, float4(input.pos, 1.0f)));
+#ifdef ALPHA_TEST
+    output.uv = input.uv;
+#endif
 
     return output;
 }
 
 float PSMain(PixelInput input) : SV_TARGET
 {
+#ifdef ALPHA_TEST
+    alpha_test(input.uv);
+#endif
+
     return input.pos.z;
 }
@@ -0,0 +1,53 @@
+#include "Common.hlsl"
+#include "FullscreenVS.hlsl"
+
+#ifndef DEPTH_TILE_SIZE
+#define DEPTH_TILE_SIZE 16
+#endif
+
+cbuffer GlobalBuffer : register(b0, space0) {
+    Globals g_data;
+}
+
+Texture2D<float> depth_t : register(t0, space1);
+
+// Each texel receives the view-space min/max depth of a tile, pixels on the
+// far plane are skipped so the sky does not stretch the range.
+float2 Main(FullscreenVertex input) : SV_Target {
+    uint2 size;
+    depth_t.GetDimensions(size.x, size.y);
+
+    uint2 origin = uint2(input.pos.xy) * DEPTH_TILE_SIZE;
+    float2 range = float2(1.0f, 0.0f);
+
+    for (uint y = 0; y < DEPTH_TILE_SIZE; ++y)
+    {
+        for (uint x = 0; x < DEPTH_TILE_SIZE; ++x)
+        {
+            uint2 coord = origin + uint2(x, y);
+            if (any(coord >= size))
+            {
+                continue;
+            }
+
+            float depth = depth_t.Load(int3(coord, 0));
+            if (depth >= 1.0f)
+            {
+                continue;
+            }
+
+            range.x = min(range.x, depth);
+            range.y = max(range.y, depth);
+        }
+    }
+
+    if (range.x > range.y)
+    {
+        return float2(0.0f, 0.0f);
+    }
+
+    float4 near_view = mul(g_data.inv_proj, float4(0.0f, 0.0f, range.x, 1.0f));
+    float4 far_view = mul(g_data.inv_proj, float4(0.0f, 0.0f, range.y, 1.0f));
+
+    return float2(near_view.z / near_view.w, far_view.z / far_view.w);
+}
@@ -8,6 +8,14 @@
 #define CASCADES_COUNT 4
 #endif
 
+#ifndef CASCADES_ATLAS_COLS
+#define CASCADES_ATLAS_COLS 2
+#endif
+
+#ifndef CASCADES_ATLAS_ROWS
+#define CASCADES_ATLAS_ROWS 2
+#endif
+
 SamplerComparisonState comp_shadow_s : register(s0, space0);
 
 cbuffer GlobalBuffer : register(b0, space0) {
@@ -17,6 +25,7 @@ cbuffer GlobalBuffer : register(b0, space0) {
 Texture2D diffuse_t : register(t0, space1);
 Texture2D normal_t : register(t1, space1);
 Texture2D material_t : register(t2, space1);
+Texture2D emissive_t : register(t3, space1);
 
 cbuffer LightBuffer : register(b0, space1)
 {
@@ -24,7 +33,11 @@ cbuffer LightBuffer : register(b0, space1)
     AmbientLight ambient_light;
 }
 
+#ifdef CASCADES_ARRAY
+Texture2DArray csm_t : register(t0, space2);
+#else
 Texture2D csm_t : register(t0, space2);
+#endif
 
 cbuffer CsmBuffer : register(b0, space2) {
     CsmData csm_data;
@@ -37,10 +50,10 @@ float3 compute_dir_light(float4 diffuse, float3 normal, float3 to_eye, float4 ma
     float3 light_strength = dir_light.strength * ndotl;
 
     MaterialEntry mat;
-    mat.fresnel_r0 = material.x;
+    mat.metallic = material.x;
     mat.roughness = material.y;
 
-    return blinn_phong(diffuse, mat, light_strength, lightVec, normal, to_eye);
+    return cook_torrance(diffuse.rgb, mat, light_strength, lightVec, normal, to_eye);
 }
 
 float4 Main(FullscreenVertex input) : SV_Target {
@@ -49,28 +62,51 @@ float4 Main(FullscreenVertex input) : SV_Target {
     float4 diffuse = diffuse_t.Load(int3(tex_coord, 0));
     float3 normal = unpack_normal_from_texture(normal_t.Load(int3(tex_coord, 0)));
     float4 material = material_t.Load(int3(tex_coord, 0));
+    float3 emissive = emissive_t.Load(int3(tex_coord, 0)).rgb;
 
     float depth = material.w;
     float4 world_pos = screen_to_world(float4(tex_coord, depth, 1.0f), g_data.screen_dim, g_data.inv_proj_view);
 
-    float fragment_dist = mul(g_data.view, world_pos).z;
+    // Shadow maps may be several frames older than the gbuffer, so pick the
+    // cascade with the camera they were rendered with and fall back to a
+    // coarser one when the pixel left the stale frustum.
+    float fragment_dist = mul(csm_data.camera_view, world_pos).z;
     uint cascade_idx = 0;
 
-     for (uint i = 0; i < CASCADES_COUNT - 1; ++i)
+    for (uint i = 0; i < CASCADES_COUNT - 1; ++i)
     {
-        if (fragment_dist > csm_data.split_distances[i])
+        if (fragment_dist > split_distance(csm_data, i))
         {
             cascade_idx = i + 1;
         }
     }
 
-    float shadow_factor = sample_csm_atlas(csm_t, comp_shadow_s, mul(csm_data.proj_view[cascade_idx], world_pos), cascade_idx);
+    float shadow_factor = 1.0f;
+    for (uint c = cascade_idx; c < CASCADES_COUNT; ++c)
+    {
+        float4 shadow_pos_h = mul(csm_data.proj_view[c], world_pos);
+        if (csm_in_bounds(shadow_pos_h))
+        {
+#ifdef CASCADES_ARRAY
+            shadow_factor = sample_csm(csm_t, comp_shadow_s, shadow_pos_h, c);
+#else
+            shadow_factor = sample_csm_atlas(
+                csm_t,
+                comp_shadow_s,
+                shadow_pos_h,
+                c,
+                uint2(CASCADES_ATLAS_COLS, CASCADES_ATLAS_ROWS)
+            );
+#endif
+            break;
+        }
+    }
 
     float3 to_eye = normalize(g_data.eye_pos - world_pos.xyz);
-    float4 ambient = ambient_light.color * diffuse;
+    float4 ambient = ambient_light.color * diffuse * material.z;
 
     float4 dir_light_color = float4(shadow_factor * compute_dir_light(diffuse, normal, to_eye, material), 1.0);
-	float4 lit_color = ambient + dir_light_color;
+	float4 lit_color = ambient + dir_light_color + float4(emissive, 0.0);
 
 	lit_color.a = diffuse.a;
     return lit_color;
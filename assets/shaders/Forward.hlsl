@@ -0,0 +1,168 @@
+#include "Common.hlsl"
+#include "ShadowCommon.hlsl"
+#include "Light.hlsl"
+#include "Pbr.hlsl"
+
+#ifndef CASCADES_COUNT
+#define CASCADES_COUNT 4
+#endif
+
+#ifndef CASCADES_ATLAS_COLS
+#define CASCADES_ATLAS_COLS 2
+#endif
+
+#ifndef CASCADES_ATLAS_ROWS
+#define CASCADES_ATLAS_ROWS 2
+#endif
+
+SamplerState linear_wrap_s : register(s0);
+SamplerComparisonState comp_shadow_s : register(s1);
+
+cbuffer GlobalBuffer : register(b0, space0) {
+    Globals g_data;
+}
+
+Texture2D base_color_t : register(t0, space1);
+Texture2D normal_t : register(t1, space1);
+Texture2D metallic_roughness_t : register(t2, space1);
+Texture2D occlusion_t : register(t3, space1);
+Texture2D emissive_t : register(t4, space1);
+
+cbuffer MaterialBuffer : register(b0, space1) {
+    Material material_data;
+}
+
+cbuffer ObjectTransform : register(b0, space2)
+{
+    matrix transforms[MAX_INSTANCES];
+}
+
+#ifdef CASCADES_ARRAY
+Texture2DArray csm_t : register(t0, space3);
+#else
+Texture2D csm_t : register(t0, space3);
+#endif
+
+Texture2D gbuffer_material_t : register(t1, space3);
+
+cbuffer LightBuffer : register(b0, space3)
+{
+    DirectionalLight dir_light;
+    AmbientLight ambient_light;
+}
+
+cbuffer CsmBuffer : register(b1, space3) {
+    CsmData csm_data;
+}
+
+struct VertexInput {
+    float3 pos : POSITION;
+    float3 normal : NORMAL;
+    float4 uv : TEXCOORD;
+    float4 tangent: TANGENT;
+};
+
+struct PixelInput {
+    float4 pos : SV_POSITION;
+    float3 pos_w: POSITION;
+    float3 normal: NORMAL;
+    float3 tangent: TANGENT;
+    float3 bitangent : BITANGENT;
+    float4 uv : TEXCOORD;
+};
+
+PixelInput VSMain(VertexInput input, uint instance : SV_InstanceID) {
+    PixelInput output = (PixelInput) 0;
+    matrix transform = transforms[instance];
+
+    float4 world_pos = mul(transform, float4(input.pos, 1.0f));
+    output.pos_w = world_pos.xyz;
+    output.pos = mul(g_data.proj_view, world_pos);
+    output.normal = transform_normal(transform, input.normal);
+    output.tangent = mul((float3x3) transform, input.tangent.xyz);
+    output.bitangent = normalize(cross(output.normal, output.tangent));
+    output.uv = input.uv;
+
+    return output;
+}
+
+float2 select_uv(float4 uv, uint set) {
+    return set == 0 ? uv.xy : uv.zw;
+}
+
+float shadow_factor(float4 world_pos) {
+    float fragment_dist = mul(csm_data.camera_view, world_pos).z;
+    uint cascade_idx = 0;
+
+    for (uint i = 0; i < CASCADES_COUNT - 1; ++i)
+    {
+        if (fragment_dist > split_distance(csm_data, i))
+        {
+            cascade_idx = i + 1;
+        }
+    }
+
+    for (uint c = cascade_idx; c < CASCADES_COUNT; ++c)
+    {
+        float4 shadow_pos_h = mul(csm_data.proj_view[c], world_pos);
+        if (csm_in_bounds(shadow_pos_h))
+        {
+#ifdef CASCADES_ARRAY
+            return sample_csm(csm_t, comp_shadow_s, shadow_pos_h, c);
+#else
+            return sample_csm_atlas(
+                csm_t,
+                comp_shadow_s,
+                shadow_pos_h,
+                c,
+                uint2(CASCADES_ATLAS_COLS, CASCADES_ATLAS_ROWS)
+            );
+#endif
+        }
+    }
+
+    return 1.0f;
+}
+
+// The prepass depth may live on another device, so the depth stored in the
+// gbuffer stands in for the depth test.
+float4 PSMain(PixelInput input, bool front_face : SV_IsFrontFace) : SV_Target {
+    float depth = gbuffer_material_t.Load(int3(input.pos.xy, 0)).w;
+    if (input.pos.z > depth)
+    {
+        discard;
+    }
+
+    float4 base_color = base_color_t.Sample(linear_wrap_s, select_uv(input.uv, material_data.base_color_uv)) * material_data.base_color;
+
+    float3 normal_sample = normal_t.Sample(linear_wrap_s, select_uv(input.uv, material_data.normal_uv)).rgb * 2.0 - 1.0;
+    normal_sample.xy *= material_data.normal_scale;
+
+    float3 t = normalize(input.tangent);
+    float3 b = normalize(input.bitangent);
+    float3 n = normalize(input.normal);
+    if (material_data.double_sided && !front_face)
+    {
+        n = -n;
+    }
+
+    float3 normal = normalize(mul(normal_sample, float3x3(t, b, n)));
+
+    float4 metallic_roughness = metallic_roughness_t.Sample(linear_wrap_s, select_uv(input.uv, material_data.metallic_roughness_uv));
+    float occlusion = occlusion_t.Sample(linear_wrap_s, select_uv(input.uv, material_data.occlusion_uv)).r;
+    float3 emissive = emissive_t.Sample(linear_wrap_s, select_uv(input.uv, material_data.emissive_uv)).rgb * material_data.emissive;
+
+    MaterialEntry mat;
+    mat.metallic = metallic_roughness.b * material_data.metallic;
+    mat.roughness = metallic_roughness.g * material_data.roughness;
+
+    float4 world_pos = float4(input.pos_w, 1.0f);
+    float3 to_eye = normalize(g_data.eye_pos - input.pos_w);
+    float3 light_vec = -dir_light.direction;
+    float3 light_strength = dir_light.strength * max(dot(light_vec, normal), 0.0f);
+
+    float3 ambient = ambient_light.color.rgb * base_color.rgb * lerp(1.0f, occlusion, material_data.occlusion_strength);
+    float3 direct = shadow_factor(world_pos) * cook_torrance(base_color.rgb, mat, light_strength, light_vec, normal, to_eye);
+
+    return float4(ambient + direct + emissive, base_color.a);
+}
@@ -7,8 +7,11 @@ cbuffer GlobalBuffer : register(b0, space0) {
     Globals g_data;
 }
 
-Texture2D diffuse_t : register(t0, space1);
+Texture2D base_color_t : register(t0, space1);
 Texture2D normal_t : register(t1, space1);
+Texture2D metallic_roughness_t : register(t2, space1);
+Texture2D occlusion_t : register(t3, space1);
+Texture2D emissive_t : register(t4, space1);
 
 cbuffer MaterialBuffer : register(b0, space1) {
     Material material_data;
@@ -16,13 +19,13 @@ cbuffer MaterialBuffer : register(b0, space1) {
 
 cbuffer ObjectTransform : register(b0, space2)
 {
-    matrix transform;
+    matrix transforms[MAX_INSTANCES];
 }
 
 struct VertexInput {
     float3 pos : POSITION;
     float3 normal : NORMAL;
-    float2 uv : TEXCOORD;
+    float4 uv : TEXCOORD;
     float4 tangent: TANGENT;
 };
 
@@ -32,17 +35,18 @@ struct PixelInput {
     float3 normal: NORMAL;
     float3 tangent: TANGENT;
     float3 bitangent : BITANGENT;
-    float2 uv : TEXCOORD;
+    float4 uv : TEXCOORD;
 };
 
-PixelInput VSMain(VertexInput input) {
+PixelInput VSMain(VertexInput input, uint instance : SV_InstanceID) {
     PixelInput output = (PixelInput) 0;
+    matrix transform = transforms[instance];
 
     float4 world_pos = mul(transform, float4(input.pos, 1.0f));
     output.pos_w = world_pos.xyz;
     output.pos = mul(g_data.proj_view, world_pos);
-    output.normal = mul(transform, input.normal);
-    output.tangent = mul(transform, input.tangent.xyz);
+    output.normal = transform_normal(transform, input.normal);
+    output.tangent = mul((float3x3) transform, input.tangent.xyz);
     output.bitangent = normalize(cross(output.normal, output.tangent));
     output.uv = input.uv;
 
@@ -53,23 +57,45 @@ struct PixelShaderOutput {
     float4 diffuse : SV_Target0;
     float4 normal : SV_Target1;
     float4 material : SV_Target2;
+    float4 emissive : SV_Target3;
 };
 
+// TEXCOORD_0 is packed in xy and TEXCOORD_1 in zw.
+float2 select_uv(float4 uv, uint set) {
+    return set == 0 ? uv.xy : uv.zw;
+}
+
 [earlydepthstencil]
-PixelShaderOutput PSMain(PixelInput input) {
+PixelShaderOutput PSMain(PixelInput input, bool front_face : SV_IsFrontFace) {
     PixelShaderOutput output;
 
-    float3 normal_sample = normal_t.Sample(linear_clamp_s, input.uv).rgb * 2.0 - 1.0;
+    float3 normal_sample = normal_t.Sample(linear_clamp_s, select_uv(input.uv, material_data.normal_uv)).rgb * 2.0 - 1.0;
+    normal_sample.xy *= material_data.normal_scale;
 
     float3 t = normalize(input.tangent);
     float3 b = normalize(input.bitangent);
     float3 n = normalize(input.normal);
+    if (material_data.double_sided && !front_face)
+    {
+        n = -n;
+    }
+
     float3x3 tbn = float3x3(t, b, n);
     float3 normal = normalize(mul(normal_sample, tbn));
 
-    output.diffuse = diffuse_t.Sample(linear_clamp_s, input.uv) * material_data.diffuse;
+    float4 metallic_roughness = metallic_roughness_t.Sample(linear_clamp_s, select_uv(input.uv, material_data.metallic_roughness_uv));
+    float occlusion = occlusion_t.Sample(linear_clamp_s, select_uv(input.uv, material_data.occlusion_uv)).r;
+    float3 emissive = emissive_t.Sample(linear_clamp_s, select_uv(input.uv, material_data.emissive_uv)).rgb;
+
+    output.diffuse = base_color_t.Sample(linear_clamp_s, select_uv(input.uv, material_data.base_color_uv)) * material_data.base_color;
     output.normal = pack_normal_to_texture(normal);
-    output.material = float4(material_data.fresnel_r0, material_data.roughness, 0.0, input.pos.z);
+    output.material = float4(
+        metallic_roughness.b * material_data.metallic,
+        metallic_roughness.g * material_data.roughness,
+        lerp(1.0f, occlusion, material_data.occlusion_strength),
+        input.pos.z
+    );
+    output.emissive = float4(emissive * material_data.emissive, 1.0f);
 
     return output;
 }
@@ -1,14 +1,31 @@
 #ifndef PBR_HLSL
 #define PBR_HLSL
 
+#define ALPHA_MODE_OPAQUE 0
+#define ALPHA_MODE_MASK 1
+#define ALPHA_MODE_BLEND 2
+
+static const float PI = 3.14159265f;
+
 struct Material {
-    float4 diffuse;
-    float fresnel_r0;
+    float4 base_color;
+    float3 emissive;
+    float metallic;
     float roughness;
+    float normal_scale;
+    float occlusion_strength;
+    float alpha_cutoff;
+    uint alpha_mode;
+    uint double_sided;
+    uint base_color_uv;
+    uint metallic_roughness_uv;
+    uint normal_uv;
+    uint occlusion_uv;
+    uint emissive_uv;
 };
 
 struct MaterialEntry {
-    float fresnel_r0;
+    float metallic;
     float roughness;
 };
 
@@ -20,19 +37,41 @@ float3 schlick_fresnel(float3 r0, float3 normal, float3 light_vec) {
     return reflect_percent;
 }
 
-float3 blinn_phong(float4 diffuse_albedo, MaterialEntry material, float3 light_strength, float3 light_vec, float3 normal, float3 to_eye)
+float distribution_ggx(float ndoth, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = ndoth * ndoth * (a2 - 1.0f) + 1.0f;
+
+    return a2 / max(PI * d * d, 1e-6f);
+}
+
+float geometry_smith(float ndotv, float ndotl, float roughness) {
+    float k = (roughness + 1.0f) * (roughness + 1.0f) / 8.0f;
+    float gv = ndotv / (ndotv * (1.0f - k) + k);
+    float gl = ndotl / (ndotl * (1.0f - k) + k);
+
+    return gv * gl;
+}
+
+// Light strengths are tuned for an albedo that is not divided by PI, so the
+// whole BRDF is scaled by PI to keep the same brightness.
+float3 cook_torrance(float3 base_color, MaterialEntry material, float3 light_strength, float3 light_vec, float3 normal, float3 to_eye)
 {
-    const float m = (1.0 - material.roughness) * 256.0f;
+    float roughness = clamp(material.roughness, 0.045f, 1.0f);
     float3 half_vec = normalize(to_eye + light_vec);
 
-    float roughness_factor = (m + 8.0f)*pow(max(dot(half_vec, normal), 0.0f), m) / 8.0f;
-    float3 fresnel_factor = schlick_fresnel(material.fresnel_r0, half_vec, light_vec);
+    float ndotl = saturate(dot(normal, light_vec));
+    float ndotv = max(dot(normal, to_eye), 1e-4f);
+    float ndoth = saturate(dot(normal, half_vec));
 
-    float3 spec_albedo = fresnel_factor * roughness_factor;
+    float3 f0 = lerp(float3(0.04f, 0.04f, 0.04f), base_color, material.metallic);
+    float3 fresnel = schlick_fresnel(f0, half_vec, light_vec);
 
-    spec_albedo = spec_albedo / (spec_albedo + 1.0f);
+    float3 specular = distribution_ggx(ndoth, roughness) * geometry_smith(ndotv, ndotl, roughness) * fresnel
+        / max(4.0f * ndotv * ndotl, 1e-4f);
+    float3 diffuse = (1.0f - fresnel) * (1.0f - material.metallic) * base_color;
 
-    return (diffuse_albedo.rgb + spec_albedo) * light_strength;
+    return (diffuse + PI * specular) * light_strength;
 }
 
 #endif
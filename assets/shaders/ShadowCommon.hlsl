@@ -1,8 +1,26 @@
+#ifndef CASCADES_MAX
+#define CASCADES_MAX 8
+#endif
+
 struct CsmData {
-    matrix proj_view[4];
-    float4 split_distances;
+    matrix proj_view[CASCADES_MAX];
+    matrix camera_view;
+    float4 split_distances[CASCADES_MAX / 4];
 };
 
+float split_distance(CsmData data, uint cascade_idx) {
+    return data.split_distances[cascade_idx / 4][cascade_idx % 4];
+}
+
+static const float csm_border = 0.01f;
+
+bool csm_in_bounds(float4 shadow_pos_h) {
+    float3 proj_coords = shadow_pos_h.xyz / shadow_pos_h.w;
+
+    return all(abs(proj_coords.xy) <= 1.0f - csm_border)
+        && proj_coords.z >= 0.0f && proj_coords.z <= 1.0f;
+}
+
 float sample_csm(
     Texture2DArray csm_t,
     SamplerComparisonState comp_shadow_s,
@@ -38,26 +56,22 @@ float sample_csm_array(
     return csm_t[cascade_idx].SampleCmpLevelZero(comp_shadow_s, tex_coord, proj_coords.z).r;
 }
 
-static float2 atlas_offets[4] = {
-    float2(0.0f, 0.0f),
-    float2(0.5f, 0.0f),
-    float2(0.0f, 0.5f),
-    float2(0.50f, 0.5f),
-};
-
 float sample_csm_atlas(
     Texture2D csm_t,
     SamplerComparisonState comp_shadow_s,
     float4 shadow_pos_h,
-    int cascade_idx
+    int cascade_idx,
+    uint2 grid
 ) {
     float3 proj_coords = shadow_pos_h.xyz / shadow_pos_h.w;
 
     proj_coords.x = proj_coords.x * 0.5 + 0.5;
     proj_coords.y = -proj_coords.y * 0.5 + 0.5;
 
+    float2 cell = float2(cascade_idx % grid.x, cascade_idx / grid.x);
+
     float2 tex_coord;
-    tex_coord.xy = proj_coords.xy * 0.5 + atlas_offets[cascade_idx];
+    tex_coord.xy = (proj_coords.xy + cell) / float2(grid);
 
     return csm_t.SampleCmpLevelZero(comp_shadow_s, tex_coord, proj_coords.z).r;
 }
@@ -1,28 +1,48 @@
 #include "Common.hlsl"
 #include "Pbr.hlsl"
 
+#ifdef ALPHA_TEST
+#include "AlphaTest.hlsl"
+#endif
+
 cbuffer GlobalBuffer : register(b0, space0) {
     Globals g_data;
 }
 
 cbuffer ObjectTransform : register(b0, space1)
 {
-    matrix transform;
+    matrix transforms[MAX_INSTANCES];
 }
 
 struct VertexInput {
     float3 pos : POSITION;
+#ifdef ALPHA_TEST
+    float4 uv : TEXCOORD;
+#endif
 };
 
 struct PixelInput {
     float4 pos : SV_POSITION;
+#ifdef ALPHA_TEST
+    float4 uv : TEXCOORD;
+#endif
 };
 
-PixelInput Main(VertexInput input) {
+PixelInput Main(VertexInput input, uint instance : SV_InstanceID) {
     PixelInput output = (PixelInput) 0;
+    matrix transform = transforms[instance];
 
     float4 world_pos = mul(transform, float4(input.pos, 1.0f));
     output.pos = mul(g_data.proj_view, world_pos);
+#ifdef ALPHA_TEST
+    output.uv = input.uv;
+#endif
 
     return output;
 }
+
+#ifdef ALPHA_TEST
+void PSMain(PixelInput input) {
+    alpha_test(input.uv);
+}
+#endif
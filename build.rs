@@ -6,11 +6,9 @@ fn main() {
     let assets_path = Path::new("assets");
     if assets_path.exists() {
         println!("cargo:rerun-if-changed=assets");
-        for entry in WalkDir::new("assets") {
-            if let Ok(entry) = entry {
-                if entry.file_type().is_file() {
-                    println!("cargo:rerun-if-changed={}", entry.path().display());
-                }
+        for entry in WalkDir::new("assets").into_iter().flatten() {
+            if entry.file_type().is_file() {
+                println!("cargo:rerun-if-changed={}", entry.path().display());
             }
         }
     }
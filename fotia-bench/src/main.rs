@@ -19,6 +19,10 @@ enum TimingsInfo {
     SecondaryMultiGpu(Timings),
     SingleCpuTotal(Duration),
     MultiCpuTotal(Duration),
+    CameraPredictionError {
+        position: f32,
+        angle: f32,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -66,6 +70,9 @@ struct SceneBenchmark {
 
     multi_primary_passes: HashMap<String, Vec<Duration>>,
     multi_secondary_passes: HashMap<String, Vec<Duration>>,
+
+    camera_position_errors: Vec<f32>,
+    camera_angle_errors: Vec<f32>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -84,6 +91,9 @@ struct SceneBenchmarkResult {
 
     multi_primary_passes_avg: HashMap<String, f32>,
     multi_secondary_passes_avg: HashMap<String, f32>,
+
+    camera_position_error_avg: f32,
+    camera_angle_error_avg: f32,
 }
 
 impl SceneBenchmark {
@@ -101,6 +111,8 @@ impl SceneBenchmark {
             multi_secondary_gpu: Vec::new(),
             multi_primary_passes: HashMap::new(),
             multi_secondary_passes: HashMap::new(),
+            camera_position_errors: Vec::new(),
+            camera_angle_errors: Vec::new(),
         }
     }
 
@@ -150,6 +162,16 @@ impl SceneBenchmark {
             })
             .collect();
 
+        let average = |errors: &[f32]| {
+            if errors.is_empty() {
+                0.0
+            } else {
+                errors.iter().sum::<f32>() / errors.len() as f32
+            }
+        };
+        let camera_position_error_avg = average(&self.camera_position_errors);
+        let camera_angle_error_avg = average(&self.camera_angle_errors);
+
         SceneBenchmarkResult {
             scene_name: self.scene_name,
             cascades_size: self.cascades_size,
@@ -163,6 +185,8 @@ impl SceneBenchmark {
             multi_secondary_gpu_avg: multi_secondary_gpu_avg.as_secs_f32() * 1000.0,
             multi_primary_passes_avg,
             multi_secondary_passes_avg,
+            camera_position_error_avg,
+            camera_angle_error_avg,
         }
     }
 }
@@ -248,6 +272,10 @@ async fn benchmark_scene(
                         bench_scene.multi_secondary_gpu.push(t.total);
                     }
                     TimingsInfo::MultiCpuTotal(d) => bench_scene.multi_cpu.push(d),
+                    TimingsInfo::CameraPredictionError { position, angle } => {
+                        bench_scene.camera_position_errors.push(position);
+                        bench_scene.camera_angle_errors.push(angle);
+                    }
                     TimingsInfo::GpuInfo { primary, secondary } => {
                         if bench_result.gpus.is_empty() {
                             bench_result.gpus.push(primary);
@@ -0,0 +1,363 @@
+use std::borrow::Cow;
+
+use smallvec::SmallVec;
+
+use crate::rhi::{
+    backend::RenderDeviceId,
+    command::{CommandType, SyncPoint},
+};
+
+pub trait StageFence {
+    fn is_ready_for(&self, device: RenderDeviceId, queue: CommandType, value: SyncPoint) -> bool;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineStage {
+    pub name: Cow<'static, str>,
+    pub device: RenderDeviceId,
+    pub queue: CommandType,
+}
+
+impl PipelineStage {
+    pub fn new(
+        name: impl Into<Cow<'static, str>>,
+        device: RenderDeviceId,
+        queue: CommandType,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            device,
+            queue,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlotState {
+    #[default]
+    Free,
+    Ready(usize),
+    Busy(usize, Option<SyncPoint>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineMetrics {
+    pub completed: usize,
+    pub last_latency: Option<usize>,
+    pub max_latency: usize,
+    pub total_latency: usize,
+    pub stalls: SmallVec<[usize; 4]>,
+}
+
+impl PipelineMetrics {
+    pub fn average_latency(&self) -> Option<f32> {
+        (self.completed > 0).then(|| self.total_latency as f32 / self.completed as f32)
+    }
+}
+
+#[derive(Debug)]
+pub struct FramePipeline<T, const N: usize> {
+    data: SmallVec<[T; N]>,
+    states: SmallVec<[SlotState; N]>,
+    started: SmallVec<[usize; N]>,
+
+    stages: SmallVec<[PipelineStage; 4]>,
+    cursors: SmallVec<[usize; 4]>,
+
+    latest: Option<usize>,
+    frame: usize,
+    metrics: PipelineMetrics,
+}
+
+impl<T, const N: usize> FramePipeline<T, N> {
+    pub fn new(data: SmallVec<[T; N]>, stages: impl IntoIterator<Item = PipelineStage>) -> Self {
+        let stages = stages.into_iter().collect::<SmallVec<[_; 4]>>();
+
+        assert!(
+            !data.is_empty(),
+            "frame pipeline must have at least one slot"
+        );
+        assert!(
+            !stages.is_empty(),
+            "frame pipeline must have at least one stage"
+        );
+
+        let size = data.len();
+        Self {
+            data,
+            states: (0..size).map(|_| SlotState::Free).collect(),
+            started: (0..size).map(|_| 0).collect(),
+            cursors: stages.iter().map(|_| 0).collect(),
+            metrics: PipelineMetrics {
+                stalls: stages.iter().map(|_| 0).collect(),
+                ..Default::default()
+            },
+            stages,
+            latest: None,
+            frame: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    #[inline]
+    pub fn stages(&self) -> &[PipelineStage] {
+        &self.stages
+    }
+
+    #[inline]
+    pub fn state(&self, slot: usize) -> SlotState {
+        self.states[slot]
+    }
+
+    #[inline]
+    pub fn data(&self, slot: usize) -> &T {
+        &self.data[slot]
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> &PipelineMetrics {
+        &self.metrics
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn peek(&self, stage: usize) -> Option<usize> {
+        let slot = self.cursors[stage];
+
+        match (stage, self.states[slot]) {
+            (0, SlotState::Free) => Some(slot),
+            (_, SlotState::Ready(ready)) if ready == stage => Some(slot),
+            _ => None,
+        }
+    }
+
+    pub fn acquire(&mut self, stage: usize) -> Option<usize> {
+        let Some(slot) = self.peek(stage) else {
+            self.metrics.stalls[stage] += 1;
+            return None;
+        };
+
+        if stage == 0 {
+            self.started[slot] = self.frame;
+        }
+
+        self.states[slot] = SlotState::Busy(stage, None);
+        self.cursors[stage] = (slot + 1) % self.len();
+
+        Some(slot)
+    }
+
+    pub fn submit(&mut self, slot: usize, sync_point: SyncPoint) {
+        let SlotState::Busy(stage, None) = self.states[slot] else {
+            panic!("slot {} is not acquired by any stage", slot);
+        };
+
+        self.states[slot] = SlotState::Busy(stage, Some(sync_point));
+    }
+
+    pub fn complete(&mut self, slot: usize) {
+        let SlotState::Busy(stage, _) = self.states[slot] else {
+            panic!("slot {} is not acquired by any stage", slot);
+        };
+
+        if stage + 1 < self.stages.len() {
+            self.states[slot] = SlotState::Ready(stage + 1);
+            return;
+        }
+
+        let latency = self.frame - self.started[slot];
+
+        self.metrics.completed += 1;
+        self.metrics.last_latency = Some(latency);
+        self.metrics.max_latency = self.metrics.max_latency.max(latency);
+        self.metrics.total_latency += latency;
+
+        self.states[slot] = SlotState::Free;
+        self.latest = Some(slot);
+    }
+
+    pub fn poll(&mut self, fence: &(impl StageFence + ?Sized)) {
+        let start = self.cursors[0];
+        for i in 0..self.len() {
+            let slot = (start + i) % self.len();
+
+            if let SlotState::Busy(stage, Some(value)) = self.states[slot] {
+                let desc = &self.stages[stage];
+                if fence.is_ready_for(desc.device, desc.queue, value) {
+                    self.complete(slot);
+                }
+            }
+        }
+    }
+
+    pub fn latest(&self) -> Option<usize> {
+        self.latest
+    }
+
+    pub fn latest_data(&self) -> Option<&T> {
+        self.latest.map(|slot| &self.data[slot])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smallvec::smallvec;
+
+    use super::*;
+
+    // Last completed value of every device queue.
+    #[derive(Default)]
+    struct FakeFence {
+        completed: Vec<(RenderDeviceId, CommandType, SyncPoint)>,
+    }
+
+    impl FakeFence {
+        fn signal(&mut self, device: RenderDeviceId, queue: CommandType, value: SyncPoint) {
+            self.completed
+                .retain(|(d, q, _)| (*d, *q) != (device, queue));
+            self.completed.push((device, queue, value));
+        }
+    }
+
+    impl StageFence for FakeFence {
+        fn is_ready_for(
+            &self,
+            device: RenderDeviceId,
+            queue: CommandType,
+            value: SyncPoint,
+        ) -> bool {
+            self.completed
+                .iter()
+                .any(|(d, q, v)| (*d, *q) == (device, queue) && *v >= value)
+        }
+    }
+
+    fn pipeline(slots: usize) -> FramePipeline<usize, 4> {
+        FramePipeline::new(
+            (0..slots).collect(),
+            [
+                PipelineStage::new("Primary", 0, CommandType::Graphics),
+                PipelineStage::new("Secondary", 1, CommandType::Graphics),
+            ],
+        )
+    }
+
+    #[test]
+    fn slot_moves_through_stages() {
+        let mut pipeline = pipeline(2);
+        let mut fence = FakeFence::default();
+
+        assert_eq!(pipeline.acquire(0), Some(0));
+        assert_eq!(pipeline.state(0), SlotState::Busy(0, None));
+
+        pipeline.submit(0, 1);
+        assert_eq!(pipeline.state(0), SlotState::Busy(0, Some(1)));
+
+        // Another device reaching the value does not complete the stage.
+        fence.signal(1, CommandType::Graphics, 1);
+        pipeline.poll(&fence);
+        assert_eq!(pipeline.state(0), SlotState::Busy(0, Some(1)));
+
+        fence.signal(0, CommandType::Graphics, 1);
+        pipeline.poll(&fence);
+        assert_eq!(pipeline.state(0), SlotState::Ready(1));
+        assert_eq!(pipeline.peek(1), Some(0));
+        assert_eq!(pipeline.latest(), None);
+
+        assert_eq!(pipeline.acquire(1), Some(0));
+        pipeline.submit(0, 5);
+        pipeline.poll(&fence);
+        assert_eq!(pipeline.state(0), SlotState::Busy(1, Some(5)));
+
+        fence.signal(1, CommandType::Graphics, 5);
+        pipeline.poll(&fence);
+        assert_eq!(pipeline.state(0), SlotState::Free);
+        assert_eq!(pipeline.latest(), Some(0));
+        assert_eq!(pipeline.latest_data(), Some(&0));
+    }
+
+    #[test]
+    fn stages_stall_without_slots() {
+        let mut pipeline = pipeline(1);
+
+        // Nothing is ready for the second stage yet.
+        assert_eq!(pipeline.acquire(1), None);
+        assert_eq!(pipeline.acquire(0), Some(0));
+        assert_eq!(pipeline.acquire(0), None);
+
+        pipeline.submit(0, 1);
+        pipeline.complete(0);
+        assert_eq!(pipeline.acquire(0), None);
+        assert_eq!(pipeline.acquire(1), Some(0));
+
+        assert_eq!(pipeline.metrics().stalls.as_slice(), [2, 1]);
+    }
+
+    #[test]
+    fn slots_are_acquired_in_order() {
+        let mut pipeline = pipeline(2);
+
+        assert_eq!(pipeline.acquire(0), Some(0));
+        assert_eq!(pipeline.acquire(0), Some(1));
+        pipeline.complete(1);
+        pipeline.complete(0);
+
+        // The second stage follows the order of the first one.
+        assert_eq!(pipeline.acquire(1), Some(0));
+        assert_eq!(pipeline.acquire(1), Some(1));
+    }
+
+    #[test]
+    fn latency_counts_frames_since_first_stage() {
+        let mut pipeline = FramePipeline::<usize, 2>::new(
+            smallvec![0, 1],
+            [PipelineStage::new("Primary", 0, CommandType::Graphics)],
+        );
+        let mut fence = FakeFence::default();
+
+        assert_eq!(pipeline.metrics().average_latency(), None);
+
+        assert_eq!(pipeline.acquire(0), Some(0));
+        pipeline.submit(0, 1);
+        pipeline.next_frame();
+        pipeline.next_frame();
+
+        assert_eq!(pipeline.acquire(0), Some(1));
+        pipeline.submit(1, 2);
+
+        fence.signal(0, CommandType::Graphics, 1);
+        pipeline.poll(&fence);
+        assert_eq!(pipeline.metrics().last_latency, Some(2));
+
+        pipeline.next_frame();
+        fence.signal(0, CommandType::Graphics, 2);
+        pipeline.poll(&fence);
+
+        let metrics = pipeline.metrics();
+        assert_eq!(metrics.completed, 2);
+        assert_eq!(metrics.last_latency, Some(1));
+        assert_eq!(metrics.max_latency, 2);
+        assert_eq!(metrics.total_latency, 3);
+        assert_eq!(metrics.average_latency(), Some(1.5));
+        assert_eq!(pipeline.latest(), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "not acquired")]
+    fn submit_requires_acquired_slot() {
+        let mut pipeline = pipeline(2);
+
+        pipeline.submit(0, 1);
+    }
+}
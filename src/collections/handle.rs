@@ -2,7 +2,6 @@ use std::{marker::PhantomData, num::NonZero};
 
 const _OPT_HANDLE_SIZE: () = if size_of::<Option<Handle<u32>>>() != size_of::<Handle<u32>>() {
     panic!("size of Option<Handle<T>> not equal to size of Handle<T>");
-} else {
 };
 
 pub struct Handle<T> {
@@ -69,6 +68,12 @@ pub struct HandleAllocator<T> {
     _marker: PhantomData<T>,
 }
 
+impl<T> Default for HandleAllocator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> HandleAllocator<T> {
     #[inline]
     pub fn new() -> Self {
@@ -1,4 +1,4 @@
+pub mod frame_pipeline;
 pub mod handle;
 pub mod pool;
-pub mod rwc_ring_buffer;
 pub mod sparse_map;
@@ -44,10 +44,7 @@ impl<U, W> SparseMap<U, W> {
         }
 
         if let Some(ref mut h) = self.sparse[handle.index as usize] {
-            let value = std::mem::replace(
-                &mut self.dense[h.dense_index as usize],
-                MaybeUninit::new(value),
-            );
+            let value = std::mem::replace(&mut self.dense[h.dense_index], MaybeUninit::new(value));
 
             h.dense_cookie = handle.cookie;
             unsafe { Some(value.assume_init()) }
@@ -68,7 +65,7 @@ impl<U, W> SparseMap<U, W> {
         self.sparse.get(handle.index as usize).and_then(|h| {
             if let Some(h) = h {
                 if h.dense_cookie == handle.cookie {
-                    unsafe { Some(self.dense[h.dense_index as usize].assume_init_ref()) }
+                    unsafe { Some(self.dense[h.dense_index].assume_init_ref()) }
                 } else {
                     None
                 }
@@ -82,7 +79,7 @@ impl<U, W> SparseMap<U, W> {
         self.sparse.get(handle.index as usize).and_then(|h| {
             if let Some(h) = h {
                 if h.dense_cookie == handle.cookie {
-                    unsafe { Some(self.dense[h.dense_index as usize].assume_init_mut()) }
+                    unsafe { Some(self.dense[h.dense_index].assume_init_mut()) }
                 } else {
                     None
                 }
@@ -131,7 +128,7 @@ impl<U, W> Drop for SparseMap<U, W> {
         for handle in self.sparse.iter_mut() {
             if let Some(handle) = handle.take() {
                 unsafe {
-                    self.dense[handle.dense_index as usize].assume_init_drop();
+                    self.dense[handle.dense_index].assume_init_drop();
                 }
             }
         }
@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use std::collections::VecDeque;
+
+#[derive(Clone, Debug)]
 pub struct Camera {
     pub far: f32,
     pub near: f32,
@@ -51,19 +53,23 @@ impl FpsController {
         }
     }
 
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            yaw: self.yaw,
+            pitch: self.pitch,
+        }
+    }
+
     pub fn update_position(&mut self, dt: f32, camera: &mut Camera, direction: glam::Vec3) {
-        let forward = glam::Vec3::new(
-            self.yaw.cos() * self.pitch.cos(),
-            self.pitch.sin(),
-            self.yaw.sin() * self.pitch.cos(),
-        );
+        let forward = forward(self.yaw, self.pitch);
         let right = glam::Vec3::Y.cross(forward).normalize();
         let up = right.cross(forward);
 
         self.position +=
             (forward * direction.z + right * direction.x + up * direction.y) * self.speed * dt;
 
-        camera.view = glam::Mat4::look_at_lh(self.position, self.position + forward, glam::Vec3::Y);
+        camera.view = self.state().view();
     }
 
     pub fn update_yaw_pitch(&mut self, camera: &mut Camera, x: f32, y: f32) {
@@ -75,12 +81,238 @@ impl FpsController {
             std::f32::consts::FRAC_PI_2 - 0.1,
         );
 
-        let forward = glam::Vec3::new(
-            self.yaw.cos() * self.pitch.cos(),
-            self.pitch.sin(),
-            self.yaw.sin() * self.pitch.cos(),
+        camera.view = self.state().view();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CameraState {
+    pub position: glam::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl CameraState {
+    pub fn forward(&self) -> glam::Vec3 {
+        forward(self.yaw, self.pitch)
+    }
+
+    pub fn view(&self) -> glam::Mat4 {
+        glam::Mat4::look_at_lh(self.position, self.position + self.forward(), glam::Vec3::Y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PredictionError {
+    pub position: f32,
+    pub angle: f32,
+}
+
+#[derive(Debug)]
+pub struct CameraPredictor {
+    history: VecDeque<CameraState>,
+    capacity: usize,
+    enabled: bool,
+
+    pending: VecDeque<(usize, CameraState)>,
+    frame: usize,
+    error: Option<PredictionError>,
+}
+
+impl CameraPredictor {
+    pub fn new(capacity: usize, enabled: bool) -> Self {
+        assert!(
+            capacity > 1,
+            "camera predictor needs at least two samples to estimate velocity"
         );
 
-        camera.view = glam::Mat4::look_at_lh(self.position, self.position + forward, glam::Vec3::Y);
+        Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+            enabled,
+            pending: VecDeque::new(),
+            frame: 0,
+            error: None,
+        }
+    }
+
+    pub fn push(&mut self, state: CameraState) -> Option<PredictionError> {
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(state);
+        self.frame += 1;
+
+        let mut error = None;
+        while let Some((frame, predicted)) = self.pending.front().copied() {
+            if frame > self.frame {
+                break;
+            }
+
+            self.pending.pop_front();
+            if frame == self.frame {
+                error = Some(PredictionError {
+                    position: predicted.position.distance(state.position),
+                    angle: predicted.forward().angle_between(state.forward()),
+                });
+            }
+        }
+
+        self.error = error.or(self.error);
+
+        error
+    }
+
+    // Error of the latest extrapolation that reached its frame, a disabled
+    // predictor holds the camera and has nothing to report.
+    pub fn last_error(&self) -> Option<PredictionError> {
+        self.error.filter(|_| self.enabled)
+    }
+
+    pub fn velocity(&self) -> CameraState {
+        let (Some(first), Some(last)) = (self.history.front(), self.history.back()) else {
+            return CameraState::default();
+        };
+
+        let frames = (self.history.len() - 1).max(1) as f32;
+
+        CameraState {
+            position: (last.position - first.position) / frames,
+            yaw: (last.yaw - first.yaw) / frames,
+            pitch: (last.pitch - first.pitch) / frames,
+        }
+    }
+
+    pub fn predict(&mut self, frames: usize) -> Option<CameraState> {
+        let last = *self.history.back()?;
+        let velocity = self.velocity();
+        let frames_f = frames as f32;
+
+        // A disabled predictor still tracks how far the held camera drifts
+        // so the error can be compared against the extrapolated one.
+        let predicted = if self.enabled {
+            CameraState {
+                position: last.position + velocity.position * frames_f,
+                yaw: last.yaw + velocity.yaw * frames_f,
+                pitch: (last.pitch + velocity.pitch * frames_f).clamp(
+                    -std::f32::consts::FRAC_PI_2 + 0.1,
+                    std::f32::consts::FRAC_PI_2 - 0.1,
+                ),
+            }
+        } else {
+            last
+        };
+
+        if frames > 0 {
+            self.pending.push_back((self.frame + frames, predicted));
+        }
+
+        Some(predicted)
+    }
+
+    pub fn predict_camera(&mut self, camera: &Camera, frames: usize) -> Camera {
+        match self.predict(frames) {
+            Some(state) => Camera {
+                view: state.view(),
+                ..camera.clone()
+            },
+            None => camera.clone(),
+        }
+    }
+}
+
+fn forward(yaw: f32, pitch: f32) -> glam::Vec3 {
+    glam::Vec3::new(
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+        yaw.sin() * pitch.cos(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    // Moves one unit along X and turns 0.01 radians every frame.
+    fn state(frame: usize) -> CameraState {
+        CameraState {
+            position: glam::vec3(frame as f32, 1.0, 0.0),
+            yaw: 0.01 * frame as f32,
+            pitch: 0.2,
+        }
+    }
+
+    #[test]
+    fn velocity_averages_history() {
+        let mut predictor = CameraPredictor::new(4, true);
+        assert_eq!(predictor.velocity(), CameraState::default());
+
+        for frame in 0..6 {
+            predictor.push(state(frame));
+        }
+
+        let velocity = predictor.velocity();
+        assert!(velocity.position.distance(glam::Vec3::X) < EPSILON);
+        assert!((velocity.yaw - 0.01).abs() < EPSILON);
+        assert!(velocity.pitch.abs() < EPSILON);
+    }
+
+    #[test]
+    fn constant_velocity_is_extrapolated() {
+        let mut predictor = CameraPredictor::new(4, true);
+        assert_eq!(predictor.predict(3), None);
+
+        for frame in 0..4 {
+            assert_eq!(predictor.push(state(frame)), None);
+        }
+
+        let predicted = predictor.predict(3).expect("no camera history");
+        assert!(predicted.position.distance(state(6).position) < EPSILON);
+        assert!((predicted.yaw - state(6).yaw).abs() < EPSILON);
+        assert!((predicted.pitch - state(6).pitch).abs() < EPSILON);
+
+        // The error is measured once the predicted frame arrives.
+        assert_eq!(predictor.push(state(4)), None);
+        assert_eq!(predictor.push(state(5)), None);
+        let error = predictor.push(state(6)).expect("no prediction error");
+        assert!(error.position < EPSILON);
+        assert!(error.angle < EPSILON);
+        assert_eq!(predictor.last_error(), Some(error));
+    }
+
+    #[test]
+    fn disabled_predictor_measures_held_camera() {
+        let mut predictor = CameraPredictor::new(4, false);
+        for frame in 0..4 {
+            predictor.push(state(frame));
+        }
+
+        let held = predictor.predict(2).expect("no camera history");
+        assert_eq!(held, state(3));
+
+        predictor.push(state(4));
+        let error = predictor.push(state(5)).expect("no prediction error");
+        assert!((error.position - 2.0).abs() < EPSILON);
+        assert!(error.angle > 0.0);
+        assert_eq!(predictor.last_error(), None);
+    }
+
+    #[test]
+    fn predicted_camera_keeps_projection() {
+        let mut predictor = CameraPredictor::new(2, true);
+        let camera = Camera::new(0.1, 100.0, 1.0, [16, 9]);
+
+        // Without history the camera is used as is.
+        assert_eq!(predictor.predict_camera(&camera, 2).view, camera.view);
+
+        predictor.push(state(0));
+        predictor.push(state(1));
+        let predicted = predictor.predict_camera(&camera, 2);
+
+        assert_eq!(predicted.view, state(3).view());
+        assert_eq!(predicted.fov, camera.fov);
+        assert_eq!(predicted.aspect_ratio, camera.aspect_ratio);
     }
 }
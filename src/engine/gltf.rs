@@ -1,10 +1,14 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
-use glam::{Mat4, Vec3, Vec4};
-use tracing::info;
+use glam::{Mat4, Quat, Vec3, Vec4};
+use tracing::{error, info};
 
 use crate::{
     collections::handle::Handle,
+    engine::{Aabb, MAX_INSTANCES, TransformComponent},
     ra::{
         resources::{Buffer, Texture},
         shader::ShaderArgument,
@@ -12,32 +16,140 @@ use crate::{
     },
 };
 
+// Decoded RGBA8 pixels, independent of where the encoded image came from.
 #[derive(Clone, Debug)]
-pub enum ImageSource {
-    Path(std::path::PathBuf),
-    Data(Vec<u8>),
+pub struct ImageData {
+    pub name: String,
+    pub extent: [u32; 2],
+    pub pixels: Vec<u8>,
+}
+
+impl ImageData {
+    pub fn decode(name: String, bytes: &[u8], mime_type: Option<&str>) -> image::ImageResult<Self> {
+        let format = match mime_type {
+            Some("image/png") => image::ImageFormat::Png,
+            Some("image/jpeg") => image::ImageFormat::Jpeg,
+            _ => image::guess_format(bytes)?,
+        };
+
+        let image = image::load_from_memory_with_format(bytes, format)?.to_rgba8();
+
+        Ok(Self {
+            name,
+            extent: [image.width(), image.height()],
+            pixels: image.into_raw(),
+        })
+    }
+
+    // Stands in for an image that failed to load so texture indices stay
+    // valid.
+    pub fn placeholder(name: String) -> Self {
+        Self {
+            name,
+            extent: [1, 1],
+            pixels: vec![255; 4],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask,
+    Blend,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureRef {
+    pub image: usize,
+    pub tex_coord: u32,
+}
+
+impl TextureRef {
+    fn new(texture: &gltf::Texture, tex_coord: u32) -> Self {
+        Self {
+            image: texture.source().index(),
+            tex_coord,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Material {
-    pub diffuse_color: [f32; 4],
-    pub fresnel_r0: f32,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
     pub roughness: f32,
-    pub diffuse_map: Option<usize>,
-    pub normal_map: Option<usize>,
+    pub emissive: [f32; 3],
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+
+    pub base_color_map: Option<TextureRef>,
+    pub metallic_roughness_map: Option<TextureRef>,
+    pub normal_map: Option<TextureRef>,
+    pub occlusion_map: Option<TextureRef>,
+    pub emissive_map: Option<TextureRef>,
+}
+
+impl Material {
+    fn new(material: &gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+
+        Self {
+            base_color: pbr.base_color_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            emissive: material.emissive_factor(),
+            normal_scale: normal.as_ref().map_or(1.0, |t| t.scale()),
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |t| t.strength()),
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            double_sided: material.double_sided(),
+            base_color_map: pbr
+                .base_color_texture()
+                .map(|t| TextureRef::new(&t.texture(), t.tex_coord())),
+            metallic_roughness_map: pbr
+                .metallic_roughness_texture()
+                .map(|t| TextureRef::new(&t.texture(), t.tex_coord())),
+            normal_map: normal.map(|t| TextureRef::new(&t.texture(), t.tex_coord())),
+            occlusion_map: occlusion.map(|t| TextureRef::new(&t.texture(), t.tex_coord())),
+            emissive_map: material
+                .emissive_texture()
+                .map(|t| TextureRef::new(&t.texture(), t.tex_coord())),
+        }
+    }
+
+    // Maps holding colors, glTF stores them in sRGB while the others are linear.
+    pub fn color_maps(&self) -> [Option<TextureRef>; 2] {
+        [self.base_color_map, self.emissive_map]
+    }
 }
 
 #[derive(Clone, Default, Debug)]
 pub struct GltfScene {
+    // Vertex data is kept in mesh space and shared by every node that
+    // references the mesh.
     pub positions: Vec<[f32; 3]>,
     pub normals: Vec<[f32; 3]>,
-    pub uvs: Vec<[f32; 2]>,
+    // TEXCOORD_0 in xy and TEXCOORD_1 in zw.
+    pub uvs: Vec<[f32; 4]>,
     pub tangents: Vec<[f32; 4]>,
     pub indices: Vec<u32>,
 
-    pub sub_meshes: Vec<Submesh>,
+    pub meshes: Vec<Mesh>,
+    // Parents always come before their children.
+    pub nodes: Vec<Node>,
     pub materials: Vec<Material>,
-    pub images: Vec<ImageSource>,
+    pub images: Vec<ImageData>,
 }
 
 #[derive(Clone, Debug)]
@@ -48,191 +160,284 @@ pub struct PreparedGltfScene {
     pub tangents: Handle<Buffer>,
     pub indices: Handle<Buffer>,
 
-    pub submeshes: Vec<(Handle<Buffer>, Handle<ShaderArgument>)>,
+    // One per `GltfScene::batches` entry.
+    pub transforms: Vec<(Handle<Buffer>, Handle<ShaderArgument>)>,
     pub materials: Vec<(Handle<Buffer>, Handle<ShaderArgument>)>,
     pub images: Vec<Handle<Texture>>,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub submeshes: Vec<Submesh>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub transform: TransformComponent,
+    pub mesh: Option<usize>,
+    // `EXT_mesh_gpu_instancing` transforms relative to the node, when present
+    // the node itself is not drawn.
+    pub instances: Vec<TransformComponent>,
+}
+
+// Instances of one mesh that are drawn together, each is a node index and the
+// `EXT_mesh_gpu_instancing` instance of that node if it has any.
+#[derive(Clone, Debug)]
+pub struct InstanceBatch {
+    pub mesh: usize,
+    pub instances: Vec<(usize, Option<usize>)>,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Submesh {
     pub index_count: u32,
     pub start_index_location: u32,
     pub base_vertex_location: u32,
     pub material_idx: usize,
+    pub bounds: Aabb,
 }
 
-fn iter_gltf_node_tree<F: FnMut(&gltf::scene::Node, Mat4)>(
+fn iter_gltf_node_tree<F: FnMut(&gltf::scene::Node, Option<usize>, Mat4) -> usize>(
     node: &gltf::scene::Node,
+    parent: Option<usize>,
     xform: Mat4,
     f: &mut F,
 ) {
     let node_xform = Mat4::from_cols_array_2d(&node.transform().matrix());
     let xform = xform * node_xform;
 
-    f(node, xform);
+    let idx = f(node, parent, xform);
     for child in node.children() {
-        iter_gltf_node_tree(&child, xform, f);
+        iter_gltf_node_tree(&child, Some(idx), xform, f);
     }
 }
 
 impl GltfScene {
-    pub fn load(path: impl AsRef<Path>) -> Self {
-        let curr_dir = std::env::current_dir().expect("Failed to get current dir");
+    // glTF is right handed, the scene root mirrors it into the left handed
+    // space of the renderer. Winding is flipped at load to match.
+    pub fn root_transform(scale: f32) -> TransformComponent {
+        TransformComponent {
+            scale: glam::vec3(scale, scale, -scale),
+            ..TransformComponent::IDENTITY
+        }
+    }
 
+    pub fn load(path: impl AsRef<Path>) -> Self {
         info!("Loading scene: {:?}", path.as_ref());
-        let (gltf, buffers, _) = gltf::import(&path).expect("Failed to open file");
+        let base = path.as_ref().parent().unwrap_or_else(|| Path::new("./"));
+        let gltf::Gltf {
+            document: gltf,
+            blob,
+        } = gltf::Gltf::open(&path).expect("Failed to open file");
+        let buffers =
+            gltf::import_buffers(&gltf, Some(base), blob).expect("Failed to load buffers");
 
         let scene = gltf
             .default_scene()
             .or_else(|| gltf.scenes().next())
             .expect("Failed to fetch scene");
-        let mut res = GltfScene::default();
-
-        res.images = gltf
+        let images = gltf
             .images()
-            .map(|image| match image.source() {
-                gltf::image::Source::View { view, .. } => {
-                    let buffer_data = &buffers[view.buffer().index()];
-                    let start = view.offset();
-                    let end = start + view.length();
-                    ImageSource::Data(buffer_data[start..end].to_vec())
-                }
-                gltf::image::Source::Uri { uri, .. } => {
-                    info!("Find texture by path: {:?}\\{}", curr_dir, uri);
-                    let path = path
-                        .as_ref()
-                        .parent()
-                        .unwrap_or_else(|| Path::new("./"))
-                        .join(uri);
-                    ImageSource::Path(path)
-                }
+            .map(|image| {
+                let name = image
+                    .name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("Image {}", image.index()));
+
+                read_image(&image, name.clone(), base, &buffers).unwrap_or_else(|err| {
+                    error!("Failed to load image {}: {}", name, err);
+                    ImageData::placeholder(name)
+                })
             })
             .collect();
 
-        res.materials = gltf
-            .materials()
-            .map(|m| Material {
-                diffuse_color: m.pbr_metallic_roughness().base_color_factor(),
-                fresnel_r0: m.pbr_metallic_roughness().metallic_factor(),
-                roughness: m.pbr_metallic_roughness().roughness_factor(),
-                diffuse_map: m
-                    .pbr_metallic_roughness()
-                    .base_color_texture()
-                    .map(|t| t.texture().index()),
-                normal_map: m.normal_texture().map(|m| m.texture().index()),
-            })
-            .collect();
+        let materials = gltf.materials().map(|m| Material::new(&m)).collect();
 
-        let mut process_node = |node: &gltf::scene::Node, xform: Mat4| {
-            if let Some(mesh) = node.mesh() {
-                let flip_winding_order = xform.determinant() < 0.0;
+        let mut res = GltfScene {
+            images,
+            materials,
+            ..Default::default()
+        };
 
-                for prim in mesh.primitives() {
-                    let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
-
-                    let positions = if let Some(iter) = reader.read_positions() {
-                        iter.collect::<Vec<_>>()
-                    } else {
-                        return;
-                    };
-
-                    let normals = if let Some(iter) = reader.read_normals() {
-                        iter.collect::<Vec<_>>()
-                    } else {
-                        return;
-                    };
-
-                    let (mut tangents, tangents_found) = if let Some(iter) = reader.read_tangents()
-                    {
-                        (iter.collect::<Vec<_>>(), true)
-                    } else {
-                        (vec![[1.0, 0.0, 0.0, 0.0]; positions.len()], false)
-                    };
-
-                    let (mut uvs, uvs_found) = if let Some(iter) = reader.read_tex_coords(0) {
-                        (iter.into_f32().collect::<Vec<_>>(), true)
-                    } else {
-                        (vec![[0.0, 0.0]; positions.len()], false)
-                    };
-
-                    let mut indices: Vec<u32>;
-                    {
-                        if let Some(indices_reader) = reader.read_indices() {
-                            indices = indices_reader.into_u32().collect();
-                        } else {
-                            if positions.is_empty() {
-                                return;
-                            }
-
-                            match prim.mode() {
-                                gltf::mesh::Mode::Triangles => {
-                                    indices = (0..positions.len() as u32).collect();
-                                }
-                                _ => {
-                                    panic!("Primitive mode {:?} not supported yet", prim.mode());
-                                }
-                            }
-                        }
+        // Mirrored nodes need the opposite winding, so a mesh is loaded at
+        // most twice.
+        let mut loaded = HashMap::new();
 
-                        if flip_winding_order {
-                            for tri in indices.chunks_exact_mut(3) {
-                                tri.swap(0, 2);
-                            }
-                        }
-                    }
+        let mut process_node = |node: &gltf::scene::Node, parent: Option<usize>, xform: Mat4| {
+            let mesh = node.mesh().map(|mesh| {
+                let flip_winding_order = xform.determinant() < 0.0;
 
-                    if !tangents_found && uvs_found {
-                        bevy_mikktspace::generate_tangents(&mut TangentCalcContext {
-                            indices: indices.as_slice(),
-                            positions: positions.as_slice(),
-                            normals: normals.as_slice(),
-                            uvs: uvs.as_slice(),
-                            tangents: tangents.as_mut_slice(),
-                        });
-                    }
+                *loaded
+                    .entry((mesh.index(), flip_winding_order))
+                    .or_insert_with(|| res.load_mesh(&mesh, &buffers, flip_winding_order))
+            });
+
+            let (pos, rotation, scale) = node.transform().decomposed();
+
+            res.nodes.push(Node {
+                name: node.name().map(str::to_string),
+                parent,
+                transform: TransformComponent {
+                    pos: Vec3::from(pos),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from(scale),
+                },
+                mesh,
+                instances: read_gpu_instances(&gltf, node, &buffers),
+            });
+
+            res.nodes.len() - 1
+        };
 
-                    let submesh = Submesh {
-                        index_count: indices.len() as u32,
-                        start_index_location: res.indices.len() as u32,
-                        base_vertex_location: res.positions.len() as u32,
-                        material_idx: prim.material().index().unwrap_or(0),
-                    };
+        let xform = Self::root_transform(1.0).matrix();
+        for node in scene.nodes() {
+            iter_gltf_node_tree(&node, None, xform, &mut process_node);
+        }
 
-                    res.indices.append(&mut indices);
+        res
+    }
 
-                    for v in positions {
-                        let pos = (xform * Vec3::from(v).extend(1.0)).truncate();
-                        res.positions.push(pos.into());
+    fn load_mesh(
+        &mut self,
+        mesh: &gltf::Mesh,
+        buffers: &[gltf::buffer::Data],
+        flip_winding_order: bool,
+    ) -> usize {
+        let mut submeshes = vec![];
+
+        for prim in mesh.primitives() {
+            let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let positions = if let Some(iter) = reader.read_positions() {
+                iter.collect::<Vec<_>>()
+            } else {
+                continue;
+            };
+
+            let normals = if let Some(iter) = reader.read_normals() {
+                iter.collect::<Vec<_>>()
+            } else {
+                continue;
+            };
+
+            let (mut tangents, tangents_found) = if let Some(iter) = reader.read_tangents() {
+                (iter.collect::<Vec<_>>(), true)
+            } else {
+                (vec![[1.0, 0.0, 0.0, 0.0]; positions.len()], false)
+            };
+
+            let (uvs, uvs_found) = if let Some(iter) = reader.read_tex_coords(0) {
+                (iter.into_f32().collect::<Vec<_>>(), true)
+            } else {
+                (vec![[0.0, 0.0]; positions.len()], false)
+            };
+
+            let uvs1 = if let Some(iter) = reader.read_tex_coords(1) {
+                iter.into_f32().collect::<Vec<_>>()
+            } else {
+                uvs.clone()
+            };
+
+            let mut indices: Vec<u32>;
+            {
+                if let Some(indices_reader) = reader.read_indices() {
+                    indices = indices_reader.into_u32().collect();
+                } else {
+                    if positions.is_empty() {
+                        continue;
                     }
 
-                    for v in normals {
-                        let norm = (xform * Vec3::from(v).extend(0.0)).truncate().normalize();
-                        res.normals.push(norm.into());
+                    match prim.mode() {
+                        gltf::mesh::Mode::Triangles => {
+                            indices = (0..positions.len() as u32).collect();
+                        }
+                        _ => {
+                            panic!("Primitive mode {:?} not supported yet", prim.mode());
+                        }
                     }
+                }
 
-                    for v in tangents {
-                        let v = Vec4::from(v);
-                        let t = (xform * v.truncate().extend(0.0)).truncate().normalize();
-                        res.tangents.push(
-                            t.extend(v.w * if flip_winding_order { -1.0 } else { 1.0 })
-                                .into(),
-                        );
+                if flip_winding_order {
+                    for tri in indices.chunks_exact_mut(3) {
+                        tri.swap(0, 2);
                     }
+                }
+            }
 
-                    res.uvs.append(&mut uvs);
+            if !tangents_found && uvs_found {
+                bevy_mikktspace::generate_tangents(&mut TangentCalcContext {
+                    indices: indices.as_slice(),
+                    positions: positions.as_slice(),
+                    normals: normals.as_slice(),
+                    uvs: uvs.as_slice(),
+                    tangents: tangents.as_mut_slice(),
+                });
+            }
 
-                    res.sub_meshes.push(submesh);
-                }
+            submeshes.push(Submesh {
+                index_count: indices.len() as u32,
+                start_index_location: self.indices.len() as u32,
+                base_vertex_location: self.positions.len() as u32,
+                material_idx: prim.material().index().unwrap_or(0),
+                bounds: Aabb::from_points(positions.iter().map(|v| Vec3::from(*v))),
+            });
+
+            self.indices.append(&mut indices);
+            self.positions.extend(positions);
+            self.normals.extend(normals);
+
+            for v in tangents {
+                let v = Vec4::from(v);
+                self.tangents.push(
+                    v.truncate()
+                        .extend(v.w * if flip_winding_order { -1.0 } else { 1.0 })
+                        .into(),
+                );
             }
-        };
 
-        let mut xform = Mat4::IDENTITY;
-        xform.z_axis *= -1.0;
-        for node in scene.nodes() {
-            iter_gltf_node_tree(&node, xform, &mut process_node);
+            self.uvs.extend(
+                uvs.iter()
+                    .zip(&uvs1)
+                    .map(|(uv0, uv1)| [uv0[0], uv0[1], uv1[0], uv1[1]]),
+            );
         }
 
-        res
+        self.meshes.push(Mesh { submeshes });
+        self.meshes.len() - 1
+    }
+
+    // Batches never exceed `MAX_INSTANCES`, which is what a single transform
+    // constant buffer can hold.
+    pub fn batches(&self) -> Vec<InstanceBatch> {
+        let mut by_mesh = BTreeMap::<usize, Vec<_>>::new();
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let Some(mesh) = node.mesh else {
+                continue;
+            };
+
+            let instances = by_mesh.entry(mesh).or_default();
+            if node.instances.is_empty() {
+                instances.push((idx, None));
+            } else {
+                instances.extend((0..node.instances.len()).map(|instance| (idx, Some(instance))));
+            }
+        }
+
+        by_mesh
+            .into_iter()
+            .flat_map(|(mesh, instances)| {
+                instances
+                    .chunks(MAX_INSTANCES)
+                    .map(|chunk| InstanceBatch {
+                        mesh,
+                        instances: chunk.to_vec(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
     }
 
     pub fn prepare(&self, rs: &RenderSystem) -> PreparedGltfScene {
@@ -242,8 +447,8 @@ impl GltfScene {
             uvs: rs.create_buffer_handle(),
             tangents: rs.create_buffer_handle(),
             indices: rs.create_buffer_handle(),
-            submeshes: self
-                .sub_meshes
+            transforms: self
+                .batches()
                 .iter()
                 .map(|_| {
                     (
@@ -271,6 +476,134 @@ impl GltfScene {
     }
 }
 
+// Instances are drawn with the winding of their node, mirroring scales are not
+// accounted for.
+fn read_gpu_instances(
+    gltf: &gltf::Document,
+    node: &gltf::scene::Node,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<TransformComponent> {
+    let Some(attributes) = node
+        .extension_value("EXT_mesh_gpu_instancing")
+        .and_then(|ext| ext.get("attributes"))
+    else {
+        return vec![];
+    };
+
+    let accessor = |name: &str| {
+        attributes
+            .get(name)
+            .and_then(|index| index.as_u64())
+            .and_then(|index| gltf.accessors().nth(index as usize))
+    };
+    let get_buffer_data = |buffer: gltf::Buffer| Some(&buffers[buffer.index()][..]);
+
+    let translations = accessor("TRANSLATION").map(|accessor| {
+        gltf::accessor::Iter::<[f32; 3]>::new(accessor, get_buffer_data)
+            .expect("Failed to read instance translations")
+            .collect::<Vec<_>>()
+    });
+
+    let rotations = accessor("ROTATION").map(|accessor| match accessor.data_type() {
+        gltf::accessor::DataType::I8 => {
+            gltf::accessor::Iter::<[i8; 4]>::new(accessor, get_buffer_data)
+                .expect("Failed to read instance rotations")
+                .map(|r| r.map(|c| (c as f32 / 127.0).max(-1.0)))
+                .collect::<Vec<_>>()
+        }
+        gltf::accessor::DataType::I16 => {
+            gltf::accessor::Iter::<[i16; 4]>::new(accessor, get_buffer_data)
+                .expect("Failed to read instance rotations")
+                .map(|r| r.map(|c| (c as f32 / 32767.0).max(-1.0)))
+                .collect::<Vec<_>>()
+        }
+        _ => gltf::accessor::Iter::<[f32; 4]>::new(accessor, get_buffer_data)
+            .expect("Failed to read instance rotations")
+            .collect::<Vec<_>>(),
+    });
+
+    let scales = accessor("SCALE").map(|accessor| {
+        gltf::accessor::Iter::<[f32; 3]>::new(accessor, get_buffer_data)
+            .expect("Failed to read instance scales")
+            .collect::<Vec<_>>()
+    });
+
+    // The extension requires every attribute to have the same count, surplus
+    // entries of longer attributes are dropped.
+    let counts = [
+        translations.as_ref().map(Vec::len),
+        rotations.as_ref().map(Vec::len),
+        scales.as_ref().map(Vec::len),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    let count = counts.iter().copied().min().unwrap_or(0);
+
+    if counts.iter().any(|&c| c != count) {
+        error!(
+            "Instance attributes of node {} have mismatched counts {:?}, using {}",
+            node.index(),
+            counts,
+            count
+        );
+    }
+
+    (0..count)
+        .map(|i| TransformComponent {
+            pos: translations
+                .as_ref()
+                .map_or(Vec3::ZERO, |t| Vec3::from(t[i])),
+            rotation: rotations
+                .as_ref()
+                .map_or(Quat::IDENTITY, |r| Quat::from_array(r[i]).normalize()),
+            scale: scales.as_ref().map_or(Vec3::ONE, |s| Vec3::from(s[i])),
+        })
+        .collect()
+}
+
+fn read_image(
+    image: &gltf::Image,
+    name: String,
+    base: &Path,
+    buffers: &[gltf::buffer::Data],
+) -> Result<ImageData, Box<dyn std::error::Error>> {
+    let image = match image.source() {
+        gltf::image::Source::View { view, mime_type } => {
+            let buffer_data = &buffers[view.buffer().index()];
+            let start = view.offset();
+            let end = start + view.length();
+            ImageData::decode(name, &buffer_data[start..end], Some(mime_type))?
+        }
+        gltf::image::Source::Uri { uri, mime_type } => match parse_data_uri(uri) {
+            Some((data_mime_type, data)) => {
+                let bytes = base64::decode(data)?;
+                ImageData::decode(name, &bytes, data_mime_type.or(mime_type))?
+            }
+            None => {
+                let path = base.join(uri);
+                info!("Find texture by path: {:?}", path);
+                let name = path
+                    .file_stem()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or(name);
+                let bytes = std::fs::read(&path)?;
+                ImageData::decode(name, &bytes, mime_type)?
+            }
+        },
+    };
+
+    Ok(image)
+}
+
+// `data:[<mime type>][;base64],<data>`, only base64 payloads are valid in glTF.
+fn parse_data_uri(uri: &str) -> Option<(Option<&str>, &str)> {
+    let rest = uri.strip_prefix("data:")?;
+    let (mime_type, data) = rest.split_once(";base64,")?;
+
+    Some(((!mime_type.is_empty()).then_some(mime_type), data))
+}
+
 struct TangentCalcContext<'a> {
     indices: &'a [u32],
     positions: &'a [[f32; 3]],
@@ -304,3 +637,315 @@ impl<'a> bevy_mikktspace::Geometry for TangentCalcContext<'a> {
         self.tangents[self.indices[face * 3 + vert] as usize] = tangent;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::PathBuf};
+
+    use super::*;
+
+    const PIXELS: [[u8; 4]; 2] = [[255, 0, 0, 255], [0, 0, 255, 128]];
+
+    fn png() -> Vec<u8> {
+        let image = image::RgbaImage::from_raw(2, 1, PIXELS.concat()).expect("invalid image");
+        let mut bytes = Cursor::new(vec![]);
+        image
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .expect("failed to encode png");
+
+        bytes.into_inner()
+    }
+
+    fn write(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("fotia-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).expect("failed to write asset");
+
+        path
+    }
+
+    fn document(images: &str, buffers: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [] }}],
+                "images": [{images}],
+                {buffers}
+            }}"#
+        )
+    }
+
+    // The binary chunk holds the png, referenced through a buffer view.
+    fn glb(bin: &[u8]) -> Vec<u8> {
+        let mut json = document(
+            r#"{ "name": "Embedded", "bufferView": 0, "mimeType": "image/png" }"#,
+            &format!(
+                r#""buffers": [{{ "byteLength": {0} }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": {0} }}]"#,
+                bin.len()
+            ),
+        )
+        .into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut glb = vec![];
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((length as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+
+        glb
+    }
+
+    fn assert_decoded(image: &ImageData) {
+        assert_eq!(image.extent, [2, 1]);
+        assert_eq!(image.pixels, PIXELS.concat());
+    }
+
+    #[test]
+    fn glb_image_in_buffer_view() {
+        let path = write("buffer-view.glb", &glb(&png()));
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove asset");
+
+        assert_eq!(scene.images.len(), 1);
+        assert_eq!(scene.images[0].name, "Embedded");
+        assert_decoded(&scene.images[0]);
+    }
+
+    #[test]
+    fn gltf_image_in_embedded_buffer() {
+        let png = png();
+        let json = document(
+            r#"{ "bufferView": 0, "mimeType": "image/png" }"#,
+            &format!(
+                r#""buffers": [{{
+                    "byteLength": {0},
+                    "uri": "data:application/octet-stream;base64,{1}"
+                }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": {0} }}]"#,
+                png.len(),
+                base64::encode(&png)
+            ),
+        );
+
+        let path = write("buffer-view.gltf", json.as_bytes());
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove asset");
+
+        assert_eq!(scene.images[0].name, "Image 0");
+        assert_decoded(&scene.images[0]);
+    }
+
+    #[test]
+    fn gltf_data_uri_image() {
+        let json = document(
+            &format!(
+                r#"{{ "uri": "data:image/png;base64,{}" }}"#,
+                base64::encode(png())
+            ),
+            r#""buffers": []"#,
+        );
+
+        let path = write("data-uri.gltf", json.as_bytes());
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove asset");
+
+        assert_decoded(&scene.images[0]);
+    }
+
+    #[test]
+    fn broken_images_use_placeholder() {
+        let json = document(
+            r#"{ "name": "Bad Base64", "uri": "data:image/png;base64,!!!" },
+            { "name": "Missing", "uri": "missing.png" }"#,
+            r#""buffers": []"#,
+        );
+
+        let path = write("broken.gltf", json.as_bytes());
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove asset");
+
+        let corrupt = write("corrupt.glb", &glb(b"not a png"));
+        let corrupt_scene = GltfScene::load(&corrupt);
+        std::fs::remove_file(corrupt).expect("failed to remove asset");
+
+        for image in scene.images.iter().chain(&corrupt_scene.images) {
+            assert_eq!(image.extent, [1, 1]);
+            assert_eq!(image.pixels, [255; 4]);
+        }
+        assert_eq!(scene.images[0].name, "Bad Base64");
+        assert_eq!(corrupt_scene.images[0].name, "Embedded");
+    }
+
+    fn map(map: Option<TextureRef>) -> Option<(usize, u32)> {
+        map.map(|map| (map.image, map.tex_coord))
+    }
+
+    #[test]
+    fn materials_import_factors_maps_and_alpha() {
+        let uri = format!(
+            r#"{{ "uri": "data:image/png;base64,{}" }}"#,
+            base64::encode(png())
+        );
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [] }}],
+                "images": [{uri}, {uri}],
+                "textures": [{{ "source": 0 }}, {{ "source": 1 }}],
+                "materials": [
+                    {{
+                        "pbrMetallicRoughness": {{
+                            "baseColorFactor": [0.5, 0.25, 0.125, 0.75],
+                            "baseColorTexture": {{ "index": 0, "texCoord": 1 }},
+                            "metallicFactor": 0.3,
+                            "roughnessFactor": 0.6,
+                            "metallicRoughnessTexture": {{ "index": 1 }}
+                        }},
+                        "normalTexture": {{ "index": 1, "texCoord": 1, "scale": 0.5 }},
+                        "occlusionTexture": {{ "index": 1, "strength": 0.25 }},
+                        "emissiveTexture": {{ "index": 0, "texCoord": 1 }},
+                        "emissiveFactor": [1.0, 0.5, 0.0],
+                        "alphaMode": "MASK",
+                        "alphaCutoff": 0.3,
+                        "doubleSided": true
+                    }},
+                    {{ "alphaMode": "BLEND" }},
+                    {{}}
+                ],
+                "buffers": []
+            }}"#
+        );
+
+        let path = write("materials.gltf", json.as_bytes());
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove asset");
+
+        let [textured, blend, default] = &scene.materials[..] else {
+            panic!("expected three materials, got {}", scene.materials.len());
+        };
+
+        assert_eq!(textured.base_color, [0.5, 0.25, 0.125, 0.75]);
+        assert_eq!(textured.metallic, 0.3);
+        assert_eq!(textured.roughness, 0.6);
+        assert_eq!(textured.emissive, [1.0, 0.5, 0.0]);
+        assert_eq!(textured.normal_scale, 0.5);
+        assert_eq!(textured.occlusion_strength, 0.25);
+        assert_eq!(textured.alpha_mode, AlphaMode::Mask);
+        assert_eq!(textured.alpha_cutoff, 0.3);
+        assert!(textured.double_sided);
+
+        assert_eq!(map(textured.base_color_map), Some((0, 1)));
+        assert_eq!(map(textured.metallic_roughness_map), Some((1, 0)));
+        assert_eq!(map(textured.normal_map), Some((1, 1)));
+        assert_eq!(map(textured.occlusion_map), Some((1, 0)));
+        assert_eq!(map(textured.emissive_map), Some((0, 1)));
+        assert_eq!(textured.color_maps().map(map), [Some((0, 1)); 2]);
+
+        assert_eq!(blend.alpha_mode, AlphaMode::Blend);
+
+        assert_eq!(default.base_color, [1.0; 4]);
+        assert_eq!([default.metallic, default.roughness], [1.0, 1.0]);
+        assert_eq!(default.emissive, [0.0; 3]);
+        assert_eq!(default.normal_scale, 1.0);
+        assert_eq!(default.occlusion_strength, 1.0);
+        assert_eq!(default.alpha_mode, AlphaMode::Opaque);
+        assert_eq!(default.alpha_cutoff, 0.5);
+        assert!(!default.double_sided);
+        assert!(default.color_maps().iter().all(Option::is_none));
+        assert!(default.normal_map.is_none() && default.occlusion_map.is_none());
+        assert!(default.metallic_roughness_map.is_none());
+    }
+
+    // A node with three instance translations, `scale_count` scales follow
+    // them in the buffer.
+    fn instanced_node(scale_count: usize) -> GltfScene {
+        let translations = [[1.0f32, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]];
+        let scales = [[2.0f32; 3], [3.0; 3], [4.0; 3]];
+        let data = [&translations[..], &scales[..scale_count]].concat();
+        let bytes = bytemuck::cast_slice::<_, u8>(&data);
+
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "extensionsUsed": ["EXT_mesh_gpu_instancing"],
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{{
+                    "extensions": {{
+                        "EXT_mesh_gpu_instancing": {{
+                            "attributes": {{ "TRANSLATION": 0, "SCALE": 1 }}
+                        }}
+                    }}
+                }}],
+                "buffers": [{{
+                    "byteLength": {0},
+                    "uri": "data:application/octet-stream;base64,{1}"
+                }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": {2} }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": {3}, "type": "VEC3" }}
+                ]
+            }}"#,
+            bytes.len(),
+            base64::encode(bytes),
+            scale_count * 12,
+            scale_count
+        );
+
+        let path = write(&format!("instances-{}.gltf", scale_count), json.as_bytes());
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove asset");
+
+        scene
+    }
+
+    #[test]
+    fn gpu_instances_combine_attributes() {
+        let scene = instanced_node(3);
+        let instances = &scene.nodes[0].instances;
+
+        assert_eq!(instances.len(), 3);
+        for (i, instance) in instances.iter().enumerate() {
+            assert_eq!(instance.pos, Vec3::new(i as f32 + 1.0, 0.0, 0.0));
+            assert_eq!(instance.rotation, Quat::IDENTITY);
+            assert_eq!(instance.scale, Vec3::splat(i as f32 + 2.0));
+        }
+    }
+
+    #[test]
+    fn mismatched_instance_counts_are_clamped() {
+        let scene = instanced_node(2);
+        let instances = &scene.nodes[0].instances;
+
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[1].pos, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(instances[1].scale, Vec3::splat(3.0));
+    }
+
+    #[test]
+    fn data_uri_is_split_at_payload() {
+        assert_eq!(
+            parse_data_uri("data:image/png;base64,AAAA"),
+            Some((Some("image/png"), "AAAA"))
+        );
+        assert_eq!(parse_data_uri("data:;base64,AAAA"), Some((None, "AAAA")));
+        assert_eq!(parse_data_uri("data:text/plain,AAAA"), None);
+        assert_eq!(parse_data_uri("textures/albedo.png"), None);
+    }
+}
@@ -1,7 +1,10 @@
 pub mod camera;
 pub mod gltf;
 
+use std::collections::HashMap;
+
 use glam;
+use hecs::{Entity, World};
 
 use crate::{
     collections::handle::Handle,
@@ -12,18 +15,207 @@ use crate::{
 pub struct TransformComponent {
     pub pos: glam::Vec3,
     pub rotation: glam::Quat,
-    pub scale: f32,
+    pub scale: glam::Vec3,
+}
+
+impl TransformComponent {
+    pub const IDENTITY: Self = Self {
+        pos: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+        scale: glam::Vec3::ONE,
+    };
+
+    pub fn matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.pos)
+    }
+}
+
+// Upper bound on the instances of one draw, must match `MAX_INSTANCES` in
+// Common.hlsl.
+pub const MAX_INSTANCES: usize = 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ParentComponent {
+    pub entity: Entity,
+}
+
+// World matrix, written by `propagate_transforms`.
+#[derive(Clone, Debug, Default)]
+pub struct GlobalTransformComponent {
+    pub mat: glam::Mat4,
+}
+
+// Entities without a `TransformComponent` sit at their parent's origin, which
+// is how the submeshes of a node are attached to it.
+pub fn propagate_transforms(world: &mut World) {
+    let mut resolved = HashMap::new();
+
+    let entities = world
+        .query::<()>()
+        .with::<&GlobalTransformComponent>()
+        .iter()
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+
+    for entity in entities {
+        global_transform(world, entity, &mut resolved);
+    }
+
+    for (entity, (global, bounds)) in
+        world.query_mut::<(&mut GlobalTransformComponent, Option<&mut BoundsComponent>)>()
+    {
+        global.mat = resolved[&entity];
+
+        if let Some(bounds) = bounds {
+            bounds.aabb = bounds.local.transform(&global.mat);
+        }
+    }
+
+    for (_, (instances, bounds)) in world.query_mut::<(&InstancesComponent, &mut BoundsComponent)>()
+    {
+        bounds.aabb = instances.entities.iter().fold(Aabb::EMPTY, |aabb, entity| {
+            aabb.union(&bounds.local.transform(&resolved[entity]))
+        });
+    }
+}
+
+fn global_transform(
+    world: &World,
+    entity: Entity,
+    resolved: &mut HashMap<Entity, glam::Mat4>,
+) -> glam::Mat4 {
+    if let Some(mat) = resolved.get(&entity) {
+        return *mat;
+    }
+
+    let local = world
+        .get::<&TransformComponent>(entity)
+        .map_or(glam::Mat4::IDENTITY, |transform| transform.matrix());
+    let parent = world
+        .get::<&ParentComponent>(entity)
+        .map(|parent| parent.entity)
+        .ok();
+
+    let mat = match parent {
+        Some(parent) => global_transform(world, parent, resolved) * local,
+        None => local,
+    };
+
+    resolved.insert(entity, mat);
+    mat
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: glam::Vec3::splat(f32::MAX),
+        max: glam::Vec3::splat(f32::MIN),
+    };
+
+    pub fn from_points(points: impl IntoIterator<Item = glam::Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, point| Self {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn corners(&self) -> [glam::Vec3; 8] {
+        [
+            glam::vec3(self.min.x, self.min.y, self.min.z),
+            glam::vec3(self.min.x, self.min.y, self.max.z),
+            glam::vec3(self.min.x, self.max.y, self.min.z),
+            glam::vec3(self.min.x, self.max.y, self.max.z),
+            glam::vec3(self.max.x, self.min.y, self.min.z),
+            glam::vec3(self.max.x, self.min.y, self.max.z),
+            glam::vec3(self.max.x, self.max.y, self.min.z),
+            glam::vec3(self.max.x, self.max.y, self.max.z),
+        ]
+    }
+
+    pub fn transform(&self, mat: &glam::Mat4) -> Self {
+        Self::from_points(self.corners().map(|corner| mat.transform_point3(corner)))
+    }
+
+    // Conservative overlap test against the clip volume of an orthographic
+    // projection, used to cull shadow casters per cascade.
+    pub fn intersects_ortho(&self, proj_view: &glam::Mat4) -> bool {
+        let clip = self.transform(proj_view);
+
+        clip.max.x >= -1.0
+            && clip.min.x <= 1.0
+            && clip.max.y >= -1.0
+            && clip.min.y <= 1.0
+            && clip.max.z >= 0.0
+            && clip.min.z <= 1.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BoundsComponent {
+    pub local: Aabb,
+    pub aabb: Aabb,
+}
+
+pub fn scene_bounds(world: &hecs::World) -> Option<Aabb> {
+    let aabb = world
+        .query::<&BoundsComponent>()
+        .iter()
+        .fold(Aabb::EMPTY, |aabb, (_, bounds)| aabb.union(&bounds.aabb));
+
+    (!aabb.is_empty()).then_some(aabb)
+}
+
+// Entities whose global transforms are drawn as the instances of this entity.
+#[derive(Clone, Debug)]
+pub struct InstancesComponent {
+    pub entities: Vec<Entity>,
 }
 
 #[derive(Clone, Debug)]
 pub struct GpuTransformComponent {
     pub buffer: Handle<Buffer>,
     pub argument: Handle<ShaderArgument>,
+    pub instance_count: u32,
+}
+
+impl GpuTransformComponent {
+    // Transforms per frame in flight, padded so every frame starts on a
+    // constant buffer boundary.
+    pub fn frame_len(instance_count: usize) -> usize {
+        instance_count.next_multiple_of(256 / size_of::<GpuTransform>())
+    }
+
+    pub fn offset(&self, frame_idx: usize) -> usize {
+        size_of::<GpuTransform>() * Self::frame_len(self.instance_count as usize) * frame_idx
+    }
+
+    // The shaders declare `transforms[MAX_INSTANCES]`, so the buffer is padded
+    // until the constant buffer bound for the last frame fits in it.
+    pub fn buffer_size(instance_count: usize, frames_in_flight: usize) -> usize {
+        let frames = Self::frame_len(instance_count) * frames_in_flight.saturating_sub(1);
+
+        size_of::<GpuTransform>() * (frames + MAX_INSTANCES.max(instance_count))
+    }
 }
 
 #[derive(Clone, Debug)]
 #[repr(C)]
-#[repr(align(256))]
 pub struct GpuTransform {
     pub mat: glam::Mat4,
 }
@@ -46,9 +238,20 @@ pub struct GpuMeshComponent {
 #[repr(C)]
 #[repr(align(256))]
 pub struct GpuMaterial {
-    pub diffuse_color: [f32; 4],
-    pub fresnel_r0: f32,
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub metallic: f32,
     pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub alpha_cutoff: f32,
+    pub alpha_mode: u32,
+    pub double_sided: u32,
+    pub base_color_uv: u32,
+    pub metallic_roughness_uv: u32,
+    pub normal_uv: u32,
+    pub occlusion_uv: u32,
+    pub emissive_uv: u32,
 }
 
 #[derive(Clone, Debug)]
@@ -56,3 +259,201 @@ pub struct GpuMaterialComponent {
     pub buffer: Handle<Buffer>,
     pub argument: Handle<ShaderArgument>,
 }
+
+// Submeshes without a marker are opaque.
+#[derive(Clone, Debug)]
+pub struct AlphaTestComponent;
+
+#[derive(Clone, Debug)]
+pub struct AlphaBlendComponent;
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+
+    use super::*;
+
+    fn transform(pos: Vec3, scale: f32) -> TransformComponent {
+        TransformComponent {
+            pos,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::splat(scale),
+        }
+    }
+
+    fn global(world: &World, entity: Entity) -> Mat4 {
+        world
+            .get::<&GlobalTransformComponent>(entity)
+            .expect("entity has no global transform")
+            .mat
+    }
+
+    const UNIT: Aabb = Aabb {
+        min: Vec3::ZERO,
+        max: Vec3::ONE,
+    };
+
+    #[test]
+    fn ortho_culling_keeps_boxes_touching_the_volume() {
+        let proj_view = Mat4::orthographic_lh(-2.0, 2.0, -2.0, 2.0, 0.0, 10.0);
+        let at = |min: Vec3| Aabb {
+            min,
+            max: min + Vec3::ONE,
+        };
+
+        assert!(at(Vec3::new(0.0, 0.0, 4.0)).intersects_ortho(&proj_view));
+        assert!(at(Vec3::new(1.5, 0.0, 4.0)).intersects_ortho(&proj_view));
+        assert!(at(Vec3::new(0.0, -2.5, 9.5)).intersects_ortho(&proj_view));
+
+        assert!(!at(Vec3::new(3.0, 0.0, 4.0)).intersects_ortho(&proj_view));
+        assert!(!at(Vec3::new(0.0, -3.5, 4.0)).intersects_ortho(&proj_view));
+        assert!(!at(Vec3::new(0.0, 0.0, -2.0)).intersects_ortho(&proj_view));
+        assert!(!at(Vec3::new(0.0, 0.0, 11.0)).intersects_ortho(&proj_view));
+    }
+
+    #[test]
+    fn chain_composes_parent_transforms() {
+        let mut world = World::new();
+        let root = world.spawn((transform(Vec3::X, 1.0), GlobalTransformComponent::default()));
+        let node = world.spawn((
+            transform(Vec3::ZERO, 2.0),
+            ParentComponent { entity: root },
+            GlobalTransformComponent::default(),
+        ));
+        let leaf = world.spawn((
+            transform(Vec3::Y, 1.0),
+            ParentComponent { entity: node },
+            GlobalTransformComponent::default(),
+        ));
+        let submesh = world.spawn((
+            ParentComponent { entity: leaf },
+            GlobalTransformComponent::default(),
+            BoundsComponent {
+                local: UNIT,
+                aabb: Aabb::EMPTY,
+            },
+        ));
+
+        propagate_transforms(&mut world);
+
+        assert_eq!(global(&world, root), Mat4::from_translation(Vec3::X));
+        assert_eq!(
+            global(&world, leaf),
+            Mat4::from_scale_rotation_translation(
+                Vec3::splat(2.0),
+                Quat::IDENTITY,
+                Vec3::new(1.0, 2.0, 0.0)
+            )
+        );
+        assert_eq!(global(&world, submesh), global(&world, leaf));
+
+        let bounds = world.get::<&BoundsComponent>(submesh).unwrap().aabb;
+        assert_eq!(
+            bounds,
+            Aabb {
+                min: Vec3::new(1.0, 2.0, 0.0),
+                max: Vec3::new(3.0, 4.0, 2.0),
+            }
+        );
+    }
+
+    #[test]
+    fn moving_parent_moves_children() {
+        let mut world = World::new();
+        let parent = world.spawn((
+            transform(Vec3::ZERO, 1.0),
+            GlobalTransformComponent::default(),
+        ));
+        let child = world.spawn((
+            transform(Vec3::Z, 1.0),
+            ParentComponent { entity: parent },
+            GlobalTransformComponent::default(),
+            BoundsComponent {
+                local: UNIT,
+                aabb: Aabb::EMPTY,
+            },
+        ));
+
+        propagate_transforms(&mut world);
+        assert_eq!(global(&world, child), Mat4::from_translation(Vec3::Z));
+
+        world.get::<&mut TransformComponent>(parent).unwrap().pos = Vec3::new(0.0, 5.0, 0.0);
+        propagate_transforms(&mut world);
+
+        assert_eq!(
+            global(&world, child),
+            Mat4::from_translation(Vec3::new(0.0, 5.0, 1.0))
+        );
+        assert_eq!(
+            world.get::<&BoundsComponent>(child).unwrap().aabb,
+            Aabb {
+                min: Vec3::new(0.0, 5.0, 1.0),
+                max: Vec3::new(1.0, 6.0, 2.0),
+            }
+        );
+    }
+
+    #[test]
+    fn instance_bounds_cover_every_instance() {
+        let mut world = World::new();
+        let instances = [Vec3::ZERO, Vec3::new(4.0, 0.0, 0.0)]
+            .map(|pos| world.spawn((transform(pos, 1.0), GlobalTransformComponent::default())));
+        let batch = world.spawn((
+            InstancesComponent {
+                entities: instances.to_vec(),
+            },
+            BoundsComponent {
+                local: UNIT,
+                aabb: Aabb::EMPTY,
+            },
+        ));
+
+        propagate_transforms(&mut world);
+
+        assert_eq!(
+            world.get::<&BoundsComponent>(batch).unwrap().aabb,
+            Aabb {
+                min: Vec3::ZERO,
+                max: Vec3::new(5.0, 1.0, 1.0),
+            }
+        );
+    }
+
+    #[test]
+    fn transform_frames_start_on_constant_buffer_boundaries() {
+        assert_eq!(GpuTransformComponent::frame_len(0), 0);
+        assert_eq!(GpuTransformComponent::frame_len(1), 4);
+        assert_eq!(GpuTransformComponent::frame_len(4), 4);
+        assert_eq!(GpuTransformComponent::frame_len(5), 8);
+
+        let transform = |instance_count| GpuTransformComponent {
+            buffer: Handle::new(0, 1),
+            argument: Handle::new(0, 1),
+            instance_count,
+        };
+        assert_eq!(transform(1).offset(0), 0);
+        assert_eq!(transform(1).offset(2), 512);
+        assert_eq!(transform(5).offset(1), 512);
+        for count in [1, 5, 300] {
+            assert_eq!(transform(count).offset(1) % 256, 0);
+        }
+    }
+
+    #[test]
+    fn transform_buffer_fits_last_frame_constant_buffer() {
+        let window = size_of::<GpuTransform>() * MAX_INSTANCES;
+
+        for (count, frames) in [(1, 1), (1, 3), (5, 2), (MAX_INSTANCES, 3)] {
+            let transform = GpuTransformComponent {
+                buffer: Handle::new(0, 1),
+                argument: Handle::new(0, 1),
+                instance_count: count as u32,
+            };
+
+            assert_eq!(
+                GpuTransformComponent::buffer_size(count, frames),
+                transform.offset(frames - 1) + window
+            );
+        }
+    }
+}
@@ -1,18 +1,20 @@
 pub mod collections;
 pub mod engine;
 pub mod ra;
+pub mod render_graph;
 pub mod rhi;
 pub mod timer;
 
 mod multi_gpu_renderer;
 mod settings;
 
-use std::{cell::RefCell, collections::HashMap, io::Write, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, io::Write, path::PathBuf, sync::Arc};
 
 use collections::handle::Handle;
 use engine::{
     camera::{Camera, FpsController},
     gltf::GltfScene,
+    propagate_transforms, scene_bounds,
 };
 use glam::{vec2, vec3};
 use hecs::World;
@@ -21,19 +23,22 @@ use multi_gpu_renderer::{
     graphs::{multi_gpu::MultiGpuShadows, single_gpu::SingleGpuShadows},
     pso::PsoCollection,
     shaders::ShaderCollection,
+    update_gpu_transforms,
 };
 use ra::{
+    backend::Backend,
     command::{Barrier, RenderCommandContext, RenderCommandEncoder},
-    context::{ContextDual, RenderDevice},
+    context::{ContextGroup, RenderDevice},
     resources::{Buffer, RenderResourceContext},
     shader::{RenderShaderContext, ShaderArgument},
     swapchain::{RenderSwapchainContext, Surface, Swapchain},
     system::{RenderBackend, RenderBackendSettings, RenderSystem},
+    timeline::Timeline,
 };
 use rhi::{
-    backend::{Api, DebugFlags, RenderDeviceInfo},
+    backend::{Api, DebugFlags, DeviceSelector, RenderDeviceInfo},
     command::{CommandType, Subresource},
-    dx12::device::DxDevice,
+    recording::trace::Recorder,
     resources::BufferUsages,
     swapchain::{PresentMode, SwapchainDesc},
     types::{ResourceState, Timings},
@@ -41,7 +46,7 @@ use rhi::{
 use serde::{Deserialize, Serialize};
 use settings::{RenderSettings, read_settings};
 use timer::GameTimer;
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::layer::SubscriberExt;
 use winit::{
     keyboard::{KeyCode, PhysicalKey},
@@ -60,6 +65,10 @@ pub enum TimingsInfo {
     SecondaryMultiGpu(Timings),
     SingleCpuTotal(std::time::Duration),
     MultiCpuTotal(std::time::Duration),
+    CameraPredictionError {
+        position: f32,
+        angle: f32,
+    },
     End,
 }
 
@@ -95,7 +104,7 @@ pub struct Application<D: RenderDevice> {
 
     pub wnd_ctx: Option<WindowContext<D>>,
     pub rs: Arc<RenderSystem>,
-    pub context: Arc<ContextDual<D>>,
+    pub context: Arc<ContextGroup<D>>,
 
     pub shaders: ShaderCollection,
     pub psos: PsoCollection<D>,
@@ -123,6 +132,12 @@ pub struct Application<D: RenderDevice> {
     pub buffer_frames: usize,
 
     pub bench_frames: usize,
+
+    pub timeline: Arc<Timeline>,
+    pub capture_timeline: bool,
+
+    pub recorder: Option<Arc<Recorder>>,
+    pub trace_dir: Option<PathBuf>,
 }
 
 fn main() {
@@ -152,7 +167,7 @@ fn main() {
             let mut timings = vec![];
             while let Ok(data) = rcv.recv_timeout(std::time::Duration::from_secs(30)) {
                 if data == TimingsInfo::End {
-                    let data = timings.drain(..).collect::<Vec<_>>();
+                    let data = std::mem::take(&mut timings);
                     let json = serde_json::to_string(&data).expect("failed to serialize");
 
                     connection
@@ -170,10 +185,47 @@ fn main() {
         (None, None)
     };
 
-    let mut app = Application::new(settings, sdr.clone());
-    start_sdr.send(()).expect("failed to send");
+    let rs = RenderSystem::new(&[RenderBackendSettings {
+        api: if settings.record_trace.is_some() || settings.validate {
+            RenderBackend::Recording
+        } else if cfg!(all(windows, feature = "dx12")) {
+            RenderBackend::Dx12
+        } else {
+            RenderBackend::Null
+        },
+        debug: if cfg!(debug_assertions) {
+            DebugFlags::all()
+        } else if settings.validate {
+            DebugFlags::RhiValidation
+        } else {
+            DebugFlags::empty()
+        },
+    }]);
+
+    #[cfg(not(all(windows, feature = "dx12")))]
+    let rs = if settings.record_trace.is_some() || settings.validate {
+        rs.with_recording_backend(multi_gpu_renderer::cpu_shaders::with_cpu_shaders(
+            rhi::null::backend::NullBackend::new(),
+        ))
+    } else {
+        rs.with_null_backend(multi_gpu_renderer::cpu_shaders::with_cpu_shaders(
+            rhi::null::backend::NullBackend::new(),
+        ))
+    };
+    let rs = Arc::new(rs);
 
-    event_loop.run_app(&mut app).expect("failed to run app");
+    if let Some(backend) = rs.recording_backend() {
+        let app = Application::new(Arc::clone(&rs), &backend, settings, sdr.clone());
+        run_app(event_loop, app, start_sdr);
+    } else {
+        #[cfg(all(windows, feature = "dx12"))]
+        let backend = rs.dx_backend().expect("failed to get directx backend");
+        #[cfg(not(all(windows, feature = "dx12")))]
+        let backend = rs.null_backend().expect("failed to get null backend");
+
+        let app = Application::new(Arc::clone(&rs), &backend, settings, sdr.clone());
+        run_app(event_loop, app, start_sdr);
+    }
 
     if let Some(sdr) = sdr {
         sdr.send(TimingsInfo::End).expect("failed to send message");
@@ -184,26 +236,45 @@ fn main() {
     }
 }
 
-impl Application<DxDevice> {
-    fn new(
+fn run_app<D: RenderDevice>(
+    event_loop: winit::event_loop::EventLoop<()>,
+    mut app: Application<D>,
+    start_sdr: std::sync::mpsc::SyncSender<()>,
+) {
+    start_sdr.send(()).expect("failed to send");
+
+    event_loop.run_app(&mut app).expect("failed to run app");
+}
+
+impl<D: RenderDevice> Application<D> {
+    fn new<A: Api<Device = D>>(
+        rs: Arc<RenderSystem>,
+        backend: &Backend<A>,
         settings: RenderSettings,
         sender: Option<std::sync::mpsc::Sender<TimingsInfo>>,
-    ) -> Application<DxDevice> {
-        let rs = Arc::new(RenderSystem::new(&[RenderBackendSettings {
-            api: RenderBackend::Dx12,
-            debug: if cfg!(debug_assertions) {
-                DebugFlags::all()
-            } else {
-                DebugFlags::empty()
-            },
-        }]));
+    ) -> Self {
+        let shaders = ShaderCollection::new(backend, cfg!(debug_assertions), &settings)
+            .expect("failed to compile shaders");
 
-        let backend = rs.dx_backend().expect("failed to get directx backend");
+        let mut selector = DeviceSelector::new();
+
+        if let Some(device) = &settings.primary_device {
+            selector = selector.with_primary(device.as_str());
+        }
 
-        let shaders = ShaderCollection::new(&backend, cfg!(debug_assertions), &settings);
+        if let Some(device) = &settings.secondary_device {
+            selector = selector.with_secondary(device.as_str());
+        }
+
+        let devices = backend
+            .select_devices(&selector)
+            .unwrap_or_else(|e| panic!("failed to select adapters: {}", e));
+
+        info!("Selected adapters: {:?}", devices);
 
         if let Some(sender) = &sender {
-            let mut info = backend.enumerate_devices().take(2).cloned();
+            let infos = backend.enumerate_devices().cloned().collect::<Vec<_>>();
+            let mut info = devices.iter().take(2).map(|i| infos[*i].clone());
             sender
                 .send(TimingsInfo::GpuInfo {
                     primary: info.next().expect("failed to get gpu info"),
@@ -212,29 +283,29 @@ impl Application<DxDevice> {
                 .expect("failed to send");
         }
 
-        let primary = Arc::new(backend.create_device(0));
-        let secondary = Arc::new(backend.create_device(1));
-
-        let group = Arc::new(ContextDual::new(primary, secondary));
+        let timeline = Arc::new(Timeline::new());
+        let group = Arc::new(backend.create_group_with_timeline(&devices, &timeline));
 
         let psos = PsoCollection::new(Arc::clone(&rs), Arc::clone(&group), &shaders);
 
-        let single_gpu = SingleGpuShadows::new(
+        let mut single_gpu = SingleGpuShadows::new(
             Arc::clone(&rs),
-            Arc::clone(&group.primary),
+            Arc::clone(group.primary()),
             [settings.width, settings.height],
             &psos,
             &settings,
-        );
+        )
+        .expect("failed to create single gpu graph");
 
-        let multi_gpu = MultiGpuShadows::new(
+        let mut multi_gpu = MultiGpuShadows::new(
             Arc::clone(&rs),
             Arc::clone(&group),
             [settings.width, settings.height],
             &psos,
             &settings,
             sender.clone(),
-        );
+        )
+        .expect("failed to create multi gpu graph");
 
         let mut world = World::new();
 
@@ -260,7 +331,8 @@ impl Application<DxDevice> {
                 )
                 .with_name("Global data".into()),
                 None,
-            );
+            )
+            .expect("failed to bind global buffer");
 
             ctx.bind_shader_argument(
                 global_argument,
@@ -269,13 +341,24 @@ impl Application<DxDevice> {
                     samplers: &[],
                     dynamic_buffer: Some(buffer),
                 },
-            );
+            )
+            .expect("failed to bind global argument");
         });
 
         let placeholders = TexturePlaceholders::new(&rs, &group);
 
         let scene = GltfScene::load(&settings.scene_path);
         create_multi_gpu_scene(scene, &mut world, &rs, &group, &settings, &placeholders);
+        propagate_transforms(&mut world);
+
+        let bounds = scene_bounds(&world);
+        single_gpu.set_scene_bounds(bounds);
+        multi_gpu.set_scene_bounds(bounds);
+
+        if let Some(dir) = &settings.record_trace {
+            std::fs::create_dir_all(dir).expect("failed to create trace directory");
+        }
+        let recorder = rs.recorder();
 
         Application {
             title: format!("Fotia Render Mode: {:?}", RenderMode::SingleGpu),
@@ -312,11 +395,15 @@ impl Application<DxDevice> {
             buffer_frames: 0,
 
             bench_frames: settings.bench_frames,
+
+            timeline,
+            capture_timeline: false,
+
+            recorder,
+            trace_dir: settings.record_trace,
         }
     }
-}
 
-impl<D: RenderDevice> Application<D> {
     fn update(&mut self) {
         let mut direction = glam::Vec3::ZERO;
 
@@ -362,6 +449,9 @@ impl<D: RenderDevice> Application<D> {
             );
         }
 
+        propagate_transforms(&mut self.world);
+        update_gpu_transforms(&self.world, &self.context, self.frame_idx);
+
         let view = self.camera.view();
         let proj = self.camera.proj();
 
@@ -381,15 +471,20 @@ impl<D: RenderDevice> Application<D> {
                     screen_dim: vec2(self.width as f32, self.height as f32),
                     _pad1: Default::default(),
                 }],
-            );
+            )
+            .expect("failed to update global buffer");
         });
 
-        if let RenderMode::SingleGpu = self.render_mode {
-            self.single_gpu.update(
-                &self.camera,
-                glam::Vec3::new(-1.0, -1.0, -1.0),
-                self.frame_idx,
-            );
+        match self.render_mode {
+            RenderMode::SingleGpu => self
+                .single_gpu
+                .update(
+                    &self.camera,
+                    glam::Vec3::new(-1.0, -1.0, -1.0),
+                    self.frame_idx,
+                )
+                .expect("failed to update single gpu graph"),
+            RenderMode::MultiGpu => self.multi_gpu.update(self.fps_controller.state()),
         }
     }
 
@@ -398,10 +493,17 @@ impl<D: RenderDevice> Application<D> {
             return;
         };
 
+        if self.capture_timeline {
+            self.timeline.begin_frame();
+        }
+
         let frame = wnd.swapchain.next_frame();
         self.context.call_primary(|ctx| {
-            ctx.wait_on_cpu(CommandType::Graphics, frame.last_access);
-            let mut encoder = ctx.create_encoder(CommandType::Graphics);
+            ctx.wait_on_cpu(CommandType::Graphics, frame.last_access)
+                .expect("failed to wait for frame");
+            let mut encoder = ctx
+                .create_encoder(CommandType::Graphics)
+                .expect("failed to create encoder");
             let timings = encoder.begin(ctx);
 
             if let Some(sdr) = &mut self.bench_sender {
@@ -426,45 +528,51 @@ impl<D: RenderDevice> Application<D> {
                 info!("Timings: {:?}", timings);
             }
 
-            encoder.set_barriers(&[Barrier::Texture(
-                frame.texture,
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            )]);
+            encoder
+                .set_barriers(&[Barrier::Texture(
+                    frame.texture,
+                    ResourceState::RenderTarget,
+                    Subresource::Local(None),
+                )])
+                .expect("failed to set barriers");
             ctx.enqueue(encoder);
 
             let time = std::time::Instant::now();
 
             match self.render_mode {
-                RenderMode::SingleGpu => {
-                    self.single_gpu.render(
-                        &self.world,
-                        self.global_argument,
-                        frame.texture,
-                        self.frame_idx,
-                    );
-                }
-                RenderMode::MultiGpu => {
-                    self.multi_gpu.render(
-                        &self.world,
-                        self.global_argument,
-                        frame.texture,
-                        &self.camera,
-                        vec3(-1.0, -1.0, -1.0),
-                        self.frame_idx,
-                    );
-                }
+                RenderMode::SingleGpu => self.single_gpu.render(
+                    &self.world,
+                    self.global_argument,
+                    frame.texture,
+                    &self.camera,
+                    self.frame_idx,
+                ),
+                RenderMode::MultiGpu => self.multi_gpu.render(
+                    &self.world,
+                    self.global_argument,
+                    frame.texture,
+                    &self.camera,
+                    vec3(-1.0, -1.0, -1.0),
+                    self.frame_idx,
+                ),
             }
-
-            let mut encoder = ctx.create_encoder(CommandType::Graphics);
-            encoder.set_barriers(&[Barrier::Texture(
-                frame.texture,
-                ResourceState::Present,
-                Subresource::Local(None),
-            )]);
+            .expect("failed to render frame");
+
+            let mut encoder = ctx
+                .create_encoder(CommandType::Graphics)
+                .expect("failed to create encoder");
+            encoder
+                .set_barriers(&[Barrier::Texture(
+                    frame.texture,
+                    ResourceState::Present,
+                    Subresource::Local(None),
+                )])
+                .expect("failed to set barriers");
 
             ctx.commit(encoder);
-            frame.last_access = ctx.submit(CommandType::Graphics);
+            frame.last_access = ctx
+                .submit(CommandType::Graphics)
+                .expect("failed to submit frame");
 
             if let Some(sdr) = &mut self.bench_sender {
                 match self.render_mode {
@@ -478,11 +586,41 @@ impl<D: RenderDevice> Application<D> {
                 }
             } else {
                 info!("CPU time: {:?}", time.elapsed());
+
+                let transients = match self.render_mode {
+                    RenderMode::SingleGpu => &self.single_gpu.transients,
+                    RenderMode::MultiGpu => &self.multi_gpu.transients,
+                };
+                info!("Transient memory peak: {} bytes", transients.peak_bytes());
             }
         });
 
         wnd.swapchain.present();
 
+        if self.capture_timeline {
+            let timeline = self.timeline.end_frame();
+            let name = format!("timeline_{:?}_{}", self.render_mode, timeline.frame);
+
+            timeline.save_json(format!("{}.json", name));
+            timeline.save_dot(format!("{}.dot", name));
+            info!("Saved frame timeline to {}.json and {}.dot", name, name);
+
+            self.capture_timeline = false;
+        }
+
+        // Resources created at startup land in the first frame's trace.
+        // The recorder is drained even when only validating.
+        if let Some(recorder) = &self.recorder {
+            let trace = recorder.end_frame();
+
+            if let Some(dir) = &self.trace_dir {
+                let path = dir.join(format!("trace_{:?}_{}.json", self.render_mode, trace.frame));
+                if let Err(err) = trace.save(path) {
+                    error!("Failed to save frame trace: {}", err);
+                }
+            }
+        }
+
         self.frame_idx = (self.frame_idx + 1) % self.frames_in_flight;
     }
 
@@ -533,16 +671,20 @@ impl<D: RenderDevice> winit::application::ApplicationHandler for Application<D>
             .map(|h| h.as_raw())
             .expect("failed to get window handle");
 
-        let swapchain = self.context.primary.create_swapchain(
-            SwapchainDesc {
-                width: self.width,
-                height: self.height,
-                present_mode: PresentMode::Mailbox,
-                frames: self.frames_in_flight,
-            },
-            &wnd,
-            &self.rs.handles,
-        );
+        let swapchain = self
+            .context
+            .primary()
+            .create_swapchain(
+                SwapchainDesc {
+                    width: self.width,
+                    height: self.height,
+                    present_mode: PresentMode::Mailbox,
+                    frames: self.frames_in_flight,
+                },
+                &wnd,
+                &self.rs.handles,
+            )
+            .expect("failed to create swapchain");
 
         self.wnd_ctx = Some(WindowContext {
             window,
@@ -579,6 +721,8 @@ impl<D: RenderDevice> winit::application::ApplicationHandler for Application<D>
                     } else if event.physical_key == KeyCode::Digit2 {
                         self.render_mode = RenderMode::MultiGpu;
                         self.title = format!("Fotia Render Mode: {:?}", self.render_mode);
+                    } else if event.physical_key == KeyCode::KeyT {
+                        self.capture_timeline = true;
                     }
 
                     self.keys.insert(event.physical_key, true);
@@ -593,14 +737,24 @@ impl<D: RenderDevice> winit::application::ApplicationHandler for Application<D>
             },
             winit::event::WindowEvent::Resized(size) => {
                 if let Some(window) = self.wnd_ctx.as_mut() {
-                    self.context.primary.wait_idle();
-                    self.context.primary.resize(
-                        &mut window.swapchain,
-                        [size.width, size.height],
-                        &self.rs.handles,
-                    );
-                    self.single_gpu.resize([size.width, size.height]);
-                    self.multi_gpu.resize([size.width, size.height]);
+                    self.context
+                        .primary()
+                        .wait_idle()
+                        .expect("failed to wait for device");
+                    self.context
+                        .primary()
+                        .resize(
+                            &mut window.swapchain,
+                            [size.width, size.height],
+                            &self.rs.handles,
+                        )
+                        .expect("failed to resize swapchain");
+                    self.single_gpu
+                        .resize([size.width, size.height])
+                        .expect("failed to resize single gpu graph");
+                    self.multi_gpu
+                        .resize([size.width, size.height])
+                        .expect("failed to resize multi gpu graph");
 
                     self.width = size.width;
                     self.height = size.height;
@@ -662,6 +816,10 @@ impl<D: RenderDevice> winit::application::ApplicationHandler for Application<D>
 
 impl<D: RenderDevice> Drop for Application<D> {
     fn drop(&mut self) {
-        self.context.call(|ctx| ctx.wait_idle());
+        self.context.call(|ctx| {
+            if let Err(err) = ctx.wait_idle() {
+                error!("Failed to wait for device on exit: {}", err);
+            }
+        });
     }
 }
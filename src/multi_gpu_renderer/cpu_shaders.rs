@@ -0,0 +1,452 @@
+use std::mem::offset_of;
+
+use glam::{Mat4, Vec2, Vec4};
+
+use crate::{
+    engine::{GpuMaterial, GpuTransform, gltf::AlphaMode},
+    rhi::null::{
+        backend::NullBackend,
+        raster::{PixelInput, ShaderResources, VertexOutput},
+    },
+};
+
+use super::{GpuGlobals, csm::Cascade};
+
+const ALPHA_TEST: &[&str] = &["ALPHA_TEST"];
+
+pub fn with_cpu_shaders(backend: NullBackend) -> NullBackend {
+    backend
+        .with_vertex_shader("Zpass.hlsl", "Main", zpass_vs)
+        .with_vertex_shader("Csm.hlsl", "VSMain", csm_vs)
+        .with_pixel_shader("Csm.hlsl", "PSMain", csm_ps)
+        .with_vertex_shader_variant("Zpass.hlsl", "Main", ALPHA_TEST, |resources, input| {
+            zpass_vs(resources, input).with_varyings(&input[1].to_array())
+        })
+        .with_pixel_shader_variant("Zpass.hlsl", "PSMain", ALPHA_TEST, |resources, input, _| {
+            alpha_test(resources, input.varyings)
+        })
+        .with_vertex_shader_variant("Csm.hlsl", "VSMain", ALPHA_TEST, |resources, input| {
+            csm_vs(resources, input).with_varyings(&input[1].to_array())
+        })
+        .with_pixel_shader_variant(
+            "Csm.hlsl",
+            "PSMain",
+            ALPHA_TEST,
+            |resources, input, output| {
+                alpha_test(resources, input.varyings) && csm_ps(resources, input, output)
+            },
+        )
+}
+
+fn object_transform(resources: &ShaderResources<'_>) -> Mat4 {
+    resources.read(
+        1,
+        size_of::<GpuTransform>() * resources.instance() as usize + offset_of!(GpuTransform, mat),
+    )
+}
+
+fn zpass_vs(resources: &ShaderResources<'_>, input: &[Vec4]) -> VertexOutput {
+    let proj_view: Mat4 = resources.read(0, offset_of!(GpuGlobals, proj_view));
+    let world_pos = object_transform(resources) * input[0].truncate().extend(1.0);
+
+    VertexOutput::new(proj_view * world_pos)
+}
+
+fn csm_vs(resources: &ShaderResources<'_>, input: &[Vec4]) -> VertexOutput {
+    let proj_view: Mat4 = resources.read(0, offset_of!(Cascade, proj_view));
+    let world_pos = object_transform(resources) * input[0].truncate().extend(1.0);
+
+    VertexOutput::new(proj_view * world_pos)
+}
+
+// The single GPU pass renders depth only.
+fn csm_ps(_resources: &ShaderResources<'_>, input: &PixelInput<'_>, output: &mut [Vec4]) -> bool {
+    if let Some(depth) = output.first_mut() {
+        *depth = Vec4::new(input.position.z, 0.0, 0.0, 1.0);
+    }
+    true
+}
+
+// Mirrors AlphaTest.hlsl, the material and its base color are bound to set 2.
+fn alpha_test(resources: &ShaderResources<'_>, uv: &[f32]) -> bool {
+    let base_color: Vec4 = resources.read(2, offset_of!(GpuMaterial, base_color));
+    let alpha_mode: u32 = resources.read(2, offset_of!(GpuMaterial, alpha_mode));
+    let base_color_uv: u32 = resources.read(2, offset_of!(GpuMaterial, base_color_uv));
+
+    let tex_coord = if base_color_uv == 0 {
+        Vec2::new(uv[0], uv[1])
+    } else {
+        Vec2::new(uv[2], uv[3])
+    };
+    let alpha = resources.sample(2, 0, tex_coord).w * base_color.w;
+    let cutoff = if alpha_mode == AlphaMode::Mask as u32 {
+        resources.read(2, offset_of!(GpuMaterial, alpha_cutoff))
+    } else {
+        0.5
+    };
+
+    alpha >= cutoff
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::{
+        collections::handle::Handle,
+        engine::GpuTransformComponent,
+        rhi::{
+            backend::Api,
+            command::{
+                CommandType, RenderCommandBuffer, RenderCommandDevice, RenderCommandQueue,
+                RenderEncoder,
+            },
+            null::{device::NullDevice, resources::NullTexture},
+            resources::{
+                Buffer, BufferDesc, BufferUsages, RenderResourceDevice, TextureDesc, TextureUsages,
+            },
+            shader::{
+                RasterPipelineDesc, RenderShaderDevice, ShaderArgumentDesc, ShaderDesc, ShaderEntry,
+            },
+            types::{
+                BlendMode, CullMode, DepthOp, DepthStateDesc, Format, IndexType, InputElementDesc,
+                Region, ShaderType, VertexAttribute, VertexType,
+            },
+        },
+    };
+
+    use super::*;
+
+    const EXTENT: [u32; 2] = [8, 8];
+
+    fn buffer<T>(
+        device: &NullDevice,
+        data: &[T],
+        usage: BufferUsages,
+    ) -> <NullDevice as RenderResourceDevice>::Buffer {
+        let mut buffer = device
+            .create_buffer(
+                BufferDesc::cpu_to_gpu(size_of_val(data), usage).with_stride(size_of::<T>()),
+            )
+            .expect("failed to create buffer");
+        for (dst, src) in buffer.map_mut::<T>().iter_mut().zip(data) {
+            unsafe { std::ptr::copy_nonoverlapping(src, dst, 1) };
+        }
+
+        buffer
+    }
+
+    fn cascade_pipeline(
+        backend: &NullBackend,
+        device: &NullDevice,
+        alpha_test: bool,
+    ) -> <NullDevice as RenderShaderDevice>::RasterPipeline {
+        let defines = if alpha_test {
+            vec![("ALPHA_TEST".into(), "1".into())]
+        } else {
+            vec![]
+        };
+        let compile = |ty, entry_point: &str| {
+            backend
+                .compile_shader(&ShaderDesc {
+                    ty,
+                    path: "Csm.hlsl",
+                    entry_point: entry_point.into(),
+                    debug: false,
+                    defines: defines.clone(),
+                })
+                .expect("failed to compile shader")
+        };
+        let vs = compile(ShaderType::Vertex, "VSMain");
+        let ps = compile(ShaderType::Pixel, "PSMain");
+
+        let input_elements = [
+            InputElementDesc {
+                semantic: VertexAttribute::Position(0),
+                format: VertexType::Float3,
+            },
+            InputElementDesc {
+                semantic: VertexAttribute::Uv(0),
+                format: VertexType::Float4,
+            },
+        ];
+
+        device
+            .create_raster_pipeline(RasterPipelineDesc {
+                layout: None,
+                input_elements: &input_elements[..if alpha_test { 2 } else { 1 }],
+                depth_bias: 0,
+                slope_bias: 0.0,
+                depth_clip: true,
+                depth: Some(DepthStateDesc {
+                    op: DepthOp::LessEqual,
+                    format: Format::D32,
+                    read_only: false,
+                }),
+                render_targets: &[Format::R32],
+                cull_mode: CullMode::None,
+                blend: BlendMode::None,
+                vs: &vs,
+                shaders: &[&ps],
+            })
+            .expect("failed to create pipeline")
+    }
+
+    fn cascade_targets(device: &NullDevice) -> (NullTexture, NullTexture) {
+        let target = device
+            .create_texture(TextureDesc::new_2d(
+                EXTENT,
+                Format::R32,
+                TextureUsages::RenderTarget,
+            ))
+            .expect("failed to create cascade target");
+        let depth = device
+            .create_texture(TextureDesc::new_2d(
+                EXTENT,
+                Format::D32,
+                TextureUsages::DepthTarget,
+            ))
+            .expect("failed to create cascade depth");
+
+        (target, depth)
+    }
+
+    // Renders a full screen quad whose base color is opaque on the left half
+    // and transparent on the right half into a cascade.
+    fn render_masked_quad(alpha_mode: AlphaMode, alpha_cutoff: f32) -> (NullTexture, NullTexture) {
+        let backend = with_cpu_shaders(NullBackend::new());
+        let device = backend.create_device(0);
+        let queue = device.create_command_queue(CommandType::Graphics, None);
+
+        let pipeline = cascade_pipeline(&backend, &device, true);
+        let (target, depth) = cascade_targets(&device);
+        let base_color = device
+            .create_texture(TextureDesc::new_2d(
+                [2, 1],
+                Format::Rgba8Unorm,
+                TextureUsages::RenderTarget | TextureUsages::Resource,
+            ))
+            .expect("failed to create base color");
+
+        let cascade = buffer(
+            &device,
+            &[Cascade {
+                proj_view: Mat4::IDENTITY,
+            }],
+            BufferUsages::Uniform,
+        );
+        let transform = buffer(
+            &device,
+            &[GpuTransform {
+                mat: Mat4::IDENTITY,
+            }],
+            BufferUsages::Uniform,
+        );
+        let material = buffer(
+            &device,
+            &[GpuMaterial {
+                base_color: [1.0; 4],
+                emissive: [0.0; 3],
+                metallic: 0.0,
+                roughness: 1.0,
+                normal_scale: 1.0,
+                occlusion_strength: 1.0,
+                alpha_cutoff,
+                alpha_mode: alpha_mode as u32,
+                double_sided: 1,
+                base_color_uv: 0,
+                metallic_roughness_uv: 0,
+                normal_uv: 0,
+                occlusion_uv: 0,
+                emissive_uv: 0,
+            }],
+            BufferUsages::Uniform,
+        );
+
+        let argument = |views: Vec<ShaderEntry<'_, NullDevice>>, dynamic_buffer| {
+            device
+                .create_shader_argument(ShaderArgumentDesc {
+                    views,
+                    samplers: [],
+                    dynamic_buffer: Some(dynamic_buffer),
+                })
+                .expect("failed to create shader argument")
+        };
+        let cascade_argument = argument(vec![], &cascade);
+        let transform_argument = argument(vec![], &transform);
+        let material_argument = argument(vec![ShaderEntry::Srv(&base_color)], &material);
+
+        let positions = buffer(
+            &device,
+            &[
+                [-1.0f32, -1.0, 0.5],
+                [1.0, -1.0, 0.5],
+                [1.0, 1.0, 0.5],
+                [-1.0, -1.0, 0.5],
+                [1.0, 1.0, 0.5],
+                [-1.0, 1.0, 0.5],
+            ],
+            BufferUsages::Vertex,
+        );
+        let uvs = buffer(
+            &device,
+            &[
+                [0.0f32, 1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+            ],
+            BufferUsages::Vertex,
+        );
+
+        let mut cmd = queue
+            .create_command_buffer(&device)
+            .expect("failed to create command buffer");
+        cmd.begin(&device);
+        {
+            let encoder = cmd.render("Base Color".into(), vec![&base_color], None);
+            encoder.clear_rt(&base_color, Some([1.0; 4]), None);
+            encoder.clear_rt(
+                &base_color,
+                Some([1.0, 1.0, 1.0, 0.0]),
+                Some(Region {
+                    x: 1,
+                    y: 0,
+                    w: 1,
+                    h: 1,
+                }),
+            );
+        }
+        {
+            let mut encoder = cmd.render("Cascade".into(), vec![&target], Some(&depth));
+            encoder.clear_rt(&target, Some([1.0; 4]), None);
+            encoder.clear_depth(&depth, Some(1.0), None);
+            encoder.set_raster_pipeline(&pipeline);
+            encoder.bind_shader_argument(0, &cascade_argument, 0);
+            encoder.bind_shader_argument(1, &transform_argument, 0);
+            encoder.bind_shader_argument(2, &material_argument, 0);
+            encoder.bind_vertex_buffer(&positions, 0);
+            encoder.bind_vertex_buffer(&uvs, 1);
+            encoder.draw(6, 0);
+        }
+
+        (target, depth)
+    }
+
+    // Every row of the quad is expected to match the first one.
+    fn columns(texture: &NullTexture) -> Vec<f32> {
+        let texels = texture.texels();
+        let width = EXTENT[0] as usize;
+
+        for row in texels.chunks(width) {
+            assert_eq!(row, &texels[..width]);
+        }
+
+        texels[..width].iter().map(|texel| texel.x).collect()
+    }
+
+    #[test]
+    fn masked_texels_leave_holes_in_cascade() {
+        let (target, depth) = render_masked_quad(AlphaMode::Mask, 0.5);
+
+        let expected = [0.5, 0.5, 0.5, 0.5, 1.0, 1.0, 1.0, 1.0];
+        assert_eq!(columns(&target), expected);
+        assert_eq!(columns(&depth), expected);
+    }
+
+    #[test]
+    fn blended_materials_are_cut_at_half_coverage() {
+        // The cutoff of blended materials is ignored.
+        let (target, _) = render_masked_quad(AlphaMode::Blend, 0.0);
+
+        assert_eq!(columns(&target), [0.5, 0.5, 0.5, 0.5, 1.0, 1.0, 1.0, 1.0]);
+    }
+
+    // Two instances of a thin quad are moved apart by the transforms of the
+    // second frame, the first frame pushes them behind the far plane.
+    #[test]
+    fn instances_read_their_transforms_at_frame_offset() {
+        let backend = with_cpu_shaders(NullBackend::new());
+        let device = backend.create_device(0);
+        let queue = device.create_command_queue(CommandType::Graphics, None);
+
+        let pipeline = cascade_pipeline(&backend, &device, false);
+        let (target, depth) = cascade_targets(&device);
+
+        let component = GpuTransformComponent {
+            buffer: Handle::new(0, 1),
+            argument: Handle::new(0, 1),
+            instance_count: 2,
+        };
+        let frame_len = GpuTransformComponent::frame_len(2);
+        let mut transforms = device
+            .create_buffer(BufferDesc::cpu_to_gpu(
+                GpuTransformComponent::buffer_size(2, 2),
+                BufferUsages::Uniform,
+            ))
+            .expect("failed to create transform buffer");
+        for (i, transform) in transforms.map_mut::<GpuTransform>().iter_mut().enumerate() {
+            let pos = match i.checked_sub(frame_len) {
+                Some(0) => Vec3::new(-0.5, 0.0, 0.0),
+                Some(1) => Vec3::new(0.5, 0.0, 0.0),
+                _ => Vec3::new(0.0, 0.0, 10.0),
+            };
+            *transform = GpuTransform {
+                mat: Mat4::from_translation(pos),
+            };
+        }
+
+        let cascade = buffer(
+            &device,
+            &[Cascade {
+                proj_view: Mat4::IDENTITY,
+            }],
+            BufferUsages::Uniform,
+        );
+        let argument = |dynamic_buffer| {
+            device
+                .create_shader_argument(ShaderArgumentDesc {
+                    views: [],
+                    samplers: [],
+                    dynamic_buffer: Some(dynamic_buffer),
+                })
+                .expect("failed to create shader argument")
+        };
+        let cascade_argument = argument(&cascade);
+        let transform_argument = argument(&transforms);
+
+        let positions = buffer(
+            &device,
+            &[
+                [-0.25f32, -1.0, 0.5],
+                [0.25, -1.0, 0.5],
+                [0.25, 1.0, 0.5],
+                [-0.25, 1.0, 0.5],
+            ],
+            BufferUsages::Vertex,
+        );
+        let indices = buffer(&device, &[0u32, 1, 2, 0, 2, 3], BufferUsages::Index);
+
+        let mut cmd = queue
+            .create_command_buffer(&device)
+            .expect("failed to create command buffer");
+        cmd.begin(&device);
+        {
+            let mut encoder = cmd.render("Cascade".into(), vec![&target], Some(&depth));
+            encoder.clear_rt(&target, Some([1.0; 4]), None);
+            encoder.clear_depth(&depth, Some(1.0), None);
+            encoder.set_raster_pipeline(&pipeline);
+            encoder.bind_shader_argument(0, &cascade_argument, 0);
+            encoder.bind_shader_argument(1, &transform_argument, component.offset(1));
+            encoder.bind_vertex_buffer(&positions, 0);
+            encoder.bind_index_buffer(&indices, IndexType::U32);
+            encoder.draw_indexed_instanced(6, component.instance_count, 0, 0, 0);
+        }
+
+        let expected = [1.0, 0.5, 0.5, 1.0, 1.0, 0.5, 0.5, 1.0];
+        assert_eq!(columns(&target), expected);
+        assert_eq!(columns(&depth), expected);
+    }
+}
@@ -1,14 +1,22 @@
 use glam::Vec4Swizzles;
 
-use crate::engine::camera::Camera;
+use crate::{
+    engine::{Aabb, camera::Camera},
+    rhi::{
+        resources::{TextureDesc, TextureUsages, TextureViewDesc, TextureViewType},
+        types::{Format, Region, Viewport},
+    },
+    settings::{CascadeLayout, RenderSettings},
+};
 
-const CASCADES_MAX: usize = 4;
+pub const CASCADES_MAX: usize = 8;
 
 #[repr(C)]
 #[repr(align(256))]
 #[derive(Clone, Debug)]
 pub struct Cascades {
     pub cascade_proj_views: [glam::Mat4; CASCADES_MAX],
+    pub camera_view: glam::Mat4,
     pub distances: [f32; CASCADES_MAX],
 }
 
@@ -19,112 +27,1083 @@ pub struct Cascade {
     pub proj_view: glam::Mat4,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraSnapshot {
+    pub view: glam::Mat4,
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub frame: usize,
+}
+
+impl CameraSnapshot {
+    pub fn new(camera: &Camera, frame: usize) -> Self {
+        Self {
+            view: camera.view(),
+            fov: camera.fov,
+            aspect_ratio: camera.aspect_ratio,
+            frame,
+        }
+    }
+
+    pub fn forward(&self) -> glam::Vec3 {
+        self.view
+            .inverse()
+            .transform_vector3(glam::Vec3::Z)
+            .normalize()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CascadeSnapshot {
+    pub camera: CameraSnapshot,
+    pub cascades: Cascades,
+}
+
+// Fraction of a cascade's half-extent the camera is expected to sweep
+// through before a shadow map rendered now reaches the light pass. The
+// snapshots may be several frames apart, so the rotation between them is
+// turned into a per-frame angular velocity first.
+pub fn motion_expansion(
+    previous: &CameraSnapshot,
+    current: &CameraSnapshot,
+    latency: usize,
+) -> f32 {
+    let angle = previous
+        .forward()
+        .dot(current.forward())
+        .clamp(-1.0, 1.0)
+        .acos();
+    let elapsed = current.frame.saturating_sub(previous.frame).max(1);
+    let velocity = angle / elapsed as f32;
+
+    sweep_expansion(current, velocity * latency as f32)
+}
+
+// Fraction of a cascade's half-extent covered by rotating the view by the
+// given angle.
+pub fn sweep_expansion(camera: &CameraSnapshot, angle: f32) -> f32 {
+    let angle = angle.min(std::f32::consts::FRAC_PI_2 - 0.01);
+    let half_extent = (0.5 * camera.fov).tan() * camera.aspect_ratio.max(1.0);
+
+    (angle.tan() / half_extent).clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CascadeFit {
+    Bounds,
+    Sphere { resolution: u32 },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CascadeMask(u32);
+
+impl CascadeMask {
+    pub const EMPTY: Self = Self(0);
+
+    pub fn all(count: usize) -> Self {
+        Self((1 << count) - 1)
+    }
+
+    pub fn contains(self, cascade: usize) -> bool {
+        self.0 & (1 << cascade) != 0
+    }
+
+    pub fn insert(&mut self, cascade: usize) {
+        self.0 |= 1 << cascade;
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = usize> {
+        (0..CASCADES_MAX).filter(move |cascade| self.contains(*cascade))
+    }
+}
+
+impl std::ops::BitOrAssign for CascadeMask {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CascadeSchedule {
+    intervals: [u32; CASCADES_MAX],
+    count: usize,
+    frame: u64,
+    pending: CascadeMask,
+}
+
+impl CascadeSchedule {
+    // Cascades without an interval are updated every frame.
+    pub fn new(intervals: &[u32], count: usize) -> Self {
+        let mut all = [1; CASCADES_MAX];
+        for (interval, requested) in all.iter_mut().zip(intervals) {
+            *interval = (*requested).max(1);
+        }
+
+        Self {
+            intervals: all,
+            count,
+            frame: 0,
+            pending: CascadeMask::all(count),
+        }
+    }
+
+    // Cascades sharing an interval are offset by their index so the slow
+    // cascades do not all land on the same frame.
+    pub fn is_due(&self, frame: u64, cascade: usize) -> bool {
+        (frame + cascade as u64).is_multiple_of(self.intervals[cascade] as u64)
+    }
+
+    pub fn invalidate(&mut self) {
+        self.pending = CascadeMask::all(self.count);
+    }
+
+    pub fn invalidate_cascades(&mut self, cascades: CascadeMask) {
+        self.pending |= cascades;
+    }
+
+    pub fn next(&mut self) -> CascadeMask {
+        let mut due = std::mem::take(&mut self.pending);
+        for cascade in 0..self.count {
+            if self.is_due(self.frame, cascade) {
+                due.insert(cascade);
+            }
+        }
+
+        self.frame += 1;
+
+        due
+    }
+}
+
 #[derive(Debug)]
 pub struct CascadedShadowMaps {
     pub cascades: Cascades,
     pub lambda: f32,
     pub shadow_far: Option<f32>,
     pub count: usize,
+    pub expansion: f32,
+    pub fit: CascadeFit,
+    pub scene_bounds: Option<Aabb>,
+    pub schedule: CascadeSchedule,
+    pub light_dir: Option<glam::Vec3>,
+    pub splits: Option<(f32, [f32; CASCADES_MAX])>,
 }
 
 impl CascadedShadowMaps {
     pub fn new(lambda: f32, shadow_far: Option<f32>, count: usize) -> Self {
+        assert!(
+            (1..=CASCADES_MAX).contains(&count),
+            "cascades count must be in 1..={}",
+            CASCADES_MAX
+        );
+
         Self {
             cascades: Cascades {
                 cascade_proj_views: [glam::Mat4::IDENTITY; CASCADES_MAX],
-                distances: [0.0; 4],
+                camera_view: glam::Mat4::IDENTITY,
+                distances: [0.0; CASCADES_MAX],
             },
             lambda,
             shadow_far,
             count,
+            expansion: 0.0,
+            fit: CascadeFit::Bounds,
+            scene_bounds: None,
+            schedule: CascadeSchedule::new(&[], count),
+            light_dir: None,
+            splits: None,
         }
     }
 
-    pub fn update(&mut self, camera: &Camera, light_dir: glam::Vec3) {
-        let cascade_count = self.count;
+    pub fn from_settings(settings: &RenderSettings) -> Self {
+        let fit = if settings.stable_cascades {
+            CascadeFit::Sphere {
+                resolution: settings.cascade_size,
+            }
+        } else {
+            CascadeFit::Bounds
+        };
+
+        Self::new(
+            settings.cascades_lambda,
+            settings.shadows_far,
+            settings.cascades_count,
+        )
+        .with_fit(fit)
+        .with_schedule(CascadeSchedule::new(
+            &settings.cascade_intervals,
+            settings.cascades_count,
+        ))
+    }
+
+    pub fn with_fit(mut self, fit: CascadeFit) -> Self {
+        self.fit = fit;
+        self
+    }
 
-        let near_clip = camera.near;
-        let far_clip = self.shadow_far.unwrap_or(camera.far);
-        let clip_range = far_clip - near_clip;
+    pub fn with_schedule(mut self, schedule: CascadeSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
 
-        let min_z = near_clip;
-        let max_z = near_clip + clip_range;
+    pub fn set_scene_bounds(&mut self, bounds: Option<Aabb>) {
+        self.scene_bounds = bounds;
+        self.schedule.invalidate();
+    }
 
-        let range = max_z - min_z;
-        let ratio: f32 = max_z / min_z;
+    // Splits derived from a depth histogram replace the lambda blend. The
+    // readback jitters by a bin from frame to frame, so a split only follows
+    // the histogram once it moved further than that and only the cascades it
+    // bounds are refitted.
+    pub fn set_depth_histogram(&mut self, histogram: Option<&DepthHistogram>) {
+        let Some(histogram) = histogram else {
+            if self.splits.take().is_some() {
+                self.schedule.invalidate();
+            }
+            return;
+        };
 
-        for (i, distance) in self
-            .cascades
-            .distances
-            .iter_mut()
-            .take(self.count)
-            .enumerate()
-        {
-            let p = (i as f32 + 1.0) / cascade_count as f32;
-            let log = min_z * ratio.powf(p);
-            let uniform = min_z + range * p;
-            *distance = self.lambda * (log - uniform) + uniform;
+        let target = sdsm_splits(histogram, self.count, self.lambda);
+        match (self.splits, target) {
+            (Some(current), Some(target)) => {
+                let tolerance = histogram.bin_ratio().powf(1.5);
+                let (splits, moved) = follow_splits(current, target, self.count, tolerance);
+                self.splits = Some(splits);
+                self.schedule.invalidate_cascades(moved);
+            }
+            (current, target) => {
+                if current != target {
+                    self.splits = target;
+                    self.schedule.invalidate();
+                }
+            }
         }
+    }
 
-        let mut cur_near = camera.near;
+    // Only the cascades that are due this frame are refitted, the others keep
+    // the matrices their shadow maps were rendered with.
+    pub fn update(&mut self, camera: &Camera, light_dir: glam::Vec3) -> CascadeMask {
+        if self.light_dir != Some(light_dir) {
+            self.light_dir = Some(light_dir);
+            self.schedule.invalidate();
+        }
+
+        let due = self.schedule.next();
+        let cascade_count = self.count;
+
+        let (near, distances) = self.splits.unwrap_or_else(|| {
+            let far = self.shadow_far.unwrap_or(camera.far);
+            (
+                camera.near,
+                practical_splits(camera.near, far, cascade_count, self.lambda),
+            )
+        });
+
+        self.cascades.distances = distances;
+        self.cascades.camera_view = camera.view();
+
+        let mut cur_near = near;
 
         for i in 0..cascade_count {
             let cur_far = self.cascades.distances[i];
+            if !due.contains(i) {
+                cur_near = cur_far;
+                continue;
+            }
+
+            let corners = frustum_corners(camera, cur_near, cur_far);
+
+            self.cascades.cascade_proj_views[i] = match self.fit {
+                CascadeFit::Bounds => fit_bounds(
+                    &corners,
+                    light_dir,
+                    self.expansion,
+                    self.scene_bounds.as_ref(),
+                ),
+                CascadeFit::Sphere { resolution } => fit_sphere(
+                    &corners,
+                    light_dir,
+                    self.expansion,
+                    resolution,
+                    self.scene_bounds.as_ref(),
+                ),
+            };
+
+            cur_near = cur_far;
+        }
+
+        due
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DepthHistogram {
+    pub near: f32,
+    pub far: f32,
+    pub bins: Vec<u32>,
+}
+
+// Bins are spaced logarithmically over the view depth so the precision
+// follows the split distribution of the cascades.
+impl DepthHistogram {
+    pub fn new(near: f32, far: f32, bins: usize) -> Self {
+        assert!(0.0 < near && near < far, "depth histogram range is empty");
+        assert!(bins > 0, "depth histogram must have at least one bin");
+
+        Self {
+            near,
+            far,
+            bins: vec![0; bins],
+        }
+    }
+
+    // Non-positive depths mark tiles without geometry, depths past the far
+    // plane still need the last cascade so they are clamped into range.
+    pub fn add(&mut self, depth: f32) {
+        if depth.is_nan() || depth <= 0.0 {
+            return;
+        }
+
+        let depth = depth.clamp(self.near, self.far);
+        let t = (depth / self.near).ln() / (self.far / self.near).ln();
+        let bin = ((t * self.bins.len() as f32) as usize).min(self.bins.len() - 1);
+
+        self.bins[bin] += 1;
+    }
+
+    // Depth ratio between the two edges of a bin.
+    pub fn bin_ratio(&self) -> f32 {
+        (self.far / self.near).powf(1.0 / self.bins.len() as f32)
+    }
+
+    pub fn edge(&self, index: usize) -> f32 {
+        let t = index as f32 / self.bins.len() as f32;
+        self.near * (self.far / self.near).powf(t)
+    }
+
+    // Bin edges round the range outwards so every sampled depth stays covered.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let first = self.bins.iter().position(|count| *count > 0)?;
+        let last = self.bins.iter().rposition(|count| *count > 0)?;
+
+        Some((self.edge(first), self.edge(last + 1)))
+    }
+}
+
+pub fn practical_splits(near: f32, far: f32, count: usize, lambda: f32) -> [f32; CASCADES_MAX] {
+    let range = far - near;
+    let ratio = far / near;
+
+    let mut distances = [0.0; CASCADES_MAX];
+    for (i, distance) in distances.iter_mut().take(count).enumerate() {
+        let p = (i as f32 + 1.0) / count as f32;
+        let log = near * ratio.powf(p);
+        let uniform = near + range * p;
+        *distance = lambda * (log - uniform) + uniform;
+    }
+
+    distances
+}
+
+// Returns the near plane of the first cascade together with the split
+// distances fitted to the sampled depth range.
+pub fn sdsm_splits(
+    histogram: &DepthHistogram,
+    count: usize,
+    lambda: f32,
+) -> Option<(f32, [f32; CASCADES_MAX])> {
+    let (near, far) = histogram.range()?;
+
+    Some((near, practical_splits(near, far, count, lambda)))
+}
+
+// Moves the splits whose distance changed by more than the `tolerance` ratio
+// to the target and returns the cascades bounded by them. A kept split that a
+// moved neighbour would cross takes the target wholesale instead.
+pub fn follow_splits(
+    current: (f32, [f32; CASCADES_MAX]),
+    target: (f32, [f32; CASCADES_MAX]),
+    count: usize,
+    tolerance: f32,
+) -> ((f32, [f32; CASCADES_MAX]), CascadeMask) {
+    let moved = |from: f32, to: f32| from.max(to) > from.min(to) * tolerance;
+
+    let (mut near, mut distances) = current;
+    let mut cascades = CascadeMask::EMPTY;
+
+    if moved(near, target.0) {
+        near = target.0;
+        cascades.insert(0);
+    }
+
+    for (i, (distance, to)) in distances.iter_mut().zip(target.1).take(count).enumerate() {
+        if moved(*distance, to) {
+            *distance = to;
+            cascades.insert(i);
+            if i + 1 < count {
+                cascades.insert(i + 1);
+            }
+        }
+    }
+
+    let mut cur_near = near;
+    let ordered = distances[..count].iter().all(|distance| {
+        let ordered = cur_near < *distance;
+        cur_near = *distance;
+        ordered
+    });
+
+    if ordered {
+        ((near, distances), cascades)
+    } else {
+        (target, CascadeMask::all(count))
+    }
+}
+
+pub fn atlas_grid(count: usize) -> [u32; 2] {
+    let cols = (count as f32).sqrt().ceil() as u32;
+    let rows = (count as u32).div_ceil(cols);
+
+    [cols, rows]
+}
+
+pub fn shadow_map_desc(
+    layout: CascadeLayout,
+    size: u32,
+    count: usize,
+    format: Format,
+    usage: TextureUsages,
+) -> TextureDesc {
+    match layout {
+        CascadeLayout::Atlas => {
+            let [cols, rows] = atlas_grid(count);
+            TextureDesc::new_2d([cols * size, rows * size], format, usage)
+        }
+        CascadeLayout::Array => {
+            TextureDesc::new_2d_array([size, size], count as u32, format, usage)
+        }
+    }
+}
+
+pub fn cascade_slice_view(
+    view_ty: TextureViewType,
+    format: Format,
+    index: usize,
+) -> TextureViewDesc {
+    TextureViewDesc::default()
+        .with_view_type(view_ty)
+        .with_format(format)
+        .with_array(index as u32..index as u32 + 1)
+}
+
+pub fn cascade_region(layout: CascadeLayout, size: u32, count: usize, index: usize) -> Region {
+    let [col, row] = match layout {
+        CascadeLayout::Atlas => {
+            let [cols, _] = atlas_grid(count);
+            [index as u32 % cols, index as u32 / cols]
+        }
+        CascadeLayout::Array => [0, 0],
+    };
+
+    Region {
+        x: size * col,
+        y: size * row,
+        w: size,
+        h: size,
+    }
+}
+
+pub fn cascade_viewport(layout: CascadeLayout, size: u32, count: usize, index: usize) -> Viewport {
+    let region = cascade_region(layout, size, count, index);
+
+    Viewport {
+        x: region.x as f32,
+        y: region.y as f32,
+        w: region.w as f32,
+        h: region.h as f32,
+    }
+}
+
+pub fn frustum_corners(camera: &Camera, near: f32, far: f32) -> [glam::Vec3; 8] {
+    let mut corners = [
+        glam::vec3(-1.0, -1.0, 0.0),
+        glam::vec3(-1.0, -1.0, 1.0),
+        glam::vec3(-1.0, 1.0, 0.0),
+        glam::vec3(-1.0, 1.0, 1.0),
+        glam::vec3(1.0, -1.0, 0.0),
+        glam::vec3(1.0, -1.0, 1.0),
+        glam::vec3(1.0, 1.0, 0.0),
+        glam::vec3(1.0, 1.0, 1.0),
+    ];
+
+    let frust_proj = glam::Mat4::perspective_lh(camera.fov, camera.aspect_ratio, near, far);
+    let cam_view = camera.view();
+
+    let frust_proj_view = (frust_proj * cam_view).inverse();
+
+    for corner in corners.iter_mut() {
+        let temp = frust_proj_view * glam::vec4(corner.x, corner.y, corner.z, 1.0);
+        let temp = temp / temp.w;
+
+        *corner = temp.xyz();
+    }
+
+    corners
+}
+
+pub fn fit_bounds(
+    corners: &[glam::Vec3; 8],
+    light_dir: glam::Vec3,
+    expansion: f32,
+    scene_bounds: Option<&Aabb>,
+) -> glam::Mat4 {
+    let center = corners
+        .iter()
+        .fold(glam::Vec3::ZERO, |center, corner| center + *corner)
+        / 8.0;
+
+    let light_view = light_view(center, light_dir);
+
+    let mut min_x = f32::MAX;
+    let mut max_x = f32::MIN;
+    let mut min_y = f32::MAX;
+    let mut max_y = f32::MIN;
+    let mut min_z = f32::MAX;
+    let mut max_z = f32::MIN;
+
+    for corner in corners {
+        let temp = light_view * glam::vec4(corner.x, corner.y, corner.z, 1.0);
+
+        min_x = min_x.min(temp.x);
+        max_x = max_x.max(temp.x);
+        min_y = min_y.min(temp.y);
+        max_y = max_y.max(temp.y);
+        min_z = min_z.min(temp.z);
+        max_z = max_z.max(temp.z);
+    }
+
+    let pad_x = 0.5 * (max_x - min_x) * expansion;
+    let pad_y = 0.5 * (max_y - min_y) * expansion;
+
+    let light_proj = glam::Mat4::orthographic_lh(
+        min_x - pad_x,
+        max_x + pad_x,
+        min_y - pad_y,
+        max_y + pad_y,
+        extend_near(&light_view, min_z, scene_bounds),
+        max_z,
+    );
+
+    light_proj * light_view
+}
 
-            let mut corners = [
-                glam::vec3(-1.0, -1.0, 0.0),
-                glam::vec3(-1.0, -1.0, 1.0),
-                glam::vec3(-1.0, 1.0, 0.0),
-                glam::vec3(-1.0, 1.0, 1.0),
-                glam::vec3(1.0, -1.0, 0.0),
-                glam::vec3(1.0, -1.0, 1.0),
-                glam::vec3(1.0, 1.0, 0.0),
-                glam::vec3(1.0, 1.0, 1.0),
-            ];
+// The sphere radius does not depend on the camera orientation, and the light
+// view is anchored at the world origin so snapping its translation to whole
+// texels keeps static geometry on the same texels while the camera moves.
+pub fn fit_sphere(
+    corners: &[glam::Vec3; 8],
+    light_dir: glam::Vec3,
+    expansion: f32,
+    resolution: u32,
+    scene_bounds: Option<&Aabb>,
+) -> glam::Mat4 {
+    let center = corners
+        .iter()
+        .fold(glam::Vec3::ZERO, |center, corner| center + *corner)
+        / 8.0;
 
-            let frust_proj =
-                glam::Mat4::perspective_lh(camera.fov, camera.aspect_ratio, cur_near, cur_far);
-            let cam_view = camera.view();
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0f32, f32::max);
+    let radius = (radius * (1.0 + expansion) * 16.0).ceil() / 16.0;
 
-            let frust_proj_view = (frust_proj * cam_view).inverse();
+    let light_view = light_view(glam::Vec3::ZERO, light_dir);
+    let center = light_view.transform_point3(center);
 
-            for corner in corners.iter_mut() {
-                let temp = frust_proj_view * glam::vec4(corner.x, corner.y, corner.z, 1.0);
-                let temp = temp / temp.w;
+    let texel = 2.0 * radius / resolution as f32;
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
 
-                *corner = temp.xyz();
+    let light_proj = glam::Mat4::orthographic_lh(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        extend_near(&light_view, center.z - radius, scene_bounds),
+        center.z + radius,
+    );
+
+    light_proj * light_view
+}
+
+// A light straight above or below the scene is parallel to the usual up
+// vector, which would leave the view basis degenerate.
+fn light_view(eye: glam::Vec3, light_dir: glam::Vec3) -> glam::Mat4 {
+    let up = if light_dir.normalize().dot(glam::Vec3::Y).abs() > 0.99 {
+        glam::Vec3::Z
+    } else {
+        glam::Vec3::Y
+    };
+
+    glam::Mat4::look_to_lh(eye, light_dir, up)
+}
+
+// Casters between the light and the cascade slice still have to land in
+// the shadow map, so pull the near plane back to the scene bounds.
+fn extend_near(light_view: &glam::Mat4, near: f32, scene_bounds: Option<&Aabb>) -> f32 {
+    scene_bounds
+        .map(|bounds| bounds.transform(light_view).min.z)
+        .map_or(near, |scene_near| scene_near.min(near))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::camera::CameraState;
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    // A square 90 degree frustum, so the expansion is the tangent of the
+    // swept angle.
+    fn snapshot(yaw_degrees: f32, frame: usize) -> CameraSnapshot {
+        CameraSnapshot {
+            view: CameraState {
+                position: glam::Vec3::ZERO,
+                yaw: yaw_degrees.to_radians(),
+                pitch: 0.0,
             }
+            .view(),
+            fov: std::f32::consts::FRAC_PI_2,
+            aspect_ratio: 1.0,
+            frame,
+        }
+    }
+
+    #[test]
+    fn still_camera_needs_no_expansion() {
+        let expansion = motion_expansion(&snapshot(30.0, 0), &snapshot(30.0, 1), 3);
+
+        assert!(expansion.abs() < EPSILON);
+    }
+
+    #[test]
+    fn expansion_follows_angular_velocity() {
+        let expansion = motion_expansion(&snapshot(0.0, 0), &snapshot(1.0, 1), 3);
+
+        assert!((expansion - 3.0f32.to_radians().tan()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn expansion_ignores_update_interval() {
+        // The same angular velocity observed over three frames.
+        let every_frame = motion_expansion(&snapshot(0.0, 0), &snapshot(1.0, 1), 4);
+        let every_third = motion_expansion(&snapshot(0.0, 3), &snapshot(3.0, 6), 4);
+
+        assert!((every_frame - every_third).abs() < EPSILON);
+        assert!((every_frame - 4.0f32.to_radians().tan()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn expansion_scales_with_frustum_width() {
+        let square = motion_expansion(&snapshot(0.0, 0), &snapshot(2.0, 1), 2);
+
+        let mut previous = snapshot(0.0, 0);
+        let mut current = snapshot(2.0, 1);
+        previous.aspect_ratio = 2.0;
+        current.aspect_ratio = 2.0;
+        let wide = motion_expansion(&previous, &current, 2);
+
+        assert!((wide - 0.5 * square).abs() < EPSILON);
+    }
+
+    #[test]
+    fn expansion_is_bounded() {
+        assert_eq!(
+            motion_expansion(&snapshot(0.0, 0), &snapshot(10.0, 1), 0),
+            0.0
+        );
+        assert_eq!(
+            motion_expansion(&snapshot(0.0, 0), &snapshot(60.0, 1), 3),
+            1.0
+        );
+    }
+
+    const RESOLUTION: u32 = 1024;
+
+    fn camera(position: glam::Vec3, yaw: f32) -> Camera {
+        let mut camera = Camera::new(0.1, 100.0, 1.0, [16, 9]);
+        camera.view = CameraState {
+            position,
+            yaw,
+            pitch: -0.3,
+        }
+        .view();
+
+        camera
+    }
+
+    fn cascade(camera: &Camera, light_dir: glam::Vec3) -> glam::Mat4 {
+        let corners = frustum_corners(camera, 0.1, 20.0);
+
+        fit_sphere(&corners, light_dir, 0.0, RESOLUTION, None)
+    }
+
+    // Position of a world point in shadow map texels.
+    fn texel(proj_view: &glam::Mat4, point: glam::Vec3) -> glam::Vec2 {
+        let ndc = proj_view.project_point3(point);
+
+        (ndc.truncate() * 0.5 + 0.5) * RESOLUTION as f32
+    }
+
+    fn assert_whole_texels(offset: glam::Vec2) {
+        let fraction = offset - offset.round();
+        assert!(
+            fraction.abs().max_element() < 0.01,
+            "moved by a fraction of a texel: {offset}"
+        );
+    }
+
+    #[test]
+    fn sphere_fit_snaps_to_texels() {
+        let light_dir = glam::vec3(0.3, -1.0, 0.2).normalize();
+        let point = glam::vec3(2.0, 0.0, 6.0);
+
+        let reference = cascade(&camera(glam::Vec3::ZERO, 0.0), light_dir);
+        let start = texel(&reference, point);
+
+        for (position, yaw) in [
+            (glam::vec3(0.001, 0.0, 0.0), 0.0),
+            (glam::vec3(0.013, 0.0, -0.007), 0.0),
+            (glam::vec3(0.5, 0.2, 1.3), 0.0),
+            (glam::Vec3::ZERO, 0.4),
+            (glam::vec3(0.03, 0.0, 0.02), -0.7),
+        ] {
+            let moved = cascade(&camera(position, yaw), light_dir);
+
+            // The texel size does not depend on where the camera looks.
+            assert!((moved.x_axis.x - reference.x_axis.x).abs() < 1e-6);
+            assert_whole_texels(texel(&moved, point) - start);
+        }
+    }
+
+    #[test]
+    fn sphere_fit_moves_at_most_one_texel_for_small_steps() {
+        let light_dir = glam::vec3(-0.4, -1.0, 0.1).normalize();
+        let point = glam::vec3(-1.0, 0.5, 4.0);
+
+        let mut previous = texel(&cascade(&camera(glam::Vec3::ZERO, 0.0), light_dir), point);
+        for step in 1..50 {
+            let position = glam::vec3(0.002, 0.0, 0.001) * step as f32;
+            let current = texel(&cascade(&camera(position, 0.0), light_dir), point);
+
+            assert!((current - previous).abs().max_element() <= 1.01);
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn vertical_light_has_valid_view() {
+        let camera = camera(glam::vec3(0.0, 2.0, 0.0), 0.3);
+        let corners = frustum_corners(&camera, 0.1, 20.0);
+        let point = glam::vec3(0.5, 0.0, 5.0);
+
+        for light_dir in [glam::Vec3::NEG_Y, glam::Vec3::Y] {
+            for proj_view in [
+                fit_sphere(&corners, light_dir, 0.0, RESOLUTION, None),
+                fit_bounds(&corners, light_dir, 0.0, None),
+            ] {
+                assert!(proj_view.is_finite());
 
-            let center = corners
-                .into_iter()
-                .fold(glam::Vec3::ZERO, |center, corner| center + corner)
-                / 8.0;
-
-            let light_view = glam::Mat4::look_at_lh(center, center + light_dir, glam::Vec3::Y);
-
-            let mut min_x = f32::MAX;
-            let mut max_x = f32::MIN;
-            let mut min_y = f32::MAX;
-            let mut max_y = f32::MIN;
-            let mut min_z = f32::MAX;
-            let mut max_z = f32::MIN;
-
-            for corner in corners {
-                let temp = light_view * glam::vec4(corner.x, corner.y, corner.z, 1.0);
-
-                min_x = min_x.min(temp.x);
-                max_x = max_x.max(temp.x);
-                min_y = min_y.min(temp.y);
-                max_y = max_y.max(temp.y);
-                min_z = min_z.min(temp.z);
-                max_z = max_z.max(temp.z);
+                // Depth grows along the light direction.
+                let near = proj_view.project_point3(point);
+                let far = proj_view.project_point3(point + light_dir);
+                assert!(far.z > near.z);
+                assert!(near.truncate().abs().max_element() <= 1.0);
             }
+        }
+    }
 
-            let light_proj = glam::Mat4::orthographic_lh(min_x, max_x, min_y, max_y, min_z, max_z);
+    fn cascades(mask: CascadeMask) -> Vec<usize> {
+        mask.iter().collect()
+    }
 
-            self.cascades.cascade_proj_views[i] = light_proj * light_view;
+    #[test]
+    fn schedule_staggers_slow_cascades() {
+        let mut schedule = CascadeSchedule::new(&[1, 1, 2, 4], 4);
 
-            cur_near = cur_far;
+        // Everything is rendered once before the intervals apply.
+        assert_eq!(cascades(schedule.next()), [0, 1, 2, 3]);
+        assert_eq!(cascades(schedule.next()), [0, 1, 3]);
+        assert_eq!(cascades(schedule.next()), [0, 1, 2]);
+        assert_eq!(cascades(schedule.next()), [0, 1]);
+        assert_eq!(cascades(schedule.next()), [0, 1, 2]);
+        assert_eq!(cascades(schedule.next()), [0, 1, 3]);
+
+        schedule.invalidate();
+        assert_eq!(cascades(schedule.next()), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn schedule_updates_each_cascade_at_its_interval() {
+        let mut schedule = CascadeSchedule::new(&[1, 0, 3], 4);
+        schedule.next();
+
+        let mut updates = [0; 4];
+        for _ in 0..12 {
+            for cascade in schedule.next().iter() {
+                updates[cascade] += 1;
+            }
+        }
+
+        // Missing and zero intervals fall back to every frame.
+        assert_eq!(updates, [12, 12, 4, 12]);
+    }
+
+    #[test]
+    fn skipped_cascades_keep_their_matrices() {
+        let light_dir = glam::vec3(0.3, -1.0, 0.2).normalize();
+        let mut csm = CascadedShadowMaps::new(0.5, Some(40.0), 2)
+            .with_schedule(CascadeSchedule::new(&[1, 2], 2));
+
+        let still = camera(glam::Vec3::ZERO, 0.0);
+        let moved = camera(glam::vec3(3.0, 0.0, 1.0), 0.5);
+
+        assert_eq!(cascades(csm.update(&still, light_dir)), [0, 1]);
+        assert_eq!(cascades(csm.update(&still, light_dir)), [0, 1]);
+        let rendered = csm.cascades.cascade_proj_views;
+
+        assert_eq!(cascades(csm.update(&moved, light_dir)), [0]);
+        assert_ne!(csm.cascades.cascade_proj_views[0], rendered[0]);
+        assert_eq!(csm.cascades.cascade_proj_views[1], rendered[1]);
+        assert_eq!(csm.cascades.camera_view, moved.view());
+
+        // A new light direction invalidates every cascade.
+        let light_dir = glam::vec3(-0.3, -1.0, 0.2).normalize();
+        assert_eq!(cascades(csm.update(&moved, light_dir)), [0, 1]);
+        assert_ne!(csm.cascades.cascade_proj_views[1], rendered[1]);
+    }
+
+    #[test]
+    fn histogram_range_covers_samples() {
+        let mut histogram = DepthHistogram::new(0.1, 100.0, 32);
+        assert_eq!(histogram.range(), None);
+
+        // Empty tiles and depths outside the range.
+        histogram.add(0.0);
+        histogram.add(-1.0);
+        histogram.add(f32::NAN);
+        assert_eq!(histogram.range(), None);
+
+        for depth in [2.0, 3.5, 17.0] {
+            histogram.add(depth);
+        }
+
+        let (near, far) = histogram.range().expect("histogram is empty");
+        assert!(near <= 2.0 && 17.0 <= far);
+        assert!(near > 1.0 && far < 25.0);
+
+        histogram.add(1000.0);
+        let (_, far) = histogram.range().expect("histogram is empty");
+        assert!((far - 100.0).abs() < EPSILON * 100.0);
+    }
+
+    #[test]
+    fn practical_splits_blend_log_and_uniform() {
+        let uniform = practical_splits(1.0, 100.0, 4, 0.0);
+        let log = practical_splits(1.0, 100.0, 4, 1.0);
+
+        for (split, expected) in uniform.iter().zip([25.75, 50.5, 75.25, 100.0]) {
+            assert!((split - expected).abs() < EPSILON * 100.0);
+        }
+        for (split, expected) in log.iter().zip([3.1623, 10.0, 31.623, 100.0]) {
+            assert!((split - expected).abs() < 1e-3 * expected);
+        }
+        assert!(uniform[4..].iter().all(|split| *split == 0.0));
+    }
+
+    #[test]
+    fn sdsm_splits_fit_sampled_range() {
+        let mut histogram = DepthHistogram::new(0.1, 100.0, 64);
+        assert_eq!(sdsm_splits(&histogram, 3, 0.5), None);
+
+        for depth in [4.0, 6.0, 9.0, 12.0] {
+            histogram.add(depth);
+        }
+
+        let (near, splits) = sdsm_splits(&histogram, 3, 0.5).expect("no splits");
+        let (range_near, range_far) = histogram.range().expect("histogram is empty");
+
+        assert_eq!(near, range_near);
+        assert_eq!(splits, practical_splits(range_near, range_far, 3, 0.5));
+        assert!(splits[2] < 15.0);
+    }
+
+    #[test]
+    fn depth_histogram_replaces_lambda_splits() {
+        let light_dir = glam::vec3(0.3, -1.0, 0.2).normalize();
+        let camera = camera(glam::Vec3::ZERO, 0.0);
+        let mut csm = CascadedShadowMaps::new(0.5, Some(50.0), 2)
+            .with_schedule(CascadeSchedule::new(&[4, 4], 2));
+        csm.update(&camera, light_dir);
+        assert_eq!(csm.cascades.distances[1], 50.0);
+
+        let mut histogram = DepthHistogram::new(0.1, 100.0, 64);
+        histogram.add(3.0);
+        histogram.add(8.0);
+        csm.set_depth_histogram(Some(&histogram));
+
+        // The splits moved, so every cascade is refitted.
+        assert_eq!(cascades(csm.update(&camera, light_dir)), [0, 1]);
+        assert_eq!(csm.cascades.distances[1], histogram.range().unwrap().1);
+
+        // The same histogram again changes nothing.
+        csm.set_depth_histogram(Some(&histogram));
+        assert!(cascades(csm.update(&camera, light_dir)).is_empty());
+
+        csm.set_depth_histogram(None);
+        assert_eq!(cascades(csm.update(&camera, light_dir)), [0, 1]);
+        assert_eq!(csm.cascades.distances[1], 50.0);
+    }
+
+    #[test]
+    fn casters_behind_near_plane_pull_it_back() {
+        let light_dir = glam::vec3(0.3, -1.0, 0.2).normalize();
+        let corners = frustum_corners(&camera(glam::Vec3::ZERO, 0.0), 0.1, 10.0);
+        let center = corners.iter().sum::<glam::Vec3>() / 8.0;
+
+        // A caster between the light and the slice, far outside the view.
+        let caster = center - light_dir * 50.0;
+        let bounds = Aabb::from_points([caster - 0.5, caster + 0.5]);
+
+        let fit = |fit, bounds| match fit {
+            CascadeFit::Bounds => fit_bounds(&corners, light_dir, 0.0, bounds),
+            CascadeFit::Sphere { resolution } => {
+                fit_sphere(&corners, light_dir, 0.0, resolution, bounds)
+            }
+        };
+
+        for cascade_fit in [
+            CascadeFit::Bounds,
+            CascadeFit::Sphere {
+                resolution: RESOLUTION,
+            },
+        ] {
+            assert!(fit(cascade_fit, None).project_point3(caster).z < 0.0);
+
+            let clip = fit(cascade_fit, Some(&bounds)).project_point3(caster);
+            assert!((0.0..=1.0).contains(&clip.z));
+            assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn casters_past_near_plane_keep_it() {
+        let light_view = light_view(glam::Vec3::ZERO, glam::Vec3::Z);
+        let behind = Aabb::from_points([glam::vec3(-1.0, -1.0, -8.0), glam::Vec3::ONE]);
+        let ahead = Aabb::from_points([glam::vec3(-1.0, -1.0, 2.0), glam::Vec3::splat(3.0)]);
+
+        assert_eq!(extend_near(&light_view, -2.0, None), -2.0);
+        assert_eq!(extend_near(&light_view, -2.0, Some(&behind)), -8.0);
+        assert_eq!(extend_near(&light_view, -2.0, Some(&ahead)), -2.0);
+    }
+
+    #[test]
+    fn cascade_regions_tile_the_shadow_map() {
+        let overlap = |a: &Region, b: &Region| {
+            a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+        };
+
+        for count in 1..=CASCADES_MAX {
+            for layout in [CascadeLayout::Atlas, CascadeLayout::Array] {
+                let desc = shadow_map_desc(
+                    layout,
+                    RESOLUTION,
+                    count,
+                    Format::D32,
+                    TextureUsages::DepthTarget,
+                );
+                let regions = (0..count)
+                    .map(|i| cascade_region(layout, RESOLUTION, count, i))
+                    .collect::<Vec<_>>();
+
+                for (i, region) in regions.iter().enumerate() {
+                    assert_eq!([region.w, region.h], [RESOLUTION, RESOLUTION]);
+                    assert!(region.x + region.w <= desc.extent[0]);
+                    assert!(region.y + region.h <= desc.extent[1]);
+
+                    // Array cascades share the region and differ by slice.
+                    for other in &regions[i + 1..] {
+                        assert_eq!(overlap(region, other), layout == CascadeLayout::Array);
+                    }
+                }
+
+                match layout {
+                    CascadeLayout::Atlas => {
+                        let [cols, rows] = atlas_grid(count);
+                        assert!(cols * rows >= count as u32);
+                        assert!((cols - 1) * rows < count as u32);
+                    }
+                    CascadeLayout::Array => assert_eq!(desc.extent[2], count as u32),
+                }
+            }
+        }
+    }
+
+    fn histogram(first: usize, last: usize) -> DepthHistogram {
+        let mut histogram = DepthHistogram::new(0.1, 100.0, 64);
+        histogram.bins[first] = 1;
+        histogram.bins[last] = 1;
+        histogram
+    }
+
+    #[test]
+    fn jittered_depth_histogram_keeps_schedule() {
+        let light_dir = glam::vec3(0.3, -1.0, 0.2).normalize();
+        let camera = camera(glam::Vec3::ZERO, 0.0);
+        let new = || {
+            CascadedShadowMaps::new(0.5, Some(50.0), 4)
+                .with_schedule(CascadeSchedule::new(&[1, 2, 4, 4], 4))
+        };
+
+        let mut reference = new();
+        let mut csm = new();
+        reference.set_depth_histogram(Some(&histogram(20, 40)));
+        csm.set_depth_histogram(Some(&histogram(20, 40)));
+        let splits = csm.splits;
+
+        for frame in 0..8 {
+            let jitter = frame % 2;
+            csm.set_depth_histogram(Some(&histogram(20 + jitter, 40 - jitter)));
+
+            assert_eq!(
+                csm.update(&camera, light_dir),
+                reference.update(&camera, light_dir)
+            );
+            assert_eq!(csm.splits, splits);
         }
     }
+
+    #[test]
+    fn moved_splits_refit_only_their_cascades() {
+        let light_dir = glam::vec3(0.3, -1.0, 0.2).normalize();
+        let camera = camera(glam::Vec3::ZERO, 0.0);
+        let mut csm = CascadedShadowMaps::new(1.0, Some(50.0), 4)
+            .with_schedule(CascadeSchedule::new(&[8, 8, 8, 8], 4));
+        csm.set_depth_histogram(Some(&histogram(20, 40)));
+        csm.update(&camera, light_dir);
+        let (near, before) = csm.splits.unwrap();
+
+        // Four bins further the first split moves by less than the tolerance,
+        // so the nearest cascade keeps its bounds. No cascade is due on this
+        // frame of the schedule.
+        csm.set_depth_histogram(Some(&histogram(20, 44)));
+        assert_eq!(cascades(csm.update(&camera, light_dir)), [1, 2, 3]);
+
+        let (moved_near, after) = csm.splits.unwrap();
+        assert_eq!(moved_near, near);
+        assert_eq!(after[0], before[0]);
+        assert!(after[1..4].iter().zip(&before[1..4]).all(|(a, b)| a > b));
+    }
 }
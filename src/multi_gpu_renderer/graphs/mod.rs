@@ -1,2 +1,199 @@
 pub mod multi_gpu;
 pub mod single_gpu;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use clap::Parser;
+    use hecs::World;
+
+    use crate::{
+        engine::camera::{Camera, CameraState},
+        multi_gpu_renderer::{
+            GpuGlobals, cpu_shaders::with_cpu_shaders, pso::PsoCollection,
+            shaders::ShaderCollection,
+        },
+        ra::{
+            backend::Backend,
+            command::RenderCommandContext,
+            resources::RenderResourceContext,
+            shader::{RenderShaderContext, ShaderArgumentDesc},
+            system::RenderSystem,
+        },
+        rhi::{
+            command::CommandType,
+            null::backend::NullBackend,
+            recording::{
+                backend::RecordingBackend,
+                trace::{FrameTrace, Recorder},
+            },
+            resources::{BufferDesc, BufferUsages, TextureDesc, TextureUsages},
+            types::Format,
+        },
+        settings::{CliRenderSettings, merge_settings},
+    };
+
+    use super::{multi_gpu::MultiGpuShadows, single_gpu::SingleGpuShadows};
+
+    const EXTENT: [u32; 2] = [32, 32];
+
+    // Renders one frame of each graph over an empty scene and returns the
+    // traces of both.
+    fn record_frames() -> (FrameTrace, FrameTrace) {
+        let settings = merge_settings(
+            CliRenderSettings::parse_from([
+                "fotia",
+                "--scene-path=",
+                "--asset-path=",
+                "--cascade-size=64",
+                "--cascades-count=2",
+            ]),
+            toml::from_str("").expect("failed to parse settings"),
+        );
+
+        let recorder = Arc::new(Recorder::new());
+        let backend = Backend::new(RecordingBackend::new(
+            with_cpu_shaders(NullBackend::new()),
+            Arc::clone(&recorder) as _,
+        ));
+        let rs = Arc::new(RenderSystem::new(&[]));
+        let group = Arc::new(backend.create_group(&[0, 1]));
+
+        let shaders =
+            ShaderCollection::new(&backend, false, &settings).expect("failed to compile shaders");
+        let psos = PsoCollection::new(Arc::clone(&rs), Arc::clone(&group), &shaders);
+
+        let globals_buffer = rs.create_buffer_handle();
+        let globals = rs.create_shader_argument_handle();
+        group.call(|ctx| {
+            ctx.bind_buffer(
+                globals_buffer,
+                BufferDesc::cpu_to_gpu(
+                    size_of::<GpuGlobals>() * settings.frames_in_flight,
+                    BufferUsages::Uniform,
+                ),
+                None,
+            )
+            .expect("failed to bind buffer");
+            ctx.bind_shader_argument(
+                globals,
+                ShaderArgumentDesc {
+                    views: &[],
+                    samplers: &[],
+                    dynamic_buffer: Some(globals_buffer),
+                },
+            )
+            .expect("failed to bind shader argument");
+        });
+
+        let target = rs.create_texture_handle();
+        group
+            .primary()
+            .bind_texture(
+                target,
+                TextureDesc::new_2d(EXTENT, Format::Rgba8Unorm, TextureUsages::RenderTarget),
+                None,
+            )
+            .expect("failed to bind texture");
+
+        let mut single = SingleGpuShadows::new(
+            Arc::clone(&rs),
+            Arc::clone(group.primary()),
+            EXTENT,
+            &psos,
+            &settings,
+        )
+        .expect("failed to create single gpu graph");
+        let mut multi = MultiGpuShadows::new(
+            Arc::clone(&rs),
+            Arc::clone(&group),
+            EXTENT,
+            &psos,
+            &settings,
+            None,
+        )
+        .expect("failed to create multi gpu graph");
+
+        let world = World::new();
+        let camera = Camera {
+            far: 100.0,
+            near: 0.1,
+            fov: 90.0f32.to_radians(),
+            aspect_ratio: 1.0,
+            view: glam::Mat4::IDENTITY,
+        };
+        let light_dir = glam::vec3(-1.0, -1.0, -1.0);
+
+        // Graphs only enqueue, the frame is committed the way `main` does it.
+        let finish = || {
+            group.call(|ctx| {
+                ctx.commit(
+                    ctx.create_encoder(CommandType::Graphics)
+                        .expect("failed to create encoder"),
+                );
+                ctx.submit(CommandType::Graphics).expect("failed to submit");
+            })
+        };
+
+        recorder.end_frame();
+
+        single
+            .update(&camera, light_dir, 0)
+            .expect("failed to update single gpu graph");
+        single
+            .render(&world, globals, target, &camera, 0)
+            .expect("failed to render single gpu graph");
+        finish();
+        let single_trace = recorder.end_frame();
+
+        multi.update(CameraState {
+            position: glam::Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+        });
+        multi
+            .render(&world, globals, target, &camera, light_dir, 0)
+            .expect("failed to render multi gpu graph");
+        finish();
+        let multi_trace = recorder.end_frame();
+
+        (single_trace, multi_trace)
+    }
+
+    #[test]
+    fn multi_gpu_moves_shadows_to_the_secondary() {
+        let (single, multi) = record_frames();
+
+        let single = single.passes().collect::<Vec<_>>();
+        let multi = multi.passes().collect::<Vec<_>>();
+
+        assert!(single.iter().all(|(device, _)| *device == 0));
+
+        // The same passes run in the same order once the shadow work that
+        // moved to the secondary is taken out.
+        let primary = multi
+            .iter()
+            .filter(|(device, _)| *device == 0)
+            .map(|(_, label)| *label)
+            .collect::<Vec<_>>();
+        let secondary = multi
+            .iter()
+            .filter(|(device, _)| *device == 1)
+            .map(|(_, label)| *label)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            single
+                .iter()
+                .map(|(_, label)| *label)
+                .filter(|label| *label != "Cascaded Shadow Maps")
+                .collect::<Vec<_>>(),
+            primary
+        );
+        assert_eq!(
+            secondary,
+            ["Cascaded Shadow Maps", "Copy Cascades", "Push CSM"]
+        );
+    }
+}
@@ -5,93 +5,205 @@ use tracing::info;
 
 use crate::{
     TimingsInfo,
-    collections::{handle::Handle, rwc_ring_buffer::RwcState},
-    engine::camera::Camera,
+    collections::handle::Handle,
+    engine::{
+        Aabb,
+        camera::{Camera, CameraPredictor, CameraState},
+    },
     multi_gpu_renderer::{
         passes::{
-            directional_light_pass::DirectionalLightPass, gamma_corr_pass::GammaCorrectionPass,
-            gpass::GPass, m_csm::MultiCascadedShadowMapsPass, zpass::ZPass,
+            depth_reduction::DepthReductionPass, directional_light_pass::DirectionalLightPass,
+            forward_pass::ForwardPass, gamma_corr_pass::GammaCorrectionPass, gpass::GPass,
+            m_csm::MultiCascadedShadowMapsPass, zpass::ZPass,
         },
         pso::PsoCollection,
     },
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, TransferEncoder},
-        context::{ContextDual, RenderDevice},
+        command::{RenderCommandContext, RenderCommandEncoder},
+        context::{Context, ContextGroup, RenderDevice},
         resources::Texture,
         shader::ShaderArgument,
         system::RenderSystem,
+        transient::TransientTexturePool,
     },
-    rhi::{
-        command::{CommandType, Subresource},
-        types::ResourceState,
-    },
-    settings::RenderSettings,
+    render_graph::{RenderGraph, TextureSlots, channel::CrossDeviceChannel},
+    rhi::{command::CommandType, error::RenderError, types::Timings},
+    settings::{CascadeLayout, RenderSettings},
 };
 
 pub struct MultiGpuShadows<D: RenderDevice> {
-    pub ctx: Arc<ContextDual<D>>,
+    pub ctx: Arc<ContextGroup<D>>,
+    pub gbuffer: CrossDeviceChannel<D>,
+    pub shadows: CrossDeviceChannel<D>,
+    pub accum: CrossDeviceChannel<D>,
+    pub depth: Handle<Texture>,
+    pub transients: TransientTexturePool<D>,
     pub zpass: ZPass<D>,
     pub csm: MultiCascadedShadowMapsPass<D>,
     pub gpass: GPass<D>,
     pub dir_pass: DirectionalLightPass<D>,
+    pub forward_pass: ForwardPass<D>,
     pub final_pass: GammaCorrectionPass<D>,
+    pub reduction: DepthReductionPass<D>,
+    pub sdsm: bool,
+    pub predictor: CameraPredictor,
+    pub frame: usize,
     pub sender: Option<std::sync::mpsc::Sender<TimingsInfo>>,
 }
 
 impl<D: RenderDevice> MultiGpuShadows<D> {
     pub fn new(
         rs: Arc<RenderSystem>,
-        ctx: Arc<ContextDual<D>>,
+        ctx: Arc<ContextGroup<D>>,
         extent: [u32; 2],
         psos: &PsoCollection<D>,
         settings: &RenderSettings,
         sender: Option<std::sync::mpsc::Sender<TimingsInfo>>,
-    ) -> Self {
-        let zpass = ZPass::new(Arc::clone(&rs), Arc::clone(&ctx.primary), extent, psos);
-        let csm =
-            MultiCascadedShadowMapsPass::new(Arc::clone(&rs), Arc::clone(&ctx), settings, psos);
+    ) -> Result<Self, RenderError> {
+        let device = |index: usize| -> Result<Arc<Context<D>>, RenderError> {
+            ctx.get(index)
+                .cloned()
+                .ok_or(RenderError::NotEnoughDevices {
+                    required: index + 1,
+                    found: ctx.len(),
+                })
+        };
+
+        let lighting = device(settings.light_device)?;
+        let depth = settings.frames_in_flight.min(3);
+
+        let gbuffer = CrossDeviceChannel::new(
+            &rs,
+            "GBuffer",
+            device(settings.gpass_device)?,
+            Arc::clone(&lighting),
+            &GPass::<D>::gbuffer_desc(extent),
+            depth,
+        )?;
+
+        // Cross-adapter textures are limited to a single slice, so shadow maps
+        // that travel between devices always use the atlas layout.
+        let shadows_device = device(settings.csm_device)?;
+        let shadows_layout = if Arc::ptr_eq(&shadows_device, &lighting) {
+            settings.cascade_layout
+        } else {
+            CascadeLayout::Atlas
+        };
+
+        let shadows = CrossDeviceChannel::new(
+            &rs,
+            "CSM",
+            shadows_device,
+            Arc::clone(&lighting),
+            &[MultiCascadedShadowMapsPass::<D>::shadow_map_desc(
+                settings,
+                shadows_layout,
+            )],
+            depth,
+        )?;
 
-        let gpass = GPass::new(
+        let accum = CrossDeviceChannel::new(
+            &rs,
+            "Accumulation",
+            lighting,
+            Arc::clone(ctx.primary()),
+            &[DirectionalLightPass::<D>::accum_desc(extent)],
+            depth,
+        )?;
+
+        let zpass = ZPass::new(
             Arc::clone(&rs),
-            Arc::clone(&ctx.primary),
+            Arc::clone(gbuffer.producer()),
             extent,
-            zpass.depth,
             psos,
         );
+        let csm = MultiCascadedShadowMapsPass::new(
+            Arc::clone(&rs),
+            &shadows,
+            settings,
+            shadows_layout,
+            psos,
+        )?;
+
+        let gpass = GPass::new(Arc::clone(&rs), extent, &gbuffer, psos)?;
 
         let dir_pass = DirectionalLightPass::new(
             Arc::clone(&rs),
-            Arc::clone(&ctx.primary),
             extent,
-            gpass.diffuse_srv,
-            gpass.normal_srv,
-            gpass.material_srv,
-            gpass.accum,
+            &gbuffer,
+            &accum,
             settings.frames_in_flight,
+            shadows_layout,
             psos,
-        );
+        )?;
 
-        let final_pass = GammaCorrectionPass::new(
+        let forward_pass = ForwardPass::new(
             Arc::clone(&rs),
-            Arc::clone(&ctx.primary),
+            extent,
+            &gbuffer,
+            &accum,
+            &(0..shadows.depth())
+                .map(|slot| shadows.views(slot)[0])
+                .collect::<Vec<_>>(),
+            csm.gpu_csm_buffer,
+            dir_pass.light_data,
+            shadows_layout,
             psos,
-            gpass.accum_srv,
+        )?;
+
+        let final_pass = GammaCorrectionPass::new(Arc::clone(&rs), psos, &accum, extent)?;
+        let reduction = DepthReductionPass::new(
+            Arc::clone(&rs),
+            Arc::clone(gbuffer.producer()),
             extent,
+            gbuffer.depth(),
+            psos,
+        )?;
+
+        // The gbuffer and accumulation outlive a frame while their slots are
+        // copied between devices, only the depth is transient here.
+        let mut transients = TransientTexturePool::new(
+            rs,
+            Arc::clone(gbuffer.producer()),
+            settings.frames_in_flight,
         );
+        let depth = transients.create_texture(ZPass::<D>::depth_desc(extent))?;
 
-        Self {
+        Ok(Self {
+            depth,
+            transients,
             ctx,
+            gbuffer,
+            shadows,
+            accum,
             zpass,
             csm,
             gpass,
             dir_pass,
+            forward_pass,
             final_pass,
+            reduction,
+            sdsm: settings.sdsm,
+            predictor: CameraPredictor::new(4, settings.camera_prediction),
+            frame: 0,
             sender,
-        }
+        })
     }
 
-    pub fn update(&mut self, _camera: &Camera, _light_dir: glam::Vec3, _frame_index: usize) {
-        //self.csm.update(camera, light_dir, frame_index);
+    pub fn update(&mut self, state: CameraState) {
+        let Some(error) = self.predictor.push(state) else {
+            return;
+        };
+
+        if let Some(sdr) = &mut self.sender {
+            sdr.send(TimingsInfo::CameraPredictionError {
+                position: error.position,
+                angle: error.angle,
+            })
+            .expect("failed to send");
+        } else {
+            info!("Camera prediction error: {:?}", error);
+        }
     }
 
     pub fn render(
@@ -102,139 +214,140 @@ impl<D: RenderDevice> MultiGpuShadows<D> {
         camera: &Camera,
         light_dir: glam::Vec3,
         frame_idx: usize,
-    ) {
-        if self.ctx.secondary.is_ready(CommandType::Graphics)
-            && self.csm.shared.head_state() == RwcState::WaitForWrite
-        {
-            self.csm.update(camera, light_dir);
-
-            self.ctx.call_secondary(|ctx| {
-                let mut cmd = ctx.create_encoder(CommandType::Graphics);
-                let timings = cmd.begin(ctx);
-
-                if let Some(sdr) = &mut self.sender {
-                    if let Some(timings) = timings {
-                        sdr.send(TimingsInfo::SecondaryMultiGpu(timings))
-                            .expect("failed to send");
-                    }
-                } else {
-                    info!("Secondary Timings: {:?}", timings);
-                }
+    ) -> Result<(), RenderError> {
+        self.frame += 1;
+
+        let timings = self.shadows.pull()?;
+        self.report(timings, TimingsInfo::PrimaryCopyMultiGpu, "Copy");
+        self.gbuffer.pull()?;
+        self.accum.pull()?;
 
-                ctx.enqueue(cmd);
-
-                self.csm.render(world);
-
-                let mut cmd = ctx.create_encoder(CommandType::Graphics);
-
-                cmd.set_barriers(&[
-                    Barrier::Texture(
-                        *self.csm.shared.head_data(),
-                        ResourceState::CopySrc,
-                        Subresource::Local(None),
-                    ),
-                    Barrier::Texture(
-                        *self.csm.shared.head_data(),
-                        ResourceState::CopyDst,
-                        Subresource::Shared,
-                    ),
-                ]);
-                {
-                    let encoder = cmd.transfer("Push CSM".into());
-                    encoder.push_texture(*self.csm.shared.head_data());
+        if let Some(slot) = self.gbuffer.acquire_write() {
+            // A writable slot has finished on the GPU, so its readback holds
+            // the depth range of the frame it produced last time.
+            if self.sdsm {
+                let far = self.csm.csm.shadow_far.unwrap_or(camera.far);
+                if let Some(histogram) = self.reduction.resolve(slot, camera.near, far)? {
+                    self.csm.csm.set_depth_histogram(Some(&histogram));
                 }
+            }
 
-                ctx.commit(cmd);
+            let depth = self.depth;
 
-                self.csm
-                    .shared
-                    .update_head_state(RwcState::WaitForCopy(ctx.submit(CommandType::Graphics)));
-            });
-        }
+            if self.sdsm {
+                self.reduction.prepare(depth, slot)?;
+            }
 
-        if let RwcState::WaitForCopy(v) = self.csm.shared.tail_state() {
-            if self.ctx.primary.is_ready(CommandType::Transfer)
-                && self.ctx.secondary.is_ready_for(CommandType::Graphics, v)
-            {
-                self.csm.shared.advance_head();
-
-                self.ctx.call_primary(|ctx| {
-                    let mut cmd = ctx.create_encoder(CommandType::Transfer);
-                    let timings = cmd.begin(ctx);
-
-                    if let Some(sdr) = &mut self.sender {
-                        if let Some(timings) = timings {
-                            sdr.send(TimingsInfo::PrimaryCopyMultiGpu(timings))
-                                .expect("failed to send");
-                        }
-                    } else {
-                        info!("Copy Timings: {:?}", timings);
-                    }
-
-                    cmd.set_barriers(&[
-                        Barrier::Texture(
-                            *self.csm.shared.tail_data(),
-                            ResourceState::CopyDst,
-                            Subresource::Local(None),
-                        ),
-                        Barrier::Texture(
-                            *self.csm.shared.tail_data(),
-                            ResourceState::CopySrc,
-                            Subresource::Shared,
-                        ),
-                    ]);
-                    {
-                        let encoder = cmd.transfer("Pull CSM".into());
-                        encoder.pull_texture(*self.csm.shared.tail_data());
-                    }
-
-                    ctx.commit(cmd);
-                    self.csm.shared.update_tail_state(RwcState::WaitForRead(
-                        ctx.submit(CommandType::Transfer),
-                    ));
-                });
+            let mut graph = RenderGraph::new();
+
+            self.zpass
+                .add_to_graph(&mut graph, globals, depth, frame_idx, world);
+            self.gpass
+                .add_to_graph(&mut graph, globals, depth, frame_idx, world, slot);
+            if self.sdsm {
+                self.reduction
+                    .add_to_graph(&mut graph, globals, depth, frame_idx, slot);
             }
+
+            if self.transients.allocate(&mut graph)? {
+                self.reduction.bind_depth()?;
+            }
+
+            self.gbuffer.produce(slot, graph)?;
         }
 
-        self.zpass.render(globals, frame_idx, world);
+        if let Some(slot) = self.shadows.acquire_write() {
+            let latency = self.shadows.metrics().last_latency.unwrap_or_default();
+            let predicted = self.predictor.predict_camera(camera, latency);
+            let error = self.predictor.last_error().map(|error| error.angle);
+            self.csm
+                .update(&predicted, light_dir, slot, self.frame, latency, error)?;
 
-        self.gpass.render(globals, frame_idx, world);
+            let producer = Arc::clone(self.shadows.producer());
+            if !Arc::ptr_eq(&producer, self.ctx.primary()) {
+                let mut cmd = producer.create_encoder(CommandType::Graphics)?;
+                let timings = cmd.begin(&producer);
+                producer.enqueue(cmd);
 
-        let copy_texture = if let RwcState::WaitForRead(v) = self.csm.shared.tail_state() {
-            if self.ctx.primary.is_ready_for(CommandType::Transfer, v) {
-                self.csm.shared.update_tail_state(RwcState::WaitForWrite);
-                let csm = *self.csm.shared.tail_data();
-                Some(csm)
-            } else {
-                None
+                self.report(timings, TimingsInfo::SecondaryMultiGpu, "Secondary");
             }
-        } else {
-            None
-        };
 
-        let (csm, idx) = match copy_texture {
-            Some(texture) => {
-                let idx = self.csm.shared.tail;
-                self.csm.shared.advance_tail();
-                (texture, idx)
-            }
-            None => {
-                let idx = self.csm.shared.tip_index();
-                let texture = *self.csm.shared.tip_data();
-                (texture, idx)
-            }
-        };
+            let mut graph = RenderGraph::new();
+            self.csm.add_to_graph(&mut graph, world, frame_idx, slot);
+
+            self.shadows
+                .produce_regions(slot, graph, &self.csm.dirty_regions())?;
+        }
+
+        if let Some(slot) = self.accum.acquire_write() {
+            let gbuffer_slot = self.gbuffer.acquire_read();
+            let shadows_slot = self.shadows.acquire_read();
 
-        self.dir_pass
-            .render(globals, csm, self.csm.argument[idx], frame_idx, idx);
+            let mut graph = RenderGraph::new();
 
-        self.final_pass.render(swapchain_view);
+            self.gbuffer.import(&mut graph, gbuffer_slot);
+            self.shadows.import(&mut graph, shadows_slot);
+
+            self.dir_pass.add_to_graph(
+                &mut graph,
+                globals,
+                self.shadows.views(shadows_slot)[0],
+                self.csm.argument[shadows_slot],
+                frame_idx,
+                shadows_slot,
+                gbuffer_slot,
+                slot,
+            );
+            self.forward_pass.add_to_graph(
+                &mut graph,
+                globals,
+                world,
+                camera,
+                frame_idx,
+                shadows_slot,
+                gbuffer_slot,
+                shadows_slot,
+                slot,
+            );
+
+            self.accum.produce(slot, graph)?;
+        }
+
+        let slot = self.accum.acquire_read();
+        let mut graph = RenderGraph::new();
+
+        self.accum.import(&mut graph, slot);
+        self.final_pass
+            .add_to_graph(&mut graph, swapchain_view, slot);
+
+        graph.export_texture(swapchain_view, None);
+        graph.execute(self.ctx.primary())
+    }
+
+    pub fn set_scene_bounds(&mut self, bounds: Option<Aabb>) {
+        self.csm.csm.set_scene_bounds(bounds);
     }
 
-    pub fn resize(&mut self, extent: [u32; 2]) {
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
+        self.gbuffer.resize(extent)?;
+        self.accum.resize(extent)?;
+        self.transients.resize(extent)?;
+
         self.zpass.resize(extent);
         self.gpass.resize(extent);
-        self.dir_pass.resize(extent);
-        self.final_pass.resize(extent);
+        self.reduction.resize(extent)?;
+        self.dir_pass.resize(extent)?;
+        self.forward_pass.resize(extent)?;
+        self.final_pass.resize(extent)
+    }
+
+    fn report(&mut self, timings: Option<Timings>, kind: fn(Timings) -> TimingsInfo, label: &str) {
+        if let Some(sdr) = &mut self.sender {
+            if let Some(timings) = timings {
+                sdr.send(kind(timings)).expect("failed to send");
+            }
+        } else {
+            info!("{} Timings: {:?}", label, timings);
+        }
     }
 }
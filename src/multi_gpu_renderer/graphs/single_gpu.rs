@@ -4,10 +4,11 @@ use hecs::World;
 
 use crate::{
     collections::handle::Handle,
-    engine::camera::Camera,
+    engine::{Aabb, camera::Camera},
     multi_gpu_renderer::{
         passes::{
-            csm::CascadedShadowMapsPass, directional_light_pass::DirectionalLightPass,
+            csm::CascadedShadowMapsPass, depth_reduction::DepthReductionPass,
+            directional_light_pass::DirectionalLightPass, forward_pass::ForwardPass,
             gamma_corr_pass::GammaCorrectionPass, gpass::GPass, zpass::ZPass,
         },
         pso::PsoCollection,
@@ -17,17 +18,27 @@ use crate::{
         resources::Texture,
         shader::ShaderArgument,
         system::RenderSystem,
+        transient::{TransientTargets, TransientTexturePool},
     },
+    render_graph::RenderGraph,
+    rhi::error::RenderError,
     settings::RenderSettings,
 };
 
 pub struct SingleGpuShadows<D: RenderDevice> {
     pub ctx: Arc<Context<D>>,
+    pub depth: Handle<Texture>,
+    pub gbuffer: TransientTargets<D>,
+    pub accum: TransientTargets<D>,
+    pub transients: TransientTexturePool<D>,
     pub zpass: ZPass<D>,
     pub csm: CascadedShadowMapsPass<D>,
     pub gpass: GPass<D>,
     pub dir_pass: DirectionalLightPass<D>,
+    pub forward_pass: ForwardPass<D>,
     pub final_pass: GammaCorrectionPass<D>,
+    pub reduction: DepthReductionPass<D>,
+    pub sdsm: bool,
 }
 
 impl<D: RenderDevice> SingleGpuShadows<D> {
@@ -37,74 +48,146 @@ impl<D: RenderDevice> SingleGpuShadows<D> {
         extent: [u32; 2],
         psos: &PsoCollection<D>,
         settings: &RenderSettings,
-    ) -> Self {
+    ) -> Result<Self, RenderError> {
         let zpass = ZPass::new(Arc::clone(&rs), Arc::clone(&ctx), extent, psos);
-        let csm = CascadedShadowMapsPass::new(Arc::clone(&rs), Arc::clone(&ctx), settings, psos);
+        let csm = CascadedShadowMapsPass::new(Arc::clone(&rs), Arc::clone(&ctx), settings, psos)?;
 
-        let gpass = GPass::new(Arc::clone(&rs), Arc::clone(&ctx), extent, zpass.depth, psos);
+        let mut transients =
+            TransientTexturePool::new(Arc::clone(&rs), Arc::clone(&ctx), settings.frames_in_flight);
+        let depth = transients.create_texture(ZPass::<D>::depth_desc(extent))?;
+        let gbuffer = transients.create_targets(&GPass::<D>::gbuffer_desc(extent))?;
+        let accum = transients.create_targets(&[DirectionalLightPass::<D>::accum_desc(extent)])?;
+
+        let gpass = GPass::new(Arc::clone(&rs), extent, &gbuffer, psos)?;
 
         let dir_pass = DirectionalLightPass::new(
             Arc::clone(&rs),
-            Arc::clone(&ctx),
             extent,
-            gpass.diffuse_srv,
-            gpass.normal_srv,
-            gpass.material_srv,
-            gpass.accum,
+            &gbuffer,
+            &accum,
             settings.frames_in_flight,
+            settings.cascade_layout,
             psos,
-        );
+        )?;
 
-        let final_pass = GammaCorrectionPass::new(
+        let forward_pass = ForwardPass::new(
             Arc::clone(&rs),
-            Arc::clone(&ctx),
+            extent,
+            &gbuffer,
+            &accum,
+            &[csm.srv],
+            csm.gpu_csm_buffer,
+            dir_pass.light_data,
+            settings.cascade_layout,
             psos,
-            gpass.accum_srv,
+        )?;
+
+        let final_pass = GammaCorrectionPass::new(Arc::clone(&rs), psos, &accum, extent)?;
+
+        let reduction = DepthReductionPass::new(
+            Arc::clone(&rs),
+            Arc::clone(&ctx),
             extent,
-        );
+            settings.frames_in_flight,
+            psos,
+        )?;
 
-        Self {
+        Ok(Self {
             ctx,
+            depth,
+            gbuffer,
+            accum,
+            transients,
             zpass,
             csm,
             gpass,
             dir_pass,
+            forward_pass,
             final_pass,
-        }
+            reduction,
+            sdsm: settings.sdsm,
+        })
     }
 
-    pub fn update(&mut self, camera: &Camera, light_dir: glam::Vec3, frame_index: usize) {
-        self.csm.update(camera, light_dir, frame_index);
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        light_dir: glam::Vec3,
+        frame_index: usize,
+    ) -> Result<(), RenderError> {
+        if self.sdsm {
+            let far = self.csm.csm.shadow_far.unwrap_or(camera.far);
+            if let Some(histogram) = self.reduction.resolve(frame_index, camera.near, far)? {
+                self.csm.csm.set_depth_histogram(Some(&histogram));
+            }
+        }
+
+        self.csm.update(camera, light_dir, frame_index)
     }
 
     pub fn render(
-        &self,
+        &mut self,
         world: &World,
         globals: Handle<ShaderArgument>,
         swapchain_view: Handle<Texture>,
+        camera: &Camera,
         frame_idx: usize,
-    ) {
-        self.zpass.render(globals, frame_idx, world);
+    ) -> Result<(), RenderError> {
+        let depth = self.depth;
 
-        self.csm.render(frame_idx, world);
+        if self.sdsm {
+            self.reduction.prepare(depth, frame_idx)?;
+        }
 
-        self.gpass.render(globals, frame_idx, world);
+        let mut graph = RenderGraph::new();
 
-        self.dir_pass.render(
+        self.zpass
+            .add_to_graph(&mut graph, globals, depth, frame_idx, world);
+        self.csm.add_to_graph(&mut graph, frame_idx, world);
+        self.gpass
+            .add_to_graph(&mut graph, globals, depth, frame_idx, world, 0);
+        if self.sdsm {
+            self.reduction
+                .add_to_graph(&mut graph, globals, depth, frame_idx, frame_idx);
+        }
+        self.dir_pass.add_to_graph(
+            &mut graph,
             globals,
             self.csm.srv,
             self.csm.argument,
             frame_idx,
             frame_idx,
+            0,
+            0,
+        );
+        self.forward_pass.add_to_graph(
+            &mut graph, globals, world, camera, frame_idx, frame_idx, 0, 0, 0,
         );
+        self.final_pass.add_to_graph(&mut graph, swapchain_view, 0);
 
-        self.final_pass.render(swapchain_view);
+        if self.transients.allocate(&mut graph)? {
+            self.reduction.bind_depth()?;
+            self.dir_pass.bind_arguments()?;
+            self.forward_pass.bind_arguments()?;
+            self.final_pass.bind_arguments()?;
+        }
+
+        graph.export_texture(swapchain_view, None);
+        graph.execute(&self.ctx)
     }
 
-    pub fn resize(&mut self, extent: [u32; 2]) {
+    pub fn set_scene_bounds(&mut self, bounds: Option<Aabb>) {
+        self.csm.csm.set_scene_bounds(bounds);
+    }
+
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
+        self.transients.resize(extent)?;
+
         self.zpass.resize(extent);
         self.gpass.resize(extent);
-        self.dir_pass.resize(extent);
-        self.final_pass.resize(extent);
+        self.reduction.resize(extent)?;
+        self.dir_pass.resize(extent)?;
+        self.forward_pass.resize(extent)?;
+        self.final_pass.resize(extent)
     }
 }
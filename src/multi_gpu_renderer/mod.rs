@@ -1,14 +1,17 @@
-use glam::vec3;
+use std::collections::HashSet;
+
 use hecs::World;
 
 use crate::{
     collections::handle::Handle,
     engine::{
+        Aabb, AlphaBlendComponent, AlphaTestComponent, BoundsComponent, GlobalTransformComponent,
         GpuMaterial, GpuMaterialComponent, GpuMeshComponent, GpuTransform, GpuTransformComponent,
-        gltf::{GltfScene, ImageSource},
+        InstancesComponent, ParentComponent,
+        gltf::{AlphaMode, GltfScene, Material, TextureRef},
     },
     ra::{
-        context::{ContextDual, RenderDevice},
+        context::{ContextGroup, RenderDevice},
         resources::{RenderResourceContext, Texture},
         shader::{RenderShaderContext, ShaderArgumentDesc, ShaderEntry},
         system::RenderSystem,
@@ -20,6 +23,7 @@ use crate::{
     settings::RenderSettings,
 };
 
+pub mod cpu_shaders;
 pub mod csm;
 pub mod graphs;
 pub mod passes;
@@ -50,7 +54,7 @@ pub struct TexturePlaceholders {
 }
 
 impl TexturePlaceholders {
-    pub fn new<D: RenderDevice>(rs: &RenderSystem, group: &ContextDual<D>) -> Self {
+    pub fn new<D: RenderDevice>(rs: &RenderSystem, group: &ContextGroup<D>) -> Self {
         let diffuse = rs.create_texture_handle();
         let normal = rs.create_texture_handle();
 
@@ -60,14 +64,16 @@ impl TexturePlaceholders {
                 TextureDesc::new_2d([1, 1], Format::Rgba8Unorm, TextureUsages::Resource)
                     .with_name("Diffuse Placeholder".into()),
                 Some(&[255, 255, 255, 255]),
-            );
+            )
+            .expect("failed to bind texture");
 
             ctx.bind_texture(
                 normal,
                 TextureDesc::new_2d([1, 1], Format::Rgba8Unorm, TextureUsages::Resource)
                     .with_name("Normal Placeholder".into()),
                 Some(&[127, 127, 255, 255]),
-            );
+            )
+            .expect("failed to bind texture");
         });
 
         Self { diffuse, normal }
@@ -78,11 +84,12 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
     scene: GltfScene,
     world: &mut World,
     rs: &RenderSystem,
-    group: &ContextDual<D>,
+    group: &ContextGroup<D>,
     settings: &RenderSettings,
     dummy: &TexturePlaceholders,
 ) {
     let prepared = scene.prepare(rs);
+    let batches = scene.batches();
 
     group.parallel(|ctx| {
         ctx.bind_buffer(
@@ -95,7 +102,8 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
                 memory_location: MemoryLocation::GpuToGpu,
             },
             Some(bytemuck::cast_slice(&scene.positions)),
-        );
+        )
+        .expect("failed to bind buffer");
 
         ctx.bind_buffer(
             prepared.indices,
@@ -107,32 +115,26 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
                 memory_location: MemoryLocation::GpuToGpu,
             },
             Some(bytemuck::cast_slice(&scene.indices)),
-        );
-
-        for (buffer, argument) in prepared.submeshes.iter() {
-            let data = (0..settings.frames_in_flight)
-                .map(|_| GpuTransform {
-                    mat: glam::Mat4::from_scale(vec3(
-                        settings.scene_scale,
-                        settings.scene_scale,
-                        settings.scene_scale,
-                    )),
-                })
-                .collect::<Vec<_>>();
+        )
+        .expect("failed to bind buffer");
 
+        // Filled every frame by `update_gpu_transforms`.
+        for ((buffer, argument), batch) in prepared.transforms.iter().zip(&batches) {
             ctx.bind_buffer(
                 *buffer,
                 BufferDesc {
                     name: Some("Object Position".into()),
-                    size: settings.frames_in_flight * size_of::<GpuTransform>(),
+                    size: GpuTransformComponent::buffer_size(
+                        batch.instances.len(),
+                        settings.frames_in_flight,
+                    ),
                     stride: 0,
                     usage: BufferUsages::Uniform,
                     memory_location: MemoryLocation::CpuToGpu,
                 },
                 None,
-            );
-
-            ctx.update_buffer(*buffer, 0, &data);
+            )
+            .expect("failed to bind buffer");
 
             ctx.bind_shader_argument(
                 *argument,
@@ -141,11 +143,12 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
                     samplers: &[],
                     dynamic_buffer: Some(*buffer),
                 },
-            );
+            )
+            .expect("failed to bind shader argument");
         }
     });
 
-    group.call_primary(|ctx| {
+    group.parallel(|ctx| {
         ctx.bind_buffer(
             prepared.normals,
             BufferDesc {
@@ -156,19 +159,21 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
                 memory_location: MemoryLocation::GpuToGpu,
             },
             Some(bytemuck::cast_slice(&scene.normals)),
-        );
+        )
+        .expect("failed to bind buffer");
 
         ctx.bind_buffer(
             prepared.uvs,
             BufferDesc {
                 name: Some("Uv Vertex Buffer".into()),
                 size: size_of_val(&scene.uvs[..]),
-                stride: size_of::<[f32; 2]>(),
+                stride: size_of::<[f32; 4]>(),
                 usage: BufferUsages::Vertex,
                 memory_location: MemoryLocation::GpuToGpu,
             },
             Some(bytemuck::cast_slice(&scene.uvs)),
-        );
+        )
+        .expect("failed to bind buffer");
 
         ctx.bind_buffer(
             prepared.tangents,
@@ -180,34 +185,31 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
                 memory_location: MemoryLocation::GpuToGpu,
             },
             Some(bytemuck::cast_slice(&scene.tangents)),
-        );
-
-        for (handle, image) in prepared.images.iter().zip(scene.images.iter()) {
-            match image {
-                ImageSource::Path(path) => {
-                    let image = image::open(&path).expect("failed to load png").to_rgba8();
-
-                    let filename = path
-                        .file_stem()
-                        .map(|n| n.to_string_lossy())
-                        .map(|n| n.to_string())
-                        .expect("failed to get filename");
-
-                    ctx.bind_texture(
-                        *handle,
-                        TextureDesc::new_2d(
-                            [image.width(), image.height()],
-                            Format::Rgba8Unorm,
-                            TextureUsages::Resource,
-                        )
-                        .with_name(filename.into()),
-                        Some(image.as_raw()),
-                    );
-                }
-                ImageSource::Data(_) => {
-                    todo!()
-                }
-            }
+        )
+        .expect("failed to bind buffer");
+
+        let srgb = scene
+            .materials
+            .iter()
+            .flat_map(Material::color_maps)
+            .flatten()
+            .map(|map| map.image)
+            .collect::<HashSet<_>>();
+
+        for (i, (handle, image)) in prepared.images.iter().zip(scene.images.iter()).enumerate() {
+            let format = if srgb.contains(&i) {
+                Format::Rgba8UnormSrgb
+            } else {
+                Format::Rgba8Unorm
+            };
+
+            ctx.bind_texture(
+                *handle,
+                TextureDesc::new_2d(image.extent, format, TextureUsages::Resource)
+                    .with_name(image.name.clone().into()),
+                Some(&image.pixels),
+            )
+            .expect("failed to bind texture");
         }
 
         for ((buffer, argument), material) in prepared.materials.iter().zip(scene.materials.iter())
@@ -222,61 +224,178 @@ pub fn create_multi_gpu_scene<D: RenderDevice>(
                     memory_location: MemoryLocation::CpuToGpu,
                 },
                 None,
-            );
+            )
+            .expect("failed to bind buffer");
 
             ctx.update_buffer(
                 *buffer,
                 0,
                 &[GpuMaterial {
-                    diffuse_color: material.diffuse_color,
-                    fresnel_r0: material.fresnel_r0,
+                    base_color: material.base_color,
+                    emissive: material.emissive,
+                    metallic: material.metallic,
                     roughness: material.roughness,
+                    normal_scale: material.normal_scale,
+                    occlusion_strength: material.occlusion_strength,
+                    alpha_cutoff: material.alpha_cutoff,
+                    alpha_mode: material.alpha_mode as u32,
+                    double_sided: material.double_sided as u32,
+                    base_color_uv: tex_coord(material.base_color_map),
+                    metallic_roughness_uv: tex_coord(material.metallic_roughness_map),
+                    normal_uv: tex_coord(material.normal_map),
+                    occlusion_uv: tex_coord(material.occlusion_map),
+                    emissive_uv: tex_coord(material.emissive_map),
                 }],
-            );
+            )
+            .expect("failed to update buffer");
+
+            // Missing textures sample white so the factors pass through,
+            // apart from the normal map which falls back to a flat normal.
+            let view = |map: Option<TextureRef>, placeholder| {
+                ShaderEntry::Srv(map.map_or(placeholder, |map| prepared.images[map.image]))
+            };
 
             ctx.bind_shader_argument(
                 *argument,
                 ShaderArgumentDesc {
                     views: &[
-                        ShaderEntry::Srv(
-                            material
-                                .diffuse_map
-                                .map(|idx| prepared.images[idx])
-                                .unwrap_or(dummy.diffuse),
-                        ),
-                        ShaderEntry::Srv(
-                            material
-                                .normal_map
-                                .map(|idx| prepared.images[idx])
-                                .unwrap_or(dummy.normal),
-                        ),
+                        view(material.base_color_map, dummy.diffuse),
+                        view(material.normal_map, dummy.normal),
+                        view(material.metallic_roughness_map, dummy.diffuse),
+                        view(material.occlusion_map, dummy.diffuse),
+                        view(material.emissive_map, dummy.diffuse),
                     ],
                     samplers: &[],
                     dynamic_buffer: Some(*buffer),
                 },
-            );
+            )
+            .expect("failed to bind shader argument");
         }
     });
 
-    for (mesh, (buffer, argument)) in scene.sub_meshes.iter().zip(prepared.submeshes) {
-        let material = prepared.materials[mesh.material_idx];
-
-        world.spawn((
-            GpuTransformComponent { buffer, argument },
-            GpuMeshComponent {
-                pos_vb: prepared.positions,
-                normal_vb: prepared.normals,
-                uv_vb: prepared.uvs,
-                tangent_vb: prepared.tangents,
-                ib: prepared.indices,
-                index_count: mesh.index_count,
-                start_index_location: mesh.start_index_location,
-                base_vertex_location: mesh.base_vertex_location,
-            },
-            GpuMaterialComponent {
-                buffer: material.0,
-                argument: material.1,
-            },
-        ));
+    let root = world.spawn((
+        GltfScene::root_transform(settings.scene_scale),
+        GlobalTransformComponent::default(),
+    ));
+
+    let mut nodes = Vec::with_capacity(scene.nodes.len());
+    for node in &scene.nodes {
+        let parent = node.parent.map_or(root, |parent| nodes[parent]);
+
+        nodes.push(world.spawn((
+            node.transform.clone(),
+            ParentComponent { entity: parent },
+            GlobalTransformComponent::default(),
+        )));
+    }
+
+    // `EXT_mesh_gpu_instancing` instances are children of their node.
+    let gpu_instances = scene
+        .nodes
+        .iter()
+        .zip(&nodes)
+        .map(|(node, entity)| {
+            node.instances
+                .iter()
+                .map(|transform| {
+                    world.spawn((
+                        transform.clone(),
+                        ParentComponent { entity: *entity },
+                        GlobalTransformComponent::default(),
+                    ))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // The submeshes of a batch share its transform buffer.
+    for (batch, (buffer, argument)) in batches.iter().zip(prepared.transforms) {
+        let entities = batch
+            .instances
+            .iter()
+            .map(|&(node, instance)| instance.map_or(nodes[node], |idx| gpu_instances[node][idx]))
+            .collect::<Vec<_>>();
+
+        for mesh in &scene.meshes[batch.mesh].submeshes {
+            let material = prepared.materials[mesh.material_idx];
+
+            let entity = world.spawn((
+                GpuTransformComponent {
+                    buffer,
+                    argument,
+                    instance_count: entities.len() as u32,
+                },
+                InstancesComponent {
+                    entities: entities.clone(),
+                },
+                GpuMeshComponent {
+                    pos_vb: prepared.positions,
+                    normal_vb: prepared.normals,
+                    uv_vb: prepared.uvs,
+                    tangent_vb: prepared.tangents,
+                    ib: prepared.indices,
+                    index_count: mesh.index_count,
+                    start_index_location: mesh.start_index_location,
+                    base_vertex_location: mesh.base_vertex_location,
+                },
+                GpuMaterialComponent {
+                    buffer: material.0,
+                    argument: material.1,
+                },
+                BoundsComponent {
+                    local: mesh.bounds,
+                    aabb: Aabb::EMPTY,
+                },
+            ));
+
+            let inserted = match scene.materials[mesh.material_idx].alpha_mode {
+                AlphaMode::Opaque => Ok(()),
+                AlphaMode::Mask => world.insert_one(entity, AlphaTestComponent),
+                AlphaMode::Blend => world.insert_one(entity, AlphaBlendComponent),
+            };
+            inserted.expect("failed to insert alpha component");
+        }
     }
 }
+
+// Expects `propagate_transforms` to have run for this frame.
+pub fn update_gpu_transforms<D: RenderDevice>(
+    world: &World,
+    group: &ContextGroup<D>,
+    frame_idx: usize,
+) {
+    group.call(|ctx| {
+        // The submeshes of a batch share a buffer.
+        let mut written = HashSet::new();
+
+        for (_, (transform, instances)) in world
+            .query::<(&GpuTransformComponent, &InstancesComponent)>()
+            .iter()
+        {
+            if !written.insert(transform.buffer) {
+                continue;
+            }
+
+            let mats = instances
+                .entities
+                .iter()
+                .map(|entity| GpuTransform {
+                    mat: world
+                        .get::<&GlobalTransformComponent>(*entity)
+                        .map_or(glam::Mat4::IDENTITY, |global| global.mat),
+                })
+                .collect::<Vec<_>>();
+
+            ctx.update_buffer(
+                transform.buffer,
+                GpuTransformComponent::frame_len(mats.len()) * frame_idx,
+                &mats,
+            )
+            .expect("failed to update transform buffer");
+        }
+    });
+}
+
+fn tex_coord(map: Option<TextureRef>) -> u32 {
+    map.map_or(0, |map| map.tex_coord)
+}
@@ -1,16 +1,23 @@
 use std::sync::Arc;
 
-use hecs::World;
+use hecs::{Or, World};
+use smallvec::SmallVec;
 
 use crate::{
     collections::handle::Handle,
-    engine::{GpuMeshComponent, GpuTransform, GpuTransformComponent, camera::Camera},
+    engine::{
+        AlphaBlendComponent, AlphaTestComponent, BoundsComponent, GpuMaterialComponent,
+        GpuMeshComponent, GpuTransformComponent, camera::Camera,
+    },
     multi_gpu_renderer::{
-        csm::{Cascade, CascadedShadowMaps, Cascades},
+        csm::{
+            Cascade, CascadeMask, CascadedShadowMaps, Cascades, cascade_region, cascade_slice_view,
+            cascade_viewport, shadow_map_desc,
+        },
         pso::PsoCollection,
     },
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder},
         context::{Context, RenderDevice},
         resources::{Buffer, RenderResourceContext, Texture},
         shader::{
@@ -18,14 +25,13 @@ use crate::{
         },
         system::RenderSystem,
     },
+    render_graph::{PassDesc, RenderGraph},
     rhi::{
-        command::{CommandType, Subresource},
-        resources::{
-            BufferDesc, BufferUsages, TextureDesc, TextureUsages, TextureViewDesc, TextureViewType,
-        },
-        types::{ClearColor, Format, GeomTopology, IndexType, ResourceState, Scissor, Viewport},
+        error::RenderError,
+        resources::{BufferDesc, BufferUsages, TextureUsages, TextureViewDesc, TextureViewType},
+        types::{ClearColor, Format, GeomTopology, IndexType, ResourceState, Scissor},
     },
-    settings::RenderSettings,
+    settings::{CascadeLayout, RenderSettings},
 };
 
 pub struct CascadedShadowMapsPass<D: RenderDevice> {
@@ -34,8 +40,10 @@ pub struct CascadedShadowMapsPass<D: RenderDevice> {
 
     pub size: u32,
     pub count: usize,
+    pub layout: CascadeLayout,
 
     pub csm: CascadedShadowMaps,
+    pub due: CascadeMask,
 
     pub gpu_csm_buffer: Handle<Buffer>,
     pub argument: Handle<ShaderArgument>,
@@ -45,8 +53,10 @@ pub struct CascadedShadowMapsPass<D: RenderDevice> {
 
     pub dsv: Handle<Texture>,
     pub srv: Handle<Texture>,
+    pub slices: SmallVec<[Handle<Texture>; 8]>,
 
     pub pso: Handle<RasterPipeline>,
+    pub alpha_test_pso: Handle<RasterPipeline>,
 }
 
 impl<D: RenderDevice> CascadedShadowMapsPass<D> {
@@ -55,7 +65,7 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
         ctx: Arc<Context<D>>,
         settings: &RenderSettings,
         psos: &PsoCollection<D>,
-    ) -> Self {
+    ) -> Result<Self, RenderError> {
         let dsv = rs.create_texture_handle();
         let srv = rs.create_texture_handle();
 
@@ -65,16 +75,36 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
         let argument = rs.create_shader_argument_handle();
         let local_argument = rs.create_shader_argument_handle();
 
+        let layout = settings.cascade_layout;
+
         ctx.bind_texture(
             dsv,
-            TextureDesc::new_2d(
-                [2 * settings.cascade_size, 2 * settings.cascade_size],
+            shadow_map_desc(
+                layout,
+                settings.cascade_size,
+                settings.cascades_count,
                 Format::D32,
                 TextureUsages::DepthTarget | TextureUsages::Resource,
             )
             .with_color(ClearColor::Depth(1.0)),
             None,
-        );
+        )?;
+
+        let slices = match layout {
+            CascadeLayout::Atlas => SmallVec::new(),
+            CascadeLayout::Array => (0..settings.cascades_count)
+                .map(|i| {
+                    let slice = rs.create_texture_handle();
+                    ctx.bind_texture_view(
+                        slice,
+                        dsv,
+                        cascade_slice_view(TextureViewType::DepthStencil, Format::D32, i),
+                    )?;
+
+                    Ok(slice)
+                })
+                .collect::<Result<_, RenderError>>()?,
+        };
 
         ctx.bind_texture_view(
             srv,
@@ -82,7 +112,7 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
             TextureViewDesc::default()
                 .with_view_type(TextureViewType::ShaderResource)
                 .with_format(Format::R32),
-        );
+        )?;
 
         ctx.bind_buffer(
             gpu_csm_buffer,
@@ -92,7 +122,7 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
             )
             .with_name("CSM Buffer".into()),
             None,
-        );
+        )?;
 
         ctx.bind_buffer(
             gpu_csm_proj_view_buffer,
@@ -102,7 +132,7 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
             )
             .with_name("CSM Proj View Buffer".into()),
             None,
-        );
+        )?;
 
         ctx.bind_shader_argument(
             argument,
@@ -111,7 +141,7 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
                 samplers: &[],
                 dynamic_buffer: Some(gpu_csm_buffer),
             },
-        );
+        )?;
 
         ctx.bind_shader_argument(
             local_argument,
@@ -120,36 +150,41 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
                 samplers: &[],
                 dynamic_buffer: Some(gpu_csm_proj_view_buffer),
             },
-        );
+        )?;
 
-        Self {
+        Ok(Self {
             rs,
             ctx,
             size: settings.cascade_size,
             count: settings.cascades_count,
-            csm: CascadedShadowMaps::new(
-                settings.cascades_lambda,
-                settings.shadows_far,
-                settings.cascades_count,
-            ),
+            layout,
+            csm: CascadedShadowMaps::from_settings(settings),
+            due: CascadeMask::all(settings.cascades_count),
             gpu_csm_buffer,
             argument,
             gpu_csm_proj_view_buffer,
             local_argument,
             dsv,
             srv,
+            slices,
             pso: psos.csm_pass,
-        }
+            alpha_test_pso: psos.csm_alpha_test_pass,
+        })
     }
 
-    pub fn update(&mut self, camera: &Camera, light_dir: glam::Vec3, frame_index: usize) {
-        self.csm.update(camera, light_dir);
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        light_dir: glam::Vec3,
+        frame_index: usize,
+    ) -> Result<(), RenderError> {
+        self.due = self.csm.update(camera, light_dir);
 
         self.ctx.update_buffer(
             self.gpu_csm_buffer,
             frame_index,
-            &[self.csm.cascades.clone()],
-        );
+            std::slice::from_ref(&self.csm.cascades),
+        )?;
 
         for i in 0..self.count {
             self.ctx.update_buffer(
@@ -158,69 +193,146 @@ impl<D: RenderDevice> CascadedShadowMapsPass<D> {
                 &[Cascade {
                     proj_view: self.csm.cascades.cascade_proj_views[i],
                 }],
-            );
+            )?;
         }
+
+        Ok(())
     }
 
-    pub fn render(&self, frame_idx: usize, world: &World) {
-        let mut cmd = self.ctx.create_encoder(CommandType::Graphics);
-        cmd.set_barriers(&[Barrier::Texture(
-            self.dsv,
-            ResourceState::DepthWrite,
-            Subresource::Local(None),
-        )]);
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        frame_idx: usize,
+        world: &'a World,
+    ) {
+        graph.alias_texture(self.srv, self.dsv);
+        for slice in &self.slices {
+            graph.alias_texture(*slice, self.dsv);
+        }
 
-        {
-            let mut encoder = cmd.render("Cascaded Shadow Maps".into(), &[], Some(self.dsv));
-            encoder.set_render_pipeline(self.pso);
-            encoder.clear_depth(self.dsv, None);
-            encoder.set_topology(GeomTopology::Triangles);
-
-            encoder.set_scissor(Scissor {
-                x: 0,
-                y: 0,
-                w: self.size * 2,
-                h: self.size * 2,
-            });
-
-            for i in 0..self.count {
-                let i = i as u32;
-
-                let row = i / 2;
-                let col = i % 2;
-                encoder.set_viewport(Viewport {
-                    x: (self.size * col) as f32,
-                    y: (self.size * row) as f32,
-                    w: self.size as f32,
-                    h: self.size as f32,
-                });
-
-                encoder.bind_shader_argument(
-                    0,
-                    self.local_argument,
-                    size_of::<Cascade>() * (frame_idx * self.count + i as usize),
-                );
-
-                for (_, (transform, mesh)) in world
-                    .query::<(&GpuTransformComponent, &GpuMeshComponent)>()
-                    .iter()
-                {
-                    encoder.bind_shader_argument(
-                        1,
-                        transform.argument,
-                        size_of::<GpuTransform>() * frame_idx,
-                    );
-                    encoder.bind_vertex_buffer(mesh.pos_vb, 0);
-                    encoder.bind_index_buffer(mesh.ib, IndexType::U32);
-                    encoder.draw_indexed(
-                        mesh.index_count,
-                        mesh.start_index_location,
-                        mesh.base_vertex_location,
-                    );
+        graph.add_pass(
+            PassDesc::new("Cascaded Shadow Maps")
+                .with_write_texture(self.dsv, ResourceState::DepthWrite),
+            move |cmd| self.render(cmd, frame_idx, world),
+        );
+    }
+
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        frame_idx: usize,
+        world: &World,
+    ) -> Result<(), RenderError> {
+        match self.layout {
+            CascadeLayout::Atlas => {
+                let mut encoder = cmd.render("Cascaded Shadow Maps".into(), &[], Some(self.dsv))?;
+
+                for i in self.due.iter() {
+                    encoder.clear_depth_region(
+                        self.dsv,
+                        None,
+                        cascade_region(self.layout, self.size, self.count, i),
+                    )?;
+                    self.render_cascade(&mut encoder, world, frame_idx, i)?;
+                }
+            }
+            CascadeLayout::Array => {
+                for i in self.due.iter() {
+                    let slice = &self.slices[i];
+                    let mut encoder =
+                        cmd.render(format!("Cascade {}", i).into(), &[], Some(*slice))?;
+                    encoder.clear_depth(*slice, None)?;
+
+                    self.render_cascade(&mut encoder, world, frame_idx, i)?;
                 }
             }
         }
 
-        self.ctx.enqueue(cmd);
+        Ok(())
+    }
+
+    fn render_cascade(
+        &self,
+        encoder: &mut impl RenderEncoder,
+        world: &World,
+        frame_idx: usize,
+        i: usize,
+    ) -> Result<(), RenderError> {
+        let viewport = cascade_viewport(self.layout, self.size, self.count, i);
+
+        encoder.set_render_pipeline(self.pso)?;
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.set_viewport(viewport);
+        encoder.set_scissor(Scissor {
+            x: viewport.x as i32,
+            y: viewport.y as i32,
+            w: self.size,
+            h: self.size,
+        });
+
+        let local_offset = size_of::<Cascade>() * (frame_idx * self.count + i);
+        encoder.bind_shader_argument(0, self.local_argument, local_offset)?;
+
+        let proj_view = self.csm.cascades.cascade_proj_views[i];
+
+        for (_, (transform, mesh, bounds)) in world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                Option<&BoundsComponent>,
+            )>()
+            .without::<&AlphaTestComponent>()
+            .without::<&AlphaBlendComponent>()
+            .iter()
+        {
+            if bounds.is_some_and(|bounds| !bounds.aabb.intersects_ortho(&proj_view)) {
+                continue;
+            }
+
+            encoder.bind_shader_argument(1, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
+        }
+
+        // Blended geometry casts cutout shadows as well.
+        encoder.set_render_pipeline(self.alpha_test_pso)?;
+        encoder.bind_shader_argument(0, self.local_argument, local_offset)?;
+
+        for (_, (transform, mesh, material, bounds)) in world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                &GpuMaterialComponent,
+                Option<&BoundsComponent>,
+            )>()
+            .with::<Or<&AlphaTestComponent, &AlphaBlendComponent>>()
+            .iter()
+        {
+            if bounds.is_some_and(|bounds| !bounds.aabb.intersects_ortho(&proj_view)) {
+                continue;
+            }
+
+            encoder.bind_shader_argument(1, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_shader_argument(2, material.argument, 0)?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_vertex_buffer(mesh.uv_vb, 1)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
+        }
+
+        Ok(())
     }
 }
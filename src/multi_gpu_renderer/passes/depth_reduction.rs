@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+
+use crate::{
+    collections::handle::Handle,
+    multi_gpu_renderer::{GpuGlobals, csm::DepthHistogram, pso::PsoCollection},
+    ra::{
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder, TransferEncoder},
+        context::{Context, RenderDevice},
+        resources::{Buffer, RenderResourceContext, Texture},
+        shader::{
+            RasterPipeline, RenderShaderContext, ShaderArgument, ShaderArgumentDesc, ShaderEntry,
+        },
+        system::RenderSystem,
+    },
+    render_graph::{PassDesc, RenderGraph},
+    rhi::{
+        error::RenderError,
+        resources::{
+            BufferDesc, BufferUsages, TextureDesc, TextureUsages, TextureViewDesc, TextureViewType,
+            buffer_row_pitch,
+        },
+        types::{ClearColor, Format, GeomTopology, ResourceState, Scissor, Viewport},
+    },
+};
+
+pub const DEPTH_TILE_SIZE: u32 = 16;
+pub const DEPTH_HISTOGRAM_BINS: usize = 64;
+
+// Reduces the prepass depth to per-tile view-space min/max and copies the
+// tiles into a readback buffer, the CPU reads it once the slot comes around
+// again so the histogram lags the rendered frame.
+pub struct DepthReductionPass<D: RenderDevice> {
+    pub rs: Arc<RenderSystem>,
+    pub ctx: Arc<Context<D>>,
+    pub pso: Handle<RasterPipeline>,
+
+    pub extent: [u32; 2],
+
+    pub tiles: Handle<Texture>,
+    pub depth_view: Handle<Texture>,
+    pub bound_depth: Option<Handle<Texture>>,
+    pub argument: Handle<ShaderArgument>,
+
+    pub readbacks: SmallVec<[Handle<Buffer>; 4]>,
+    pub pending: SmallVec<[bool; 4]>,
+}
+
+impl<D: RenderDevice> DepthReductionPass<D> {
+    pub fn new(
+        rs: Arc<RenderSystem>,
+        ctx: Arc<Context<D>>,
+        extent: [u32; 2],
+        slots: usize,
+        psos: &PsoCollection<D>,
+    ) -> Result<Self, RenderError> {
+        let pass = Self {
+            tiles: rs.create_texture_handle(),
+            depth_view: rs.create_texture_handle(),
+            bound_depth: None,
+            argument: rs.create_shader_argument_handle(),
+            readbacks: (0..slots).map(|_| rs.create_buffer_handle()).collect(),
+            pending: (0..slots).map(|_| false).collect(),
+            rs,
+            ctx,
+            pso: psos.depth_reduction_pass,
+            extent,
+        };
+
+        pass.bind_targets()?;
+
+        Ok(pass)
+    }
+
+    pub fn tiles_desc(extent: [u32; 2]) -> TextureDesc {
+        TextureDesc::new_2d(
+            tile_extent(extent),
+            Format::Rg32,
+            TextureUsages::RenderTarget | TextureUsages::Resource,
+        )
+        .with_name("Depth Tiles".into())
+        .with_color(ClearColor::Color([0.0, 0.0, 0.0, 0.0]))
+    }
+
+    pub fn prepare(&mut self, depth: Handle<Texture>, slot: usize) -> Result<(), RenderError> {
+        if self.bound_depth != Some(depth) {
+            self.bound_depth = Some(depth);
+            self.bind_depth()?;
+        }
+
+        self.pending[slot] = true;
+
+        Ok(())
+    }
+
+    // The depth comes from the transient pool, the view has to be recreated
+    // whenever the pool places the texture in new memory.
+    pub fn bind_depth(&self) -> Result<(), RenderError> {
+        let Some(depth) = self.bound_depth else {
+            return Ok(());
+        };
+
+        self.ctx.bind_texture_view(
+            self.depth_view,
+            depth,
+            TextureViewDesc::default()
+                .with_view_type(TextureViewType::ShaderResource)
+                .with_format(Format::R32),
+        )?;
+
+        self.ctx.bind_shader_argument(
+            self.argument,
+            ShaderArgumentDesc {
+                views: &[ShaderEntry::Srv(self.depth_view)],
+                samplers: &[],
+                dynamic_buffer: None,
+            },
+        )
+    }
+
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        globals: Handle<ShaderArgument>,
+        depth: Handle<Texture>,
+        frame_idx: usize,
+        slot: usize,
+    ) {
+        graph.alias_texture(self.depth_view, depth);
+
+        graph.add_pass(
+            PassDesc::new("Depth Reduction")
+                .with_read_texture(self.depth_view, ResourceState::Shader)
+                .with_write_texture(self.tiles, ResourceState::RenderTarget),
+            move |cmd| self.render(cmd, globals, frame_idx),
+        );
+
+        // Readback heaps never leave the copy destination state, so the
+        // buffer is not tracked by the graph.
+        let readback = self.readbacks[slot];
+        graph.add_pass(
+            PassDesc::new("Depth Readback")
+                .with_read_texture(self.tiles, ResourceState::CopySrc)
+                .with_side_effects(),
+            move |cmd| {
+                let encoder = cmd.transfer("Depth Readback".into());
+                encoder.copy_texture_to_buffer(self.tiles, readback)
+            },
+        );
+    }
+
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        globals: Handle<ShaderArgument>,
+        frame_idx: usize,
+    ) -> Result<(), RenderError> {
+        let [w, h] = tile_extent(self.extent);
+
+        let mut encoder = cmd.render("Depth Reduction".into(), &[self.tiles], None)?;
+        encoder.set_render_pipeline(self.pso)?;
+
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: w as f32,
+            h: h as f32,
+        });
+        encoder.set_scissor(Scissor { x: 0, y: 0, w, h });
+
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx)?;
+        encoder.bind_shader_argument(1, self.argument, 0)?;
+
+        encoder.draw(3, 0);
+
+        Ok(())
+    }
+
+    // Must only be called once the GPU work that last used the slot is done.
+    pub fn resolve(
+        &mut self,
+        slot: usize,
+        near: f32,
+        far: f32,
+    ) -> Result<Option<DepthHistogram>, RenderError> {
+        if !std::mem::take(&mut self.pending[slot]) {
+            return Ok(None);
+        }
+
+        let [w, h] = tile_extent(self.extent);
+        let pitch = buffer_row_pitch(&Self::tiles_desc(self.extent)) / size_of::<f32>();
+
+        let mut data = vec![0.0f32; pitch * h as usize];
+        self.ctx.read_buffer(self.readbacks[slot], 0, &mut data)?;
+
+        let mut histogram = DepthHistogram::new(near, far, DEPTH_HISTOGRAM_BINS);
+        for row in data.chunks_exact(pitch) {
+            for &depth in &row[..2 * w as usize] {
+                histogram.add(depth);
+            }
+        }
+
+        Ok(Some(histogram))
+    }
+
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
+        self.extent = extent;
+        self.bound_depth = None;
+        self.pending.fill(false);
+
+        self.bind_targets()
+    }
+
+    fn bind_targets(&self) -> Result<(), RenderError> {
+        let desc = Self::tiles_desc(self.extent);
+        let size = buffer_row_pitch(&desc) * desc.extent[1] as usize;
+
+        self.ctx.bind_texture(self.tiles, desc, None)?;
+
+        for readback in &self.readbacks {
+            self.ctx.bind_buffer(
+                *readback,
+                BufferDesc::gpu_to_cpu(size, BufferUsages::Copy)
+                    .with_name("Depth Readback Buffer".into()),
+                None,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn tile_extent(extent: [u32; 2]) -> [u32; 2] {
+    extent.map(|e| e.div_ceil(DEPTH_TILE_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ra::backend::Backend,
+        rhi::null::{backend::NullBackend, device::NullDevice},
+    };
+
+    use super::*;
+
+    #[test]
+    fn resolve_reads_pending_slots() {
+        let backend = Backend::new(NullBackend::new());
+        let ctx = Arc::clone(backend.create_group(&[0]).primary());
+        let rs = Arc::new(RenderSystem::new(&[]));
+
+        // 3x2 tiles.
+        let extent = [40, 20];
+        let mut pass = DepthReductionPass {
+            tiles: rs.create_texture_handle(),
+            depth_view: rs.create_texture_handle(),
+            bound_depth: None,
+            argument: rs.create_shader_argument_handle(),
+            readbacks: (0..2).map(|_| rs.create_buffer_handle()).collect(),
+            pending: SmallVec::from_elem(false, 2),
+            pso: rs.create_raster_pipeline_handle(),
+            rs,
+            ctx: Arc::clone(&ctx),
+            extent,
+        };
+        pass.bind_targets().expect("failed to bind targets");
+
+        // Min/max pairs per tile, the row padding holds garbage.
+        let pitch = buffer_row_pitch(&DepthReductionPass::<NullDevice>::tiles_desc(extent))
+            / size_of::<f32>();
+        let mut data = vec![-1.0f32; 2 * pitch];
+        data[..6].copy_from_slice(&[2.0, 5.0, 0.0, 0.0, 3.0, 9.0]);
+        data[pitch..pitch + 6].copy_from_slice(&[0.0; 6]);
+        ctx.update_buffer(pass.readbacks[1], 0, &data)
+            .expect("failed to fill readback");
+
+        assert!(
+            pass.resolve(1, 0.1, 100.0)
+                .expect("failed to resolve")
+                .is_none()
+        );
+
+        pass.pending[1] = true;
+        let histogram = pass
+            .resolve(1, 0.1, 100.0)
+            .expect("failed to resolve")
+            .expect("slot was pending");
+        let (near, far) = histogram.range().expect("histogram is empty");
+        assert!(near <= 2.0 && 9.0 <= far);
+        assert!(near > 1.0 && far < 12.0);
+
+        // Every readback is resolved once.
+        assert!(
+            pass.resolve(1, 0.1, 100.0)
+                .expect("failed to resolve")
+                .is_none()
+        );
+    }
+}
@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
 use glam::{vec3, vec4};
+use smallvec::SmallVec;
 
 use crate::{
     collections::handle::Handle,
     multi_gpu_renderer::{GpuGlobals, csm::Cascades, pso::PsoCollection},
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder},
         context::{Context, RenderDevice},
         resources::{Buffer, RenderResourceContext, Texture},
         shader::{
@@ -14,11 +15,13 @@ use crate::{
         },
         system::RenderSystem,
     },
+    render_graph::{PassDesc, RenderGraph, TextureSlots},
     rhi::{
-        command::{CommandType, Subresource},
-        resources::{BufferDesc, BufferUsages},
-        types::{GeomTopology, ResourceState, Scissor, Viewport},
+        error::RenderError,
+        resources::{BufferDesc, BufferUsages, TextureDesc, TextureUsages},
+        types::{ClearColor, Format, GeomTopology, ResourceState, Scissor, Viewport},
     },
+    settings::CascadeLayout,
 };
 
 #[derive(Clone, Debug)]
@@ -49,13 +52,11 @@ pub struct DirectionalLightPass<D: RenderDevice> {
 
     pub extent: [u32; 2],
 
-    pub argument: Handle<ShaderArgument>,
+    pub arguments: SmallVec<[Handle<ShaderArgument>; 4]>,
     pub light_data: Handle<Buffer>,
 
-    pub diffuse_srv: Handle<Texture>,
-    pub normal_srv: Handle<Texture>,
-    pub material_srv: Handle<Texture>,
-    pub accum: Handle<Texture>,
+    pub gbuffer: SmallVec<[[Handle<Texture>; 4]; 4]>,
+    pub accum: SmallVec<[Handle<Texture>; 4]>,
 
     pub pso: Handle<RasterPipeline>,
 }
@@ -63,16 +64,23 @@ pub struct DirectionalLightPass<D: RenderDevice> {
 impl<D: RenderDevice> DirectionalLightPass<D> {
     pub fn new(
         rs: Arc<RenderSystem>,
-        ctx: Arc<Context<D>>,
         extent: [u32; 2],
-        diffuse_srv: Handle<Texture>,
-        normal_srv: Handle<Texture>,
-        material_srv: Handle<Texture>,
-        accum: Handle<Texture>,
+        gbuffer: &impl TextureSlots<D>,
+        accum: &impl TextureSlots<D>,
         frames_in_flight: usize,
+        layout: CascadeLayout,
         psos: &PsoCollection<D>,
-    ) -> Self {
-        let argument = rs.create_shader_argument_handle();
+    ) -> Result<Self, RenderError> {
+        assert!(
+            Arc::ptr_eq(gbuffer.consumer(), accum.producer()),
+            "directional light pass must consume the gbuffer on the device it renders on"
+        );
+
+        let ctx = Arc::clone(accum.producer());
+
+        let arguments = (0..gbuffer.depth())
+            .map(|_| rs.create_shader_argument_handle())
+            .collect::<SmallVec<_>>();
         let light_data = rs.create_buffer_handle();
 
         ctx.bind_buffer(
@@ -83,7 +91,7 @@ impl<D: RenderDevice> DirectionalLightPass<D> {
             )
             .with_name("Light Date Buffer".into()),
             None,
-        );
+        )?;
 
         ctx.update_buffer(
             light_data,
@@ -120,117 +128,142 @@ impl<D: RenderDevice> DirectionalLightPass<D> {
                     },
                 },
             ],
-        );
+        )?;
 
-        ctx.bind_shader_argument(
-            argument,
-            ShaderArgumentDesc {
-                views: &[
-                    ShaderEntry::Srv(diffuse_srv),
-                    ShaderEntry::Srv(normal_srv),
-                    ShaderEntry::Srv(material_srv),
-                ],
-                samplers: &[],
-                dynamic_buffer: Some(light_data),
-            },
-        );
-
-        Self {
+        let pass = Self {
             rs,
             ctx,
             extent,
-            pso: psos.directional_light_pass,
-            argument,
+            pso: psos.directional_light_pass(layout),
+            arguments,
             light_data,
-            accum,
-            diffuse_srv,
-            normal_srv,
-            material_srv,
-        }
+            gbuffer: (0..gbuffer.depth())
+                .map(|slot| {
+                    let views = gbuffer.views(slot);
+                    [views[0], views[1], views[2], views[3]]
+                })
+                .collect(),
+            accum: (0..accum.depth())
+                .map(|slot| accum.targets(slot)[0])
+                .collect(),
+        };
+
+        pass.bind_arguments()?;
+
+        Ok(pass)
     }
 
-    pub fn render(
-        &self,
+    pub fn accum_desc(extent: [u32; 2]) -> TextureDesc {
+        TextureDesc::new_2d(
+            extent,
+            Format::Rgba32,
+            TextureUsages::RenderTarget | TextureUsages::Resource,
+        )
+        .with_name("Accumulation Texture".into())
+        .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0]))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
         globals: Handle<ShaderArgument>,
         csm: Handle<Texture>,
         csm_data: Handle<ShaderArgument>,
         frame_idx: usize,
         cascade_idx: usize,
+        gbuffer_slot: usize,
+        accum_slot: usize,
     ) {
-        let mut cmd = self.ctx.create_encoder(CommandType::Graphics);
-        cmd.set_barriers(&[
-            Barrier::Texture(
-                self.accum,
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.normal_srv,
-                ResourceState::Shader,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.diffuse_srv,
-                ResourceState::Shader,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.material_srv,
-                ResourceState::Shader,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(csm, ResourceState::Shader, Subresource::Local(None)),
-        ]);
+        let [diffuse, normal, material, emissive] = self.gbuffer[gbuffer_slot];
 
-        {
-            let mut encoder = cmd.render("Directional Light Pass".into(), &[self.accum], None);
-            encoder.set_render_pipeline(self.pso);
-
-            encoder.clear_rt(self.accum, None);
-            encoder.set_viewport(Viewport {
-                x: 0.0,
-                y: 0.0,
-                w: self.extent[0] as f32,
-                h: self.extent[1] as f32,
-            });
-            encoder.set_scissor(Scissor {
-                x: 0,
-                y: 0,
-                w: self.extent[0],
-                h: self.extent[1],
-            });
-
-            encoder.set_topology(GeomTopology::Triangles);
-            encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx);
-            encoder.bind_shader_argument(1, self.argument, 0);
-            encoder.bind_shader_argument(2, csm_data, size_of::<Cascades>() * cascade_idx);
-
-            encoder.draw(3, 0);
-        }
+        graph.add_pass(
+            PassDesc::new("Directional Light Pass")
+                .with_write_texture(self.accum[accum_slot], ResourceState::RenderTarget)
+                .with_read_texture(normal, ResourceState::Shader)
+                .with_read_texture(diffuse, ResourceState::Shader)
+                .with_read_texture(material, ResourceState::Shader)
+                .with_read_texture(emissive, ResourceState::Shader)
+                .with_read_texture(csm, ResourceState::Shader),
+            move |cmd| {
+                self.render(
+                    cmd,
+                    globals,
+                    csm_data,
+                    frame_idx,
+                    cascade_idx,
+                    gbuffer_slot,
+                    accum_slot,
+                )
+            },
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        globals: Handle<ShaderArgument>,
+        csm_data: Handle<ShaderArgument>,
+        frame_idx: usize,
+        cascade_idx: usize,
+        gbuffer_slot: usize,
+        accum_slot: usize,
+    ) -> Result<(), RenderError> {
+        let accum = self.accum[accum_slot];
+
+        let mut encoder = cmd.render("Directional Light Pass".into(), &[accum], None)?;
+        encoder.set_render_pipeline(self.pso)?;
+
+        encoder.clear_rt(accum, None)?;
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: self.extent[0] as f32,
+            h: self.extent[1] as f32,
+        });
+        encoder.set_scissor(Scissor {
+            x: 0,
+            y: 0,
+            w: self.extent[0],
+            h: self.extent[1],
+        });
 
-        cmd.set_barriers(&[Barrier::Texture(
-            csm,
-            ResourceState::Common,
-            Subresource::Local(None),
-        )]);
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx)?;
+        encoder.bind_shader_argument(1, self.arguments[gbuffer_slot], 0)?;
+        encoder.bind_shader_argument(2, csm_data, size_of::<Cascades>() * cascade_idx)?;
 
-        self.ctx.enqueue(cmd);
+        encoder.draw(3, 0);
+
+        Ok(())
     }
 
-    pub fn resize(&mut self, extent: [u32; 2]) {
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
         self.extent = extent;
 
-        self.ctx.bind_shader_argument(
-            self.argument,
-            ShaderArgumentDesc {
-                views: &[
-                    ShaderEntry::Srv(self.diffuse_srv),
-                    ShaderEntry::Srv(self.normal_srv),
-                    ShaderEntry::Srv(self.material_srv),
-                ],
-                samplers: &[],
-                dynamic_buffer: Some(self.light_data),
-            },
-        );
+        self.bind_arguments()
+    }
+
+    pub fn bind_arguments(&self) -> Result<(), RenderError> {
+        for (argument, [diffuse, normal, material, emissive]) in
+            self.arguments.iter().zip(&self.gbuffer)
+        {
+            self.ctx.bind_shader_argument(
+                *argument,
+                ShaderArgumentDesc {
+                    views: &[
+                        ShaderEntry::Srv(*diffuse),
+                        ShaderEntry::Srv(*normal),
+                        ShaderEntry::Srv(*material),
+                        ShaderEntry::Srv(*emissive),
+                    ],
+                    samplers: &[],
+                    dynamic_buffer: Some(self.light_data),
+                },
+            )?;
+        }
+
+        Ok(())
     }
 }
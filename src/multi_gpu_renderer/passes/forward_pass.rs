@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use hecs::World;
+use smallvec::SmallVec;
+
+use crate::{
+    collections::handle::Handle,
+    engine::{
+        AlphaBlendComponent, BoundsComponent, GpuMaterialComponent, GpuMeshComponent,
+        GpuTransformComponent, camera::Camera,
+    },
+    multi_gpu_renderer::{
+        GpuGlobals, csm::Cascades, passes::directional_light_pass::LightData, pso::PsoCollection,
+    },
+    ra::{
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder},
+        context::{Context, RenderDevice},
+        resources::{Buffer, Texture},
+        shader::{
+            RasterPipeline, RenderShaderContext, ShaderArgument, ShaderArgumentDesc, ShaderEntry,
+        },
+        system::RenderSystem,
+    },
+    render_graph::{PassDesc, RenderGraph, TextureSlots},
+    rhi::{
+        error::RenderError,
+        types::{GeomTopology, IndexType, ResourceState, Scissor, Viewport},
+    },
+    settings::CascadeLayout,
+};
+
+// Draws blended geometry back to front over the lit accumulation target.
+pub struct ForwardPass<D: RenderDevice> {
+    pub rs: Arc<RenderSystem>,
+    pub ctx: Arc<Context<D>>,
+
+    pub extent: [u32; 2],
+
+    // Indexed by gbuffer slot, then by shadow map slot.
+    pub arguments: SmallVec<[SmallVec<[Handle<ShaderArgument>; 4]>; 4]>,
+    pub light_data: Handle<Buffer>,
+    pub csm_buffer: Handle<Buffer>,
+
+    pub gbuffer: SmallVec<[Handle<Texture>; 4]>,
+    pub csm: SmallVec<[Handle<Texture>; 4]>,
+    pub accum: SmallVec<[Handle<Texture>; 4]>,
+
+    pub pso: Handle<RasterPipeline>,
+}
+
+impl<D: RenderDevice> ForwardPass<D> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        rs: Arc<RenderSystem>,
+        extent: [u32; 2],
+        gbuffer: &impl TextureSlots<D>,
+        accum: &impl TextureSlots<D>,
+        csm: &[Handle<Texture>],
+        csm_buffer: Handle<Buffer>,
+        light_data: Handle<Buffer>,
+        layout: CascadeLayout,
+        psos: &PsoCollection<D>,
+    ) -> Result<Self, RenderError> {
+        let ctx = Arc::clone(accum.producer());
+
+        let arguments = (0..gbuffer.depth())
+            .map(|_| {
+                csm.iter()
+                    .map(|_| rs.create_shader_argument_handle())
+                    .collect()
+            })
+            .collect();
+
+        let pass = Self {
+            rs,
+            ctx,
+            extent,
+            arguments,
+            light_data,
+            csm_buffer,
+            gbuffer: (0..gbuffer.depth())
+                .map(|slot| gbuffer.views(slot)[2])
+                .collect(),
+            csm: csm.iter().copied().collect(),
+            accum: (0..accum.depth())
+                .map(|slot| accum.targets(slot)[0])
+                .collect(),
+            pso: psos.forward_pass(layout),
+        };
+
+        pass.bind_arguments()?;
+
+        Ok(pass)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        globals: Handle<ShaderArgument>,
+        world: &'a World,
+        camera: &Camera,
+        frame_idx: usize,
+        cascade_idx: usize,
+        gbuffer_slot: usize,
+        shadow_slot: usize,
+        accum_slot: usize,
+    ) {
+        let view = camera.view();
+
+        graph.add_pass(
+            PassDesc::new("Forward Pass")
+                .with_write_texture(self.accum[accum_slot], ResourceState::RenderTarget)
+                .with_read_texture(self.gbuffer[gbuffer_slot], ResourceState::Shader)
+                .with_read_texture(self.csm[shadow_slot], ResourceState::Shader),
+            move |cmd| {
+                self.render(
+                    cmd,
+                    globals,
+                    world,
+                    view,
+                    frame_idx,
+                    cascade_idx,
+                    self.arguments[gbuffer_slot][shadow_slot],
+                    accum_slot,
+                )
+            },
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        globals: Handle<ShaderArgument>,
+        world: &World,
+        view: glam::Mat4,
+        frame_idx: usize,
+        cascade_idx: usize,
+        argument: Handle<ShaderArgument>,
+        accum_slot: usize,
+    ) -> Result<(), RenderError> {
+        let mut query = world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                &GpuMaterialComponent,
+                Option<&BoundsComponent>,
+            )>()
+            .with::<&AlphaBlendComponent>();
+
+        let mut draws = query
+            .iter()
+            .map(|(_, (transform, mesh, material, bounds))| {
+                let depth = bounds.map_or(0.0, |bounds| {
+                    let center = (bounds.aabb.min + bounds.aabb.max) * 0.5;
+                    view.transform_point3(center).z
+                });
+
+                (depth, transform, mesh, material)
+            })
+            .collect::<Vec<_>>();
+
+        if draws.is_empty() {
+            return Ok(());
+        }
+
+        draws.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let accum = self.accum[accum_slot];
+
+        let mut encoder = cmd.render("Forward Pass".into(), &[accum], None)?;
+        encoder.set_render_pipeline(self.pso)?;
+
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: self.extent[0] as f32,
+            h: self.extent[1] as f32,
+        });
+        encoder.set_scissor(Scissor {
+            x: 0,
+            y: 0,
+            w: self.extent[0],
+            h: self.extent[1],
+        });
+
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx)?;
+        encoder.bind_shader_argument(3, argument, size_of::<Cascades>() * cascade_idx)?;
+
+        for (_, transform, mesh, material) in draws {
+            encoder.bind_shader_argument(1, material.argument, 0)?;
+            encoder.bind_shader_argument(2, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_vertex_buffer(mesh.normal_vb, 1)?;
+            encoder.bind_vertex_buffer(mesh.uv_vb, 2)?;
+            encoder.bind_vertex_buffer(mesh.tangent_vb, 3)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
+        self.extent = extent;
+
+        self.bind_arguments()
+    }
+
+    pub fn bind_arguments(&self) -> Result<(), RenderError> {
+        for (arguments, material) in self.arguments.iter().zip(&self.gbuffer) {
+            for (argument, csm) in arguments.iter().zip(&self.csm) {
+                self.ctx.bind_shader_argument(
+                    *argument,
+                    ShaderArgumentDesc {
+                        views: &[
+                            ShaderEntry::Srv(*csm),
+                            ShaderEntry::Srv(*material),
+                            ShaderEntry::Cbv(self.light_data, size_of::<LightData>()),
+                        ],
+                        samplers: &[],
+                        dynamic_buffer: Some(self.csm_buffer),
+                    },
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
+use smallvec::SmallVec;
+
 use crate::{
     collections::handle::Handle,
     multi_gpu_renderer::pso::PsoCollection,
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder},
         context::{Context, RenderDevice},
         resources::Texture,
         shader::{
@@ -12,8 +14,9 @@ use crate::{
         },
         system::RenderSystem,
     },
+    render_graph::{PassDesc, RenderGraph, TextureSlots},
     rhi::{
-        command::{CommandType, Subresource},
+        error::RenderError,
         types::{GeomTopology, ResourceState, Scissor, Viewport},
     },
 };
@@ -23,8 +26,8 @@ pub struct GammaCorrectionPass<D: RenderDevice> {
     pub ctx: Arc<Context<D>>,
     pub pso: Handle<RasterPipeline>,
 
-    pub argument: Handle<ShaderArgument>,
-    pub accum_srv: Handle<Texture>,
+    pub arguments: SmallVec<[Handle<ShaderArgument>; 4]>,
+    pub accum: SmallVec<[Handle<Texture>; 4]>,
 
     pub extent: [u32; 2],
 }
@@ -32,77 +35,93 @@ pub struct GammaCorrectionPass<D: RenderDevice> {
 impl<D: RenderDevice> GammaCorrectionPass<D> {
     pub fn new(
         rs: Arc<RenderSystem>,
-        ctx: Arc<Context<D>>,
         psos: &PsoCollection<D>,
-        accum_srv: Handle<Texture>,
+        accum: &impl TextureSlots<D>,
         extent: [u32; 2],
-    ) -> Self {
-        let argument = rs.create_shader_argument_handle();
-
-        ctx.bind_shader_argument(
-            argument,
-            ShaderArgumentDesc {
-                views: &[ShaderEntry::Srv(accum_srv)],
-                samplers: &[],
-                dynamic_buffer: None,
-            },
-        );
+    ) -> Result<Self, RenderError> {
+        let arguments = (0..accum.depth())
+            .map(|_| rs.create_shader_argument_handle())
+            .collect();
 
-        Self {
+        let pass = Self {
             rs,
-            ctx,
+            ctx: Arc::clone(accum.consumer()),
             pso: psos.gamma_corr_pass,
-            argument,
-            accum_srv,
+            arguments,
+            accum: (0..accum.depth())
+                .map(|slot| accum.views(slot)[0])
+                .collect(),
             extent,
-        }
+        };
+
+        pass.bind_arguments()?;
+
+        Ok(pass)
     }
 
-    pub fn render(&self, swapchain_view: Handle<Texture>) {
-        let mut cmd = self.ctx.create_encoder(CommandType::Graphics);
-        cmd.set_barriers(&[Barrier::Texture(
-            self.accum_srv,
-            ResourceState::Shader,
-            Subresource::Local(None),
-        )]);
-
-        {
-            let mut encoder = cmd.render("Gamma Correction Pass".into(), &[swapchain_view], None);
-            encoder.set_render_pipeline(self.pso);
-
-            encoder.set_viewport(Viewport {
-                x: 0.0,
-                y: 0.0,
-                w: self.extent[0] as f32,
-                h: self.extent[1] as f32,
-            });
-            encoder.set_scissor(Scissor {
-                x: 0,
-                y: 0,
-                w: self.extent[0],
-                h: self.extent[1],
-            });
-            encoder.clear_rt(swapchain_view, Some([1.0, 1.0, 1.0, 1.0]));
-
-            encoder.set_topology(GeomTopology::Triangles);
-            encoder.bind_shader_argument(0, self.argument, 0);
-
-            encoder.draw(3, 0);
-        }
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        swapchain_view: Handle<Texture>,
+        slot: usize,
+    ) {
+        graph.add_pass(
+            PassDesc::new("Gamma Correction Pass")
+                .with_read_texture(self.accum[slot], ResourceState::Shader)
+                .with_write_texture(swapchain_view, ResourceState::RenderTarget),
+            move |cmd| self.render(cmd, swapchain_view, slot),
+        );
+    }
+
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        swapchain_view: Handle<Texture>,
+        slot: usize,
+    ) -> Result<(), RenderError> {
+        let mut encoder = cmd.render("Gamma Correction Pass".into(), &[swapchain_view], None)?;
+        encoder.set_render_pipeline(self.pso)?;
 
-        self.ctx.enqueue(cmd);
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: self.extent[0] as f32,
+            h: self.extent[1] as f32,
+        });
+        encoder.set_scissor(Scissor {
+            x: 0,
+            y: 0,
+            w: self.extent[0],
+            h: self.extent[1],
+        });
+        encoder.clear_rt(swapchain_view, Some([1.0, 1.0, 1.0, 1.0]))?;
+
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.bind_shader_argument(0, self.arguments[slot], 0)?;
+
+        encoder.draw(3, 0);
+
+        Ok(())
     }
 
-    pub fn resize(&mut self, extent: [u32; 2]) {
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
         self.extent = extent;
 
-        self.ctx.bind_shader_argument(
-            self.argument,
-            ShaderArgumentDesc {
-                views: &[ShaderEntry::Srv(self.accum_srv)],
-                samplers: &[],
-                dynamic_buffer: None,
-            },
-        );
+        self.bind_arguments()
+    }
+
+    pub fn bind_arguments(&self) -> Result<(), RenderError> {
+        for (argument, accum) in self.arguments.iter().zip(&self.accum) {
+            self.ctx.bind_shader_argument(
+                *argument,
+                ShaderArgumentDesc {
+                    views: &[ShaderEntry::Srv(*accum)],
+                    samplers: &[],
+                    dynamic_buffer: None,
+                },
+            )?;
+        }
+
+        Ok(())
     }
 }
@@ -1,21 +1,23 @@
 use std::sync::Arc;
 
 use hecs::World;
+use smallvec::SmallVec;
 
 use crate::{
     collections::handle::Handle,
-    engine::{GpuMaterialComponent, GpuMeshComponent, GpuTransform, GpuTransformComponent},
+    engine::{AlphaBlendComponent, GpuMaterialComponent, GpuMeshComponent, GpuTransformComponent},
     multi_gpu_renderer::{GpuGlobals, pso::PsoCollection},
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder},
         context::{Context, RenderDevice},
-        resources::{RenderResourceContext, Texture},
+        resources::Texture,
         shader::{RasterPipeline, ShaderArgument},
         system::RenderSystem,
     },
+    render_graph::{PassDesc, RenderGraph, TextureSlots},
     rhi::{
-        command::{CommandType, Subresource},
-        resources::{TextureDesc, TextureUsages, TextureViewDesc, TextureViewType},
+        error::RenderError,
+        resources::{TextureDesc, TextureUsages},
         types::{ClearColor, Format, GeomTopology, IndexType, ResourceState, Scissor, Viewport},
     },
 };
@@ -26,19 +28,7 @@ pub struct GPass<D: RenderDevice> {
 
     pub extent: [u32; 2],
 
-    pub diffuse: Handle<Texture>,
-    pub diffuse_srv: Handle<Texture>,
-
-    pub normal: Handle<Texture>,
-    pub normal_srv: Handle<Texture>,
-
-    pub material: Handle<Texture>,
-    pub material_srv: Handle<Texture>,
-
-    pub accum: Handle<Texture>,
-    pub accum_srv: Handle<Texture>,
-
-    pub depth: Handle<Texture>,
+    pub targets: SmallVec<[[Handle<Texture>; 4]; 4]>,
 
     pub pso: Handle<RasterPipeline>,
 }
@@ -46,276 +36,130 @@ pub struct GPass<D: RenderDevice> {
 impl<D: RenderDevice> GPass<D> {
     pub fn new(
         rs: Arc<RenderSystem>,
-        ctx: Arc<Context<D>>,
         extent: [u32; 2],
-        depth: Handle<Texture>,
+        gbuffer: &impl TextureSlots<D>,
         psos: &PsoCollection<D>,
-    ) -> Self {
-        let diffuse = rs.create_texture_handle();
-        let diffuse_srv = rs.create_texture_handle();
-
-        ctx.bind_texture(
-            diffuse,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Diffuse Texture".into())
-            .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0])),
-            None,
-        );
-
-        ctx.bind_texture_view(
-            diffuse_srv,
-            diffuse,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
-
-        let normal = rs.create_texture_handle();
-        let normal_srv = rs.create_texture_handle();
-
-        ctx.bind_texture(
-            normal,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Normal Texture".into())
-            .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0])),
-            None,
-        );
-
-        ctx.bind_texture_view(
-            normal_srv,
-            normal,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
-
-        let material = rs.create_texture_handle();
-        let material_srv = rs.create_texture_handle();
+    ) -> Result<Self, RenderError> {
+        let targets = (0..gbuffer.depth())
+            .map(|slot| {
+                let targets = gbuffer.targets(slot);
+                [targets[0], targets[1], targets[2], targets[3]]
+            })
+            .collect();
+
+        Ok(Self {
+            rs,
+            ctx: Arc::clone(gbuffer.producer()),
+            extent,
+            pso: psos.g_pass,
+            targets,
+        })
+    }
 
-        ctx.bind_texture(
-            material,
+    pub fn gbuffer_desc(extent: [u32; 2]) -> [TextureDesc; 4] {
+        [
+            ("Diffuse Texture", [1.0, 1.0, 1.0, 1.0]),
+            ("Normal Texture", [1.0, 1.0, 1.0, 1.0]),
+            ("Material Texture", [1.0, 1.0, 1.0, 1.0]),
+            ("Emissive Texture", [0.0, 0.0, 0.0, 0.0]),
+        ]
+        .map(|(name, color)| {
             TextureDesc::new_2d(
                 extent,
                 Format::Rgba32,
                 TextureUsages::RenderTarget | TextureUsages::Resource,
             )
-            .with_name("Material Texture".into())
-            .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0])),
-            None,
-        );
+            .with_name(name.into())
+            .with_color(ClearColor::Color(color))
+        })
+    }
 
-        ctx.bind_texture_view(
-            material_srv,
-            material,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        globals: Handle<ShaderArgument>,
+        depth: Handle<Texture>,
+        frame_idx: usize,
+        world: &'a World,
+        slot: usize,
+    ) {
+        let [diffuse, normal, material, emissive] = self.targets[slot];
+
+        graph.add_pass(
+            PassDesc::new("GPass")
+                .with_write_texture(diffuse, ResourceState::RenderTarget)
+                .with_write_texture(normal, ResourceState::RenderTarget)
+                .with_write_texture(material, ResourceState::RenderTarget)
+                .with_write_texture(emissive, ResourceState::RenderTarget)
+                .with_read_texture(depth, ResourceState::DepthRead),
+            move |cmd| self.render(cmd, globals, depth, frame_idx, world, slot),
         );
+    }
 
-        let accum = rs.create_texture_handle();
-        let accum_srv = rs.create_texture_handle();
-
-        ctx.bind_texture(
-            accum,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Accumulation Texture".into())
-            .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0])),
-            None,
-        );
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        globals: Handle<ShaderArgument>,
+        depth: Handle<Texture>,
+        frame_idx: usize,
+        world: &World,
+        slot: usize,
+    ) -> Result<(), RenderError> {
+        let targets = &self.targets[slot];
 
-        ctx.bind_texture_view(
-            accum_srv,
-            accum,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
+        let mut encoder = cmd.render("GPass".into(), targets, Some(depth))?;
+        encoder.set_render_pipeline(self.pso)?;
 
-        Self {
-            rs,
-            ctx,
-            extent,
-            pso: psos.g_pass,
-
-            depth,
-            diffuse,
-            diffuse_srv,
-            normal,
-            normal_srv,
-            material,
-            material_srv,
-            accum,
-            accum_srv,
+        for target in targets {
+            encoder.clear_rt(*target, None)?;
         }
-    }
-
-    pub fn render(&self, globals: Handle<ShaderArgument>, frame_idx: usize, world: &World) {
-        let mut cmd = self.ctx.create_encoder(CommandType::Graphics);
-        cmd.set_barriers(&[
-            Barrier::Texture(
-                self.diffuse,
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.normal,
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.material,
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.accum,
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            ),
-            Barrier::Texture(
-                self.depth,
-                ResourceState::DepthRead,
-                Subresource::Local(None),
-            ),
-        ]);
 
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: self.extent[0] as f32,
+            h: self.extent[1] as f32,
+        });
+        encoder.set_scissor(Scissor {
+            x: 0,
+            y: 0,
+            w: self.extent[0],
+            h: self.extent[1],
+        });
+
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx)?;
+
+        for (_, (transform, mesh, material)) in world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                &GpuMaterialComponent,
+            )>()
+            .without::<&AlphaBlendComponent>()
+            .iter()
         {
-            let mut encoder = cmd.render(
-                "GPass".into(),
-                &[self.diffuse, self.normal, self.material],
-                Some(self.depth),
+            encoder.bind_shader_argument(1, material.argument, 0)?;
+
+            encoder.bind_shader_argument(2, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_vertex_buffer(mesh.normal_vb, 1)?;
+            encoder.bind_vertex_buffer(mesh.uv_vb, 2)?;
+            encoder.bind_vertex_buffer(mesh.tangent_vb, 3)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
             );
-            encoder.set_render_pipeline(self.pso);
-
-            encoder.clear_rt(self.diffuse, None);
-            encoder.clear_rt(self.normal, None);
-            encoder.clear_rt(self.material, None);
-            encoder.clear_rt(self.accum, None);
-
-            encoder.set_viewport(Viewport {
-                x: 0.0,
-                y: 0.0,
-                w: self.extent[0] as f32,
-                h: self.extent[1] as f32,
-            });
-            encoder.set_scissor(Scissor {
-                x: 0,
-                y: 0,
-                w: self.extent[0],
-                h: self.extent[1],
-            });
-
-            encoder.set_topology(GeomTopology::Triangles);
-            encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx);
-
-            for (_, (transform, mesh, material)) in world
-                .query::<(
-                    &GpuTransformComponent,
-                    &GpuMeshComponent,
-                    &GpuMaterialComponent,
-                )>()
-                .iter()
-            {
-                encoder.bind_shader_argument(1, material.argument, 0);
-
-                encoder.bind_shader_argument(
-                    2,
-                    transform.argument,
-                    size_of::<GpuTransform>() * frame_idx,
-                );
-                encoder.bind_vertex_buffer(mesh.pos_vb, 0);
-                encoder.bind_vertex_buffer(mesh.normal_vb, 1);
-                encoder.bind_vertex_buffer(mesh.uv_vb, 2);
-                encoder.bind_vertex_buffer(mesh.tangent_vb, 3);
-                encoder.bind_index_buffer(mesh.ib, IndexType::U32);
-                encoder.draw_indexed(
-                    mesh.index_count,
-                    mesh.start_index_location,
-                    mesh.base_vertex_location,
-                );
-            }
         }
 
-        self.ctx.enqueue(cmd);
+        Ok(())
     }
 
     pub fn resize(&mut self, extent: [u32; 2]) {
-        self.ctx.bind_texture(
-            self.diffuse,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Diffuse Texture".into())
-            .with_color(ClearColor::Color([0.301, 0.5607, 0.675, 1.0])),
-            None,
-        );
-
-        self.ctx.bind_texture_view(
-            self.diffuse_srv,
-            self.diffuse,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
-
-        self.ctx.bind_texture(
-            self.normal,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Normal Texture".into())
-            .with_color(ClearColor::Color([0.0, 0.0, 0.0, 1.0])),
-            None,
-        );
-
-        self.ctx.bind_texture_view(
-            self.normal_srv,
-            self.normal,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
-
-        self.ctx.bind_texture(
-            self.material,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Material Texture".into())
-            .with_color(ClearColor::Color([0.0, 0.0, 0.0, 1.0])),
-            None,
-        );
-
-        self.ctx.bind_texture_view(
-            self.material_srv,
-            self.material,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
-
-        self.ctx.bind_texture(
-            self.accum,
-            TextureDesc::new_2d(
-                extent,
-                Format::Rgba32,
-                TextureUsages::RenderTarget | TextureUsages::Resource,
-            )
-            .with_name("Accum Texture".into())
-            .with_color(ClearColor::Color([0.0, 0.0, 0.0, 1.0])),
-            None,
-        );
-
-        self.ctx.bind_texture_view(
-            self.accum_srv,
-            self.accum,
-            TextureViewDesc::default().with_view_type(TextureViewType::ShaderResource),
-        );
-
         self.extent = extent;
     }
 }
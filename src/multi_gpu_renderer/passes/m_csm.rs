@@ -1,42 +1,56 @@
 use std::sync::Arc;
 
-use hecs::World;
+use hecs::{Or, World};
 use smallvec::SmallVec;
 
 use crate::{
-    collections::{handle::Handle, rwc_ring_buffer::RwcRingBuffer},
-    engine::{GpuMeshComponent, GpuTransform, GpuTransformComponent, camera::Camera},
+    collections::handle::Handle,
+    engine::{
+        AlphaBlendComponent, AlphaTestComponent, BoundsComponent, GpuMaterialComponent,
+        GpuMeshComponent, GpuTransformComponent, camera::Camera,
+    },
     multi_gpu_renderer::{
-        csm::{Cascade, CascadedShadowMaps, Cascades},
+        csm::{
+            CameraSnapshot, Cascade, CascadeMask, CascadeSnapshot, CascadedShadowMaps, Cascades,
+            cascade_region, cascade_slice_view, cascade_viewport, motion_expansion,
+            shadow_map_desc, sweep_expansion,
+        },
         pso::PsoCollection,
     },
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
-        context::{ContextDual, RenderDevice},
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder, TransferEncoder},
+        context::{Context, RenderDevice},
         resources::{Buffer, RenderResourceContext, Texture},
         shader::{
             RasterPipeline, RenderShaderContext, ShaderArgument, ShaderArgumentDesc, ShaderEntry,
         },
         system::RenderSystem,
     },
+    render_graph::{PassDesc, RenderGraph, TextureSlots, channel::CrossDeviceChannel},
     rhi::{
-        command::{CommandType, Subresource},
-        resources::{
-            BufferDesc, BufferUsages, TextureDesc, TextureUsages, TextureViewDesc, TextureViewType,
-        },
-        types::{ClearColor, Format, GeomTopology, IndexType, ResourceState, Scissor, Viewport},
+        error::RenderError,
+        resources::{BufferDesc, BufferUsages, TextureDesc, TextureUsages, TextureViewType},
+        types::{ClearColor, Format, GeomTopology, IndexType, Region, ResourceState, Scissor},
     },
-    settings::RenderSettings,
+    settings::{CascadeLayout, RenderSettings},
 };
 
 pub struct MultiCascadedShadowMapsPass<D: RenderDevice> {
     pub rs: Arc<RenderSystem>,
-    pub group: Arc<ContextDual<D>>,
+    pub producer: Arc<Context<D>>,
+    pub consumer: Arc<Context<D>>,
 
     pub size: u32,
     pub count: usize,
+    pub layout: CascadeLayout,
 
     pub csm: CascadedShadowMaps,
+    pub previous: Option<CameraSnapshot>,
+    pub snapshots: SmallVec<[Option<CascadeSnapshot>; 4]>,
+
+    pub due: CascadeMask,
+    pub dirty: CascadeMask,
+    pub stale: SmallVec<[CascadeMask; 4]>,
 
     pub gpu_csm_buffer: Handle<Buffer>,
     pub argument: SmallVec<[Handle<ShaderArgument>; 4]>,
@@ -45,25 +59,33 @@ pub struct MultiCascadedShadowMapsPass<D: RenderDevice> {
     pub local_argument: Handle<ShaderArgument>,
 
     pub depth: Handle<Texture>,
-    pub shared: RwcRingBuffer<Handle<Texture>, 4>,
+    pub atlas: Option<Handle<Texture>>,
+    pub depth_slices: SmallVec<[Handle<Texture>; 8]>,
+    pub targets: SmallVec<[Handle<Texture>; 4]>,
+    pub target_slices: SmallVec<[SmallVec<[Handle<Texture>; 8]>; 4]>,
 
     pub pso: Handle<RasterPipeline>,
+    pub alpha_test_pso: Handle<RasterPipeline>,
 }
 
 impl<D: RenderDevice> MultiCascadedShadowMapsPass<D> {
     pub fn new(
         rs: Arc<RenderSystem>,
-        group: Arc<ContextDual<D>>,
+        shadows: &CrossDeviceChannel<D>,
         settings: &RenderSettings,
+        layout: CascadeLayout,
         psos: &PsoCollection<D>,
-    ) -> Self {
-        let texture_count = settings.frames_in_flight.min(3);
+    ) -> Result<Self, RenderError> {
+        assert!(
+            layout == CascadeLayout::Atlas || shadows.is_local(),
+            "cross-device shadow maps must use the atlas layout"
+        );
 
-        let depth = rs.create_texture_handle();
+        let texture_count = shadows.depth();
+        let producer = Arc::clone(shadows.producer());
+        let consumer = Arc::clone(shadows.consumer());
 
-        let shared = (0..texture_count)
-            .map(|_| rs.create_texture_handle())
-            .collect::<SmallVec<_>>();
+        let depth = rs.create_texture_handle();
 
         let gpu_csm_buffer = rs.create_buffer_handle();
         let gpu_csm_proj_view_buffer = rs.create_buffer_handle();
@@ -73,199 +95,371 @@ impl<D: RenderDevice> MultiCascadedShadowMapsPass<D> {
             .collect::<SmallVec<_>>();
         let local_argument = rs.create_shader_argument_handle();
 
-        group.call_secondary(|ctx| {
-            ctx.bind_texture(
-                depth,
-                TextureDesc::new_2d(
-                    [2 * settings.cascade_size, 2 * settings.cascade_size],
-                    Format::D32,
-                    TextureUsages::DepthTarget,
-                )
-                .with_name("Depth for CSM".into())
-                .with_color(ClearColor::Depth(1.0)),
-                None,
-            );
-
-            shared.iter().enumerate().for_each(|(i, t)| {
-                ctx.bind_texture(
-                    *t,
-                    TextureDesc::new_2d(
-                        [2 * settings.cascade_size, 2 * settings.cascade_size],
-                        Format::R32,
-                        TextureUsages::RenderTarget
-                            | TextureUsages::Resource
-                            | TextureUsages::Shared,
-                    )
-                    .with_name(format!("Shared Cascaded Shadow Maps {i}").into())
-                    .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0])),
-                    None,
-                );
-            });
-
-            ctx.bind_buffer(
-                gpu_csm_proj_view_buffer,
-                BufferDesc::cpu_to_gpu(
-                    size_of::<Cascade>() * texture_count * settings.cascades_count,
-                    BufferUsages::Uniform,
-                )
-                .with_name("CSM Proj View Buffer".into()),
+        producer.bind_texture(
+            depth,
+            shadow_map_desc(
+                layout,
+                settings.cascade_size,
+                settings.cascades_count,
+                Format::D32,
+                TextureUsages::DepthTarget,
+            )
+            .with_name("Depth for CSM".into())
+            .with_color(ClearColor::Depth(1.0)),
+            None,
+        )?;
+
+        let targets = (0..texture_count)
+            .map(|slot| shadows.targets(slot)[0])
+            .collect::<SmallVec<[_; 4]>>();
+
+        // Cascades are skipped on most frames, so across devices they are
+        // rendered into a persistent atlas and only the changed regions are
+        // copied into the slot that is about to be pushed.
+        let atlas = if shadows.is_local() {
+            None
+        } else {
+            let atlas = rs.create_texture_handle();
+            producer.bind_texture(
+                atlas,
+                Self::shadow_map_desc(settings, layout).with_name("CSM Atlas".into()),
                 None,
-            );
+            )?;
+
+            Some(atlas)
+        };
+
+        let slices =
+            |texture: Handle<Texture>, view_ty: TextureViewType, format: Format| match layout {
+                CascadeLayout::Atlas => Ok(SmallVec::new()),
+                CascadeLayout::Array => (0..settings.cascades_count)
+                    .map(|i| {
+                        let slice = rs.create_texture_handle();
+                        producer.bind_texture_view(
+                            slice,
+                            texture,
+                            cascade_slice_view(view_ty, format, i),
+                        )?;
+
+                        Ok(slice)
+                    })
+                    .collect::<Result<SmallVec<_>, RenderError>>(),
+            };
+
+        let depth_slices = slices(depth, TextureViewType::DepthStencil, Format::D32)?;
+        let target_slices = targets
+            .iter()
+            .map(|target| slices(*target, TextureViewType::RenderTarget, Format::R32))
+            .collect::<Result<SmallVec<_>, RenderError>>()?;
+
+        producer.bind_buffer(
+            gpu_csm_proj_view_buffer,
+            BufferDesc::cpu_to_gpu(
+                size_of::<Cascade>() * texture_count * settings.cascades_count,
+                BufferUsages::Uniform,
+            )
+            .with_name("CSM Proj View Buffer".into()),
+            None,
+        )?;
+
+        producer.bind_shader_argument(
+            local_argument,
+            ShaderArgumentDesc {
+                views: &[],
+                samplers: &[],
+                dynamic_buffer: Some(gpu_csm_proj_view_buffer),
+            },
+        )?;
+
+        consumer.bind_buffer(
+            gpu_csm_buffer,
+            BufferDesc::cpu_to_gpu(size_of::<Cascades>() * texture_count, BufferUsages::Uniform)
+                .with_name("CSM Buffer".into()),
+            None,
+        )?;
 
-            ctx.bind_shader_argument(
-                local_argument,
+        for (slot, arg) in argument.iter().enumerate() {
+            consumer.bind_shader_argument(
+                *arg,
                 ShaderArgumentDesc {
-                    views: &[],
+                    views: &[ShaderEntry::Srv(shadows.views(slot)[0])],
                     samplers: &[],
-                    dynamic_buffer: Some(gpu_csm_proj_view_buffer),
+                    dynamic_buffer: Some(gpu_csm_buffer),
                 },
-            );
-        });
-
-        group.call_primary(|ctx| {
-            shared.iter().enumerate().for_each(|(_, t)| {
-                ctx.open_texture_handle(
-                    *t,
-                    &group.secondary,
-                    Some(
-                        TextureViewDesc::default()
-                            .with_view_type(TextureViewType::ShaderResource)
-                            .with_format(Format::R32),
-                    ),
-                );
-            });
-
-            ctx.bind_buffer(
-                gpu_csm_buffer,
-                BufferDesc::cpu_to_gpu(
-                    size_of::<Cascades>() * texture_count,
-                    BufferUsages::Uniform,
-                )
-                .with_name("CSM Buffer".into()),
-                None,
-            );
-
-            shared.iter().zip(argument.iter()).for_each(|(srv, arg)| {
-                ctx.bind_shader_argument(
-                    *arg,
-                    ShaderArgumentDesc {
-                        views: &[ShaderEntry::Srv(*srv)],
-                        samplers: &[],
-                        dynamic_buffer: Some(gpu_csm_buffer),
-                    },
-                );
-            });
-        });
+            )?;
+        }
 
-        Self {
+        Ok(Self {
             rs,
-            group,
+            producer,
+            consumer,
             size: settings.cascade_size,
             count: settings.cascades_count,
-            csm: CascadedShadowMaps::new(
-                settings.cascades_lambda,
-                settings.shadows_far,
-                settings.cascades_count,
-            ),
+            layout,
+            csm: CascadedShadowMaps::from_settings(settings),
+            previous: None,
+            snapshots: (0..texture_count).map(|_| None).collect(),
+            due: CascadeMask::EMPTY,
+            dirty: CascadeMask::EMPTY,
+            stale: (0..texture_count)
+                .map(|_| CascadeMask::all(settings.cascades_count))
+                .collect(),
             gpu_csm_buffer,
             argument,
             gpu_csm_proj_view_buffer,
             local_argument,
             pso: psos.multi_csm_pass,
+            alpha_test_pso: psos.multi_csm_alpha_test_pass,
             depth,
-            shared: RwcRingBuffer::new(shared),
-        }
+            atlas,
+            depth_slices,
+            targets,
+            target_slices,
+        })
     }
 
-    pub fn update(&mut self, camera: &Camera, light_dir: glam::Vec3) {
-        self.csm.update(camera, light_dir);
+    pub fn shadow_map_desc(settings: &RenderSettings, layout: CascadeLayout) -> TextureDesc {
+        shadow_map_desc(
+            layout,
+            settings.cascade_size,
+            settings.cascades_count,
+            Format::R32,
+            TextureUsages::RenderTarget | TextureUsages::Resource,
+        )
+        .with_name("Shared Cascaded Shadow Maps".into())
+        .with_color(ClearColor::Color([1.0, 1.0, 1.0, 1.0]))
+    }
 
-        self.group.call_primary(|ctx| {
-            ctx.update_buffer(
-                self.gpu_csm_buffer,
-                self.shared.head,
-                &[self.csm.cascades.clone()],
-            );
-        });
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        light_dir: glam::Vec3,
+        slot: usize,
+        frame: usize,
+        latency: usize,
+        prediction_error: Option<f32>,
+    ) -> Result<(), RenderError> {
+        let snapshot = CameraSnapshot::new(camera, frame);
+        // A predicted camera already follows the motion, expanding by the
+        // whole sweep again would only waste shadow map resolution.
+        self.csm.expansion = match prediction_error {
+            Some(angle) => sweep_expansion(&snapshot, angle),
+            None => self
+                .previous
+                .map(|previous| motion_expansion(&previous, &snapshot, latency))
+                .unwrap_or_default(),
+        };
+        self.previous = Some(snapshot);
+
+        self.due = self.csm.update(camera, light_dir);
+        for stale in &mut self.stale {
+            *stale |= self.due;
+        }
+        self.dirty = std::mem::take(&mut self.stale[slot]);
 
-        self.group.call_secondary(|ctx| {
-            for i in 0..self.count {
-                ctx.update_buffer(
-                    self.gpu_csm_proj_view_buffer,
-                    self.count * self.shared.head + i,
-                    &[Cascade {
-                        proj_view: self.csm.cascades.cascade_proj_views[i],
-                    }],
-                );
-            }
+        self.snapshots[slot] = Some(CascadeSnapshot {
+            camera: snapshot,
+            cascades: self.csm.cascades.clone(),
         });
+
+        self.consumer.update_buffer(
+            self.gpu_csm_buffer,
+            slot,
+            std::slice::from_ref(&self.csm.cascades),
+        )?;
+
+        for i in 0..self.count {
+            self.producer.update_buffer(
+                self.gpu_csm_proj_view_buffer,
+                self.count * slot + i,
+                &[Cascade {
+                    proj_view: self.csm.cascades.cascade_proj_views[i],
+                }],
+            )?;
+        }
+
+        Ok(())
     }
 
-    pub fn render(&self, world: &World) {
-        self.group.call_secondary(|ctx| {
-            let mut cmd = ctx.create_encoder(CommandType::Graphics);
-            cmd.set_barriers(&[Barrier::Texture(
-                *self.shared.head_data(),
-                ResourceState::RenderTarget,
-                Subresource::Local(None),
-            )]);
-
-            {
-                let mut encoder = cmd.render(
-                    "Cascaded Shadow Maps".into(),
-                    &[*self.shared.head_data()],
-                    Some(self.depth),
-                );
-                encoder.set_render_pipeline(self.pso);
-                encoder.clear_depth(self.depth, None);
-                encoder.clear_rt(*self.shared.head_data(), None);
-                encoder.set_topology(GeomTopology::Triangles);
-
-                encoder.set_scissor(Scissor {
-                    x: 0,
-                    y: 0,
-                    w: self.size * 2,
-                    h: self.size * 2,
-                });
-
-                for i in 0..self.count {
-                    let i = i as u32;
-                    let row = i / 2;
-                    let col = i % 2;
-                    encoder.set_viewport(Viewport {
-                        x: (self.size * col) as f32,
-                        y: (self.size * row) as f32,
-                        w: self.size as f32,
-                        h: self.size as f32,
-                    });
-
-                    encoder.bind_shader_argument(
-                        0,
-                        self.local_argument,
-                        size_of::<Cascade>() * (self.shared.head * self.count + i as usize),
-                    );
-
-                    for (_, (transform, mesh)) in world
-                        .query::<(&GpuTransformComponent, &GpuMeshComponent)>()
-                        .iter()
-                    {
-                        encoder.bind_shader_argument(
-                            1,
-                            transform.argument,
-                            size_of::<GpuTransform>() * self.shared.head,
-                        );
-                        encoder.bind_vertex_buffer(mesh.pos_vb, 0);
-                        encoder.bind_index_buffer(mesh.ib, IndexType::U32);
-                        encoder.draw_indexed(
-                            mesh.index_count,
-                            mesh.start_index_location,
-                            mesh.base_vertex_location,
-                        );
+    pub fn snapshot(&self, slot: usize) -> Option<&CascadeSnapshot> {
+        self.snapshots[slot].as_ref()
+    }
+
+    pub fn dirty_regions(&self) -> SmallVec<[Region; 8]> {
+        self.dirty
+            .iter()
+            .map(|i| cascade_region(self.layout, self.size, self.count, i))
+            .collect()
+    }
+
+    fn target(&self, slot: usize) -> Handle<Texture> {
+        self.atlas.unwrap_or(self.targets[slot])
+    }
+
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        world: &'a World,
+        frame_idx: usize,
+        slot: usize,
+    ) {
+        graph.add_pass(
+            PassDesc::new("Cascaded Shadow Maps")
+                .with_write_texture(self.target(slot), ResourceState::RenderTarget)
+                .with_write_texture(self.depth, ResourceState::DepthWrite),
+            move |cmd| self.render(cmd, world, frame_idx, slot),
+        );
+
+        if let Some(atlas) = self.atlas {
+            graph.add_pass(
+                PassDesc::new("Copy Cascades")
+                    .with_read_texture(atlas, ResourceState::CopySrc)
+                    .with_write_texture(self.targets[slot], ResourceState::CopyDst),
+                move |cmd| {
+                    let encoder = cmd.transfer("Copy Cascades".into());
+                    for region in self.dirty_regions() {
+                        encoder.copy_texture(atlas, self.targets[slot], Some(region))?;
                     }
+
+                    Ok(())
+                },
+            );
+        }
+
+        for slice in &self.depth_slices {
+            graph.alias_texture(*slice, self.depth);
+        }
+
+        for slice in &self.target_slices[slot] {
+            graph.alias_texture(*slice, self.targets[slot]);
+        }
+    }
+
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        world: &World,
+        frame_idx: usize,
+        slot: usize,
+    ) -> Result<(), RenderError> {
+        match self.layout {
+            CascadeLayout::Atlas => {
+                let target = self.target(slot);
+
+                let mut encoder =
+                    cmd.render("Cascaded Shadow Maps".into(), &[target], Some(self.depth))?;
+
+                for i in self.due.iter() {
+                    let region = cascade_region(self.layout, self.size, self.count, i);
+                    encoder.clear_depth_region(self.depth, None, region)?;
+                    encoder.clear_rt_region(target, None, region)?;
+
+                    self.render_cascade(&mut encoder, world, frame_idx, slot, i)?;
+                }
+            }
+            CascadeLayout::Array => {
+                for i in self.due.iter() {
+                    let target = &self.target_slices[slot][i];
+                    let depth = &self.depth_slices[i];
+                    let mut encoder =
+                        cmd.render(format!("Cascade {}", i).into(), &[*target], Some(*depth))?;
+                    encoder.clear_depth(*depth, None)?;
+                    encoder.clear_rt(*target, None)?;
+
+                    self.render_cascade(&mut encoder, world, frame_idx, slot, i)?;
                 }
             }
+        }
 
-            ctx.enqueue(cmd);
+        Ok(())
+    }
+
+    fn render_cascade(
+        &self,
+        encoder: &mut impl RenderEncoder,
+        world: &World,
+        frame_idx: usize,
+        slot: usize,
+        i: usize,
+    ) -> Result<(), RenderError> {
+        let cascades = self
+            .snapshot(slot)
+            .map_or(&self.csm.cascades, |snapshot| &snapshot.cascades);
+        let viewport = cascade_viewport(self.layout, self.size, self.count, i);
+
+        encoder.set_render_pipeline(self.pso)?;
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.set_viewport(viewport);
+        encoder.set_scissor(Scissor {
+            x: viewport.x as i32,
+            y: viewport.y as i32,
+            w: self.size,
+            h: self.size,
         });
+
+        let local_offset = size_of::<Cascade>() * (slot * self.count + i);
+        encoder.bind_shader_argument(0, self.local_argument, local_offset)?;
+
+        let proj_view = cascades.cascade_proj_views[i];
+
+        for (_, (transform, mesh, bounds)) in world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                Option<&BoundsComponent>,
+            )>()
+            .without::<&AlphaTestComponent>()
+            .without::<&AlphaBlendComponent>()
+            .iter()
+        {
+            if bounds.is_some_and(|bounds| !bounds.aabb.intersects_ortho(&proj_view)) {
+                continue;
+            }
+
+            encoder.bind_shader_argument(1, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
+        }
+
+        // Blended geometry casts cutout shadows as well.
+        encoder.set_render_pipeline(self.alpha_test_pso)?;
+        encoder.bind_shader_argument(0, self.local_argument, local_offset)?;
+
+        for (_, (transform, mesh, material, bounds)) in world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                &GpuMaterialComponent,
+                Option<&BoundsComponent>,
+            )>()
+            .with::<Or<&AlphaTestComponent, &AlphaBlendComponent>>()
+            .iter()
+        {
+            if bounds.is_some_and(|bounds| !bounds.aabb.intersects_ortho(&proj_view)) {
+                continue;
+            }
+
+            encoder.bind_shader_argument(1, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_shader_argument(2, material.argument, 0)?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_vertex_buffer(mesh.uv_vb, 1)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
+        }
+
+        Ok(())
     }
 }
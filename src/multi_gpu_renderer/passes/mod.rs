@@ -1,6 +1,366 @@
 pub mod csm;
+pub mod depth_reduction;
 pub mod directional_light_pass;
+pub mod forward_pass;
 pub mod gamma_corr_pass;
 pub mod gpass;
 pub mod m_csm;
 pub mod zpass;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use clap::Parser;
+    use glam::{Mat4, Vec3, Vec4};
+    use hecs::World;
+
+    use crate::{
+        collections::handle::Handle,
+        engine::{camera::Camera, gltf::GltfScene, propagate_transforms, scene_bounds},
+        multi_gpu_renderer::{
+            GpuGlobals, TexturePlaceholders, cpu_shaders::with_cpu_shaders, create_multi_gpu_scene,
+            pso::PsoCollection, shaders::ShaderCollection, update_gpu_transforms,
+        },
+        ra::{
+            backend::Backend,
+            command::{RenderCommandContext, RenderCommandEncoder, TransferEncoder},
+            context::{Context, ContextGroup},
+            resources::{RenderResourceContext, Texture},
+            shader::{RenderShaderContext, ShaderArgument, ShaderArgumentDesc},
+            system::RenderSystem,
+        },
+        render_graph::{RenderGraph, TextureSlots, channel::CrossDeviceChannel},
+        rhi::{
+            command::CommandType,
+            null::{
+                backend::NullBackend,
+                device::NullDevice,
+                golden::{assert_golden, texels_to_image},
+            },
+            resources::{BufferDesc, BufferUsages, TextureDesc, buffer_row_pitch},
+        },
+        settings::{CliRenderSettings, RenderSettings, merge_settings},
+    };
+
+    use super::{m_csm::MultiCascadedShadowMapsPass, zpass::ZPass};
+
+    const EXTENT: [u32; 2] = [64, 64];
+
+    // Faces of a unit cube, wound counter-clockwise seen from outside as
+    // glTF expects.
+    fn cube() -> (Vec<Vec3>, Vec<Vec3>, Vec<u32>) {
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut indices = vec![];
+
+        for normal in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ] {
+            let u = normal.any_orthonormal_vector();
+            let v = normal.cross(u);
+
+            let base = positions.len() as u32;
+            for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                positions.push(0.5 * (normal + a * u + b * v));
+                normals.push(normal);
+            }
+            indices.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+        }
+
+        (positions, normals, indices)
+    }
+
+    // A ground slab with a pillar and a smaller box on it, all instances of
+    // the same cube.
+    fn scene() -> GltfScene {
+        let (positions, normals, indices) = cube();
+        let bytes = [
+            bytemuck::cast_slice::<_, u8>(&positions),
+            bytemuck::cast_slice(&normals),
+            bytemuck::cast_slice(&indices),
+        ];
+        let [positions_len, normals_len, indices_len] = bytes.map(<[u8]>::len);
+
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0, 1, 2] }}],
+                "nodes": [
+                    {{ "mesh": 0, "translation": [0.0, -0.1, 0.0], "scale": [12.0, 0.2, 12.0] }},
+                    {{ "mesh": 0, "translation": [0.0, 1.0, 0.0], "scale": [1.0, 2.0, 1.0] }},
+                    {{ "mesh": 0, "translation": [2.5, 0.5, -1.5] }}
+                ],
+                "meshes": [{{
+                    "primitives": [{{
+                        "attributes": {{ "POSITION": 0, "NORMAL": 1 }},
+                        "indices": 2,
+                        "material": 0
+                    }}]
+                }}],
+                "materials": [{{}}],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": {vertices},
+                        "type": "VEC3", "min": [-0.5, -0.5, -0.5], "max": [0.5, 0.5, 0.5]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": {vertices}, "type": "VEC3" }},
+                    {{ "bufferView": 2, "componentType": 5125, "count": {index_count}, "type": "SCALAR" }}
+                ],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteLength": {positions_len} }},
+                    {{ "buffer": 0, "byteOffset": {positions_len}, "byteLength": {normals_len} }},
+                    {{
+                        "buffer": 0, "byteOffset": {indices_offset},
+                        "byteLength": {indices_len}
+                    }}
+                ],
+                "buffers": [{{
+                    "byteLength": {buffer_len},
+                    "uri": "data:application/octet-stream;base64,{data}"
+                }}]
+            }}"#,
+            vertices = positions.len(),
+            index_count = indices.len(),
+            indices_offset = positions_len + normals_len,
+            buffer_len = positions_len + normals_len + indices_len,
+            data = base64::encode(bytes.concat()),
+        );
+
+        let path = std::env::temp_dir().join(format!("fotia-{}-passes.gltf", std::process::id()));
+        std::fs::write(&path, json).expect("failed to write scene");
+        let scene = GltfScene::load(&path);
+        std::fs::remove_file(path).expect("failed to remove scene");
+
+        scene
+    }
+
+    struct Fixture {
+        rs: Arc<RenderSystem>,
+        group: Arc<ContextGroup<NullDevice>>,
+        settings: RenderSettings,
+        psos: PsoCollection<NullDevice>,
+        world: World,
+        globals: Handle<ShaderArgument>,
+        camera: Camera,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let settings = merge_settings(
+                CliRenderSettings::parse_from([
+                    "fotia",
+                    "--scene-path=",
+                    "--asset-path=",
+                    "--cascade-size=64",
+                    "--cascades-count=2",
+                    "--shadows-far=20",
+                    "--cascade-layout=atlas",
+                ]),
+                toml::from_str("").expect("failed to parse settings"),
+            );
+
+            let backend = Backend::new(with_cpu_shaders(NullBackend::new()));
+            let rs = Arc::new(RenderSystem::new(&[]));
+            let group = Arc::new(backend.create_group(&[0]));
+
+            let shaders = ShaderCollection::new(&backend, false, &settings)
+                .expect("failed to compile shaders");
+            let psos = PsoCollection::new(Arc::clone(&rs), Arc::clone(&group), &shaders);
+
+            let mut world = World::new();
+            let placeholders = TexturePlaceholders::new(&rs, &group);
+            create_multi_gpu_scene(scene(), &mut world, &rs, &group, &settings, &placeholders);
+            propagate_transforms(&mut world);
+            update_gpu_transforms(&world, &group, 0);
+
+            let mut camera = Camera::new(0.1, 30.0, 60.0f32.to_radians(), EXTENT);
+            camera.view = Mat4::look_at_lh(Vec3::new(2.0, 5.0, -8.0), Vec3::ZERO, Vec3::Y);
+
+            let ctx = group.primary();
+            let globals_buffer = rs.create_buffer_handle();
+            let globals = rs.create_shader_argument_handle();
+            let (view, proj) = (camera.view(), camera.proj());
+            ctx.bind_buffer(
+                globals_buffer,
+                BufferDesc::cpu_to_gpu(size_of::<GpuGlobals>(), BufferUsages::Uniform),
+                None,
+            )
+            .expect("failed to bind buffer");
+            ctx.update_buffer(
+                globals_buffer,
+                0,
+                &[GpuGlobals {
+                    view,
+                    proj,
+                    proj_view: proj * view,
+                    inv_view: view.inverse(),
+                    inv_proj: proj.inverse(),
+                    inv_proj_view: (proj * view).inverse(),
+                    eye_pos: view.inverse().w_axis.truncate(),
+                    _pad0: 0.0,
+                    screen_dim: glam::Vec2::from(EXTENT.map(|e| e as f32)),
+                    _pad1: Default::default(),
+                }],
+            )
+            .expect("failed to update globals");
+            ctx.bind_shader_argument(
+                globals,
+                ShaderArgumentDesc {
+                    views: &[],
+                    samplers: &[],
+                    dynamic_buffer: Some(globals_buffer),
+                },
+            )
+            .expect("failed to bind shader argument");
+
+            Self {
+                rs,
+                group,
+                settings,
+                psos,
+                world,
+                globals,
+                camera,
+            }
+        }
+
+        fn ctx(&self) -> &Arc<Context<NullDevice>> {
+            self.group.primary()
+        }
+
+        // Graphs only enqueue, so the frame is committed and submitted here.
+        fn execute(&self, mut graph: RenderGraph<'_, NullDevice>, output: Handle<Texture>) {
+            let ctx = self.ctx();
+            graph.export_texture(output, None);
+            graph.execute(ctx).expect("failed to execute graph");
+            ctx.commit(
+                ctx.create_encoder(CommandType::Graphics)
+                    .expect("failed to create encoder"),
+            );
+            ctx.submit(CommandType::Graphics).expect("failed to submit");
+        }
+
+        // Copies the texture into a readback buffer the way a GPU would.
+        fn read_back(&self, texture: Handle<Texture>, desc: &TextureDesc) -> Vec<Vec4> {
+            let ctx = self.ctx();
+            let pitch = buffer_row_pitch(desc) / size_of::<f32>();
+            let [width, height] = [desc.extent[0] as usize, desc.extent[1] as usize];
+
+            let readback = self.rs.create_buffer_handle();
+            ctx.bind_buffer(
+                readback,
+                BufferDesc::gpu_to_cpu(pitch * height * size_of::<f32>(), BufferUsages::Copy),
+                None,
+            )
+            .expect("failed to bind readback buffer");
+
+            let mut cmd = ctx
+                .create_encoder(CommandType::Graphics)
+                .expect("failed to create encoder");
+            cmd.begin(ctx);
+            cmd.transfer("Readback".into())
+                .copy_texture_to_buffer(texture, readback)
+                .expect("failed to copy texture");
+            ctx.commit(cmd);
+            ctx.submit(CommandType::Graphics).expect("failed to submit");
+            ctx.wait_idle().expect("failed to wait for device");
+
+            let mut data = vec![0.0f32; pitch * height];
+            ctx.read_buffer(readback, 0, &mut data)
+                .expect("failed to read back texture");
+
+            data.chunks_exact(pitch)
+                .flat_map(|row| &row[..width])
+                .map(|depth| Vec4::new(*depth, 0.0, 0.0, 1.0))
+                .collect()
+        }
+    }
+
+    fn assert_covered(texels: &[Vec4], clear: f32) {
+        let covered = texels.iter().filter(|texel| texel.x < clear).count();
+        assert!(
+            covered > texels.len() / 4,
+            "only {} texels covered",
+            covered
+        );
+    }
+
+    #[test]
+    fn depth_prepass_matches_reference() {
+        let fixture = Fixture::new();
+        let ctx = fixture.ctx();
+
+        let zpass = ZPass::new(
+            Arc::clone(&fixture.rs),
+            Arc::clone(ctx),
+            EXTENT,
+            &fixture.psos,
+        );
+        let depth = fixture.rs.create_texture_handle();
+        let desc = ZPass::<NullDevice>::depth_desc(EXTENT);
+        ctx.bind_texture(depth, desc.clone(), None)
+            .expect("failed to bind depth");
+
+        let mut graph = RenderGraph::new();
+        zpass.add_to_graph(&mut graph, fixture.globals, depth, 0, &fixture.world);
+        fixture.execute(graph, depth);
+
+        let texels = fixture.read_back(depth, &desc);
+        assert_covered(&texels, 1.0);
+        assert_golden(
+            &texels_to_image(desc.format, EXTENT, &texels),
+            "zpass_depth",
+        );
+    }
+
+    #[test]
+    fn cascade_atlas_matches_reference() {
+        let fixture = Fixture::new();
+        let ctx = fixture.ctx();
+        let settings = &fixture.settings;
+
+        let desc = MultiCascadedShadowMapsPass::<NullDevice>::shadow_map_desc(
+            settings,
+            settings.cascade_layout,
+        );
+        let shadows = CrossDeviceChannel::new(
+            &fixture.rs,
+            "CSM",
+            Arc::clone(ctx),
+            Arc::clone(ctx),
+            std::slice::from_ref(&desc),
+            1,
+        )
+        .expect("failed to create channel");
+
+        let mut csm = MultiCascadedShadowMapsPass::new(
+            Arc::clone(&fixture.rs),
+            &shadows,
+            settings,
+            settings.cascade_layout,
+            &fixture.psos,
+        )
+        .expect("failed to create cascade pass");
+        csm.csm.set_scene_bounds(scene_bounds(&fixture.world));
+        csm.update(&fixture.camera, Vec3::new(-1.0, -1.0, -1.0), 0, 0, 1, None)
+            .expect("failed to update cascades");
+
+        let mut graph = RenderGraph::new();
+        csm.add_to_graph(&mut graph, &fixture.world, 0, 0);
+        let target = shadows.targets(0)[0];
+        fixture.execute(graph, target);
+
+        let texels = fixture.read_back(target, &desc);
+        let extent = [desc.extent[0], desc.extent[1]];
+        assert_eq!(extent, [2 * settings.cascade_size, settings.cascade_size]);
+        assert_covered(&texels, 1.0);
+        assert_golden(&texels_to_image(desc.format, extent, &texels), "csm_atlas");
+    }
+}
@@ -4,17 +4,21 @@ use hecs::World;
 
 use crate::{
     collections::handle::Handle,
-    engine::{GpuMeshComponent, GpuTransform, GpuTransformComponent},
+    engine::{
+        AlphaBlendComponent, AlphaTestComponent, GpuMaterialComponent, GpuMeshComponent,
+        GpuTransformComponent,
+    },
     multi_gpu_renderer::{GpuGlobals, pso::PsoCollection},
     ra::{
-        command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+        command::{CommandEncoder, RenderCommandEncoder, RenderEncoder},
         context::{Context, RenderDevice},
-        resources::{RenderResourceContext, Texture},
+        resources::Texture,
         shader::{RasterPipeline, ShaderArgument},
         system::RenderSystem,
     },
+    render_graph::{PassDesc, RenderGraph},
     rhi::{
-        command::{CommandType, Subresource},
+        error::RenderError,
         resources::{TextureDesc, TextureUsages},
         types::{ClearColor, Format, GeomTopology, IndexType, ResourceState, Scissor, Viewport},
     },
@@ -25,8 +29,8 @@ pub struct ZPass<D: RenderDevice> {
     pub ctx: Arc<Context<D>>,
 
     pub extent: [u32; 2],
-    pub depth: Handle<Texture>,
     pub pso: Handle<RasterPipeline>,
+    pub alpha_test_pso: Handle<RasterPipeline>,
 }
 
 impl<D: RenderDevice> ZPass<D> {
@@ -36,84 +40,116 @@ impl<D: RenderDevice> ZPass<D> {
         extent: [u32; 2],
         psos: &PsoCollection<D>,
     ) -> Self {
-        let depth = rs.create_texture_handle();
-
-        ctx.bind_texture(
-            depth,
-            TextureDesc::new_2d(extent, Format::D24S8, TextureUsages::DepthTarget)
-                .with_name("Prepass Depth".into())
-                .with_color(ClearColor::Depth(1.0)),
-            None,
-        );
-
         Self {
             rs,
             ctx,
             extent,
-            depth,
             pso: psos.zpass,
+            alpha_test_pso: psos.zpass_alpha_test,
         }
     }
 
-    pub fn render(&self, globals: Handle<ShaderArgument>, frame_idx: usize, world: &World) {
-        let mut cmd = self.ctx.create_encoder(CommandType::Graphics);
-        cmd.set_barriers(&[Barrier::Texture(
-            self.depth,
-            ResourceState::DepthWrite,
-            Subresource::Local(None),
-        )]);
+    pub fn depth_desc(extent: [u32; 2]) -> TextureDesc {
+        TextureDesc::new_2d(
+            extent,
+            Format::D32,
+            TextureUsages::DepthTarget | TextureUsages::Resource,
+        )
+        .with_name("Prepass Depth".into())
+        .with_color(ClearColor::Depth(1.0))
+    }
 
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a, D>,
+        globals: Handle<ShaderArgument>,
+        depth: Handle<Texture>,
+        frame_idx: usize,
+        world: &'a World,
+    ) {
+        graph.add_pass(
+            PassDesc::new("Z Prepass").with_write_texture(depth, ResourceState::DepthWrite),
+            move |cmd| self.render(cmd, globals, depth, frame_idx, world),
+        );
+    }
+
+    pub fn render(
+        &self,
+        cmd: &mut CommandEncoder<D>,
+        globals: Handle<ShaderArgument>,
+        depth: Handle<Texture>,
+        frame_idx: usize,
+        world: &World,
+    ) -> Result<(), RenderError> {
+        let mut encoder = cmd.render("Z Prepass".into(), &[], Some(depth))?;
+        encoder.set_render_pipeline(self.pso)?;
+
+        encoder.clear_depth(depth, None)?;
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: self.extent[0] as f32,
+            h: self.extent[1] as f32,
+        });
+        encoder.set_scissor(Scissor {
+            x: 0,
+            y: 0,
+            w: self.extent[0],
+            h: self.extent[1],
+        });
+        encoder.set_topology(GeomTopology::Triangles);
+        encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx)?;
+
+        // Blended geometry is shaded in the forward pass and must not occlude
+        // what is behind it.
+        for (_, (transform, mesh)) in world
+            .query::<(&GpuTransformComponent, &GpuMeshComponent)>()
+            .without::<&AlphaTestComponent>()
+            .without::<&AlphaBlendComponent>()
+            .iter()
         {
-            let mut encoder = cmd.render("Z Prepass".into(), &[], Some(self.depth));
-            encoder.set_render_pipeline(self.pso);
+            encoder.bind_shader_argument(1, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
+        }
 
-            encoder.clear_depth(self.depth, None);
-            encoder.set_viewport(Viewport {
-                x: 0.0,
-                y: 0.0,
-                w: self.extent[0] as f32,
-                h: self.extent[1] as f32,
-            });
-            encoder.set_scissor(Scissor {
-                x: 0,
-                y: 0,
-                w: self.extent[0],
-                h: self.extent[1],
-            });
-            encoder.set_topology(GeomTopology::Triangles);
-            encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx);
+        encoder.set_render_pipeline(self.alpha_test_pso)?;
+        encoder.bind_shader_argument(0, globals, size_of::<GpuGlobals>() * frame_idx)?;
 
-            for (_, (transform, mesh)) in world
-                .query::<(&GpuTransformComponent, &GpuMeshComponent)>()
-                .iter()
-            {
-                encoder.bind_shader_argument(
-                    1,
-                    transform.argument,
-                    size_of::<GpuTransform>() * frame_idx,
-                );
-                encoder.bind_vertex_buffer(mesh.pos_vb, 0);
-                encoder.bind_index_buffer(mesh.ib, IndexType::U32);
-                encoder.draw_indexed(
-                    mesh.index_count,
-                    mesh.start_index_location,
-                    mesh.base_vertex_location,
-                );
-            }
+        for (_, (transform, mesh, material)) in world
+            .query::<(
+                &GpuTransformComponent,
+                &GpuMeshComponent,
+                &GpuMaterialComponent,
+            )>()
+            .with::<&AlphaTestComponent>()
+            .iter()
+        {
+            encoder.bind_shader_argument(1, transform.argument, transform.offset(frame_idx))?;
+            encoder.bind_shader_argument(2, material.argument, 0)?;
+            encoder.bind_vertex_buffer(mesh.pos_vb, 0)?;
+            encoder.bind_vertex_buffer(mesh.uv_vb, 1)?;
+            encoder.bind_index_buffer(mesh.ib, IndexType::U32)?;
+            encoder.draw_indexed_instanced(
+                mesh.index_count,
+                transform.instance_count,
+                mesh.start_index_location,
+                mesh.base_vertex_location,
+                0,
+            );
         }
 
-        self.ctx.enqueue(cmd);
+        Ok(())
     }
 
     pub fn resize(&mut self, extent: [u32; 2]) {
-        self.ctx.bind_texture(
-            self.depth,
-            TextureDesc::new_2d(extent, Format::D24S8, TextureUsages::DepthTarget)
-                .with_name("Prepass Depth".into())
-                .with_color(ClearColor::Depth(1.0)),
-            None,
-        );
-
         self.extent = extent;
     }
 }
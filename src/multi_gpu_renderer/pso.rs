@@ -3,7 +3,7 @@ use std::sync::Arc;
 use crate::{
     collections::handle::Handle,
     ra::{
-        context::{ContextDual, RenderDevice},
+        context::{ContextGroup, RenderDevice},
         shader::{RasterPipeline, RasterPipelineDesc, RenderShaderContext},
         system::RenderSystem,
     },
@@ -12,37 +12,53 @@ use crate::{
             BindingEntry, BindingSet, BindingType, PipelineLayoutDesc, SamplerType, StaticSampler,
         },
         types::{
-            AddressMode, ComparisonFunc, CullMode, DepthOp, DepthStateDesc, Filter, Format,
-            InputElementDesc, VertexAttribute, VertexType,
+            AddressMode, BlendMode, ComparisonFunc, CullMode, DepthOp, DepthStateDesc, Filter,
+            Format, InputElementDesc, VertexAttribute, VertexType,
         },
     },
 };
 
+use crate::settings::CascadeLayout;
+
 use super::shaders::ShaderCollection;
 
 pub struct PsoCollection<D: RenderDevice> {
     rs: Arc<RenderSystem>,
-    group: Arc<ContextDual<D>>,
+    group: Arc<ContextGroup<D>>,
     pub zpass: Handle<RasterPipeline>,
+    pub zpass_alpha_test: Handle<RasterPipeline>,
     pub csm_pass: Handle<RasterPipeline>,
+    pub csm_alpha_test_pass: Handle<RasterPipeline>,
     pub multi_csm_pass: Handle<RasterPipeline>,
-    pub directional_light_pass: Handle<RasterPipeline>,
+    pub multi_csm_alpha_test_pass: Handle<RasterPipeline>,
+    pub directional_light_atlas_pass: Handle<RasterPipeline>,
+    pub directional_light_array_pass: Handle<RasterPipeline>,
     pub gamma_corr_pass: Handle<RasterPipeline>,
+    pub depth_reduction_pass: Handle<RasterPipeline>,
     pub g_pass: Handle<RasterPipeline>,
+    pub forward_atlas_pass: Handle<RasterPipeline>,
+    pub forward_array_pass: Handle<RasterPipeline>,
 }
 
 impl<D: RenderDevice> PsoCollection<D> {
     pub fn new(
         rs: Arc<RenderSystem>,
-        group: Arc<ContextDual<D>>,
+        group: Arc<ContextGroup<D>>,
         shaders: &ShaderCollection,
     ) -> Self {
         let zpass = rs.create_raster_pipeline_handle();
+        let zpass_alpha_test = rs.create_raster_pipeline_handle();
         let csm_pass = rs.create_raster_pipeline_handle();
+        let csm_alpha_test_pass = rs.create_raster_pipeline_handle();
         let multi_csm_pass = rs.create_raster_pipeline_handle();
-        let directional_light_pass = rs.create_raster_pipeline_handle();
+        let multi_csm_alpha_test_pass = rs.create_raster_pipeline_handle();
+        let directional_light_atlas_pass = rs.create_raster_pipeline_handle();
+        let directional_light_array_pass = rs.create_raster_pipeline_handle();
         let gamma_corr_pass = rs.create_raster_pipeline_handle();
+        let depth_reduction_pass = rs.create_raster_pipeline_handle();
         let g_pass = rs.create_raster_pipeline_handle();
+        let forward_atlas_pass = rs.create_raster_pipeline_handle();
+        let forward_array_pass = rs.create_raster_pipeline_handle();
 
         group.parallel(|ctx| {
             // CSM Pass
@@ -63,7 +79,8 @@ impl<D: RenderDevice> PsoCollection<D> {
                     ],
                     static_samplers: &[],
                 },
-            );
+            )
+            .expect("failed to bind pipeline layout");
 
             ctx.bind_raster_pipeline(
                 csm_pass,
@@ -75,7 +92,7 @@ impl<D: RenderDevice> PsoCollection<D> {
                     }],
                     depth_bias: 10000,
                     slope_bias: 5.0,
-                    depth_clip: false,
+                    depth_clip: true,
                     depth: Some(DepthStateDesc {
                         op: DepthOp::LessEqual,
                         format: Format::D32,
@@ -83,10 +100,12 @@ impl<D: RenderDevice> PsoCollection<D> {
                     }),
                     render_targets: &[],
                     cull_mode: CullMode::Back,
+                    blend: BlendMode::None,
                     vs: &shaders.csm,
                     shaders: &[],
                 },
-            );
+            )
+            .expect("failed to bind raster pipeline");
 
             ctx.bind_raster_pipeline(
                 multi_csm_pass,
@@ -98,7 +117,7 @@ impl<D: RenderDevice> PsoCollection<D> {
                     }],
                     depth_bias: 10000,
                     slope_bias: 5.0,
-                    depth_clip: false,
+                    depth_clip: true,
                     depth: Some(DepthStateDesc {
                         op: DepthOp::LessEqual,
                         format: Format::D32,
@@ -106,15 +125,110 @@ impl<D: RenderDevice> PsoCollection<D> {
                     }),
                     render_targets: &[Format::R32],
                     cull_mode: CullMode::Back,
+                    blend: BlendMode::None,
                     vs: &shaders.csm,
                     shaders: &[&shaders.csm_ps],
                 },
-            );
+            )
+            .expect("failed to bind raster pipeline");
 
             rs.free_pipeline_layout_handle(csm_layout);
+
+            // Alpha Tested CSM Pass
+            let csm_alpha_test_layout = rs.create_pipeline_layout_handle();
+
+            ctx.bind_pipeline_layout(
+                csm_alpha_test_layout,
+                PipelineLayoutDesc {
+                    sets: &[
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[BindingEntry::new(BindingType::Srv, 5)],
+                            use_dynamic_buffer: true,
+                        },
+                    ],
+                    static_samplers: &[StaticSampler {
+                        ty: SamplerType::Sample(Filter::Linear),
+                        address_mode: AddressMode::Wrap,
+                    }],
+                },
+            )
+            .expect("failed to bind pipeline layout");
+
+            // Foliage is mostly double sided, so cutout casters are not culled.
+            ctx.bind_raster_pipeline(
+                csm_alpha_test_pass,
+                RasterPipelineDesc {
+                    layout: Some(csm_alpha_test_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float4,
+                        },
+                    ],
+                    depth_bias: 10000,
+                    slope_bias: 5.0,
+                    depth_clip: true,
+                    depth: Some(DepthStateDesc {
+                        op: DepthOp::LessEqual,
+                        format: Format::D32,
+                        read_only: false,
+                    }),
+                    render_targets: &[],
+                    cull_mode: CullMode::None,
+                    blend: BlendMode::None,
+                    vs: &shaders.csm_alpha_test,
+                    shaders: &[&shaders.csm_alpha_test_ps],
+                },
+            )
+            .expect("failed to bind raster pipeline");
+
+            ctx.bind_raster_pipeline(
+                multi_csm_alpha_test_pass,
+                RasterPipelineDesc {
+                    layout: Some(csm_alpha_test_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float4,
+                        },
+                    ],
+                    depth_bias: 10000,
+                    slope_bias: 5.0,
+                    depth_clip: true,
+                    depth: Some(DepthStateDesc {
+                        op: DepthOp::LessEqual,
+                        format: Format::D32,
+                        read_only: false,
+                    }),
+                    render_targets: &[Format::R32],
+                    cull_mode: CullMode::None,
+                    blend: BlendMode::None,
+                    vs: &shaders.csm_alpha_test,
+                    shaders: &[&shaders.csm_alpha_test_ps],
+                },
+            )
+            .expect("failed to bind raster pipeline");
+
+            rs.free_pipeline_layout_handle(csm_alpha_test_layout);
         });
 
-        group.call_primary(|ctx| {
+        group.parallel(|ctx| {
             // ZPass
             let zpass_layout = rs.create_pipeline_layout_handle();
 
@@ -133,7 +247,8 @@ impl<D: RenderDevice> PsoCollection<D> {
                     ],
                     static_samplers: &[],
                 },
-            );
+            )
+            .expect("failed to bind pipeline layout");
 
             ctx.bind_raster_pipeline(
                 zpass,
@@ -148,18 +263,81 @@ impl<D: RenderDevice> PsoCollection<D> {
                     depth_clip: true,
                     depth: Some(DepthStateDesc {
                         op: DepthOp::LessEqual,
-                        format: Format::D24S8,
+                        format: Format::D32,
                         read_only: false,
                     }),
                     render_targets: &[],
                     cull_mode: CullMode::Back,
+                    blend: BlendMode::None,
                     vs: &shaders.zpass,
                     shaders: &[],
                 },
-            );
+            )
+            .expect("failed to bind raster pipeline");
 
             rs.free_pipeline_layout_handle(zpass_layout);
 
+            // Alpha Tested ZPass
+            let zpass_alpha_test_layout = rs.create_pipeline_layout_handle();
+
+            ctx.bind_pipeline_layout(
+                zpass_alpha_test_layout,
+                PipelineLayoutDesc {
+                    sets: &[
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[BindingEntry::new(BindingType::Srv, 5)],
+                            use_dynamic_buffer: true,
+                        },
+                    ],
+                    static_samplers: &[StaticSampler {
+                        ty: SamplerType::Sample(Filter::Linear),
+                        address_mode: AddressMode::Wrap,
+                    }],
+                },
+            )
+            .expect("failed to bind pipeline layout");
+
+            ctx.bind_raster_pipeline(
+                zpass_alpha_test,
+                RasterPipelineDesc {
+                    layout: Some(zpass_alpha_test_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float4,
+                        },
+                    ],
+                    depth_bias: 0,
+                    slope_bias: 0.0,
+                    depth_clip: true,
+                    depth: Some(DepthStateDesc {
+                        op: DepthOp::LessEqual,
+                        format: Format::D32,
+                        read_only: false,
+                    }),
+                    render_targets: &[],
+                    cull_mode: CullMode::Back,
+                    blend: BlendMode::None,
+                    vs: &shaders.zpass_alpha_test,
+                    shaders: &[&shaders.zpass_alpha_test_ps],
+                },
+            )
+            .expect("failed to bind raster pipeline");
+
+            rs.free_pipeline_layout_handle(zpass_alpha_test_layout);
+
             // Directional Light Pass
             let directional_light_layout = rs.create_pipeline_layout_handle();
 
@@ -172,7 +350,7 @@ impl<D: RenderDevice> PsoCollection<D> {
                             use_dynamic_buffer: true,
                         },
                         BindingSet {
-                            entries: &[BindingEntry::new(BindingType::Srv, 3)],
+                            entries: &[BindingEntry::new(BindingType::Srv, 4)],
                             use_dynamic_buffer: true,
                         },
                         BindingSet {
@@ -185,10 +363,38 @@ impl<D: RenderDevice> PsoCollection<D> {
                         address_mode: AddressMode::Clamp,
                     }],
                 },
-            );
+            )
+            .expect("failed to bind pipeline layout");
+
+            ctx.bind_raster_pipeline(
+                directional_light_atlas_pass,
+                RasterPipelineDesc {
+                    layout: Some(directional_light_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float3,
+                        },
+                    ],
+                    depth_bias: 0,
+                    slope_bias: 0.0,
+                    depth_clip: false,
+                    depth: None,
+                    render_targets: &[Format::Rgba32],
+                    cull_mode: CullMode::None,
+                    blend: BlendMode::None,
+                    vs: &shaders.fullscreen,
+                    shaders: &[&shaders.directional_light_atlas_pass],
+                },
+            )
+            .expect("failed to bind raster pipeline");
 
             ctx.bind_raster_pipeline(
-                directional_light_pass,
+                directional_light_array_pass,
                 RasterPipelineDesc {
                     layout: Some(directional_light_layout),
                     input_elements: &[
@@ -207,10 +413,12 @@ impl<D: RenderDevice> PsoCollection<D> {
                     depth: None,
                     render_targets: &[Format::Rgba32],
                     cull_mode: CullMode::None,
+                    blend: BlendMode::None,
                     vs: &shaders.fullscreen,
-                    shaders: &[&shaders.directional_light_pass],
+                    shaders: &[&shaders.directional_light_array_pass],
                 },
-            );
+            )
+            .expect("failed to bind raster pipeline");
 
             rs.free_pipeline_layout_handle(directional_light_layout);
 
@@ -229,7 +437,8 @@ impl<D: RenderDevice> PsoCollection<D> {
                         address_mode: AddressMode::Clamp,
                     }],
                 },
-            );
+            )
+            .expect("failed to bind pipeline layout");
 
             ctx.bind_raster_pipeline(
                 gamma_corr_pass,
@@ -251,13 +460,65 @@ impl<D: RenderDevice> PsoCollection<D> {
                     depth: None,
                     render_targets: &[Format::Rgba8Unorm],
                     cull_mode: CullMode::None,
+                    blend: BlendMode::None,
                     vs: &shaders.fullscreen,
                     shaders: &[&shaders.gamma_corr_pass],
                 },
-            );
+            )
+            .expect("failed to bind raster pipeline");
 
             rs.free_pipeline_layout_handle(gamme_corr_layout);
 
+            // Depth Reduction Pass
+            let depth_reduction_layout = rs.create_pipeline_layout_handle();
+
+            ctx.bind_pipeline_layout(
+                depth_reduction_layout,
+                PipelineLayoutDesc {
+                    sets: &[
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[BindingEntry::new(BindingType::Srv, 1)],
+                            use_dynamic_buffer: false,
+                        },
+                    ],
+                    static_samplers: &[],
+                },
+            )
+            .expect("failed to bind pipeline layout");
+
+            ctx.bind_raster_pipeline(
+                depth_reduction_pass,
+                RasterPipelineDesc {
+                    layout: Some(depth_reduction_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float2,
+                        },
+                    ],
+                    depth_bias: 0,
+                    slope_bias: 0.0,
+                    depth_clip: false,
+                    depth: None,
+                    render_targets: &[Format::Rg32],
+                    cull_mode: CullMode::None,
+                    blend: BlendMode::None,
+                    vs: &shaders.fullscreen,
+                    shaders: &[&shaders.depth_reduction_pass],
+                },
+            )
+            .expect("failed to bind raster pipeline");
+
+            rs.free_pipeline_layout_handle(depth_reduction_layout);
+
             // G Pass
             let gpass_layout = rs.create_pipeline_layout_handle();
 
@@ -270,7 +531,7 @@ impl<D: RenderDevice> PsoCollection<D> {
                             use_dynamic_buffer: true,
                         },
                         BindingSet {
-                            entries: &[BindingEntry::new(BindingType::Srv, 2)],
+                            entries: &[BindingEntry::new(BindingType::Srv, 5)],
                             use_dynamic_buffer: true,
                         },
                         BindingSet {
@@ -283,7 +544,8 @@ impl<D: RenderDevice> PsoCollection<D> {
                         address_mode: AddressMode::Wrap,
                     }],
                 },
-            );
+            )
+            .expect("failed to bind pipeline layout");
 
             ctx.bind_raster_pipeline(
                 g_pass,
@@ -300,7 +562,7 @@ impl<D: RenderDevice> PsoCollection<D> {
                         },
                         InputElementDesc {
                             semantic: VertexAttribute::Uv(0),
-                            format: VertexType::Float3,
+                            format: VertexType::Float4,
                         },
                         InputElementDesc {
                             semantic: VertexAttribute::Tangent(0),
@@ -312,28 +574,171 @@ impl<D: RenderDevice> PsoCollection<D> {
                     depth_clip: true,
                     depth: Some(DepthStateDesc {
                         op: DepthOp::Equal,
-                        format: Format::D24S8,
+                        format: Format::D32,
                         read_only: true,
                     }),
-                    render_targets: &[Format::Rgba32, Format::Rgba32, Format::Rgba32],
+                    render_targets: &[
+                        Format::Rgba32,
+                        Format::Rgba32,
+                        Format::Rgba32,
+                        Format::Rgba32,
+                    ],
                     cull_mode: CullMode::Back,
+                    blend: BlendMode::None,
                     vs: &shaders.gpass_vs,
                     shaders: &[&shaders.gpass_ps],
                 },
-            );
+            )
+            .expect("failed to bind raster pipeline");
 
             rs.free_pipeline_layout_handle(gpass_layout);
+
+            // Forward Pass
+            let forward_layout = rs.create_pipeline_layout_handle();
+
+            ctx.bind_pipeline_layout(
+                forward_layout,
+                PipelineLayoutDesc {
+                    sets: &[
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[BindingEntry::new(BindingType::Srv, 5)],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[],
+                            use_dynamic_buffer: true,
+                        },
+                        BindingSet {
+                            entries: &[
+                                BindingEntry::new(BindingType::Srv, 2),
+                                BindingEntry::new(BindingType::Cbv, 1),
+                            ],
+                            use_dynamic_buffer: true,
+                        },
+                    ],
+                    static_samplers: &[
+                        StaticSampler {
+                            ty: SamplerType::Sample(Filter::Linear),
+                            address_mode: AddressMode::Wrap,
+                        },
+                        StaticSampler {
+                            ty: SamplerType::Comparasion(ComparisonFunc::LessEqual),
+                            address_mode: AddressMode::Clamp,
+                        },
+                    ],
+                },
+            )
+            .expect("failed to bind pipeline layout");
+
+            ctx.bind_raster_pipeline(
+                forward_atlas_pass,
+                RasterPipelineDesc {
+                    layout: Some(forward_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Normal(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float4,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Tangent(0),
+                            format: VertexType::Float4,
+                        },
+                    ],
+                    depth_bias: 0,
+                    slope_bias: 0.0,
+                    depth_clip: true,
+                    depth: None,
+                    render_targets: &[Format::Rgba32],
+                    cull_mode: CullMode::Back,
+                    blend: BlendMode::Alpha,
+                    vs: &shaders.forward_vs,
+                    shaders: &[&shaders.forward_atlas_ps],
+                },
+            )
+            .expect("failed to bind raster pipeline");
+
+            ctx.bind_raster_pipeline(
+                forward_array_pass,
+                RasterPipelineDesc {
+                    layout: Some(forward_layout),
+                    input_elements: &[
+                        InputElementDesc {
+                            semantic: VertexAttribute::Position(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Normal(0),
+                            format: VertexType::Float3,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Uv(0),
+                            format: VertexType::Float4,
+                        },
+                        InputElementDesc {
+                            semantic: VertexAttribute::Tangent(0),
+                            format: VertexType::Float4,
+                        },
+                    ],
+                    depth_bias: 0,
+                    slope_bias: 0.0,
+                    depth_clip: true,
+                    depth: None,
+                    render_targets: &[Format::Rgba32],
+                    cull_mode: CullMode::Back,
+                    blend: BlendMode::Alpha,
+                    vs: &shaders.forward_vs,
+                    shaders: &[&shaders.forward_array_ps],
+                },
+            )
+            .expect("failed to bind raster pipeline");
+
+            rs.free_pipeline_layout_handle(forward_layout);
         });
 
         Self {
             rs,
             group,
             zpass,
+            zpass_alpha_test,
             csm_pass,
+            csm_alpha_test_pass,
             multi_csm_pass,
-            directional_light_pass,
+            multi_csm_alpha_test_pass,
+            directional_light_atlas_pass,
+            directional_light_array_pass,
             gamma_corr_pass,
+            depth_reduction_pass,
             g_pass,
+            forward_atlas_pass,
+            forward_array_pass,
+        }
+    }
+}
+
+impl<D: RenderDevice> PsoCollection<D> {
+    pub fn directional_light_pass(&self, layout: CascadeLayout) -> Handle<RasterPipeline> {
+        match layout {
+            CascadeLayout::Atlas => self.directional_light_atlas_pass,
+            CascadeLayout::Array => self.directional_light_array_pass,
+        }
+    }
+
+    pub fn forward_pass(&self, layout: CascadeLayout) -> Handle<RasterPipeline> {
+        match layout {
+            CascadeLayout::Atlas => self.forward_atlas_pass,
+            CascadeLayout::Array => self.forward_array_pass,
         }
     }
 }
@@ -342,19 +747,37 @@ impl<D: RenderDevice> Drop for PsoCollection<D> {
     fn drop(&mut self) {
         self.group.parallel(|ctx| {
             ctx.unbind_raster_pipeline(self.zpass);
+            ctx.unbind_raster_pipeline(self.zpass_alpha_test);
             ctx.unbind_raster_pipeline(self.gamma_corr_pass);
+            ctx.unbind_raster_pipeline(self.depth_reduction_pass);
             ctx.unbind_raster_pipeline(self.g_pass);
-            ctx.unbind_raster_pipeline(self.directional_light_pass);
+            ctx.unbind_raster_pipeline(self.directional_light_atlas_pass);
+            ctx.unbind_raster_pipeline(self.directional_light_array_pass);
             ctx.unbind_raster_pipeline(self.csm_pass);
             ctx.unbind_raster_pipeline(self.multi_csm_pass);
+            ctx.unbind_raster_pipeline(self.csm_alpha_test_pass);
+            ctx.unbind_raster_pipeline(self.multi_csm_alpha_test_pass);
+            ctx.unbind_raster_pipeline(self.forward_atlas_pass);
+            ctx.unbind_raster_pipeline(self.forward_array_pass);
         });
 
         self.rs.free_raster_pipeline_handle(self.zpass);
+        self.rs.free_raster_pipeline_handle(self.zpass_alpha_test);
         self.rs.free_raster_pipeline_handle(self.gamma_corr_pass);
+        self.rs
+            .free_raster_pipeline_handle(self.depth_reduction_pass);
         self.rs.free_raster_pipeline_handle(self.g_pass);
         self.rs
-            .free_raster_pipeline_handle(self.directional_light_pass);
+            .free_raster_pipeline_handle(self.directional_light_atlas_pass);
+        self.rs
+            .free_raster_pipeline_handle(self.directional_light_array_pass);
         self.rs.free_raster_pipeline_handle(self.csm_pass);
         self.rs.free_raster_pipeline_handle(self.multi_csm_pass);
+        self.rs
+            .free_raster_pipeline_handle(self.csm_alpha_test_pass);
+        self.rs
+            .free_raster_pipeline_handle(self.multi_csm_alpha_test_pass);
+        self.rs.free_raster_pipeline_handle(self.forward_atlas_pass);
+        self.rs.free_raster_pipeline_handle(self.forward_array_pass);
     }
 }
@@ -1,22 +1,35 @@
+use std::borrow::Cow;
+
 use crate::{
+    multi_gpu_renderer::{csm::atlas_grid, passes::depth_reduction::DEPTH_TILE_SIZE},
     ra::{backend::Backend, context::RenderDevice},
     rhi::{
         backend::Api,
+        error::RenderError,
         shader::{CompiledShader, ShaderDesc},
         types::ShaderType,
     },
-    settings::RenderSettings,
+    settings::{CascadeLayout, RenderSettings},
 };
 
 pub struct ShaderCollection {
     pub csm: CompiledShader,
     pub csm_ps: CompiledShader,
+    pub csm_alpha_test: CompiledShader,
+    pub csm_alpha_test_ps: CompiledShader,
     pub fullscreen: CompiledShader,
-    pub directional_light_pass: CompiledShader,
+    pub directional_light_atlas_pass: CompiledShader,
+    pub directional_light_array_pass: CompiledShader,
     pub gamma_corr_pass: CompiledShader,
+    pub depth_reduction_pass: CompiledShader,
     pub zpass: CompiledShader,
+    pub zpass_alpha_test: CompiledShader,
+    pub zpass_alpha_test_ps: CompiledShader,
     pub gpass_vs: CompiledShader,
     pub gpass_ps: CompiledShader,
+    pub forward_vs: CompiledShader,
+    pub forward_atlas_ps: CompiledShader,
+    pub forward_array_ps: CompiledShader,
 }
 
 impl ShaderCollection {
@@ -24,14 +37,14 @@ impl ShaderCollection {
         api: &Backend<A>,
         debug: bool,
         settings: &RenderSettings,
-    ) -> Self {
+    ) -> Result<Self, RenderError> {
         let csm = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Vertex,
             path: settings.asset_path.join("Csm.hlsl"),
             entry_point: "VSMain".into(),
             debug,
             defines: vec![],
-        });
+        })?;
 
         let csm_ps = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Pixel,
@@ -39,7 +52,23 @@ impl ShaderCollection {
             entry_point: "PSMain".into(),
             debug,
             defines: vec![],
-        });
+        })?;
+
+        let csm_alpha_test = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Vertex,
+            path: settings.asset_path.join("Csm.hlsl"),
+            entry_point: "VSMain".into(),
+            debug,
+            defines: alpha_test_defines(),
+        })?;
+
+        let csm_alpha_test_ps = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Pixel,
+            path: settings.asset_path.join("Csm.hlsl"),
+            entry_point: "PSMain".into(),
+            debug,
+            defines: alpha_test_defines(),
+        })?;
 
         let fullscreen = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Vertex,
@@ -47,18 +76,23 @@ impl ShaderCollection {
             entry_point: "Main".into(),
             debug,
             defines: vec![],
-        });
+        })?;
 
-        let directional_light_pass = api.compile_shader(&ShaderDesc {
+        let directional_light_atlas_pass = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Pixel,
             path: settings.asset_path.join("DirectionalLight.hlsl"),
             entry_point: "Main".into(),
             debug,
-            defines: vec![(
-                "CASCADES_COUNT".into(),
-                settings.cascades_count.to_string().into(),
-            )],
-        });
+            defines: cascade_defines(settings.cascades_count, CascadeLayout::Atlas),
+        })?;
+
+        let directional_light_array_pass = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Pixel,
+            path: settings.asset_path.join("DirectionalLight.hlsl"),
+            entry_point: "Main".into(),
+            debug,
+            defines: cascade_defines(settings.cascades_count, CascadeLayout::Array),
+        })?;
 
         let gamma_corr_pass = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Pixel,
@@ -66,7 +100,15 @@ impl ShaderCollection {
             entry_point: "Main".into(),
             debug,
             defines: vec![],
-        });
+        })?;
+
+        let depth_reduction_pass = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Pixel,
+            path: settings.asset_path.join("DepthReduction.hlsl"),
+            entry_point: "Main".into(),
+            debug,
+            defines: vec![("DEPTH_TILE_SIZE".into(), DEPTH_TILE_SIZE.to_string().into())],
+        })?;
 
         let zpass = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Vertex,
@@ -74,7 +116,23 @@ impl ShaderCollection {
             entry_point: "Main".into(),
             debug,
             defines: vec![],
-        });
+        })?;
+
+        let zpass_alpha_test = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Vertex,
+            path: settings.asset_path.join("Zpass.hlsl"),
+            entry_point: "Main".into(),
+            debug,
+            defines: alpha_test_defines(),
+        })?;
+
+        let zpass_alpha_test_ps = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Pixel,
+            path: settings.asset_path.join("Zpass.hlsl"),
+            entry_point: "PSMain".into(),
+            debug,
+            defines: alpha_test_defines(),
+        })?;
 
         let gpass_vs = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Vertex,
@@ -82,7 +140,7 @@ impl ShaderCollection {
             entry_point: "VSMain".into(),
             debug,
             defines: vec![],
-        });
+        })?;
 
         let gpass_ps = api.compile_shader(&ShaderDesc {
             ty: ShaderType::Pixel,
@@ -90,17 +148,73 @@ impl ShaderCollection {
             entry_point: "PSMain".into(),
             debug,
             defines: vec![],
-        });
+        })?;
+
+        let forward_vs = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Vertex,
+            path: settings.asset_path.join("Forward.hlsl"),
+            entry_point: "VSMain".into(),
+            debug,
+            defines: cascade_defines(settings.cascades_count, CascadeLayout::Atlas),
+        })?;
+
+        let forward_atlas_ps = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Pixel,
+            path: settings.asset_path.join("Forward.hlsl"),
+            entry_point: "PSMain".into(),
+            debug,
+            defines: cascade_defines(settings.cascades_count, CascadeLayout::Atlas),
+        })?;
 
-        Self {
+        let forward_array_ps = api.compile_shader(&ShaderDesc {
+            ty: ShaderType::Pixel,
+            path: settings.asset_path.join("Forward.hlsl"),
+            entry_point: "PSMain".into(),
+            debug,
+            defines: cascade_defines(settings.cascades_count, CascadeLayout::Array),
+        })?;
+
+        Ok(Self {
             csm,
             csm_ps,
+            csm_alpha_test,
+            csm_alpha_test_ps,
             fullscreen,
-            directional_light_pass,
+            directional_light_atlas_pass,
+            directional_light_array_pass,
             gamma_corr_pass,
+            depth_reduction_pass,
             zpass,
+            zpass_alpha_test,
+            zpass_alpha_test_ps,
             gpass_vs,
             gpass_ps,
-        }
+            forward_vs,
+            forward_atlas_ps,
+            forward_array_ps,
+        })
+    }
+}
+
+pub fn cascade_defines(
+    count: usize,
+    layout: CascadeLayout,
+) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+    let [cols, rows] = atlas_grid(count);
+
+    let mut defines = vec![
+        ("CASCADES_COUNT".into(), count.to_string().into()),
+        ("CASCADES_ATLAS_COLS".into(), cols.to_string().into()),
+        ("CASCADES_ATLAS_ROWS".into(), rows.to_string().into()),
+    ];
+
+    if layout == CascadeLayout::Array {
+        defines.push(("CASCADES_ARRAY".into(), "1".into()));
     }
+
+    defines
+}
+
+pub fn alpha_test_defines() -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+    vec![("ALPHA_TEST".into(), "1".into())]
 }
@@ -1,11 +1,15 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 use crate::rhi::{
-    backend::{Api, RenderDeviceId, RenderDeviceInfo},
+    backend::{Api, DeviceSelector, RenderDeviceId, RenderDeviceInfo},
+    error::RenderError,
     shader::{CompiledShader, ShaderDesc},
 };
 
-use super::context::{Context, RenderDevice};
+use super::{
+    context::{Context, ContextGroup, RenderDevice},
+    timeline::Timeline,
+};
 
 #[derive(Debug)]
 pub struct Backend<A: Api> {
@@ -18,6 +22,42 @@ impl<A: Api> Backend<A> {
     }
 }
 
+impl<A: Api<Device: RenderDevice>> Backend<A> {
+    pub fn select_devices(
+        &self,
+        selector: &DeviceSelector,
+    ) -> Result<Vec<RenderDeviceId>, RenderError> {
+        selector.select(self.api.enumerate_devices())
+    }
+
+    pub fn create_group(&self, devices: &[RenderDeviceId]) -> ContextGroup<A::Device> {
+        ContextGroup::new(
+            devices
+                .iter()
+                .map(|index| Arc::new(self.create_device(*index)))
+                .collect(),
+        )
+    }
+
+    pub fn create_group_with_timeline(
+        &self,
+        devices: &[RenderDeviceId],
+        timeline: &Arc<Timeline>,
+    ) -> ContextGroup<A::Device> {
+        ContextGroup::new(
+            devices
+                .iter()
+                .map(|index| {
+                    Arc::new(
+                        self.create_device(*index)
+                            .with_timeline(*index, Arc::clone(timeline)),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
 impl<A: Api<Device: RenderDevice>> Api for Backend<A> {
     type Device = Context<A::Device>;
 
@@ -30,7 +70,10 @@ impl<A: Api<Device: RenderDevice>> Api for Backend<A> {
         Context::new(gpu)
     }
 
-    fn compile_shader<P: AsRef<Path>>(&self, desc: &ShaderDesc<'_, P>) -> CompiledShader {
+    fn compile_shader<P: AsRef<Path>>(
+        &self,
+        desc: &ShaderDesc<'_, P>,
+    ) -> Result<CompiledShader, RenderError> {
         self.api.compile_shader(desc)
     }
 }
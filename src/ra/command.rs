@@ -4,18 +4,21 @@ use crate::{
     collections::handle::Handle,
     rhi::{
         self,
+        backend::RenderDeviceId,
         command::{
-            CommandType, RenderCommandBuffer, RenderCommandDevice, RenderCommandQueue,
+            CommandType, GpuEvent, RenderCommandBuffer, RenderCommandDevice, RenderCommandQueue,
             RenderEncoder as _, Subresource, SyncPoint, TransferEncoder as _,
         },
-        types::{GeomTopology, IndexType, ResourceState, Scissor, Timings, Viewport},
+        error::RenderError,
+        types::{GeomTopology, IndexType, Region, ResourceState, Scissor, Timings, Viewport},
     },
 };
 
 use super::{
     context::{Context, RenderDevice},
-    resources::{Buffer, ResourceMapper, Texture},
+    resources::{Buffer, ResourceMapper, Texture, bound},
     shader::{RasterPipeline, ShaderArgument},
+    timeline::{ChannelTransfer, TimelineBarrier, TimelineEvent, TimelineRecorder},
 };
 
 type CommandBuffer<D> =
@@ -24,20 +27,51 @@ type CommandBuffer<D> =
 type RenderEncoderType<'a, D> = <CommandBuffer<D> as RenderCommandBuffer>::RenderEncoder<'a>;
 type TransferEncoderType<'a, D> = <CommandBuffer<D> as RenderCommandBuffer>::TransferEncoder<'a>;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Barrier {
     Buffer(Handle<Buffer>, ResourceState),
     Texture(Handle<Texture>, ResourceState, Subresource),
+    Alias(Handle<Texture>),
 }
 
 pub struct CommandQueue<D: RenderDevice> {
     pub(super) raw: D::CommandQueue,
     pub(super) mapper: Arc<ResourceMapper<D>>,
+    pub(super) timeline: Option<TimelineRecorder>,
 }
 
 impl<D: RenderDevice> CommandQueue<D> {
     pub(super) fn new(raw: D::CommandQueue, mapper: Arc<ResourceMapper<D>>) -> Self {
-        Self { raw, mapper }
+        Self {
+            raw,
+            mapper,
+            timeline: None,
+        }
+    }
+
+    fn record(&self, events: Vec<TimelineEvent>) {
+        if let Some(timeline) = &self.timeline
+            && !events.is_empty()
+        {
+            timeline.record(self.raw.ty(), events);
+        }
+    }
+
+    // Without a signalling device the event is assumed to be signalled on
+    // this device.
+    fn wait_event_from(
+        &self,
+        event: &D::Event,
+        signaller: Option<RenderDeviceId>,
+    ) -> Result<(), RenderError> {
+        if let Some(timeline) = &self.timeline {
+            self.record(vec![TimelineEvent::WaitEvent {
+                signaller: signaller.unwrap_or(timeline.device()),
+                sync_point: event.get_goal(),
+            }]);
+        }
+
+        self.raw.wait_event(event)
     }
 }
 
@@ -54,42 +88,58 @@ impl<D: RenderDevice> RenderCommandQueue for CommandQueue<D> {
         self.raw.frequency()
     }
 
-    fn create_command_buffer(&self, device: &Self::Device) -> Self::CommandBuffer {
-        let cmd = self.raw.create_command_buffer(&device);
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
+        let cmd = self.raw.create_command_buffer(device)?;
+        let timeline = self.timeline.clone().filter(|t| t.is_enabled());
 
-        Self::CommandBuffer::new(cmd, Arc::clone(&self.mapper))
+        Ok(Self::CommandBuffer::new(
+            cmd,
+            Arc::clone(&self.mapper),
+            timeline,
+        ))
     }
 
     fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
+        self.record(cmd_buffer.events);
         self.raw.enqueue(cmd_buffer.raw);
     }
 
     fn commit(&self, cmd_buffer: Self::CommandBuffer) {
+        self.record(cmd_buffer.events);
         self.raw.commit(cmd_buffer.raw);
     }
 
-    fn submit(&self, device: &Self::Device) -> SyncPoint {
-        self.raw.submit(device)
+    fn submit(&self, device: &Self::Device) -> Result<SyncPoint, RenderError> {
+        let sync_point = self.raw.submit(device)?;
+        self.record(vec![TimelineEvent::Submit { sync_point }]);
+
+        Ok(sync_point)
     }
 
-    fn signal_event(&self, event: &Self::Event) -> SyncPoint {
-        self.raw.signal_event(event)
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
+        let sync_point = self.raw.signal_event(event)?;
+        self.record(vec![TimelineEvent::SignalEvent { sync_point }]);
+
+        Ok(sync_point)
     }
 
-    fn wait_event(&self, event: &Self::Event) {
-        self.raw.wait_event(event)
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError> {
+        self.wait_event_from(event, None)
     }
 
-    fn wait_on_cpu(&self, value: SyncPoint) {
-        self.raw.wait_on_cpu(value);
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.raw.wait_on_cpu(value)
     }
 
-    fn wait_until_complete(&self) {
-        self.raw.wait_until_complete();
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        self.raw.wait_until_complete()
     }
 
-    fn wait_idle(&self) {
-        self.raw.wait_idle();
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        self.raw.wait_idle()
     }
 
     fn is_ready(&self) -> bool {
@@ -104,31 +154,71 @@ impl<D: RenderDevice> RenderCommandQueue for CommandQueue<D> {
 pub struct CommandEncoder<D: RenderDevice> {
     pub(super) raw: CommandBuffer<D>,
     pub(super) mapper: Arc<ResourceMapper<D>>,
+    pub(super) timeline: Option<TimelineRecorder>,
+    pub(super) events: Vec<TimelineEvent>,
 }
 
 impl<D: RenderDevice> CommandEncoder<D> {
-    pub(super) fn new(raw: CommandBuffer<D>, mapper: Arc<ResourceMapper<D>>) -> Self {
-        Self { raw, mapper }
+    pub(super) fn new(
+        raw: CommandBuffer<D>,
+        mapper: Arc<ResourceMapper<D>>,
+        timeline: Option<TimelineRecorder>,
+    ) -> Self {
+        Self {
+            raw,
+            mapper,
+            timeline,
+            events: vec![],
+        }
+    }
+
+    fn record(&mut self, event: impl FnOnce() -> TimelineEvent) {
+        if self.timeline.is_some() {
+            self.events.push(event());
+        }
+    }
+
+    fn begin_transfer(
+        &mut self,
+        label: Cow<'static, str>,
+        channel: Option<ChannelTransfer>,
+    ) -> TransferEncoderImpl<'_, D> {
+        self.record(|| TimelineEvent::Transfer {
+            label: label.clone(),
+            channel,
+        });
+
+        let raw = self.raw.transfer(label);
+
+        TransferEncoderImpl {
+            raw,
+            mapper: &self.mapper,
+        }
     }
 }
 
 pub trait RenderCommandContext<D: RenderDevice> {
-    fn create_encoder(&self, ty: CommandType) -> CommandEncoder<D>;
+    fn create_encoder(&self, ty: CommandType) -> Result<CommandEncoder<D>, RenderError>;
     fn enqueue(&self, cmd: CommandEncoder<D>);
     fn commit(&self, cmd: CommandEncoder<D>);
-    fn submit(&self, ty: CommandType) -> SyncPoint;
-
-    fn signal_event(&self, ty: CommandType, event: &D::Event) -> SyncPoint;
-    fn wait_event(&self, ty: CommandType, event: &D::Event);
-    fn wait_on_cpu(&self, ty: CommandType, value: SyncPoint);
-    fn wait_until_complete(&self, ty: CommandType);
-    fn wait_idle(&self, ty: CommandType);
+    fn submit(&self, ty: CommandType) -> Result<SyncPoint, RenderError>;
+
+    fn signal_event(&self, ty: CommandType, event: &D::Event) -> Result<SyncPoint, RenderError>;
+    fn wait_event(
+        &self,
+        ty: CommandType,
+        event: &D::Event,
+        signaller: RenderDeviceId,
+    ) -> Result<(), RenderError>;
+    fn wait_on_cpu(&self, ty: CommandType, value: SyncPoint) -> Result<(), RenderError>;
+    fn wait_until_complete(&self, ty: CommandType) -> Result<(), RenderError>;
+    fn wait_idle(&self, ty: CommandType) -> Result<(), RenderError>;
     fn is_ready(&self, ty: CommandType) -> bool;
     fn is_ready_for(&self, ty: CommandType, v: SyncPoint) -> bool;
 }
 
 impl<D: RenderDevice> RenderCommandContext<D> for Context<D> {
-    fn create_encoder(&self, ty: CommandType) -> CommandEncoder<D> {
+    fn create_encoder(&self, ty: CommandType) -> Result<CommandEncoder<D>, RenderError> {
         match ty {
             CommandType::Graphics => self.graphics_queue.create_command_buffer(&self.gpu),
             CommandType::Compute => self.compute_queue.create_command_buffer(&self.gpu),
@@ -152,7 +242,7 @@ impl<D: RenderDevice> RenderCommandContext<D> for Context<D> {
         }
     }
 
-    fn submit(&self, ty: CommandType) -> SyncPoint {
+    fn submit(&self, ty: CommandType) -> Result<SyncPoint, RenderError> {
         match ty {
             CommandType::Graphics => self.graphics_queue.submit(&self.gpu),
             CommandType::Compute => self.compute_queue.submit(&self.gpu),
@@ -160,7 +250,7 @@ impl<D: RenderDevice> RenderCommandContext<D> for Context<D> {
         }
     }
 
-    fn signal_event(&self, ty: CommandType, event: &D::Event) -> SyncPoint {
+    fn signal_event(&self, ty: CommandType, event: &D::Event) -> Result<SyncPoint, RenderError> {
         match ty {
             CommandType::Graphics => self.graphics_queue.signal_event(event),
             CommandType::Compute => self.compute_queue.signal_event(event),
@@ -168,15 +258,22 @@ impl<D: RenderDevice> RenderCommandContext<D> for Context<D> {
         }
     }
 
-    fn wait_event(&self, ty: CommandType, event: &D::Event) {
-        match ty {
-            CommandType::Graphics => self.graphics_queue.wait_event(event),
-            CommandType::Compute => self.compute_queue.wait_event(event),
-            CommandType::Transfer => self.transfer_queue.wait_event(event),
-        }
+    fn wait_event(
+        &self,
+        ty: CommandType,
+        event: &D::Event,
+        signaller: RenderDeviceId,
+    ) -> Result<(), RenderError> {
+        let queue = match ty {
+            CommandType::Graphics => &self.graphics_queue,
+            CommandType::Compute => &self.compute_queue,
+            CommandType::Transfer => &self.transfer_queue,
+        };
+
+        queue.wait_event_from(event, Some(signaller))
     }
 
-    fn wait_on_cpu(&self, ty: CommandType, value: SyncPoint) {
+    fn wait_on_cpu(&self, ty: CommandType, value: SyncPoint) -> Result<(), RenderError> {
         match ty {
             CommandType::Graphics => self.graphics_queue.wait_on_cpu(value),
             CommandType::Compute => self.compute_queue.wait_on_cpu(value),
@@ -184,7 +281,7 @@ impl<D: RenderDevice> RenderCommandContext<D> for Context<D> {
         }
     }
 
-    fn wait_until_complete(&self, ty: CommandType) {
+    fn wait_until_complete(&self, ty: CommandType) -> Result<(), RenderError> {
         match ty {
             CommandType::Graphics => self.graphics_queue.wait_until_complete(),
             CommandType::Compute => self.compute_queue.wait_until_complete(),
@@ -192,7 +289,7 @@ impl<D: RenderDevice> RenderCommandContext<D> for Context<D> {
         }
     }
 
-    fn wait_idle(&self, ty: CommandType) {
+    fn wait_idle(&self, ty: CommandType) -> Result<(), RenderError> {
         match ty {
             CommandType::Graphics => self.graphics_queue.wait_idle(),
             CommandType::Compute => self.compute_queue.wait_idle(),
@@ -228,16 +325,22 @@ pub trait RenderCommandEncoder<D: RenderDevice> {
 
     fn begin(&mut self, ctx: &Context<D>) -> Option<Timings>;
 
-    fn set_barriers(&mut self, barriers: &[Barrier]);
+    fn set_barriers(&mut self, barriers: &[Barrier]) -> Result<(), RenderError>;
 
     fn render(
         &mut self,
         label: Cow<'static, str>,
         targets: &[Handle<Texture>],
         depth: Option<Handle<Texture>>,
-    ) -> Self::RenderEncoder<'_>;
+    ) -> Result<Self::RenderEncoder<'_>, RenderError>;
 
     fn transfer(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_>;
+    // A transfer between the devices of a cross-device channel.
+    fn channel_transfer(
+        &mut self,
+        label: Cow<'static, str>,
+        channel: ChannelTransfer,
+    ) -> Self::TransferEncoder<'_>;
 }
 
 impl<D: RenderDevice> RenderCommandEncoder<D> for CommandEncoder<D> {
@@ -255,23 +358,37 @@ impl<D: RenderDevice> RenderCommandEncoder<D> for CommandEncoder<D> {
         self.raw.begin(&ctx.gpu)
     }
 
-    fn set_barriers(&mut self, barriers: &[Barrier]) {
+    fn set_barriers(&mut self, barriers: &[Barrier]) -> Result<(), RenderError> {
+        self.record(|| TimelineEvent::Barriers {
+            barriers: barriers.iter().map(TimelineBarrier::from).collect(),
+        });
+
         let buffers = self.mapper.buffers.read();
         let textures = self.mapper.textures.read();
 
-        let barriers = barriers.iter().map(|b| match b {
-            Barrier::Buffer(handle, resource_state) => rhi::command::Barrier::Buffer(
-                buffers.get(*handle).expect("failed to get buffer"),
-                *resource_state,
-            ),
-            Barrier::Texture(handle, resource_state, sub) => rhi::command::Barrier::Texture(
-                textures.get(*handle).expect("failed to get buffer"),
-                *resource_state,
-                *sub,
-            ),
-        });
+        let barriers = barriers
+            .iter()
+            .map(|b| match b {
+                Barrier::Buffer(handle, resource_state) => Ok(rhi::command::Barrier::Buffer(
+                    bound(&buffers, *handle, "buffer")?,
+                    *resource_state,
+                )),
+                Barrier::Texture(handle, resource_state, sub) => {
+                    Ok(rhi::command::Barrier::Texture(
+                        bound(&textures, *handle, "texture")?,
+                        *resource_state,
+                        *sub,
+                    ))
+                }
+                Barrier::Alias(handle) => Ok(rhi::command::Barrier::Alias(bound(
+                    &textures, *handle, "texture",
+                )?)),
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?;
 
         self.raw.set_barriers(barriers);
+
+        Ok(())
     }
 
     fn render(
@@ -279,32 +396,40 @@ impl<D: RenderDevice> RenderCommandEncoder<D> for CommandEncoder<D> {
         label: Cow<'static, str>,
         targets: &[Handle<Texture>],
         depth: Option<Handle<Texture>>,
-    ) -> Self::RenderEncoder<'_> {
+    ) -> Result<Self::RenderEncoder<'_>, RenderError> {
+        self.record(|| TimelineEvent::Render {
+            label: label.clone(),
+        });
+
         let guard = self.mapper.textures.read();
         let targets = targets
             .iter()
-            .map(|h| guard.get(*h).expect("failed to get texture"));
-        let depth = depth.map(|h| guard.get(h).expect("failed to get texture"));
+            .map(|h| bound(&guard, *h, "texture"))
+            .collect::<Result<Vec<_>, RenderError>>()?;
+        let depth = depth.map(|h| bound(&guard, h, "texture")).transpose()?;
 
         let raw = self.raw.render(label, targets, depth);
 
-        Self::RenderEncoder {
+        Ok(Self::RenderEncoder {
             raw,
             mapper: &self.mapper,
             active_vbs: Default::default(),
             active_ibs: Default::default(),
             active_arguments: Default::default(),
             active_dyn_offsets: Default::default(),
-        }
+        })
     }
 
     fn transfer(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_> {
-        let raw = self.raw.transfer(label);
+        self.begin_transfer(label, None)
+    }
 
-        Self::TransferEncoder {
-            raw,
-            mapper: &self.mapper,
-        }
+    fn channel_transfer(
+        &mut self,
+        label: Cow<'static, str>,
+        channel: ChannelTransfer,
+    ) -> Self::TransferEncoder<'_> {
+        self.begin_transfer(label, Some(channel))
     }
 }
 
@@ -318,39 +443,113 @@ pub struct RenderEncoderImpl<'a, D: RenderDevice> {
 }
 
 pub trait RenderEncoder {
-    fn clear_rt(&mut self, texture: Handle<Texture>, color: Option<[f32; 4]>);
-    fn clear_depth(&mut self, texture: Handle<Texture>, depth: Option<f32>);
+    fn clear_rt(
+        &mut self,
+        texture: Handle<Texture>,
+        color: Option<[f32; 4]>,
+    ) -> Result<(), RenderError>;
+    fn clear_depth(
+        &mut self,
+        texture: Handle<Texture>,
+        depth: Option<f32>,
+    ) -> Result<(), RenderError>;
+    fn clear_rt_region(
+        &mut self,
+        texture: Handle<Texture>,
+        color: Option<[f32; 4]>,
+        region: Region,
+    ) -> Result<(), RenderError>;
+    fn clear_depth_region(
+        &mut self,
+        texture: Handle<Texture>,
+        depth: Option<f32>,
+        region: Region,
+    ) -> Result<(), RenderError>;
 
     fn set_viewport(&mut self, viewport: Viewport);
     fn set_scissor(&mut self, scissor: Scissor);
     fn set_topology(&self, topology: GeomTopology);
 
-    fn set_render_pipeline(&mut self, pipeline: Handle<RasterPipeline>);
+    fn set_render_pipeline(&mut self, pipeline: Handle<RasterPipeline>) -> Result<(), RenderError>;
     fn bind_shader_argument(
         &mut self,
         space: u32,
         argument: Handle<ShaderArgument>,
         dynamic_offset: usize,
-    );
+    ) -> Result<(), RenderError>;
 
-    fn bind_vertex_buffer(&mut self, buffer: Handle<Buffer>, slot: usize);
-    fn bind_index_buffer(&mut self, buffer: Handle<Buffer>, ty: IndexType);
+    fn bind_vertex_buffer(
+        &mut self,
+        buffer: Handle<Buffer>,
+        slot: usize,
+    ) -> Result<(), RenderError>;
+    fn bind_index_buffer(
+        &mut self,
+        buffer: Handle<Buffer>,
+        ty: IndexType,
+    ) -> Result<(), RenderError>;
 
     fn draw(&mut self, count: u32, start_vertex: u32);
     fn draw_indexed(&mut self, count: u32, start_index: u32, base_index: u32);
+    fn draw_indexed_instanced(
+        &mut self,
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_index: u32,
+        start_instance: u32,
+    );
 }
 
 impl<'a, D: RenderDevice> RenderEncoder for RenderEncoderImpl<'a, D> {
-    fn clear_rt(&mut self, texture: Handle<Texture>, color: Option<[f32; 4]>) {
+    fn clear_rt(
+        &mut self,
+        texture: Handle<Texture>,
+        color: Option<[f32; 4]>,
+    ) -> Result<(), RenderError> {
         let guard = self.mapper.textures.read();
         self.raw
-            .clear_rt(guard.get(texture).expect("failed to get texture"), color);
+            .clear_rt(bound(&guard, texture, "texture")?, color, None);
+
+        Ok(())
     }
 
-    fn clear_depth(&mut self, texture: Handle<Texture>, depth: Option<f32>) {
+    fn clear_depth(
+        &mut self,
+        texture: Handle<Texture>,
+        depth: Option<f32>,
+    ) -> Result<(), RenderError> {
         let guard = self.mapper.textures.read();
         self.raw
-            .clear_depth(guard.get(texture).expect("failed to get texture"), depth);
+            .clear_depth(bound(&guard, texture, "texture")?, depth, None);
+
+        Ok(())
+    }
+
+    fn clear_rt_region(
+        &mut self,
+        texture: Handle<Texture>,
+        color: Option<[f32; 4]>,
+        region: Region,
+    ) -> Result<(), RenderError> {
+        let guard = self.mapper.textures.read();
+        self.raw
+            .clear_rt(bound(&guard, texture, "texture")?, color, Some(region));
+
+        Ok(())
+    }
+
+    fn clear_depth_region(
+        &mut self,
+        texture: Handle<Texture>,
+        depth: Option<f32>,
+        region: Region,
+    ) -> Result<(), RenderError> {
+        let guard = self.mapper.textures.read();
+        self.raw
+            .clear_depth(bound(&guard, texture, "texture")?, depth, Some(region));
+
+        Ok(())
     }
 
     fn set_viewport(&mut self, viewport: Viewport) {
@@ -365,11 +564,13 @@ impl<'a, D: RenderDevice> RenderEncoder for RenderEncoderImpl<'a, D> {
         self.raw.set_topology(topology);
     }
 
-    fn set_render_pipeline(&mut self, pipeline: Handle<RasterPipeline>) {
+    fn set_render_pipeline(&mut self, pipeline: Handle<RasterPipeline>) -> Result<(), RenderError> {
         let guard = self.mapper.raster_pipelines.read();
-        let pipeline = guard.get(pipeline).expect("failed to get pipeline");
+        let pipeline = bound(&guard, pipeline, "raster pipeline")?;
 
         self.raw.set_raster_pipeline(pipeline);
+
+        Ok(())
     }
 
     fn bind_shader_argument(
@@ -377,46 +578,58 @@ impl<'a, D: RenderDevice> RenderEncoder for RenderEncoderImpl<'a, D> {
         set: u32,
         argument: Handle<ShaderArgument>,
         dynamic_offset: usize,
-    ) {
+    ) -> Result<(), RenderError> {
         if self.active_arguments[set as usize] == Some(argument)
             && self.active_dyn_offsets[set as usize] == dynamic_offset
         {
-            return;
-        } else {
-            self.active_arguments[set as usize] = Some(argument);
-            self.active_dyn_offsets[set as usize] = dynamic_offset;
+            return Ok(());
         }
 
         let guard = self.mapper.shader_arguments.read();
-        let argument = guard.get(argument).expect("failed to get shader argument");
+        let raw_argument = bound(&guard, argument, "shader argument")?;
+
+        self.raw
+            .bind_shader_argument(set, raw_argument, dynamic_offset);
+        self.active_arguments[set as usize] = Some(argument);
+        self.active_dyn_offsets[set as usize] = dynamic_offset;
 
-        self.raw.bind_shader_argument(set, argument, dynamic_offset);
+        Ok(())
     }
 
-    fn bind_vertex_buffer(&mut self, buffer: Handle<Buffer>, slot: usize) {
+    fn bind_vertex_buffer(
+        &mut self,
+        buffer: Handle<Buffer>,
+        slot: usize,
+    ) -> Result<(), RenderError> {
         if self.active_vbs[slot] == Some(buffer) {
-            return;
-        } else {
-            self.active_vbs[slot] = Some(buffer);
+            return Ok(());
         }
 
         let guard = self.mapper.buffers.read();
-        let buffer = guard.get(buffer).expect("failed to get buffer");
+        let raw_buffer = bound(&guard, buffer, "buffer")?;
+
+        self.raw.bind_vertex_buffer(raw_buffer, slot);
+        self.active_vbs[slot] = Some(buffer);
 
-        self.raw.bind_vertex_buffer(buffer, slot);
+        Ok(())
     }
 
-    fn bind_index_buffer(&mut self, buffer: Handle<Buffer>, ty: IndexType) {
+    fn bind_index_buffer(
+        &mut self,
+        buffer: Handle<Buffer>,
+        ty: IndexType,
+    ) -> Result<(), RenderError> {
         if self.active_ibs == Some(buffer) {
-            return;
-        } else {
-            self.active_ibs = Some(buffer);
+            return Ok(());
         }
 
         let guard = self.mapper.buffers.read();
-        let buffer = guard.get(buffer).expect("failed to get buffer");
+        let raw_buffer = bound(&guard, buffer, "buffer")?;
 
-        self.raw.bind_index_buffer(buffer, ty);
+        self.raw.bind_index_buffer(raw_buffer, ty);
+        self.active_ibs = Some(buffer);
+
+        Ok(())
     }
 
     fn draw(&mut self, count: u32, start_vertex: u32) {
@@ -426,6 +639,23 @@ impl<'a, D: RenderDevice> RenderEncoder for RenderEncoderImpl<'a, D> {
     fn draw_indexed(&mut self, count: u32, start_index: u32, base_index: u32) {
         self.raw.draw_indexed(count, start_index, base_index);
     }
+
+    fn draw_indexed_instanced(
+        &mut self,
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_index: u32,
+        start_instance: u32,
+    ) {
+        self.raw.draw_indexed_instanced(
+            count,
+            instance_count,
+            start_index,
+            base_index,
+            start_instance,
+        );
+    }
 }
 
 pub struct TransferEncoderImpl<'a, D: RenderDevice> {
@@ -434,22 +664,86 @@ pub struct TransferEncoderImpl<'a, D: RenderDevice> {
 }
 
 pub trait TransferEncoder {
-    fn pull_texture(&self, texture: Handle<Texture>);
-    fn push_texture(&self, texture: Handle<Texture>);
+    fn pull_texture(
+        &self,
+        texture: Handle<Texture>,
+        region: Option<Region>,
+    ) -> Result<(), RenderError>;
+    fn push_texture(
+        &self,
+        texture: Handle<Texture>,
+        region: Option<Region>,
+    ) -> Result<(), RenderError>;
+    fn copy_texture(
+        &self,
+        src: Handle<Texture>,
+        dst: Handle<Texture>,
+        region: Option<Region>,
+    ) -> Result<(), RenderError>;
+    fn copy_texture_to_buffer(
+        &self,
+        src: Handle<Texture>,
+        dst: Handle<Buffer>,
+    ) -> Result<(), RenderError>;
 }
 
 impl<'a, D: RenderDevice> TransferEncoder for TransferEncoderImpl<'a, D> {
-    fn pull_texture(&self, texture: Handle<Texture>) {
+    fn pull_texture(
+        &self,
+        texture: Handle<Texture>,
+        region: Option<Region>,
+    ) -> Result<(), RenderError> {
         let guard = self.mapper.textures.read();
 
         self.raw
-            .pull_texture(guard.get(texture).expect("failed to get texture"));
+            .pull_texture(bound(&guard, texture, "texture")?, region);
+
+        Ok(())
     }
 
-    fn push_texture(&self, texture: Handle<Texture>) {
+    fn push_texture(
+        &self,
+        texture: Handle<Texture>,
+        region: Option<Region>,
+    ) -> Result<(), RenderError> {
         let guard = self.mapper.textures.read();
 
         self.raw
-            .push_texture(guard.get(texture).expect("failed to get texture"));
+            .push_texture(bound(&guard, texture, "texture")?, region);
+
+        Ok(())
+    }
+
+    fn copy_texture(
+        &self,
+        src: Handle<Texture>,
+        dst: Handle<Texture>,
+        region: Option<Region>,
+    ) -> Result<(), RenderError> {
+        let guard = self.mapper.textures.read();
+
+        self.raw.copy_texture(
+            bound(&guard, src, "texture")?,
+            bound(&guard, dst, "texture")?,
+            region,
+        );
+
+        Ok(())
+    }
+
+    fn copy_texture_to_buffer(
+        &self,
+        src: Handle<Texture>,
+        dst: Handle<Buffer>,
+    ) -> Result<(), RenderError> {
+        let textures = self.mapper.textures.read();
+        let buffers = self.mapper.buffers.read();
+
+        self.raw.copy_texture_to_buffer(
+            bound(&textures, src, "texture")?,
+            bound(&buffers, dst, "buffer")?,
+        );
+
+        Ok(())
     }
 }
@@ -1,20 +1,30 @@
 use std::sync::Arc;
 
-use crate::rhi::{
-    command::{
-        CommandType, RenderCommandBuffer, RenderCommandDevice, RenderCommandQueue, RenderEncoder,
-        TransferEncoder,
+use crate::{
+    collections::frame_pipeline::StageFence,
+    rhi::{
+        backend::RenderDeviceId,
+        command::{
+            CommandType, GpuEvent, RenderCommandBuffer, RenderCommandDevice, RenderCommandQueue,
+            RenderEncoder, SyncPoint, TransferEncoder,
+        },
+        error::RenderError,
+        resources::RenderResourceDevice,
+        shader::RenderShaderDevice,
+        swapchain::{RenderSwapchainDevice, Surface},
     },
-    resources::RenderResourceDevice,
-    shader::RenderShaderDevice,
-    swapchain::{RenderSwapchainDevice, Surface},
 };
 
-use super::{command::CommandQueue, resources::ResourceMapper};
+use super::{
+    command::{CommandQueue, RenderCommandContext},
+    resources::ResourceMapper,
+    timeline::{Timeline, TimelineRecorder},
+};
 
 pub trait RenderDevice:
     RenderResourceDevice
     + RenderCommandDevice<
+        Event: GpuEvent,
         CommandQueue: for<'a> RenderCommandQueue<
             CommandBuffer: RenderCommandBuffer<
                 Device = Self,
@@ -24,7 +34,10 @@ pub trait RenderDevice:
                     ShaderArgument = Self::ShaderArgument,
                     RasterPipeline = Self::RasterPipeline,
                 >,
-                TransferEncoder<'a>: TransferEncoder<Texture = Self::Texture>,
+                TransferEncoder<'a>: TransferEncoder<
+                    Buffer = Self::Buffer,
+                    Texture = Self::Texture,
+                >,
             >,
             Event = Self::Event,
         >,
@@ -38,6 +51,7 @@ pub trait RenderDevice:
 impl<T> RenderDevice for T where
     T: RenderResourceDevice
         + RenderCommandDevice<
+            Event: GpuEvent,
             CommandQueue: for<'a> RenderCommandQueue<
                 CommandBuffer: RenderCommandBuffer<
                     Device = T,
@@ -47,7 +61,7 @@ impl<T> RenderDevice for T where
                         ShaderArgument = T::ShaderArgument,
                         RasterPipeline = T::RasterPipeline,
                     >,
-                    TransferEncoder<'a>: TransferEncoder<Texture = T::Texture>,
+                    TransferEncoder<'a>: TransferEncoder<Buffer = T::Buffer, Texture = T::Texture>,
                 >,
                 Event = T::Event,
             >,
@@ -101,41 +115,193 @@ impl<D: RenderDevice> Context<D> {
         }
     }
 
-    pub fn wait_idle(&self) {
-        self.graphics_queue.wait_idle();
-        self.compute_queue.wait_idle();
-        self.transfer_queue.wait_idle();
-        self.uploader.wait_idle();
+    pub fn with_timeline(mut self, device: RenderDeviceId, timeline: Arc<Timeline>) -> Self {
+        let recorder = TimelineRecorder::new(device, timeline);
+
+        self.graphics_queue.timeline = Some(recorder.clone());
+        self.compute_queue.timeline = Some(recorder.clone());
+        self.transfer_queue.timeline = Some(recorder);
+
+        self
+    }
+
+    pub fn wait_idle(&self) -> Result<(), RenderError> {
+        self.graphics_queue.wait_idle()?;
+        self.compute_queue.wait_idle()?;
+        self.transfer_queue.wait_idle()?;
+        self.uploader.wait_idle()
     }
 }
 
-pub struct ContextDual<D: RenderDevice> {
-    pub primary: Arc<Context<D>>,
-    pub secondary: Arc<Context<D>>,
+pub struct ContextGroup<D: RenderDevice> {
+    contexts: Vec<Arc<Context<D>>>,
 }
 
-impl<D: RenderDevice> ContextDual<D> {
-    pub fn new(primary: Arc<Context<D>>, secondary: Arc<Context<D>>) -> Self {
-        Self { primary, secondary }
+impl<D: RenderDevice> ContextGroup<D> {
+    pub fn new(contexts: Vec<Arc<Context<D>>>) -> Self {
+        assert!(
+            !contexts.is_empty(),
+            "context group must have a primary device"
+        );
+
+        Self { contexts }
+    }
+
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Arc<Context<D>>> {
+        self.contexts.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Context<D>>> + '_ {
+        self.contexts.iter()
+    }
+
+    pub fn primary(&self) -> &Arc<Context<D>> {
+        &self.contexts[0]
+    }
+
+    pub fn secondary(&self) -> &Arc<Context<D>> {
+        self.contexts
+            .get(1)
+            .expect("context group has no secondary device")
+    }
+
+    pub fn secondaries(&self) -> &[Arc<Context<D>>] {
+        &self.contexts[1..]
     }
 
     pub fn call(&self, func: impl Fn(&Context<D>)) {
-        func(&self.primary);
-        func(&self.secondary);
+        for ctx in &self.contexts {
+            func(ctx);
+        }
     }
 
     pub fn parallel(&self, func: impl Fn(&Context<D>) + Sync) {
         std::thread::scope(|s| {
-            s.spawn(|| func(&self.primary));
-            s.spawn(|| func(&self.secondary));
+            for ctx in &self.contexts {
+                s.spawn(|| func(ctx));
+            }
         });
     }
 
-    pub fn call_primary(&self, mut func: impl FnMut(&Context<D>)) {
-        func(&self.primary);
+    pub fn broadcast<R: Send>(&self, func: impl Fn(&Context<D>) -> R + Sync) -> Vec<R> {
+        std::thread::scope(|s| {
+            let threads = self
+                .contexts
+                .iter()
+                .map(|ctx| s.spawn(|| func(ctx)))
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|t| t.join().expect("failed to join device thread"))
+                .collect()
+        })
+    }
+
+    pub fn call_at<R>(&self, index: usize, mut func: impl FnMut(&Context<D>) -> R) -> R {
+        func(&self.contexts[index])
     }
 
-    pub fn call_secondary(&self, mut func: impl FnMut(&Context<D>)) {
-        func(&self.secondary);
+    pub fn call_primary<R>(&self, func: impl FnMut(&Context<D>) -> R) -> R {
+        self.call_at(0, func)
+    }
+
+    pub fn call_secondary<R>(&self, mut func: impl FnMut(&Context<D>) -> R) -> R {
+        func(self.secondary())
+    }
+}
+
+impl<D: RenderDevice> StageFence for [Arc<Context<D>>] {
+    fn is_ready_for(&self, device: RenderDeviceId, queue: CommandType, value: SyncPoint) -> bool {
+        self[device].is_ready_for(queue, value)
+    }
+}
+
+impl<D: RenderDevice> StageFence for ContextGroup<D> {
+    fn is_ready_for(&self, device: RenderDeviceId, queue: CommandType, value: SyncPoint) -> bool {
+        self.contexts[..].is_ready_for(device, queue, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{
+        ra::backend::Backend,
+        rhi::{
+            backend::{DeviceType, RenderDeviceInfo},
+            null::{backend::NullBackend, device::NullDevice},
+        },
+    };
+
+    use super::*;
+
+    fn group(count: usize) -> ContextGroup<NullDevice> {
+        let adapters = (0..count)
+            .map(|i| RenderDeviceInfo {
+                name: format!("Null Device {}", i),
+                id: i,
+                is_cross_adapter_texture_supported: true,
+                is_uma: false,
+                ty: DeviceType::Discrete,
+                copy_timestamp_support: true,
+            })
+            .collect();
+
+        Backend::new(NullBackend::with_adapters(adapters))
+            .create_group(&(0..count).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn group_reaches_every_secondary() {
+        let group = group(3);
+
+        assert_eq!(group.len(), 3);
+        assert_eq!(group.secondaries().len(), 2);
+        assert!(Arc::ptr_eq(group.secondary(), &group.secondaries()[0]));
+
+        // Device i submits i times, so the next sync point tells them apart.
+        for i in 0..group.len() {
+            for _ in 0..i {
+                group
+                    .call_at(i, |ctx| ctx.submit(CommandType::Graphics))
+                    .expect("failed to submit");
+            }
+        }
+
+        let next =
+            group.broadcast(|ctx| ctx.submit(CommandType::Graphics).expect("failed to submit"));
+        assert_eq!(next, [1, 2, 3]);
+
+        let calls = AtomicUsize::new(0);
+        group.parallel(|_| {
+            calls.fetch_add(1, Ordering::Relaxed);
+        });
+        group.call(|_| {
+            calls.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(calls.into_inner(), 6);
+    }
+
+    #[test]
+    fn group_fences_are_per_device() {
+        let group = group(3);
+
+        let sync_point = group
+            .call_at(2, |ctx| ctx.submit(CommandType::Graphics))
+            .expect("failed to submit");
+
+        assert!(group.is_ready_for(2, CommandType::Graphics, sync_point));
+        assert!(!group.is_ready_for(1, CommandType::Graphics, sync_point));
+        assert!(!group.is_ready_for(2, CommandType::Graphics, sync_point + 1));
     }
 }
@@ -5,5 +5,7 @@ pub mod resources;
 pub mod shader;
 pub mod swapchain;
 pub mod system;
+pub mod timeline;
+pub mod transient;
 
 mod container;
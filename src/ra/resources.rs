@@ -4,6 +4,7 @@ use crate::{
     collections::{handle::Handle, sparse_map::SparseMap},
     rhi::{
         command::{IoCommandBuffer, RenderCommandQueue},
+        error::RenderError,
         resources::{Buffer as _, BufferDesc, SamplerDesc, TextureDesc, TextureViewDesc},
     },
 };
@@ -24,11 +25,32 @@ pub struct Sampler;
 
 pub trait RenderResourceContext {
     // Resources
-    fn bind_buffer(&self, handle: Handle<Buffer>, desc: BufferDesc, init_data: Option<&[u8]>);
+    fn bind_buffer(
+        &self,
+        handle: Handle<Buffer>,
+        desc: BufferDesc,
+        init_data: Option<&[u8]>,
+    ) -> Result<(), RenderError>;
     fn unbind_buffer(&self, handle: Handle<Buffer>);
-    fn update_buffer<T: Clone>(&self, handle: Handle<Buffer>, offset: usize, data: &[T]);
+    fn update_buffer<T: Clone>(
+        &self,
+        handle: Handle<Buffer>,
+        offset: usize,
+        data: &[T],
+    ) -> Result<(), RenderError>;
+    fn read_buffer<T: Clone>(
+        &self,
+        handle: Handle<Buffer>,
+        offset: usize,
+        data: &mut [T],
+    ) -> Result<(), RenderError>;
 
-    fn bind_texture(&self, handle: Handle<Texture>, desc: TextureDesc, init_data: Option<&[u8]>);
+    fn bind_texture(
+        &self,
+        handle: Handle<Texture>,
+        desc: TextureDesc,
+        init_data: Option<&[u8]>,
+    ) -> Result<(), RenderError>;
     fn unbind_texture(&self, handle: Handle<Texture>);
 
     fn bind_texture_view(
@@ -36,33 +58,41 @@ pub trait RenderResourceContext {
         handle: Handle<Texture>,
         texture: Handle<Texture>,
         desc: TextureViewDesc,
-    );
+    ) -> Result<(), RenderError>;
 
     fn open_texture_handle(
         &self,
         handle: Handle<Texture>,
         other: &Self,
         overrided_view: Option<TextureViewDesc>,
-    );
+    ) -> Result<(), RenderError>;
 
-    fn bind_sampler(&self, handle: Handle<Sampler>, desc: SamplerDesc);
+    fn bind_sampler(&self, handle: Handle<Sampler>, desc: SamplerDesc) -> Result<(), RenderError>;
     fn unbind_sampler(&self, handle: Handle<Sampler>);
 }
 
 impl<D: RenderDevice> RenderResourceContext for Context<D> {
-    fn bind_buffer(&self, handle: Handle<Buffer>, desc: BufferDesc, init_data: Option<&[u8]>) {
-        let mut buffer = self.gpu.create_buffer(desc);
+    fn bind_buffer(
+        &self,
+        handle: Handle<Buffer>,
+        desc: BufferDesc,
+        init_data: Option<&[u8]>,
+    ) -> Result<(), RenderError> {
+        let mut buffer = self.gpu.create_buffer(desc)?;
 
         if let Some(init_data) = init_data {
-            let mut cmd = self.uploader.create_command_buffer(&self.gpu);
-            cmd.load_to_buffer(&self.gpu, &mut buffer, init_data);
+            let mut cmd = self.uploader.create_command_buffer(&self.gpu)?;
+            cmd.load_to_buffer(&self.gpu, &mut buffer, init_data)?;
             self.uploader.commit(cmd);
-            self.uploader.wait_on_cpu(self.uploader.submit(&self.gpu));
+            self.uploader
+                .wait_on_cpu(self.uploader.submit(&self.gpu)?)?;
         }
 
         if let Some(buffer) = self.mapper.buffers.write().set(handle, buffer) {
             self.gpu.destroy_buffer(buffer);
         }
+
+        Ok(())
     }
 
     fn unbind_buffer(&self, handle: Handle<Buffer>) {
@@ -73,29 +103,60 @@ impl<D: RenderDevice> RenderResourceContext for Context<D> {
         self.gpu.destroy_buffer(buffer);
     }
 
-    fn update_buffer<T: Clone>(&self, handle: Handle<Buffer>, offset: usize, data: &[T]) {
+    fn update_buffer<T: Clone>(
+        &self,
+        handle: Handle<Buffer>,
+        offset: usize,
+        data: &[T],
+    ) -> Result<(), RenderError> {
         let mut guard = self.mapper.buffers.write();
         let Some(buffer) = guard.get_mut(handle) else {
-            panic!("buffer doesn't exist")
+            return Err(not_bound("buffer", handle));
         };
 
         let buffer = &mut buffer.map_mut()[offset..(offset + data.len())];
         buffer.clone_from_slice(data);
+
+        Ok(())
+    }
+
+    fn read_buffer<T: Clone>(
+        &self,
+        handle: Handle<Buffer>,
+        offset: usize,
+        data: &mut [T],
+    ) -> Result<(), RenderError> {
+        let guard = self.mapper.buffers.read();
+        let Some(buffer) = guard.get(handle) else {
+            return Err(not_bound("buffer", handle));
+        };
+
+        data.clone_from_slice(&buffer.map()[offset..(offset + data.len())]);
+
+        Ok(())
     }
 
-    fn bind_texture(&self, handle: Handle<Texture>, desc: TextureDesc, init_data: Option<&[u8]>) {
-        let texture = self.gpu.create_texture(desc);
+    fn bind_texture(
+        &self,
+        handle: Handle<Texture>,
+        desc: TextureDesc,
+        init_data: Option<&[u8]>,
+    ) -> Result<(), RenderError> {
+        let texture = self.gpu.create_texture(desc)?;
 
         if let Some(init_data) = init_data {
-            let mut cmd = self.uploader.create_command_buffer(&self.gpu);
-            cmd.load_to_texture(&self.gpu, &texture, init_data);
+            let mut cmd = self.uploader.create_command_buffer(&self.gpu)?;
+            cmd.load_to_texture(&self.gpu, &texture, init_data)?;
             self.uploader.commit(cmd);
-            self.uploader.wait_on_cpu(self.uploader.submit(&self.gpu));
+            self.uploader
+                .wait_on_cpu(self.uploader.submit(&self.gpu)?)?;
         }
 
         if let Some(texture) = self.mapper.textures.write().set(handle, texture) {
             self.gpu.destroy_texture(texture);
         }
+
+        Ok(())
     }
 
     fn unbind_texture(&self, handle: Handle<Texture>) {
@@ -111,16 +172,16 @@ impl<D: RenderDevice> RenderResourceContext for Context<D> {
         handle: Handle<Texture>,
         texture: Handle<Texture>,
         desc: TextureViewDesc,
-    ) {
+    ) -> Result<(), RenderError> {
         let mut guard = self.mapper.textures.write();
-        let Some(texture) = guard.get(texture) else {
-            panic!("texture doesn't exist")
-        };
+        let texture = bound(&guard, texture, "texture")?;
 
-        let texture = self.gpu.create_texture_view(texture, desc);
+        let texture = self.gpu.create_texture_view(texture, desc)?;
         if let Some(view) = guard.set(handle, texture) {
             self.gpu.destroy_texture(view);
         }
+
+        Ok(())
     }
 
     fn open_texture_handle(
@@ -128,25 +189,27 @@ impl<D: RenderDevice> RenderResourceContext for Context<D> {
         handle: Handle<Texture>,
         other: &Self,
         overrided_view: Option<TextureViewDesc>,
-    ) {
+    ) -> Result<(), RenderError> {
         let guard = other.mapper.textures.write();
-        let Some(texture) = guard.get(handle) else {
-            panic!("texture doesn't exist")
-        };
+        let texture = bound(&guard, handle, "texture")?;
 
-        let texture = self.gpu.open_texture(texture, &other.gpu, overrided_view);
+        let texture = self.gpu.open_texture(texture, &other.gpu, overrided_view)?;
         let mut self_guard = self.mapper.textures.write();
         if let Some(texture) = self_guard.set(handle, texture) {
             self.gpu.destroy_texture(texture);
         }
+
+        Ok(())
     }
 
-    fn bind_sampler(&self, handle: Handle<Sampler>, desc: SamplerDesc) {
-        let sampler = self.gpu.create_sampler(desc);
+    fn bind_sampler(&self, handle: Handle<Sampler>, desc: SamplerDesc) -> Result<(), RenderError> {
+        let sampler = self.gpu.create_sampler(desc)?;
 
         if let Some(sampler) = self.mapper.samplers.write().set(handle, sampler) {
             self.gpu.destroy_sampler(sampler);
         }
+
+        Ok(())
     }
 
     fn unbind_sampler(&self, handle: Handle<Sampler>) {
@@ -180,3 +243,18 @@ impl<D: RenderDevice> Default for ResourceMapper<D> {
         }
     }
 }
+
+pub(super) fn bound<'a, U, W>(
+    map: &'a SparseMap<U, W>,
+    handle: Handle<U>,
+    kind: &'static str,
+) -> Result<&'a W, RenderError> {
+    map.get(handle).ok_or_else(|| not_bound(kind, handle))
+}
+
+pub(super) fn not_bound<U>(kind: &'static str, handle: Handle<U>) -> RenderError {
+    RenderError::HandleNotBound {
+        kind,
+        index: handle.idx(),
+    }
+}
@@ -2,14 +2,15 @@ use crate::{
     collections::handle::Handle,
     rhi::{
         self,
+        error::RenderError,
         shader::{CompiledShader, PipelineLayoutDesc},
-        types::{CullMode, DepthStateDesc, Format, InputElementDesc},
+        types::{BlendMode, CullMode, DepthStateDesc, Format, InputElementDesc},
     },
 };
 
 use super::{
     context::{Context, RenderDevice},
-    resources::{Buffer, Sampler, Texture},
+    resources::{Buffer, Sampler, Texture, bound},
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -22,23 +23,41 @@ pub struct ShaderArgument;
 pub struct RasterPipeline;
 
 pub trait RenderShaderContext {
-    fn bind_pipeline_layout(&self, handle: Handle<PipelineLayout>, desc: PipelineLayoutDesc<'_>);
+    fn bind_pipeline_layout(
+        &self,
+        handle: Handle<PipelineLayout>,
+        desc: PipelineLayoutDesc<'_>,
+    ) -> Result<(), RenderError>;
     fn unbind_pipeline_layout(&self, handle: Handle<PipelineLayout>);
 
-    fn bind_shader_argument(&self, handle: Handle<ShaderArgument>, desc: ShaderArgumentDesc<'_>);
+    fn bind_shader_argument(
+        &self,
+        handle: Handle<ShaderArgument>,
+        desc: ShaderArgumentDesc<'_>,
+    ) -> Result<(), RenderError>;
     fn unbind_shader_argument(&self, handle: Handle<ShaderArgument>);
 
-    fn bind_raster_pipeline(&self, handle: Handle<RasterPipeline>, desc: RasterPipelineDesc<'_>);
+    fn bind_raster_pipeline(
+        &self,
+        handle: Handle<RasterPipeline>,
+        desc: RasterPipelineDesc<'_>,
+    ) -> Result<(), RenderError>;
     fn unbind_raster_pipeline(&self, handle: Handle<RasterPipeline>);
 }
 
 impl<D: RenderDevice> RenderShaderContext for Context<D> {
-    fn bind_pipeline_layout(&self, handle: Handle<PipelineLayout>, desc: PipelineLayoutDesc<'_>) {
-        let layout = self.gpu.create_pipeline_layout(desc);
+    fn bind_pipeline_layout(
+        &self,
+        handle: Handle<PipelineLayout>,
+        desc: PipelineLayoutDesc<'_>,
+    ) -> Result<(), RenderError> {
+        let layout = self.gpu.create_pipeline_layout(desc)?;
 
         if let Some(layout) = self.mapper.pipeline_layouts.write().set(handle, layout) {
             self.gpu.destroy_pipeline_layout(layout);
         }
+
+        Ok(())
     }
 
     fn unbind_pipeline_layout(&self, handle: Handle<PipelineLayout>) {
@@ -49,32 +68,42 @@ impl<D: RenderDevice> RenderShaderContext for Context<D> {
         self.gpu.destroy_pipeline_layout(layout);
     }
 
-    fn bind_shader_argument(&self, handle: Handle<ShaderArgument>, desc: ShaderArgumentDesc<'_>) {
+    fn bind_shader_argument(
+        &self,
+        handle: Handle<ShaderArgument>,
+        desc: ShaderArgumentDesc<'_>,
+    ) -> Result<(), RenderError> {
         let buffers = self.mapper.buffers.write();
         let textures = self.mapper.textures.write();
         let samplers = self.mapper.samplers.write();
 
-        let views = desc.views.iter().map(|e| match e {
-            ShaderEntry::Cbv(handle, size) => rhi::shader::ShaderEntry::Cbv(
-                buffers.get(*handle).expect("failed to get buffer"),
-                *size,
-            ),
-            ShaderEntry::Srv(handle) => {
-                rhi::shader::ShaderEntry::Srv(textures.get(*handle).expect("failed to get texture"))
-            }
-            ShaderEntry::Uav(handle) => {
-                rhi::shader::ShaderEntry::Uav(textures.get(*handle).expect("failed to get texture"))
-            }
-        });
+        let views = desc
+            .views
+            .iter()
+            .map(|e| match e {
+                ShaderEntry::Cbv(handle, size) => Ok(rhi::shader::ShaderEntry::Cbv(
+                    bound(&buffers, *handle, "buffer")?,
+                    *size,
+                )),
+                ShaderEntry::Srv(handle) => Ok(rhi::shader::ShaderEntry::Srv(bound(
+                    &textures, *handle, "texture",
+                )?)),
+                ShaderEntry::Uav(handle) => Ok(rhi::shader::ShaderEntry::Uav(bound(
+                    &textures, *handle, "texture",
+                )?)),
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?;
 
         let samplers = desc
             .samplers
             .iter()
-            .map(|s| samplers.get(*s).expect("failed to get sampler"));
+            .map(|s| bound(&samplers, *s, "sampler"))
+            .collect::<Result<Vec<_>, RenderError>>()?;
 
         let dynamic_buffer = desc
             .dynamic_buffer
-            .map(|b| buffers.get(b).expect("failed to get buffer"));
+            .map(|b| bound(&buffers, b, "buffer"))
+            .transpose()?;
 
         let desc = rhi::shader::ShaderArgumentDesc {
             views,
@@ -82,11 +111,13 @@ impl<D: RenderDevice> RenderShaderContext for Context<D> {
             dynamic_buffer,
         };
 
-        let argument = self.gpu.create_shader_argument(desc);
+        let argument = self.gpu.create_shader_argument(desc)?;
 
         if let Some(argument) = self.mapper.shader_arguments.write().set(handle, argument) {
             self.gpu.destroy_shader_argument(argument);
         }
+
+        Ok(())
     }
 
     fn unbind_shader_argument(&self, handle: Handle<ShaderArgument>) {
@@ -97,11 +128,16 @@ impl<D: RenderDevice> RenderShaderContext for Context<D> {
         self.gpu.destroy_shader_argument(argument);
     }
 
-    fn bind_raster_pipeline(&self, handle: Handle<RasterPipeline>, desc: RasterPipelineDesc<'_>) {
+    fn bind_raster_pipeline(
+        &self,
+        handle: Handle<RasterPipeline>,
+        desc: RasterPipelineDesc<'_>,
+    ) -> Result<(), RenderError> {
         let guard = self.mapper.pipeline_layouts.write();
         let layout = desc
             .layout
-            .map(|h| guard.get(h).expect("failed to get pipeline layout"));
+            .map(|h| bound(&guard, h, "pipeline layout"))
+            .transpose()?;
 
         let desc = crate::rhi::shader::RasterPipelineDesc {
             layout,
@@ -112,15 +148,18 @@ impl<D: RenderDevice> RenderShaderContext for Context<D> {
             depth: desc.depth,
             render_targets: desc.render_targets,
             cull_mode: desc.cull_mode,
+            blend: desc.blend,
             vs: desc.vs,
             shaders: desc.shaders,
         };
 
-        let pipeline = self.gpu.create_raster_pipeline(desc);
+        let pipeline = self.gpu.create_raster_pipeline(desc)?;
 
         if let Some(pipeline) = self.mapper.raster_pipelines.write().set(handle, pipeline) {
             self.gpu.destroy_raster_pipeline(pipeline);
         }
+
+        Ok(())
     }
 
     fn unbind_raster_pipeline(&self, handle: Handle<RasterPipeline>) {
@@ -142,6 +181,7 @@ pub struct RasterPipelineDesc<'a> {
     pub depth: Option<DepthStateDesc>,
     pub render_targets: &'a [Format],
     pub cull_mode: CullMode,
+    pub blend: BlendMode,
 
     pub vs: &'a CompiledShader,
     pub shaders: &'a [&'a CompiledShader],
@@ -3,7 +3,10 @@ use winit::raw_window_handle::RawWindowHandle;
 
 use crate::{
     collections::handle::Handle,
-    rhi::swapchain::{Surface as _, SwapchainDesc, SwapchainFrame},
+    rhi::{
+        error::RenderError,
+        swapchain::{Surface as _, SwapchainDesc, SwapchainFrame},
+    },
 };
 
 use super::{
@@ -26,13 +29,13 @@ pub trait RenderSwapchainContext {
         desc: SwapchainDesc,
         wnd: &RawWindowHandle,
         handle_allocator: &HandleContainer,
-    ) -> Self::Swapchain;
+    ) -> Result<Self::Swapchain, RenderError>;
     fn resize(
         &self,
         swapchain: &mut Self::Swapchain,
         extent: [u32; 2],
         handle_allocator: &HandleContainer,
-    );
+    ) -> Result<(), RenderError>;
     fn destroy_swapchain(&self, swapchain: Self::Swapchain, handle_allocator: &HandleContainer);
 }
 
@@ -44,12 +47,12 @@ impl<D: RenderDevice> RenderSwapchainContext for Context<D> {
         desc: SwapchainDesc,
         wnd: &RawWindowHandle,
         handle_allocator: &HandleContainer,
-    ) -> Self::Swapchain {
+    ) -> Result<Self::Swapchain, RenderError> {
         let handles = (0..desc.frames).map(|_| handle_allocator.create_texture_handle());
 
         let mut raw = self
             .gpu
-            .create_swapchain(desc.clone(), wnd, &self.graphics_queue.raw);
+            .create_swapchain(desc.clone(), wnd, &self.graphics_queue.raw)?;
 
         let frames = raw.drain_frames();
 
@@ -66,7 +69,7 @@ impl<D: RenderDevice> RenderSwapchainContext for Context<D> {
             })
             .collect();
 
-        Swapchain { raw, frames, desc }
+        Ok(Swapchain { raw, frames, desc })
     }
 
     fn resize(
@@ -74,7 +77,7 @@ impl<D: RenderDevice> RenderSwapchainContext for Context<D> {
         swapchain: &mut Self::Swapchain,
         extent: [u32; 2],
         handle_allocator: &HandleContainer,
-    ) {
+    ) -> Result<(), RenderError> {
         for frame in swapchain.frames.drain(..) {
             if let Some(texture) = self.mapper.textures.write().remove(frame.texture) {
                 self.gpu.destroy_swapchain_image(texture);
@@ -83,7 +86,7 @@ impl<D: RenderDevice> RenderSwapchainContext for Context<D> {
             handle_allocator.free_texture_handle(frame.texture);
         }
 
-        self.gpu.resize(&mut swapchain.raw, extent);
+        self.gpu.resize(&mut swapchain.raw, extent)?;
 
         let handles = (0..swapchain.desc.frames).map(|_| handle_allocator.create_texture_handle());
 
@@ -103,6 +106,8 @@ impl<D: RenderDevice> RenderSwapchainContext for Context<D> {
             .collect();
 
         swapchain.frames = frames;
+
+        Ok(())
     }
 
     fn destroy_swapchain(&self, swapchain: Self::Swapchain, handle_allocator: &HandleContainer) {
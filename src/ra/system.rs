@@ -1,8 +1,18 @@
 use std::sync::Arc;
 
+#[cfg(all(windows, feature = "dx12"))]
+use crate::rhi::dx12::backend::DxBackend;
 use crate::{
     collections::handle::Handle,
-    rhi::{backend::DebugFlags, dx12::backend::DxBackend},
+    rhi::{
+        backend::DebugFlags,
+        null::backend::NullBackend,
+        recording::{
+            backend::RecordingBackend,
+            trace::{Recorder, TraceSink, TraceSinks},
+        },
+        validation::Validator,
+    },
 };
 
 use super::{
@@ -12,31 +22,109 @@ use super::{
     shader::{PipelineLayout, RasterPipeline, ShaderArgument},
 };
 
+// The backend the recording wrapper goes over.
+#[cfg(all(windows, feature = "dx12"))]
+pub type NativeBackend = DxBackend;
+#[cfg(not(all(windows, feature = "dx12")))]
+pub type NativeBackend = NullBackend;
+
 #[derive(Debug)]
 pub struct RenderSystem {
+    #[cfg(all(windows, feature = "dx12"))]
     pub(super) dx_backend: Option<Arc<Backend<DxBackend>>>,
+    pub(super) null_backend: Option<Arc<Backend<NullBackend>>>,
+    pub(super) recording_backend: Option<Arc<Backend<RecordingBackend<NativeBackend>>>>,
+    pub(super) recorder: Option<Arc<Recorder>>,
+    pub(super) validator: Option<Arc<Validator>>,
 
     pub handles: HandleContainer,
 }
 
 impl RenderSystem {
     pub fn new(backend_settings: &[RenderBackendSettings]) -> Self {
+        #[cfg(all(windows, feature = "dx12"))]
         let dx_backend = backend_settings
             .iter()
             .find(|b| b.api == RenderBackend::Dx12)
             .and_then(|settings| Some(Arc::new(Backend::new(DxBackend::new(settings.debug)))));
 
+        let null_backend = backend_settings
+            .iter()
+            .find(|b| b.api == RenderBackend::Null)
+            .map(|_| Arc::new(Backend::new(NullBackend::new())));
+
+        let recording = backend_settings
+            .iter()
+            .find(|b| b.api == RenderBackend::Recording);
+
+        let recorder = recording.map(|_| Arc::new(Recorder::new()));
+        let validator = recording
+            .filter(|settings| settings.debug.contains(DebugFlags::RhiValidation))
+            .map(|_| Arc::new(Validator::new()));
+
+        let recording_backend = recording.map(|settings| {
+            Arc::new(Backend::new(RecordingBackend::new(
+                native_backend(settings.debug),
+                trace_sink(recorder.as_ref(), validator.as_ref()),
+            )))
+        });
+
         Self {
+            #[cfg(all(windows, feature = "dx12"))]
             dx_backend,
+            null_backend,
+            recording_backend,
+            recorder,
+            validator,
             handles: HandleContainer::new(),
         }
     }
 
+    pub fn with_null_backend(mut self, backend: NullBackend) -> Self {
+        self.null_backend = Some(Arc::new(Backend::new(backend)));
+        self
+    }
+
+    // Replaces the recorded backend, keeping the recorder and validator of
+    // `new` if there are any.
+    pub fn with_recording_backend(mut self, backend: NativeBackend) -> Self {
+        let recorder = self
+            .recorder
+            .get_or_insert_with(|| Arc::new(Recorder::new()));
+
+        self.recording_backend = Some(Arc::new(Backend::new(RecordingBackend::new(
+            backend,
+            trace_sink(Some(recorder), self.validator.as_ref()),
+        ))));
+        self
+    }
+
+    #[cfg(all(windows, feature = "dx12"))]
     #[inline]
     pub fn dx_backend(&self) -> Option<Arc<Backend<DxBackend>>> {
         self.dx_backend.clone()
     }
 
+    #[inline]
+    pub fn null_backend(&self) -> Option<Arc<Backend<NullBackend>>> {
+        self.null_backend.clone()
+    }
+
+    #[inline]
+    pub fn recording_backend(&self) -> Option<Arc<Backend<RecordingBackend<NativeBackend>>>> {
+        self.recording_backend.clone()
+    }
+
+    #[inline]
+    pub fn recorder(&self) -> Option<Arc<Recorder>> {
+        self.recorder.clone()
+    }
+
+    #[inline]
+    pub fn validator(&self) -> Option<Arc<Validator>> {
+        self.validator.clone()
+    }
+
     #[inline]
     pub fn create_buffer_handle(&self) -> Handle<Buffer> {
         self.handles.create_buffer_handle()
@@ -98,6 +186,30 @@ impl RenderSystem {
     }
 }
 
+fn trace_sink(
+    recorder: Option<&Arc<Recorder>>,
+    validator: Option<&Arc<Validator>>,
+) -> Arc<dyn TraceSink> {
+    let recorder = recorder.map(|r| Arc::clone(r) as Arc<dyn TraceSink>);
+    let validator = validator.map(|v| Arc::clone(v) as Arc<dyn TraceSink>);
+
+    match (recorder, validator) {
+        (Some(recorder), Some(validator)) => Arc::new(TraceSinks::new([recorder, validator])),
+        (Some(sink), None) | (None, Some(sink)) => sink,
+        (None, None) => Arc::new(Recorder::new()),
+    }
+}
+
+#[cfg(all(windows, feature = "dx12"))]
+fn native_backend(debug: DebugFlags) -> NativeBackend {
+    DxBackend::new(debug)
+}
+
+#[cfg(not(all(windows, feature = "dx12")))]
+fn native_backend(_: DebugFlags) -> NativeBackend {
+    NullBackend::new()
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RenderBackendSettings {
     pub api: RenderBackend,
@@ -107,4 +219,7 @@ pub struct RenderBackendSettings {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RenderBackend {
     Dx12,
+    Null,
+    // Records every call over the native backend, see `rhi::recording`.
+    Recording,
 }
@@ -0,0 +1,577 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::rhi::{
+    backend::RenderDeviceId,
+    command::{CommandType, Subresource, SyncPoint},
+    types::ResourceState,
+};
+
+use super::command::Barrier;
+
+#[derive(Debug, Default)]
+pub struct Timeline {
+    enabled: AtomicBool,
+    frame: AtomicUsize,
+    entries: Mutex<Vec<TimelineEntry>>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn begin_frame(&self) {
+        self.entries.lock().clear();
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn end_frame(&self) -> FrameTimeline {
+        self.enabled.store(false, Ordering::Relaxed);
+
+        FrameTimeline {
+            frame: self.frame.fetch_add(1, Ordering::Relaxed),
+            entries: std::mem::take(&mut *self.entries.lock()),
+        }
+    }
+
+    fn record(&self, entries: impl IntoIterator<Item = TimelineEntry>) {
+        if self.is_enabled() {
+            self.entries.lock().extend(entries);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct TimelineRecorder {
+    device: RenderDeviceId,
+    timeline: Arc<Timeline>,
+}
+
+impl TimelineRecorder {
+    pub(super) fn new(device: RenderDeviceId, timeline: Arc<Timeline>) -> Self {
+        Self { device, timeline }
+    }
+
+    pub(super) fn device(&self) -> RenderDeviceId {
+        self.device
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.timeline.is_enabled()
+    }
+
+    pub(super) fn record(&self, queue: CommandType, events: Vec<TimelineEvent>) {
+        self.timeline
+            .record(events.into_iter().map(|event| TimelineEntry {
+                device: self.device,
+                queue,
+                event,
+            }));
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameTimeline {
+    pub frame: usize,
+    pub entries: Vec<TimelineEntry>,
+}
+
+impl FrameTimeline {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("failed to serialize timeline")
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut lanes: BTreeMap<(RenderDeviceId, u8), Vec<usize>> = BTreeMap::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            lanes
+                .entry((entry.device, queue_order(entry.queue)))
+                .or_default()
+                .push(i);
+        }
+
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph \"Frame {}\" {{", self.frame);
+        let _ = writeln!(dot, "    rankdir=LR;");
+        let _ = writeln!(dot, "    node [shape=box, fontname=\"monospace\"];");
+
+        for ((device, _), indices) in &lanes {
+            let queue = self.entries[indices[0]].queue;
+
+            let _ = writeln!(dot, "    subgraph \"cluster_{}_{:?}\" {{", device, queue);
+            let _ = writeln!(dot, "        label=\"Device {} {:?}\";", device, queue);
+
+            for i in indices {
+                let event = &self.entries[*i].event;
+                let _ = writeln!(
+                    dot,
+                    "        n{} [label=\"#{} {}\", shape={}];",
+                    i,
+                    i,
+                    escape(&event.label()),
+                    event.shape()
+                );
+            }
+
+            for pair in indices.windows(2) {
+                let _ = writeln!(dot, "        n{} -> n{};", pair[0], pair[1]);
+            }
+
+            let _ = writeln!(dot, "    }}");
+        }
+
+        // Pulls are linked to the push whose data they copy and waits to the
+        // signal they wait for. Either may have been encoded in an earlier
+        // frame, in which case it has no node to link to.
+        for (i, entry) in self.entries.iter().enumerate() {
+            let source = match &entry.event {
+                TimelineEvent::Transfer {
+                    channel: Some(pull),
+                    ..
+                } if pull.direction == TransferDirection::Pull => {
+                    self.entries.iter().position(|other| match &other.event {
+                        TimelineEvent::Transfer {
+                            channel: Some(push),
+                            ..
+                        } => push.direction == TransferDirection::Push && push.copies(pull),
+                        _ => false,
+                    })
+                }
+                TimelineEvent::WaitEvent {
+                    signaller,
+                    sync_point,
+                } => self.entries.iter().position(|other| {
+                    other.device == *signaller
+                        && other.event
+                            == TimelineEvent::SignalEvent {
+                                sync_point: *sync_point,
+                            }
+                }),
+                _ => None,
+            };
+
+            if let Some(source) = source {
+                let _ = writeln!(dot, "    n{} -> n{} [style=dashed];", source, i);
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+
+        dot
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) {
+        std::fs::write(path, self.to_json()).expect("failed to write timeline");
+    }
+
+    pub fn save_dot(&self, path: impl AsRef<Path>) {
+        std::fs::write(path, self.to_dot()).expect("failed to write timeline");
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub device: RenderDeviceId,
+    pub queue: CommandType,
+    pub event: TimelineEvent,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TimelineEvent {
+    Render {
+        label: Cow<'static, str>,
+    },
+    Transfer {
+        label: Cow<'static, str>,
+        channel: Option<ChannelTransfer>,
+    },
+    Barriers {
+        barriers: Vec<TimelineBarrier>,
+    },
+    Submit {
+        sync_point: SyncPoint,
+    },
+    SignalEvent {
+        sync_point: SyncPoint,
+    },
+    WaitEvent {
+        signaller: RenderDeviceId,
+        sync_point: SyncPoint,
+    },
+}
+
+// Identifies the data a cross-device transfer carries, a pull copies the
+// push with the same number into the same slot.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelTransfer {
+    pub channel: Cow<'static, str>,
+    pub direction: TransferDirection,
+    pub slot: usize,
+    pub push: u64,
+}
+
+impl ChannelTransfer {
+    fn copies(&self, other: &Self) -> bool {
+        self.channel == other.channel && self.slot == other.slot && self.push == other.push
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    Push,
+    Pull,
+}
+
+impl TimelineEvent {
+    fn label(&self) -> String {
+        match self {
+            TimelineEvent::Render { label } => format!("Render: {}", label),
+            TimelineEvent::Transfer {
+                label,
+                channel: None,
+            } => format!("Transfer: {}", label),
+            TimelineEvent::Transfer {
+                label,
+                channel: Some(channel),
+            } => format!(
+                "Transfer: {} (slot {}, push #{})",
+                label, channel.slot, channel.push
+            ),
+            TimelineEvent::Barriers { barriers } => barriers
+                .iter()
+                .map(|b| match b {
+                    TimelineBarrier::Buffer { handle, state } => {
+                        format!("Buffer {} -> {:?}", handle, state)
+                    }
+                    TimelineBarrier::Texture {
+                        handle,
+                        state,
+                        subresource,
+                    } => format!("Texture {} -> {:?} ({:?})", handle, state, subresource),
+                    TimelineBarrier::Alias { handle } => format!("Texture {} aliased", handle),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            TimelineEvent::Submit { sync_point } => format!("Submit @{}", sync_point),
+            TimelineEvent::SignalEvent { sync_point } => format!("Signal @{}", sync_point),
+            TimelineEvent::WaitEvent {
+                signaller,
+                sync_point,
+            } => format!("Wait @{} on device {}", sync_point, signaller),
+        }
+    }
+
+    fn shape(&self) -> &'static str {
+        match self {
+            TimelineEvent::Render { .. } | TimelineEvent::Transfer { .. } => "box",
+            TimelineEvent::Barriers { .. } => "note",
+            TimelineEvent::Submit { .. } => "doublecircle",
+            TimelineEvent::SignalEvent { .. } | TimelineEvent::WaitEvent { .. } => "diamond",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimelineBarrier {
+    Buffer {
+        handle: u32,
+        state: ResourceState,
+    },
+    Texture {
+        handle: u32,
+        state: ResourceState,
+        subresource: Subresource,
+    },
+    Alias {
+        handle: u32,
+    },
+}
+
+impl From<&Barrier> for TimelineBarrier {
+    fn from(barrier: &Barrier) -> Self {
+        match barrier {
+            Barrier::Buffer(handle, state) => TimelineBarrier::Buffer {
+                handle: handle.idx(),
+                state: *state,
+            },
+            Barrier::Texture(handle, state, subresource) => TimelineBarrier::Texture {
+                handle: handle.idx(),
+                state: *state,
+                subresource: *subresource,
+            },
+            Barrier::Alias(handle) => TimelineBarrier::Alias {
+                handle: handle.idx(),
+            },
+        }
+    }
+}
+
+fn queue_order(ty: CommandType) -> u8 {
+    match ty {
+        CommandType::Graphics => 0,
+        CommandType::Compute => 1,
+        CommandType::Transfer => 2,
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        collections::handle::Handle,
+        ra::{
+            backend::Backend,
+            command::{RenderCommandContext, RenderCommandEncoder},
+            context::Context,
+            resources::{RenderResourceContext, Texture},
+            system::RenderSystem,
+        },
+        render_graph::{PassDesc, RenderGraph, channel::CrossDeviceChannel},
+        rhi::{
+            command::RenderCommandDevice,
+            null::{backend::NullBackend, device::NullDevice},
+            resources::{TextureDesc, TextureUsages},
+            types::Format,
+        },
+    };
+
+    use super::*;
+
+    fn target(rs: &RenderSystem, ctx: &Context<NullDevice>) -> Handle<Texture> {
+        let texture = rs.create_texture_handle();
+        ctx.bind_texture(
+            texture,
+            TextureDesc::new_2d([4, 4], Format::Rgba8Unorm, TextureUsages::RenderTarget),
+            None,
+        )
+        .expect("failed to bind texture");
+
+        texture
+    }
+
+    fn shade(graph: &mut RenderGraph<'_, NullDevice>, target: Handle<Texture>) {
+        graph.add_pass(
+            PassDesc::new("Shade").with_write_texture(target, ResourceState::RenderTarget),
+            move |cmd| cmd.render("Shade".into(), &[target], None).map(|_| ()),
+        );
+        graph.export_texture(target, Some(ResourceState::Present));
+    }
+
+    fn position(frame: &FrameTimeline, event: &TimelineEvent) -> usize {
+        frame
+            .entries
+            .iter()
+            .position(|entry| entry.event == *event)
+            .expect("event not recorded")
+    }
+
+    fn dashed_edges(dot: &str) -> Vec<&str> {
+        dot.lines()
+            .filter(|line| line.contains("style=dashed"))
+            .map(str::trim)
+            .collect()
+    }
+
+    fn channel_transfer(direction: TransferDirection, slot: usize, push: u64) -> TimelineEvent {
+        TimelineEvent::Transfer {
+            label: format!("{:?} Shadows", direction).into(),
+            channel: Some(ChannelTransfer {
+                channel: "Shadows".into(),
+                direction,
+                slot,
+                push,
+            }),
+        }
+    }
+
+    #[test]
+    fn single_gpu_frame() {
+        let timeline = Arc::new(Timeline::new());
+        let group = Backend::new(NullBackend::new()).create_group_with_timeline(&[0], &timeline);
+        let ctx = group.primary();
+        let rs = RenderSystem::new(&[]);
+        let target = target(&rs, ctx);
+
+        timeline.begin_frame();
+        let mut graph = RenderGraph::new();
+        shade(&mut graph, target);
+        graph.execute(ctx).expect("failed to execute graph");
+        ctx.submit(CommandType::Graphics).expect("failed to submit");
+        let frame = timeline.end_frame();
+
+        let barrier = |state| TimelineEvent::Barriers {
+            barriers: vec![TimelineBarrier::Texture {
+                handle: target.idx(),
+                state,
+                subresource: Subresource::Local(None),
+            }],
+        };
+        assert_eq!(
+            frame
+                .entries
+                .iter()
+                .map(|entry| &entry.event)
+                .collect::<Vec<_>>(),
+            [
+                &barrier(ResourceState::RenderTarget),
+                &TimelineEvent::Render {
+                    label: "Shade".into()
+                },
+                &barrier(ResourceState::Present),
+                &TimelineEvent::Submit { sync_point: 1 },
+            ]
+        );
+        assert!(
+            frame
+                .entries
+                .iter()
+                .all(|entry| entry.device == 0 && entry.queue == CommandType::Graphics)
+        );
+
+        let json = frame.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).expect("invalid json");
+        assert_eq!(value["entries"][1]["event"]["Render"]["label"], "Shade");
+        assert_eq!(value["entries"][3]["event"]["Submit"]["sync_point"], 1);
+        assert_eq!(
+            serde_json::from_str::<FrameTimeline>(&json).expect("invalid timeline"),
+            frame
+        );
+
+        let dot = frame.to_dot();
+        assert!(dot.contains("subgraph \"cluster_0_Graphics\""));
+        assert!(dot.contains("n1 [label=\"#1 Render: Shade\", shape=box];"));
+        assert!(dot.contains("n3 [label=\"#3 Submit @1\", shape=doublecircle];"));
+        for edge in ["n0 -> n1;", "n1 -> n2;", "n2 -> n3;"] {
+            assert!(dot.contains(edge), "missing edge {}", edge);
+        }
+        assert!(dashed_edges(&dot).is_empty());
+    }
+
+    // Two pushes are in flight when the consumer pulls, the pull copies the
+    // older one. The readback after it is not part of any channel.
+    #[test]
+    fn multi_gpu_frame() {
+        let timeline = Arc::new(Timeline::new());
+        let group = Backend::new(NullBackend::new()).create_group_with_timeline(&[0, 1], &timeline);
+        let [primary, secondary] = [0, 1].map(|i| Arc::clone(group.get(i).expect("no device")));
+        let rs = RenderSystem::new(&[]);
+
+        let mut channel = CrossDeviceChannel::new(
+            &rs,
+            "Shadows",
+            Arc::clone(&secondary),
+            Arc::clone(&primary),
+            &[TextureDesc::new_2d(
+                [4, 4],
+                Format::R32,
+                TextureUsages::RenderTarget | TextureUsages::Resource,
+            )],
+            2,
+        )
+        .expect("failed to create channel");
+        let event = secondary.gpu.create_event(true);
+        let opened = primary.gpu.open_event(&event, &secondary.gpu);
+
+        timeline.begin_frame();
+        for _ in 0..2 {
+            let slot = channel.acquire_write().expect("no free slot");
+            channel
+                .produce(slot, RenderGraph::new())
+                .expect("failed to produce");
+        }
+        let sync_point = secondary
+            .signal_event(CommandType::Graphics, &event)
+            .expect("failed to signal");
+        primary
+            .wait_event(CommandType::Graphics, &opened, 1)
+            .expect("failed to wait");
+        channel.pull().expect("failed to pull");
+        let mut cmd = primary
+            .create_encoder(CommandType::Transfer)
+            .expect("failed to create encoder");
+        cmd.transfer("Depth Readback".into());
+        primary.commit(cmd);
+        let frame = timeline.end_frame();
+
+        let first_push = position(&frame, &channel_transfer(TransferDirection::Push, 0, 1));
+        let second_push = position(&frame, &channel_transfer(TransferDirection::Push, 1, 2));
+        let pull = position(&frame, &channel_transfer(TransferDirection::Pull, 0, 1));
+        let signal = position(&frame, &TimelineEvent::SignalEvent { sync_point });
+        let wait = position(
+            &frame,
+            &TimelineEvent::WaitEvent {
+                signaller: 1,
+                sync_point,
+            },
+        );
+        let readback = frame.entries.len() - 1;
+
+        for push in [first_push, second_push, signal] {
+            assert_eq!(frame.entries[push].device, 1);
+        }
+        assert_eq!(frame.entries[pull].device, 0);
+        assert_eq!(frame.entries[pull].queue, CommandType::Transfer);
+        assert_eq!(frame.entries[wait].device, 0);
+
+        let value: serde_json::Value =
+            serde_json::from_str(&frame.to_json()).expect("invalid json");
+        assert_eq!(
+            value["entries"][pull]["event"]["Transfer"]["channel"],
+            serde_json::json!({
+                "channel": "Shadows",
+                "direction": "Pull",
+                "slot": 0,
+                "push": 1,
+            })
+        );
+        assert_eq!(
+            value["entries"][wait]["event"]["WaitEvent"],
+            serde_json::json!({ "signaller": 1, "sync_point": sync_point })
+        );
+        assert_eq!(
+            value["entries"][readback]["event"]["Transfer"],
+            serde_json::json!({ "label": "Depth Readback", "channel": null })
+        );
+
+        let dot = frame.to_dot();
+        assert!(dot.contains("subgraph \"cluster_0_Transfer\""));
+        assert!(dot.contains("subgraph \"cluster_1_Graphics\""));
+        assert!(dot.contains(&format!(
+            "n{} [label=\"#{} Transfer: Pull Shadows (slot 0, push #1)\", shape=box];",
+            pull, pull
+        )));
+        assert!(dot.contains(&format!(
+            "n{} [label=\"#{} Wait @{} on device 1\", shape=diamond];",
+            wait, wait, sync_point
+        )));
+        assert_eq!(
+            dashed_edges(&dot),
+            [
+                format!("n{} -> n{} [style=dashed];", signal, wait),
+                format!("n{} -> n{} [style=dashed];", first_push, pull),
+            ]
+        );
+    }
+}
@@ -0,0 +1,654 @@
+use std::{cmp::Reverse, collections::VecDeque, sync::Arc};
+
+use smallvec::SmallVec;
+
+use crate::{
+    collections::handle::Handle,
+    render_graph::{RenderGraph, TextureSlots},
+    rhi::{
+        error::RenderError,
+        resources::{TextureDesc, TextureUsages, TextureViewDesc, TextureViewType, texture_bytes},
+    },
+};
+
+use super::{
+    context::{Context, RenderDevice},
+    resources::{RenderResourceContext, Texture, bound},
+    system::RenderSystem,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransientRequest {
+    pub desc: TextureDesc,
+    pub first_pass: usize,
+    pub last_pass: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransientPlan {
+    // Memory block of every request, requests sharing a block alias.
+    pub assignments: Vec<usize>,
+    pub blocks: Vec<usize>,
+    pub requested_bytes: usize,
+    pub peak_bytes: usize,
+    pub allocated_bytes: usize,
+}
+
+impl TransientPlan {
+    pub fn new(requests: &[TransientRequest]) -> Self {
+        let mut order = (0..requests.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| {
+            (
+                requests[*i].first_pass,
+                Reverse(texture_bytes(&requests[*i].desc)),
+            )
+        });
+
+        let mut assignments = vec![0; requests.len()];
+        // Size and last pass of every block.
+        let mut blocks: Vec<(usize, usize)> = vec![];
+
+        for i in order {
+            let request = &requests[i];
+            let bytes = texture_bytes(&request.desc);
+
+            // The smallest free block the texture fits in, otherwise the
+            // largest free block grows to hold it.
+            let free = blocks
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, last_pass))| *last_pass < request.first_pass);
+            let block = free
+                .clone()
+                .filter(|(_, (size, _))| *size >= bytes)
+                .min_by_key(|(_, (size, _))| *size)
+                .or_else(|| free.max_by_key(|(_, (size, _))| *size))
+                .map(|(block, _)| block);
+
+            assignments[i] = match block {
+                Some(block) => {
+                    blocks[block] = (blocks[block].0.max(bytes), request.last_pass);
+                    block
+                }
+                None => {
+                    blocks.push((bytes, request.last_pass));
+                    blocks.len() - 1
+                }
+            };
+        }
+
+        let last_pass = requests.iter().map(|r| r.last_pass).max();
+        let peak_bytes = last_pass
+            .map(|last_pass| {
+                (0..=last_pass)
+                    .map(|pass| {
+                        requests
+                            .iter()
+                            .filter(|r| r.first_pass <= pass && pass <= r.last_pass)
+                            .map(|r| texture_bytes(&r.desc))
+                            .sum::<usize>()
+                    })
+                    .max()
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+
+        let blocks = blocks.into_iter().map(|(size, _)| size).collect::<Vec<_>>();
+
+        Self {
+            assignments,
+            requested_bytes: requests.iter().map(|r| texture_bytes(&r.desc)).sum(),
+            peak_bytes,
+            allocated_bytes: blocks.iter().sum(),
+            blocks,
+        }
+    }
+}
+
+pub struct TransientTargets<D: RenderDevice> {
+    ctx: Arc<Context<D>>,
+    targets: SmallVec<[Handle<Texture>; 4]>,
+    views: SmallVec<[Handle<Texture>; 4]>,
+}
+
+impl<D: RenderDevice> TextureSlots<D> for TransientTargets<D> {
+    fn depth(&self) -> usize {
+        1
+    }
+
+    fn producer(&self) -> &Arc<Context<D>> {
+        &self.ctx
+    }
+
+    fn consumer(&self) -> &Arc<Context<D>> {
+        &self.ctx
+    }
+
+    fn targets(&self, slot: usize) -> &[Handle<Texture>] {
+        debug_assert_eq!(slot, 0, "transient targets have a single slot");
+        &self.targets
+    }
+
+    fn views(&self, slot: usize) -> &[Handle<Texture>] {
+        debug_assert_eq!(slot, 0, "transient targets have a single slot");
+        &self.views
+    }
+}
+
+struct TransientEntry {
+    desc: TextureDesc,
+    texture: Handle<Texture>,
+    view: Option<Handle<Texture>>,
+}
+
+struct Retired<D: RenderDevice> {
+    frame: usize,
+    textures: Vec<D::Texture>,
+    memory: Vec<D::Memory>,
+}
+
+// Render and depth targets that only live within a frame. Handles stay the
+// same for the lifetime of the pool, the memory behind them is planned from
+// the pass order of each frame's graph and shared by textures whose passes
+// do not overlap.
+pub struct TransientTexturePool<D: RenderDevice> {
+    rs: Arc<RenderSystem>,
+    ctx: Arc<Context<D>>,
+    frames_in_flight: usize,
+
+    entries: Vec<TransientEntry>,
+    requests: Vec<TransientRequest>,
+    plan: TransientPlan,
+    memory: Vec<D::Memory>,
+
+    frame: usize,
+    retired: VecDeque<Retired<D>>,
+}
+
+impl<D: RenderDevice> TransientTexturePool<D> {
+    pub fn new(rs: Arc<RenderSystem>, ctx: Arc<Context<D>>, frames_in_flight: usize) -> Self {
+        Self {
+            rs,
+            ctx,
+            frames_in_flight,
+            entries: vec![],
+            requests: vec![],
+            plan: TransientPlan::default(),
+            memory: vec![],
+            frame: 0,
+            retired: VecDeque::new(),
+        }
+    }
+
+    // Until the first graph is allocated the texture has memory of its own.
+    pub fn create_texture(&mut self, desc: TextureDesc) -> Result<Handle<Texture>, RenderError> {
+        let entry = self.create(desc, false)?;
+
+        Ok(entry.texture)
+    }
+
+    // Targets with shader resource views, for passes that consume them the
+    // same way as a cross-device channel.
+    pub fn create_targets(
+        &mut self,
+        descs: &[TextureDesc],
+    ) -> Result<TransientTargets<D>, RenderError> {
+        let mut targets = SmallVec::new();
+        let mut views = SmallVec::new();
+
+        for desc in descs {
+            let entry = self.create(desc.clone(), true)?;
+            targets.push(entry.texture);
+            views.extend(entry.view);
+        }
+
+        Ok(TransientTargets {
+            ctx: Arc::clone(&self.ctx),
+            targets,
+            views,
+        })
+    }
+
+    // Derives the lifetime of every texture from the graph and places them in
+    // memory, activating each one at its first pass. Returns true when the
+    // textures were recreated and shader arguments reading them must be
+    // bound again.
+    pub fn allocate(&mut self, graph: &mut RenderGraph<'_, D>) -> Result<bool, RenderError> {
+        self.frame += 1;
+        self.collect();
+
+        for entry in &self.entries {
+            if let Some(view) = entry.view {
+                graph.alias_texture(view, entry.texture);
+            }
+        }
+
+        let lifetimes = graph.lifetimes();
+        let end = lifetimes.values().map(|(_, last)| *last).max().unwrap_or(0);
+
+        let requests = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let (first_pass, last_pass) =
+                    lifetimes.get(&entry.texture).copied().unwrap_or((0, end));
+
+                TransientRequest {
+                    desc: entry.desc.clone(),
+                    first_pass,
+                    last_pass,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for entry in &self.entries {
+            graph.activate_texture(entry.texture);
+        }
+
+        if requests == self.requests {
+            return Ok(false);
+        }
+
+        let plan = TransientPlan::new(&requests);
+
+        let memory = (0..plan.blocks.len())
+            .map(|block| {
+                let descs = requests
+                    .iter()
+                    .zip(&plan.assignments)
+                    .filter(|(_, assigned)| **assigned == block)
+                    .map(|(request, _)| request.desc.clone())
+                    .collect::<Vec<_>>();
+
+                self.ctx.gpu.create_memory(&descs)
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?;
+
+        let mut retired = vec![];
+        for (index, block) in plan.assignments.iter().enumerate() {
+            retired.extend(self.place(index, Some(&memory[*block]))?);
+        }
+
+        let stale = std::mem::replace(&mut self.memory, memory);
+        self.retire(retired, stale);
+
+        self.requests = requests;
+        self.plan = plan;
+
+        Ok(true)
+    }
+
+    // Textures get memory of their own again until the next allocation, the
+    // old ones are released once the frames using them are done.
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
+        let mut retired = vec![];
+        for index in 0..self.entries.len() {
+            let desc = &mut self.entries[index].desc;
+            desc.extent[0] = extent[0];
+            desc.extent[1] = extent[1];
+
+            retired.extend(self.place(index, None)?);
+        }
+
+        let stale = std::mem::take(&mut self.memory);
+        self.retire(retired, stale);
+
+        self.requests.clear();
+        self.plan = TransientPlan::default();
+
+        Ok(())
+    }
+
+    pub fn plan(&self) -> &TransientPlan {
+        &self.plan
+    }
+
+    pub fn peak_bytes(&self) -> usize {
+        self.plan.peak_bytes
+    }
+
+    fn create(&mut self, desc: TextureDesc, view: bool) -> Result<&TransientEntry, RenderError> {
+        assert!(
+            desc.usage
+                .intersects(TextureUsages::RenderTarget | TextureUsages::DepthTarget),
+            "transient textures must be render or depth targets"
+        );
+
+        self.entries.push(TransientEntry {
+            desc,
+            texture: self.rs.create_texture_handle(),
+            view: view.then(|| self.rs.create_texture_handle()),
+        });
+
+        let index = self.entries.len() - 1;
+        self.place(index, None)?;
+        self.requests.clear();
+
+        Ok(&self.entries[index])
+    }
+
+    // Binds a new texture for the entry and hands back the ones it replaced.
+    fn place(
+        &self,
+        index: usize,
+        memory: Option<&D::Memory>,
+    ) -> Result<Vec<D::Texture>, RenderError> {
+        let entry = &self.entries[index];
+
+        let texture = match memory {
+            Some(memory) => self
+                .ctx
+                .gpu
+                .create_placed_texture(entry.desc.clone(), memory)?,
+            None => self.ctx.gpu.create_texture(entry.desc.clone())?,
+        };
+
+        let mut textures = self.ctx.mapper.textures.write();
+        let mut replaced = textures
+            .set(entry.texture, texture)
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        if let Some(view) = entry.view {
+            let desc = TextureViewDesc::default()
+                .with_view_type(TextureViewType::ShaderResource)
+                .with_format(entry.desc.format);
+
+            let texture = bound(&textures, entry.texture, "texture")?;
+            let texture = self.ctx.gpu.create_texture_view(texture, desc)?;
+            replaced.extend(textures.set(view, texture));
+        }
+
+        Ok(replaced)
+    }
+
+    fn retire(&mut self, textures: Vec<D::Texture>, memory: Vec<D::Memory>) {
+        if textures.is_empty() && memory.is_empty() {
+            return;
+        }
+
+        self.retired.push_back(Retired {
+            frame: self.frame,
+            textures,
+            memory,
+        });
+    }
+
+    // Everything retired frames_in_flight frames ago is no longer in use.
+    fn collect(&mut self) {
+        while let Some(retired) = self.retired.front()
+            && retired.frame + self.frames_in_flight <= self.frame
+        {
+            if let Some(retired) = self.retired.pop_front() {
+                self.destroy(retired);
+            }
+        }
+    }
+
+    fn destroy(&self, retired: Retired<D>) {
+        for texture in retired.textures {
+            self.ctx.gpu.destroy_texture(texture);
+        }
+
+        for memory in retired.memory {
+            self.ctx.gpu.destroy_memory(memory);
+        }
+    }
+}
+
+// The owner waits for the device before dropping the pool.
+impl<D: RenderDevice> Drop for TransientTexturePool<D> {
+    fn drop(&mut self) {
+        while let Some(retired) = self.retired.pop_front() {
+            self.destroy(retired);
+        }
+
+        for entry in std::mem::take(&mut self.entries) {
+            for handle in entry.view.into_iter().chain([entry.texture]) {
+                self.ctx.unbind_texture(handle);
+                self.rs.free_texture_handle(handle);
+            }
+        }
+
+        for memory in std::mem::take(&mut self.memory) {
+            self.ctx.gpu.destroy_memory(memory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ra::command::{Barrier, RenderCommandContext},
+        render_graph::PassDesc,
+        rhi::{
+            command::CommandType,
+            null::device::NullDevice,
+            recording::{
+                device::RecordingDevice,
+                null_device,
+                trace::{Recorder, TraceCommand},
+            },
+            types::{
+                Format,
+                ResourceState::{self, *},
+            },
+        },
+    };
+
+    use super::*;
+
+    type Device = RecordingDevice<NullDevice>;
+
+    const EXTENT: [u32; 2] = [8, 8];
+
+    fn target(format: Format) -> TextureDesc {
+        TextureDesc::new_2d(
+            EXTENT,
+            format,
+            TextureUsages::RenderTarget | TextureUsages::Resource,
+        )
+    }
+
+    fn depth() -> TextureDesc {
+        TextureDesc::new_2d(
+            EXTENT,
+            Format::D32,
+            TextureUsages::DepthTarget | TextureUsages::Resource,
+        )
+    }
+
+    fn request(desc: TextureDesc, first_pass: usize, last_pass: usize) -> TransientRequest {
+        TransientRequest {
+            desc,
+            first_pass,
+            last_pass,
+        }
+    }
+
+    struct Frame {
+        depth: Handle<Texture>,
+        gbuffer: TransientTargets<Device>,
+        accum: TransientTargets<Device>,
+        output: Handle<Texture>,
+    }
+
+    impl Frame {
+        fn new(rs: &RenderSystem, pool: &mut TransientTexturePool<Device>) -> Self {
+            let output = rs.create_texture_handle();
+            pool.ctx
+                .bind_texture(output, target(Format::Rgba8Unorm), None)
+                .expect("failed to bind output");
+
+            Self {
+                depth: pool
+                    .create_texture(depth())
+                    .expect("failed to create depth"),
+                gbuffer: pool
+                    .create_targets(&[target(Format::Rgba32)])
+                    .expect("failed to create gbuffer"),
+                accum: pool
+                    .create_targets(&[target(Format::Rgba32)])
+                    .expect("failed to create accum"),
+                output,
+            }
+        }
+
+        // Depth -> GBuffer -> Lighting -> Present, without lighting the
+        // gbuffer and depth passes are culled.
+        fn graph(&self, lighting: bool) -> RenderGraph<'static, Device> {
+            let mut graph = RenderGraph::new();
+
+            let mut add = |desc: PassDesc| graph.add_pass(desc, |_| Ok(()));
+            add(PassDesc::new("Depth").with_write_texture(self.depth, DepthWrite));
+            add(PassDesc::new("GBuffer")
+                .with_read_texture(self.depth, DepthRead)
+                .with_write_texture(self.gbuffer.targets[0], ResourceState::RenderTarget));
+            if lighting {
+                add(PassDesc::new("Lighting")
+                    .with_read_texture(self.gbuffer.views[0], Shader)
+                    .with_write_texture(self.accum.targets[0], ResourceState::RenderTarget));
+            }
+            add(PassDesc::new("Present")
+                .with_read_texture(self.accum.views[0], Shader)
+                .with_write_texture(self.output, ResourceState::RenderTarget));
+
+            graph.export_texture(self.output, Some(Present));
+            graph
+        }
+    }
+
+    #[test]
+    fn plan_aliases_disjoint_lifetimes() {
+        let bytes = texture_bytes(&target(Format::Rgba32));
+        let plan = TransientPlan::new(&[
+            request(depth(), 0, 1),
+            request(target(Format::Rgba32), 1, 2),
+            request(target(Format::Rgba32), 2, 3),
+        ]);
+
+        // The accumulation starts after the depth is done and grows its block.
+        assert_eq!(plan.assignments, [0, 1, 0]);
+        assert_eq!(plan.blocks, [bytes, bytes]);
+        assert_eq!(plan.allocated_bytes, 2 * bytes);
+        assert_eq!(plan.requested_bytes, 2 * bytes + texture_bytes(&depth()));
+        assert_eq!(plan.peak_bytes, 2 * bytes);
+    }
+
+    #[test]
+    fn plan_keeps_overlapping_lifetimes_apart() {
+        let plan = TransientPlan::new(&[
+            request(target(Format::Rgba32), 0, 1),
+            request(target(Format::Rgba32), 1, 2),
+            request(depth(), 0, 2),
+        ]);
+
+        assert_eq!(plan.blocks.len(), 3);
+        assert_eq!(plan.allocated_bytes, plan.requested_bytes);
+        assert_eq!(plan.peak_bytes, plan.requested_bytes);
+    }
+
+    #[test]
+    fn plan_prefers_smallest_fitting_block() {
+        let plan = TransientPlan::new(&[
+            request(target(Format::Rgba32), 0, 0),
+            request(depth(), 0, 0),
+            request(target(Format::Rgba8Unorm), 1, 1),
+        ]);
+
+        assert_eq!(plan.assignments[2], plan.assignments[1]);
+        assert_eq!(
+            plan.blocks,
+            [
+                texture_bytes(&target(Format::Rgba32)),
+                texture_bytes(&depth())
+            ]
+        );
+    }
+
+    #[test]
+    fn allocate_follows_graph() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = Arc::new(RenderSystem::new(&[]));
+        let ctx = null_device(Arc::clone(&recorder) as _);
+        let mut pool = TransientTexturePool::new(Arc::clone(&rs), Arc::clone(&ctx), 2);
+        let frame = Frame::new(&rs, &mut pool);
+
+        let mut graph = frame.graph(true);
+        assert!(pool.allocate(&mut graph).expect("failed to allocate"));
+        assert_eq!(pool.plan().assignments, [0, 1, 0]);
+        assert!(pool.plan().allocated_bytes < pool.plan().requested_bytes);
+
+        let compiled = graph.compile();
+        let aliased = |pass: usize| {
+            compiled.passes[pass]
+                .barriers
+                .iter()
+                .filter_map(|barrier| match barrier {
+                    Barrier::Alias(texture) => Some(*texture),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(aliased(0), [frame.depth]);
+        assert_eq!(aliased(1), [frame.gbuffer.targets[0]]);
+        assert_eq!(aliased(2), [frame.accum.targets[0]]);
+        assert_eq!(aliased(3), []);
+
+        graph.execute(&ctx).expect("failed to execute");
+        ctx.submit(CommandType::Graphics).expect("failed to submit");
+
+        // Same pass order, same memory.
+        let mut graph = frame.graph(true);
+        assert!(!pool.allocate(&mut graph).expect("failed to allocate"));
+
+        // The culled passes leave every texture alive for the whole graph.
+        let mut graph = frame.graph(false);
+        assert!(pool.allocate(&mut graph).expect("failed to allocate"));
+        assert_eq!(pool.plan().blocks.len(), 3);
+        assert_eq!(graph.compile().passes.len(), 1);
+    }
+
+    #[test]
+    fn replaced_textures_outlive_frames_in_flight() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = Arc::new(RenderSystem::new(&[]));
+        let ctx = null_device(Arc::clone(&recorder) as _);
+        let mut pool = TransientTexturePool::new(Arc::clone(&rs), Arc::clone(&ctx), 2);
+        let frame = Frame::new(&rs, &mut pool);
+        recorder.end_frame();
+
+        let destroyed = |trace: &[TraceCommand]| {
+            trace
+                .iter()
+                .filter(|command| matches!(command, TraceCommand::DestroyTexture { .. }))
+                .count()
+        };
+
+        let mut traces = vec![];
+        for lighting in [true, true, true, false] {
+            let mut graph = frame.graph(lighting);
+            pool.allocate(&mut graph).expect("failed to allocate");
+            graph.execute(&ctx).expect("failed to execute");
+            ctx.submit(CommandType::Graphics).expect("failed to submit");
+
+            let trace = recorder
+                .end_frame()
+                .entries
+                .into_iter()
+                .map(|entry| entry.command)
+                .collect::<Vec<_>>();
+            assert!(
+                !trace
+                    .iter()
+                    .any(|command| matches!(command, TraceCommand::DeviceWaitIdle))
+            );
+
+            traces.push(destroyed(&trace));
+        }
+
+        // The dedicated textures and views are released two frames after the
+        // first allocation replaced them, the placed ones still live.
+        assert_eq!(traces, [0, 0, 5, 0]);
+    }
+}
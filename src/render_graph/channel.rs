@@ -0,0 +1,476 @@
+use std::{borrow::Cow, sync::Arc};
+
+use smallvec::SmallVec;
+
+use crate::{
+    collections::{
+        frame_pipeline::{FramePipeline, PipelineMetrics, PipelineStage},
+        handle::Handle,
+    },
+    ra::{
+        command::{Barrier, RenderCommandContext, RenderCommandEncoder, TransferEncoder},
+        context::{Context, RenderDevice},
+        resources::{RenderResourceContext, Texture},
+        system::RenderSystem,
+        timeline::{ChannelTransfer, TransferDirection},
+    },
+    rhi::{
+        command::{CommandType, Subresource},
+        error::RenderError,
+        resources::{TextureDesc, TextureUsages, TextureViewDesc, TextureViewType},
+        types::{Region, ResourceState, Timings},
+    },
+};
+
+use super::{RenderGraph, TextureSlots};
+
+type Slot = SmallVec<[Handle<Texture>; 4]>;
+type Regions = Option<SmallVec<[Region; 8]>>;
+
+pub struct CrossDeviceChannel<D: RenderDevice> {
+    name: Cow<'static, str>,
+
+    producer: Arc<Context<D>>,
+    consumer: Arc<Context<D>>,
+
+    descs: Vec<TextureDesc>,
+    targets: SmallVec<[Slot; 4]>,
+    views: SmallVec<[Slot; 4]>,
+    regions: SmallVec<[Regions; 4]>,
+    // Number of the push each slot holds, recorded on the timeline to pair
+    // pulls with the push they copy.
+    pushed: SmallVec<[u64; 4]>,
+    pushes: u64,
+
+    pipeline: FramePipeline<usize, 4>,
+}
+
+const PRODUCE: usize = 0;
+const COPY: usize = 1;
+
+impl<D: RenderDevice> CrossDeviceChannel<D> {
+    pub fn new(
+        rs: &RenderSystem,
+        name: impl Into<Cow<'static, str>>,
+        producer: Arc<Context<D>>,
+        consumer: Arc<Context<D>>,
+        descs: &[TextureDesc],
+        depth: usize,
+    ) -> Result<Self, RenderError> {
+        assert!(
+            depth > 0,
+            "cross-device channel must have at least one slot"
+        );
+
+        let local = Arc::ptr_eq(&producer, &consumer);
+        let depth = if local { 1 } else { depth };
+
+        let targets = (0..depth)
+            .map(|_| descs.iter().map(|_| rs.create_texture_handle()).collect())
+            .collect::<SmallVec<[Slot; 4]>>();
+
+        let views = if local {
+            (0..depth)
+                .map(|_| descs.iter().map(|_| rs.create_texture_handle()).collect())
+                .collect()
+        } else {
+            targets.clone()
+        };
+
+        let channel = Self {
+            name: name.into(),
+            producer,
+            consumer,
+            descs: descs.to_vec(),
+            targets,
+            views,
+            regions: (0..depth).map(|_| None).collect(),
+            pushed: (0..depth).map(|_| 0).collect(),
+            pushes: 0,
+            pipeline: Self::pipeline(depth, local),
+        };
+
+        channel.bind()?;
+
+        Ok(channel)
+    }
+
+    pub fn is_local(&self) -> bool {
+        Arc::ptr_eq(&self.producer, &self.consumer)
+    }
+
+    pub fn metrics(&self) -> &PipelineMetrics {
+        self.pipeline.metrics()
+    }
+
+    pub fn acquire_write(&mut self) -> Option<usize> {
+        if !self.is_local() && !self.producer.is_ready(CommandType::Graphics) {
+            return None;
+        }
+
+        self.pipeline.acquire(PRODUCE)
+    }
+
+    pub fn produce(&mut self, slot: usize, graph: RenderGraph<'_, D>) -> Result<(), RenderError> {
+        self.submit(slot, graph, None)
+    }
+
+    // Only the given regions of the targets are transferred to the consumer,
+    // the rest of the slot must already hold the data the consumer expects.
+    pub fn produce_regions(
+        &mut self,
+        slot: usize,
+        graph: RenderGraph<'_, D>,
+        regions: &[Region],
+    ) -> Result<(), RenderError> {
+        self.submit(slot, graph, Some(regions.into()))
+    }
+
+    fn submit(
+        &mut self,
+        slot: usize,
+        mut graph: RenderGraph<'_, D>,
+        regions: Regions,
+    ) -> Result<(), RenderError> {
+        self.regions[slot] = regions;
+
+        for target in &self.targets[slot] {
+            graph.export_texture(*target, None);
+        }
+
+        graph.execute(&self.producer)?;
+
+        if self.is_local() {
+            self.pipeline.complete(slot);
+
+            return Ok(());
+        }
+
+        let mut cmd = self.producer.create_encoder(CommandType::Graphics)?;
+
+        cmd.set_barriers(
+            &self.targets[slot]
+                .iter()
+                .flat_map(|texture| {
+                    [
+                        Barrier::Texture(
+                            *texture,
+                            ResourceState::CopySrc,
+                            Subresource::Local(None),
+                        ),
+                        Barrier::Texture(*texture, ResourceState::CopyDst, Subresource::Shared),
+                    ]
+                })
+                .collect::<SmallVec<[_; 8]>>(),
+        )?;
+        self.pushes += 1;
+        self.pushed[slot] = self.pushes;
+        {
+            let encoder = cmd.channel_transfer(
+                format!("Push {}", self.name).into(),
+                self.transfer(TransferDirection::Push, slot),
+            );
+            for texture in &self.targets[slot] {
+                for region in self.slot_regions(slot) {
+                    encoder.push_texture(*texture, region)?;
+                }
+            }
+        }
+
+        self.producer.commit(cmd);
+        self.pipeline
+            .submit(slot, self.producer.submit(CommandType::Graphics)?);
+
+        Ok(())
+    }
+
+    pub fn pull(&mut self) -> Result<Option<Timings>, RenderError> {
+        if self.is_local() {
+            return Ok(None);
+        }
+
+        self.poll();
+
+        if !self.consumer.is_ready(CommandType::Transfer) {
+            return Ok(None);
+        }
+
+        let Some(slot) = self.pipeline.acquire(COPY) else {
+            return Ok(None);
+        };
+
+        let mut cmd = self.consumer.create_encoder(CommandType::Transfer)?;
+        let timings = cmd.begin(&self.consumer);
+
+        cmd.set_barriers(
+            &self.views[slot]
+                .iter()
+                .flat_map(|texture| {
+                    [
+                        Barrier::Texture(
+                            *texture,
+                            ResourceState::CopyDst,
+                            Subresource::Local(None),
+                        ),
+                        Barrier::Texture(*texture, ResourceState::CopySrc, Subresource::Shared),
+                    ]
+                })
+                .collect::<SmallVec<[_; 8]>>(),
+        )?;
+        {
+            let encoder = cmd.channel_transfer(
+                format!("Pull {}", self.name).into(),
+                self.transfer(TransferDirection::Pull, slot),
+            );
+            for texture in &self.views[slot] {
+                for region in self.slot_regions(slot) {
+                    encoder.pull_texture(*texture, region)?;
+                }
+            }
+        }
+
+        self.consumer.commit(cmd);
+        self.pipeline
+            .submit(slot, self.consumer.submit(CommandType::Transfer)?);
+
+        Ok(timings)
+    }
+
+    pub fn acquire_read(&mut self) -> usize {
+        self.poll();
+        self.pipeline.next_frame();
+
+        self.pipeline.latest_data().copied().unwrap_or_default()
+    }
+
+    pub fn import(&self, graph: &mut RenderGraph<'_, D>, slot: usize) {
+        for (view, target) in self.views[slot].iter().zip(self.targets[slot].iter()) {
+            if self.is_local() {
+                graph.alias_texture(*view, *target);
+            } else {
+                graph.export_texture(*view, Some(ResourceState::Common));
+            }
+        }
+    }
+
+    pub fn resize(&mut self, extent: [u32; 2]) -> Result<(), RenderError> {
+        self.producer.wait_idle()?;
+        self.consumer.wait_idle()?;
+
+        for desc in &mut self.descs {
+            desc.extent[0] = extent[0];
+            desc.extent[1] = extent[1];
+        }
+
+        self.pipeline = Self::pipeline(self.depth(), self.is_local());
+        self.regions.iter_mut().for_each(|regions| *regions = None);
+
+        self.bind()
+    }
+
+    fn transfer(&self, direction: TransferDirection, slot: usize) -> ChannelTransfer {
+        ChannelTransfer {
+            channel: self.name.clone(),
+            direction,
+            slot,
+            push: self.pushed[slot],
+        }
+    }
+
+    fn slot_regions(&self, slot: usize) -> SmallVec<[Option<Region>; 8]> {
+        match &self.regions[slot] {
+            Some(regions) => regions.iter().copied().map(Some).collect(),
+            None => smallvec::smallvec![None],
+        }
+    }
+
+    fn poll(&mut self) {
+        if !self.is_local() {
+            let devices = [Arc::clone(&self.producer), Arc::clone(&self.consumer)];
+            self.pipeline.poll(&devices[..]);
+        }
+    }
+
+    fn pipeline(depth: usize, local: bool) -> FramePipeline<usize, 4> {
+        let produce = PipelineStage::new("Produce", 0, CommandType::Graphics);
+        let copy = PipelineStage::new("Copy", 1, CommandType::Transfer);
+
+        if local {
+            FramePipeline::new((0..depth).collect(), [produce])
+        } else {
+            FramePipeline::new((0..depth).collect(), [produce, copy])
+        }
+    }
+
+    fn bind(&self) -> Result<(), RenderError> {
+        for (slot, (targets, views)) in self.targets.iter().zip(self.views.iter()).enumerate() {
+            for (desc, (target, view)) in self.descs.iter().zip(targets.iter().zip(views.iter())) {
+                let mut desc = desc.clone();
+                desc.name = desc.name.map(|name| format!("{} {}", name, slot).into());
+
+                let view_desc = TextureViewDesc::default()
+                    .with_view_type(TextureViewType::ShaderResource)
+                    .with_format(desc.format);
+
+                if self.is_local() {
+                    self.producer.bind_texture(*target, desc, None)?;
+                    self.producer.bind_texture_view(*view, *target, view_desc)?;
+                } else {
+                    desc.usage |= TextureUsages::Shared;
+
+                    self.producer.bind_texture(*target, desc, None)?;
+                    self.consumer
+                        .open_texture_handle(*view, &self.producer, Some(view_desc))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: RenderDevice> TextureSlots<D> for CrossDeviceChannel<D> {
+    fn depth(&self) -> usize {
+        self.targets.len()
+    }
+
+    fn producer(&self) -> &Arc<Context<D>> {
+        &self.producer
+    }
+
+    fn consumer(&self) -> &Arc<Context<D>> {
+        &self.consumer
+    }
+
+    fn targets(&self, slot: usize) -> &[Handle<Texture>] {
+        &self.targets[slot]
+    }
+
+    fn views(&self, slot: usize) -> &[Handle<Texture>] {
+        &self.views[slot]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ra::backend::Backend,
+        rhi::{
+            null::{backend::NullBackend, device::NullDevice},
+            recording::{
+                backend::RecordingBackend,
+                device::RecordingDevice,
+                trace::{EncoderCommand, Recorder},
+            },
+            types::Format,
+        },
+    };
+
+    use super::*;
+
+    const REGIONS: [Region; 2] = [
+        Region {
+            x: 0,
+            y: 0,
+            w: 4,
+            h: 4,
+        },
+        Region {
+            x: 4,
+            y: 4,
+            w: 4,
+            h: 4,
+        },
+    ];
+
+    fn channel(
+        recorder: &Arc<Recorder>,
+        rs: &RenderSystem,
+    ) -> CrossDeviceChannel<RecordingDevice<NullDevice>> {
+        let backend = Backend::new(RecordingBackend::new(
+            NullBackend::new(),
+            Arc::clone(recorder) as _,
+        ));
+        let group = backend.create_group(&[0, 1]);
+
+        CrossDeviceChannel::new(
+            rs,
+            "Shadows",
+            Arc::clone(group.get(1).expect("no secondary device")),
+            Arc::clone(group.primary()),
+            &[TextureDesc::new_2d(
+                [8, 8],
+                Format::R32,
+                TextureUsages::RenderTarget | TextureUsages::Resource,
+            )],
+            2,
+        )
+        .expect("failed to create channel")
+    }
+
+    // Regions of every push and pull recorded since the last call.
+    fn transfers(recorder: &Recorder) -> (Vec<Option<Region>>, Vec<Option<Region>>) {
+        let trace = recorder.end_frame();
+        let mut pushes = vec![];
+        let mut pulls = vec![];
+
+        for command in trace.commands() {
+            match command {
+                EncoderCommand::PushTexture { region, .. } => pushes.push(*region),
+                EncoderCommand::PullTexture { region, .. } => pulls.push(*region),
+                _ => {}
+            }
+        }
+
+        (pushes, pulls)
+    }
+
+    #[test]
+    fn regions_limit_transfers() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let mut channel = channel(&recorder, &rs);
+        recorder.end_frame();
+
+        let slot = channel.acquire_write().expect("no free slot");
+        channel
+            .produce_regions(slot, RenderGraph::new(), &REGIONS)
+            .expect("failed to produce");
+        channel.pull().expect("failed to pull");
+
+        let regions = REGIONS.map(Some).to_vec();
+        assert_eq!(transfers(&recorder), (regions.clone(), regions));
+    }
+
+    #[test]
+    fn whole_slot_is_transferred_without_regions() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let mut channel = channel(&recorder, &rs);
+        recorder.end_frame();
+
+        let slot = channel.acquire_write().expect("no free slot");
+        channel
+            .produce(slot, RenderGraph::new())
+            .expect("failed to produce");
+        channel.pull().expect("failed to pull");
+
+        assert_eq!(transfers(&recorder), (vec![None], vec![None]));
+    }
+
+    #[test]
+    fn unchanged_slot_is_not_transferred() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let mut channel = channel(&recorder, &rs);
+        recorder.end_frame();
+
+        let slot = channel.acquire_write().expect("no free slot");
+        channel
+            .produce_regions(slot, RenderGraph::new(), &[])
+            .expect("failed to produce");
+        channel.pull().expect("failed to pull");
+
+        assert_eq!(transfers(&recorder), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,576 @@
+pub mod channel;
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    collections::handle::Handle,
+    ra::{
+        command::{Barrier, CommandEncoder, RenderCommandContext, RenderCommandEncoder},
+        context::{Context, RenderDevice},
+        resources::{Buffer, Texture},
+    },
+    rhi::{
+        command::{CommandType, Subresource},
+        error::RenderError,
+        types::ResourceState,
+    },
+};
+
+type PassFn<'a, D> = Box<dyn FnOnce(&mut CommandEncoder<D>) -> Result<(), RenderError> + 'a>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GraphResource {
+    Buffer(Handle<Buffer>),
+    Texture(Handle<Texture>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Debug)]
+pub struct PassDesc {
+    pub name: Cow<'static, str>,
+    pub accesses: Vec<(GraphResource, ResourceState, Access)>,
+    pub side_effects: bool,
+}
+
+impl PassDesc {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: name.into(),
+            accesses: vec![],
+            side_effects: false,
+        }
+    }
+
+    pub fn with_read_texture(mut self, texture: Handle<Texture>, state: ResourceState) -> Self {
+        self.accesses
+            .push((GraphResource::Texture(texture), state, Access::Read));
+        self
+    }
+
+    pub fn with_write_texture(mut self, texture: Handle<Texture>, state: ResourceState) -> Self {
+        self.accesses
+            .push((GraphResource::Texture(texture), state, Access::Write));
+        self
+    }
+
+    pub fn with_read_buffer(mut self, buffer: Handle<Buffer>, state: ResourceState) -> Self {
+        self.accesses
+            .push((GraphResource::Buffer(buffer), state, Access::Read));
+        self
+    }
+
+    pub fn with_write_buffer(mut self, buffer: Handle<Buffer>, state: ResourceState) -> Self {
+        self.accesses
+            .push((GraphResource::Buffer(buffer), state, Access::Write));
+        self
+    }
+
+    pub fn with_side_effects(mut self) -> Self {
+        self.side_effects = true;
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledPass {
+    pub index: usize,
+    pub name: Cow<'static, str>,
+    pub barriers: Vec<Barrier>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompiledGraph {
+    pub passes: Vec<CompiledPass>,
+    pub epilogue: Vec<Barrier>,
+}
+
+// Per-slot textures passes render into and read from, pipelined through a
+// cross-device channel or owned by a transient pool.
+pub trait TextureSlots<D: RenderDevice> {
+    fn depth(&self) -> usize;
+    fn producer(&self) -> &Arc<Context<D>>;
+    fn consumer(&self) -> &Arc<Context<D>>;
+    fn targets(&self, slot: usize) -> &[Handle<Texture>];
+    fn views(&self, slot: usize) -> &[Handle<Texture>];
+}
+
+pub struct RenderGraph<'a, D: RenderDevice> {
+    passes: Vec<(PassDesc, PassFn<'a, D>)>,
+    aliases: HashMap<Handle<Texture>, Handle<Texture>>,
+    activations: HashSet<Handle<Texture>>,
+    exports: Vec<(GraphResource, Option<ResourceState>)>,
+}
+
+impl<'a, D: RenderDevice> RenderGraph<'a, D> {
+    pub fn new() -> Self {
+        Self {
+            passes: vec![],
+            aliases: HashMap::new(),
+            activations: HashSet::new(),
+            exports: vec![],
+        }
+    }
+
+    pub fn alias_texture(&mut self, view: Handle<Texture>, texture: Handle<Texture>) {
+        self.aliases.insert(view, texture);
+    }
+
+    // The first live pass touching the texture takes over its memory with an
+    // alias barrier, for textures placed in memory shared with others.
+    pub fn activate_texture(&mut self, texture: Handle<Texture>) {
+        self.activations.insert(texture);
+    }
+
+    pub fn export_texture(&mut self, texture: Handle<Texture>, state: Option<ResourceState>) {
+        self.exports.push((GraphResource::Texture(texture), state));
+    }
+
+    pub fn export_buffer(&mut self, buffer: Handle<Buffer>, state: Option<ResourceState>) {
+        self.exports.push((GraphResource::Buffer(buffer), state));
+    }
+
+    pub fn add_pass(
+        &mut self,
+        desc: PassDesc,
+        func: impl FnOnce(&mut CommandEncoder<D>) -> Result<(), RenderError> + 'a,
+    ) {
+        self.passes.push((desc, Box::new(func)));
+    }
+
+    pub fn compile(&self) -> CompiledGraph {
+        let mut writers = HashMap::new();
+        let mut deps = vec![vec![]; self.passes.len()];
+
+        for (i, (desc, _)) in self.passes.iter().enumerate() {
+            // Writes depend on the previous writer too, render targets are
+            // blended over and depth is tested against what is already there.
+            for (resource, _, _) in &desc.accesses {
+                if let Some(writer) = writers.get(&self.root(*resource)) {
+                    deps[i].push(*writer);
+                }
+            }
+
+            for (resource, _, access) in &desc.accesses {
+                if *access == Access::Write {
+                    writers.insert(self.root(*resource), i);
+                }
+            }
+        }
+
+        let mut stack = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, (desc, _))| desc.side_effects)
+            .map(|(i, _)| i)
+            .chain(
+                self.exports
+                    .iter()
+                    .filter_map(|(resource, _)| writers.get(&self.root(*resource)).copied()),
+            )
+            .collect::<Vec<_>>();
+
+        let mut live = HashSet::new();
+        while let Some(pass) = stack.pop() {
+            if live.insert(pass) {
+                stack.extend(deps[pass].iter().copied());
+            }
+        }
+
+        let mut states = HashMap::new();
+        let mut activated = HashSet::new();
+        let passes = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| live.contains(i))
+            .map(|(index, (desc, _))| CompiledPass {
+                index,
+                name: desc.name.clone(),
+                barriers: desc
+                    .accesses
+                    .iter()
+                    .filter_map(|(resource, _, _)| match self.root(*resource) {
+                        GraphResource::Texture(texture)
+                            if self.activations.contains(&texture) && activated.insert(texture) =>
+                        {
+                            Some(Barrier::Alias(texture))
+                        }
+                        _ => None,
+                    })
+                    .chain(desc.accesses.iter().filter_map(|(resource, state, _)| {
+                        self.transition(&mut states, *resource, *state)
+                    }))
+                    .collect(),
+            })
+            .collect();
+
+        let epilogue = self
+            .exports
+            .iter()
+            .filter_map(|(resource, state)| {
+                state.and_then(|state| self.transition(&mut states, *resource, state))
+            })
+            .collect();
+
+        CompiledGraph { passes, epilogue }
+    }
+
+    // First and last position of every texture among the live passes, in
+    // execution order. Exported textures live until the end of the graph.
+    pub fn lifetimes(&self) -> HashMap<Handle<Texture>, (usize, usize)> {
+        let compiled = self.compile();
+        let mut lifetimes = HashMap::new();
+
+        for (position, pass) in compiled.passes.iter().enumerate() {
+            for (resource, _, _) in &self.passes[pass.index].0.accesses {
+                if let GraphResource::Texture(texture) = self.root(*resource) {
+                    lifetimes
+                        .entry(texture)
+                        .and_modify(|(_, last)| *last = position)
+                        .or_insert((position, position));
+                }
+            }
+        }
+
+        let end = compiled.passes.len().saturating_sub(1);
+        for (resource, _) in &self.exports {
+            if let GraphResource::Texture(texture) = self.root(*resource) {
+                lifetimes.entry(texture).or_insert((0, end)).1 = end;
+            }
+        }
+
+        lifetimes
+    }
+
+    pub fn execute(self, ctx: &Context<D>) -> Result<(), RenderError> {
+        let compiled = self.compile();
+        let mut funcs = self
+            .passes
+            .into_iter()
+            .map(|(_, func)| Some(func))
+            .collect::<Vec<_>>();
+
+        let count = compiled.passes.len();
+        for (i, pass) in compiled.passes.into_iter().enumerate() {
+            let mut cmd = ctx.create_encoder(CommandType::Graphics)?;
+
+            if !pass.barriers.is_empty() {
+                cmd.set_barriers(&pass.barriers)?;
+            }
+
+            let func = funcs[pass.index].take().expect("pass executed twice");
+            func(&mut cmd)?;
+
+            if i + 1 == count && !compiled.epilogue.is_empty() {
+                cmd.set_barriers(&compiled.epilogue)?;
+            }
+
+            ctx.enqueue(cmd);
+        }
+
+        Ok(())
+    }
+
+    fn root(&self, resource: GraphResource) -> GraphResource {
+        match resource {
+            GraphResource::Texture(texture) => {
+                GraphResource::Texture(self.aliases.get(&texture).copied().unwrap_or(texture))
+            }
+            GraphResource::Buffer(_) => resource,
+        }
+    }
+
+    fn transition(
+        &self,
+        states: &mut HashMap<GraphResource, ResourceState>,
+        resource: GraphResource,
+        state: ResourceState,
+    ) -> Option<Barrier> {
+        if states.insert(self.root(resource), state) == Some(state) {
+            return None;
+        }
+
+        Some(match resource {
+            GraphResource::Buffer(buffer) => Barrier::Buffer(buffer, state),
+            GraphResource::Texture(texture) => {
+                Barrier::Texture(texture, state, Subresource::Local(None))
+            }
+        })
+    }
+}
+
+impl<D: RenderDevice> Default for RenderGraph<'_, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ra::system::RenderSystem,
+        rhi::{
+            command::Subresource,
+            null::device::NullDevice,
+            types::ResourceState::{self, *},
+        },
+    };
+
+    use super::*;
+
+    fn texture(handle: Handle<Texture>, state: ResourceState) -> Barrier {
+        Barrier::Texture(handle, state, Subresource::Local(None))
+    }
+
+    fn add<'a>(graph: &mut RenderGraph<'a, NullDevice>, desc: PassDesc) {
+        graph.add_pass(desc, |_| Ok(()));
+    }
+
+    fn names(compiled: &CompiledGraph) -> Vec<&str> {
+        compiled.passes.iter().map(|p| p.name.as_ref()).collect()
+    }
+
+    #[test]
+    fn barriers_follow_declared_states() {
+        let rs = RenderSystem::new(&[]);
+        let depth = rs.create_texture_handle();
+        let target = rs.create_texture_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        add(
+            &mut graph,
+            PassDesc::new("Depth").with_write_texture(depth, DepthWrite),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Shade")
+                .with_read_texture(depth, Shader)
+                .with_write_texture(target, RenderTarget),
+        );
+        graph.export_texture(target, Some(Present));
+
+        let compiled = graph.compile();
+
+        assert_eq!(names(&compiled), ["Depth", "Shade"]);
+        assert_eq!(compiled.passes[0].barriers, [texture(depth, DepthWrite)]);
+        assert_eq!(
+            compiled.passes[1].barriers,
+            [texture(depth, Shader), texture(target, RenderTarget)]
+        );
+        assert_eq!(compiled.epilogue, [texture(target, Present)]);
+    }
+
+    #[test]
+    fn unchanged_state_emits_no_barrier() {
+        let rs = RenderSystem::new(&[]);
+        let source = rs.create_texture_handle();
+        let buffer = rs.create_buffer_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        add(
+            &mut graph,
+            PassDesc::new("First")
+                .with_read_texture(source, Shader)
+                .with_write_buffer(buffer, CopyDst)
+                .with_side_effects(),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Second")
+                .with_read_texture(source, Shader)
+                .with_write_buffer(buffer, CopyDst)
+                .with_side_effects(),
+        );
+
+        let compiled = graph.compile();
+
+        assert_eq!(
+            compiled.passes[0].barriers,
+            [texture(source, Shader), Barrier::Buffer(buffer, CopyDst)]
+        );
+        assert!(compiled.passes[1].barriers.is_empty());
+    }
+
+    #[test]
+    fn unused_passes_are_culled() {
+        let rs = RenderSystem::new(&[]);
+        let unused = rs.create_texture_handle();
+        let used = rs.create_texture_handle();
+        let readback = rs.create_texture_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        add(
+            &mut graph,
+            PassDesc::new("Unused").with_write_texture(unused, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Used").with_write_texture(used, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Readback")
+                .with_write_texture(readback, RenderTarget)
+                .with_side_effects(),
+        );
+        graph.export_texture(used, None);
+
+        let compiled = graph.compile();
+
+        assert_eq!(names(&compiled), ["Used", "Readback"]);
+        assert!(compiled.epilogue.is_empty());
+    }
+
+    #[test]
+    fn culling_keeps_earlier_writers() {
+        let rs = RenderSystem::new(&[]);
+        let accum = rs.create_texture_handle();
+        let output = rs.create_texture_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        add(
+            &mut graph,
+            PassDesc::new("Light").with_write_texture(accum, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Blend").with_write_texture(accum, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Resolve")
+                .with_read_texture(accum, Shader)
+                .with_write_texture(output, RenderTarget),
+        );
+        graph.export_texture(output, None);
+
+        let compiled = graph.compile();
+
+        assert_eq!(names(&compiled), ["Light", "Blend", "Resolve"]);
+        assert!(compiled.passes[1].barriers.is_empty());
+    }
+
+    #[test]
+    fn aliases_share_dependencies_and_state() {
+        let rs = RenderSystem::new(&[]);
+        let target = rs.create_texture_handle();
+        let view = rs.create_texture_handle();
+        let output = rs.create_texture_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        graph.alias_texture(view, target);
+        add(
+            &mut graph,
+            PassDesc::new("Write").with_write_texture(target, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Read")
+                .with_read_texture(view, RenderTarget)
+                .with_write_texture(output, RenderTarget),
+        );
+        graph.export_texture(output, None);
+
+        let compiled = graph.compile();
+
+        assert_eq!(names(&compiled), ["Write", "Read"]);
+        assert_eq!(compiled.passes[1].barriers, [texture(output, RenderTarget)]);
+    }
+
+    #[test]
+    fn lifetimes_span_live_passes() {
+        let rs = RenderSystem::new(&[]);
+        let depth = rs.create_texture_handle();
+        let target = rs.create_texture_handle();
+        let view = rs.create_texture_handle();
+        let unused = rs.create_texture_handle();
+        let output = rs.create_texture_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        graph.alias_texture(view, target);
+        add(
+            &mut graph,
+            PassDesc::new("Unused").with_write_texture(unused, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Depth").with_write_texture(depth, DepthWrite),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Shade")
+                .with_read_texture(depth, Shader)
+                .with_write_texture(target, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Resolve")
+                .with_read_texture(view, Shader)
+                .with_write_texture(output, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Overlay").with_write_texture(output, RenderTarget),
+        );
+        graph.export_texture(output, None);
+        graph.export_texture(depth, None);
+
+        let lifetimes = graph.lifetimes();
+
+        assert_eq!(lifetimes.get(&unused), None);
+        assert_eq!(lifetimes.get(&view), None);
+        assert_eq!(lifetimes[&target], (1, 2));
+        assert_eq!(lifetimes[&output], (2, 3));
+        // Exported textures live until the last pass.
+        assert_eq!(lifetimes[&depth], (0, 3));
+    }
+
+    #[test]
+    fn activation_precedes_first_use() {
+        let rs = RenderSystem::new(&[]);
+        let target = rs.create_texture_handle();
+        let view = rs.create_texture_handle();
+        let output = rs.create_texture_handle();
+
+        let mut graph = RenderGraph::<NullDevice>::new();
+        graph.alias_texture(view, target);
+        graph.activate_texture(target);
+        graph.activate_texture(output);
+        add(
+            &mut graph,
+            PassDesc::new("Write").with_write_texture(target, RenderTarget),
+        );
+        add(
+            &mut graph,
+            PassDesc::new("Read")
+                .with_read_texture(view, Shader)
+                .with_write_texture(output, RenderTarget),
+        );
+        graph.export_texture(output, None);
+
+        let compiled = graph.compile();
+
+        assert_eq!(
+            compiled.passes[0].barriers,
+            [Barrier::Alias(target), texture(target, RenderTarget)]
+        );
+        assert_eq!(
+            compiled.passes[1].barriers,
+            [
+                Barrier::Alias(output),
+                texture(view, Shader),
+                texture(output, RenderTarget)
+            ]
+        );
+    }
+}
@@ -1,8 +1,11 @@
-use std::path::Path;
+use std::{cmp::Reverse, fmt, path::Path};
 
 use serde::{Deserialize, Serialize};
 
-use super::shader::{CompiledShader, ShaderDesc};
+use super::{
+    error::RenderError,
+    shader::{CompiledShader, ShaderDesc},
+};
 
 pub type RenderDeviceId = usize;
 
@@ -12,7 +15,10 @@ pub trait Api {
     fn enumerate_devices(&self) -> impl Iterator<Item = &RenderDeviceInfo> + '_;
     fn create_device(&self, index: RenderDeviceId) -> Self::Device;
 
-    fn compile_shader<P: AsRef<Path>>(&self, desc: &ShaderDesc<'_, P>) -> CompiledShader;
+    fn compile_shader<P: AsRef<Path>>(
+        &self,
+        desc: &ShaderDesc<'_, P>,
+    ) -> Result<CompiledShader, RenderError>;
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -39,5 +45,298 @@ bitflags::bitflags! {
         const GpuValidation = 0x2;
         const RenderDoc = 0x4;
         const Pix = 0x8;
+        // State tracking in `rhi::validation`, only over the recording backend.
+        const RhiValidation = 0x10;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceRole {
+    Primary,
+    Secondary,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceOverride {
+    Index(RenderDeviceId),
+    Name(String),
+}
+
+impl DeviceOverride {
+    pub fn matches(&self, index: RenderDeviceId, info: &RenderDeviceInfo) -> bool {
+        match self {
+            DeviceOverride::Index(i) => *i == index,
+            DeviceOverride::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl From<&str> for DeviceOverride {
+    fn from(value: &str) -> Self {
+        value
+            .parse()
+            .map(DeviceOverride::Index)
+            .unwrap_or_else(|_| DeviceOverride::Name(value.to_string()))
+    }
+}
+
+impl fmt::Display for DeviceOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceOverride::Index(index) => write!(f, "#{}", index),
+            DeviceOverride::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSelector {
+    primary: Option<DeviceOverride>,
+    secondary: Option<DeviceOverride>,
+}
+
+impl DeviceSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_primary(mut self, device: impl Into<DeviceOverride>) -> Self {
+        self.primary = Some(device.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, device: impl Into<DeviceOverride>) -> Self {
+        self.secondary = Some(device.into());
+        self
+    }
+
+    pub fn is_suitable(&self, info: &RenderDeviceInfo) -> bool {
+        info.ty != DeviceType::Cpu && info.is_cross_adapter_texture_supported
+    }
+
+    pub fn rank(&self, info: &RenderDeviceInfo, role: DeviceRole) -> u32 {
+        let ty = match (role, info.ty) {
+            (_, DeviceType::Cpu) => 0,
+            (DeviceRole::Primary, DeviceType::Discrete)
+            | (DeviceRole::Secondary, DeviceType::Integrated) => 2,
+            _ => 1,
+        };
+
+        let uma = match role {
+            DeviceRole::Primary => !info.is_uma,
+            DeviceRole::Secondary => info.is_uma,
+        };
+
+        (ty << 3)
+            | ((uma as u32) << 2)
+            | ((info.is_cross_adapter_texture_supported as u32) << 1)
+            | info.copy_timestamp_support as u32
+    }
+
+    pub fn select<'a>(
+        &self,
+        devices: impl IntoIterator<Item = &'a RenderDeviceInfo>,
+    ) -> Result<Vec<RenderDeviceId>, RenderError> {
+        let devices = devices.into_iter().enumerate().collect::<Vec<_>>();
+        let mut selected = vec![];
+
+        let primary = self.pick(
+            &devices,
+            DeviceRole::Primary,
+            self.primary.as_ref(),
+            &selected,
+        )?;
+        selected.extend(primary);
+
+        let secondary = self.pick(
+            &devices,
+            DeviceRole::Secondary,
+            self.secondary.as_ref(),
+            &selected,
+        )?;
+        selected.extend(secondary);
+
+        while let Some(index) = self.pick(&devices, DeviceRole::Secondary, None, &selected)? {
+            selected.push(index);
+        }
+
+        if selected.len() < 2 {
+            return Err(RenderError::NotEnoughDevices {
+                required: 2,
+                found: selected.len(),
+            });
+        }
+
+        Ok(selected)
+    }
+
+    fn pick(
+        &self,
+        devices: &[(RenderDeviceId, &RenderDeviceInfo)],
+        role: DeviceRole,
+        device: Option<&DeviceOverride>,
+        selected: &[RenderDeviceId],
+    ) -> Result<Option<RenderDeviceId>, RenderError> {
+        let mut candidates = devices.iter().filter(|(i, _)| !selected.contains(i));
+
+        if let Some(device) = device {
+            return candidates
+                .find(|(i, info)| device.matches(*i, info))
+                .map(|(i, _)| Some(*i))
+                .ok_or_else(|| RenderError::DeviceNotFound {
+                    query: device.to_string(),
+                });
+        }
+
+        Ok(candidates
+            .filter(|(_, info)| self.is_suitable(info))
+            .max_by_key(|(i, info)| (self.rank(info, role), Reverse(*i)))
+            .map(|(i, _)| *i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(name: &str, ty: DeviceType) -> RenderDeviceInfo {
+        RenderDeviceInfo {
+            name: name.to_string(),
+            id: 0,
+            is_cross_adapter_texture_supported: true,
+            is_uma: ty == DeviceType::Integrated,
+            ty,
+            copy_timestamp_support: true,
+        }
+    }
+
+    fn adapters() -> Vec<RenderDeviceInfo> {
+        vec![
+            adapter("Microsoft Basic Render Driver", DeviceType::Cpu),
+            adapter("Intel(R) UHD Graphics 630", DeviceType::Integrated),
+            adapter("NVIDIA GeForce RTX 3070", DeviceType::Discrete),
+            adapter("AMD Radeon RX 6600", DeviceType::Discrete),
+        ]
+    }
+
+    #[test]
+    fn rank_prefers_discrete_primary_and_integrated_secondary() {
+        let selector = DeviceSelector::new();
+        let discrete = adapter("Discrete", DeviceType::Discrete);
+        let integrated = adapter("Integrated", DeviceType::Integrated);
+        let cpu = adapter("Cpu", DeviceType::Cpu);
+
+        assert!(
+            selector.rank(&discrete, DeviceRole::Primary)
+                > selector.rank(&integrated, DeviceRole::Primary)
+        );
+        assert!(
+            selector.rank(&integrated, DeviceRole::Secondary)
+                > selector.rank(&discrete, DeviceRole::Secondary)
+        );
+        assert!(
+            selector.rank(&integrated, DeviceRole::Primary)
+                > selector.rank(&cpu, DeviceRole::Primary)
+        );
+    }
+
+    #[test]
+    fn rank_breaks_ties_on_capabilities() {
+        let selector = DeviceSelector::new();
+        let full = adapter("Full", DeviceType::Discrete);
+        let no_timestamps = RenderDeviceInfo {
+            copy_timestamp_support: false,
+            ..full.clone()
+        };
+        let no_sharing = RenderDeviceInfo {
+            is_cross_adapter_texture_supported: false,
+            ..full.clone()
+        };
+
+        let primary = |info| selector.rank(info, DeviceRole::Primary);
+        assert!(primary(&full) > primary(&no_timestamps));
+        assert!(primary(&no_timestamps) > primary(&no_sharing));
+    }
+
+    #[test]
+    fn select_ranks_roles_and_appends_remaining_devices() {
+        let adapters = adapters();
+
+        let selected = DeviceSelector::new()
+            .select(&adapters)
+            .expect("failed to select");
+
+        // The first discrete device wins ties, the CPU adapter is never picked.
+        assert_eq!(selected, [2, 1, 3]);
+    }
+
+    #[test]
+    fn select_skips_adapters_without_cross_adapter_textures() {
+        let mut adapters = adapters();
+        adapters[1].is_cross_adapter_texture_supported = false;
+
+        let selected = DeviceSelector::new()
+            .select(&adapters)
+            .expect("failed to select");
+
+        assert_eq!(selected, [2, 3]);
+    }
+
+    #[test]
+    fn overrides_match_index_or_name() {
+        let adapters = adapters();
+
+        let selected = DeviceSelector::new()
+            .with_primary("radeon")
+            .with_secondary("2")
+            .select(&adapters)
+            .expect("failed to select");
+        assert_eq!(selected, [3, 2, 1]);
+
+        // Overrides may pick adapters the ranking would skip.
+        let selected = DeviceSelector::new()
+            .with_secondary("basic render")
+            .select(&adapters)
+            .expect("failed to select");
+        assert_eq!(selected[..2], [2, 0]);
+    }
+
+    #[test]
+    fn override_is_parsed_as_index_or_name() {
+        assert_eq!(DeviceOverride::from("1"), DeviceOverride::Index(1));
+        assert_eq!(
+            DeviceOverride::from("RTX"),
+            DeviceOverride::Name("RTX".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_override_is_an_error() {
+        let result = DeviceSelector::new()
+            .with_secondary("Arc A770")
+            .select(&adapters());
+
+        assert_eq!(
+            result,
+            Err(RenderError::DeviceNotFound {
+                query: "Arc A770".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn single_suitable_device_is_not_enough() {
+        let adapters = [
+            adapter("Microsoft Basic Render Driver", DeviceType::Cpu),
+            adapter("NVIDIA GeForce RTX 3070", DeviceType::Discrete),
+        ];
+
+        assert_eq!(
+            DeviceSelector::new().select(&adapters),
+            Err(RenderError::NotEnoughDevices {
+                required: 2,
+                found: 1
+            })
+        );
     }
 }
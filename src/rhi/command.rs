@@ -1,20 +1,23 @@
 use std::borrow::Cow;
 
+use serde::{Deserialize, Serialize};
+
 use super::{
+    error::RenderError,
     resources::RenderResourceDevice,
-    types::{GeomTopology, IndexType, ResourceState, Scissor, Timings, Viewport},
+    types::{GeomTopology, IndexType, Region, ResourceState, Scissor, Timings, Viewport},
 };
 
 pub type SyncPoint = u64;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CommandType {
     Graphics,
     Compute,
     Transfer,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Subresource {
     Local(Option<u32>),
     Shared,
@@ -24,6 +27,9 @@ pub enum Subresource {
 pub enum Barrier<'a, D: RenderResourceDevice> {
     Buffer(&'a D::Buffer, ResourceState),
     Texture(&'a D::Texture, ResourceState, Subresource),
+    // Makes a placed texture the owner of its memory, the contents are
+    // undefined until the texture is cleared or fully written.
+    Alias(&'a D::Texture),
 }
 
 pub trait RenderCommandDevice: RenderResourceDevice {
@@ -49,24 +55,27 @@ pub trait RenderCommandQueue: Send + Sync {
     fn ty(&self) -> CommandType;
     fn frequency(&self) -> f64;
 
-    fn create_command_buffer(&self, device: &Self::Device) -> Self::CommandBuffer;
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError>;
     fn enqueue(&self, cmd_buffer: Self::CommandBuffer);
     fn commit(&self, cmd_buffer: Self::CommandBuffer);
-    fn submit(&self, device: &Self::Device) -> SyncPoint;
+    fn submit(&self, device: &Self::Device) -> Result<SyncPoint, RenderError>;
 
-    fn signal_event(&self, event: &Self::Event) -> SyncPoint;
-    fn wait_event(&self, event: &Self::Event);
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError>;
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError>;
 
-    fn wait_on_cpu(&self, value: SyncPoint);
-    fn wait_until_complete(&self);
-    fn wait_idle(&self);
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError>;
+    fn wait_until_complete(&self) -> Result<(), RenderError>;
+    fn wait_idle(&self) -> Result<(), RenderError>;
 
     fn is_ready(&self) -> bool;
     fn is_ready_for(&self, v: u64) -> bool;
 }
 
 pub trait RenderResourceUploader: RenderCommandQueue<CommandBuffer: IoCommandBuffer> {
-    fn flush(&self, device: &Self::Device);
+    fn flush(&self, device: &Self::Device) -> Result<(), RenderError>;
 }
 
 pub trait IoCommandBuffer {
@@ -77,13 +86,13 @@ pub trait IoCommandBuffer {
         device: &Self::Device,
         buffer: &mut <Self::Device as RenderResourceDevice>::Buffer,
         data: &'_ [u8],
-    );
+    ) -> Result<(), RenderError>;
     fn load_to_texture(
         &mut self,
         device: &Self::Device,
         texture: &<Self::Device as RenderResourceDevice>::Texture,
         data: &'_ [u8],
-    );
+    ) -> Result<(), RenderError>;
 }
 
 pub trait RenderCommandBuffer {
@@ -110,13 +119,13 @@ pub trait RenderCommandBuffer {
         depth: Option<&<Self::Device as RenderResourceDevice>::Texture>,
     ) -> Self::RenderEncoder<'_>;
 
-    fn transfer<'a>(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_>;
+    fn transfer(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_>;
 
     fn resolve_timestamp_data(&mut self) -> std::ops::Range<usize>;
 }
 
 pub trait GpuEvent {
-    fn wait(&self, value: SyncPoint) -> bool;
+    fn wait(&self, value: SyncPoint) -> Result<bool, RenderError>;
     fn increment(&self) -> SyncPoint;
     fn get_completed_value(&self) -> SyncPoint;
     fn get_goal(&self) -> SyncPoint;
@@ -128,8 +137,8 @@ pub trait RenderEncoder {
     type RasterPipeline;
     type ShaderArgument;
 
-    fn clear_rt(&self, texture: &Self::Texture, color: Option<[f32; 4]>);
-    fn clear_depth(&self, texture: &Self::Texture, depth: Option<f32>);
+    fn clear_rt(&self, texture: &Self::Texture, color: Option<[f32; 4]>, region: Option<Region>);
+    fn clear_depth(&self, texture: &Self::Texture, depth: Option<f32>, region: Option<Region>);
 
     fn set_viewport(&self, viewport: Viewport);
     fn set_scissor(&self, scissor: Scissor);
@@ -149,11 +158,22 @@ pub trait RenderEncoder {
 
     fn draw(&self, count: u32, start_vertex: u32);
     fn draw_indexed(&self, count: u32, start_index: u32, base_index: u32);
+    fn draw_indexed_instanced(
+        &self,
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_index: u32,
+        start_instance: u32,
+    );
 }
 
 pub trait TransferEncoder {
+    type Buffer;
     type Texture;
 
-    fn pull_texture(&self, texture: &Self::Texture);
-    fn push_texture(&self, texture: &Self::Texture);
+    fn pull_texture(&self, texture: &Self::Texture, region: Option<Region>);
+    fn push_texture(&self, texture: &Self::Texture, region: Option<Region>);
+    fn copy_texture(&self, src: &Self::Texture, dst: &Self::Texture, region: Option<Region>);
+    fn copy_texture_to_buffer(&self, src: &Self::Texture, dst: &Self::Buffer);
 }
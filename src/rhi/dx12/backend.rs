@@ -1,4 +1,4 @@
-use std::{ffi::CString, path::Path};
+use std::{cmp::Reverse, ffi::CString, path::Path};
 
 use oxidx::dx::{
     self, Blobby,
@@ -8,7 +8,10 @@ use smallvec::SmallVec;
 use tracing::{debug, error, info, warn};
 
 use crate::rhi::{
-    backend::{Api, DebugFlags, DeviceType, RenderDeviceId, RenderDeviceInfo},
+    backend::{
+        Api, DebugFlags, DeviceRole, DeviceSelector, DeviceType, RenderDeviceId, RenderDeviceInfo,
+    },
+    error::RenderError,
     shader::{CompiledShader, ShaderDesc},
     types::ShaderType,
 };
@@ -98,11 +101,13 @@ impl DxBackend {
                         DeviceType::Discrete
                     };
 
+                    let id = gpus.len();
+
                     gpus.push((
                         adapter,
                         RenderDeviceInfo {
                             name: desc.description().trim_matches('\0').to_string(),
-                            id: i as RenderDeviceId,
+                            id,
                             is_cross_adapter_texture_supported: feature
                                 .cross_adapter_row_major_texture_supported(),
                             is_uma: hardware.uma(),
@@ -147,11 +152,13 @@ impl DxBackend {
                         DeviceType::Discrete
                     };
 
+                    let id = gpus.len();
+
                     gpus.push((
                         adapter,
                         RenderDeviceInfo {
                             name: desc.description().trim_matches('\0').to_string(),
-                            id: i as usize,
+                            id,
                             is_cross_adapter_texture_supported: feature
                                 .cross_adapter_row_major_texture_supported(),
                             is_uma: hardware.uma(),
@@ -165,19 +172,14 @@ impl DxBackend {
                 i += 1;
             }
 
-            gpus.sort_by(|(l, _), (r, _)| {
-                let descs = (
-                    l.get_desc1().map(|d| d.vendor_id()),
-                    r.get_desc1().map(|d| d.vendor_id()),
-                );
-
-                match descs {
-                    (Ok(0x8086), Ok(0x8086)) => std::cmp::Ordering::Equal,
-                    (Ok(0x8086), Ok(_)) => std::cmp::Ordering::Less,
-                    (Ok(_), Ok(0x8086)) => std::cmp::Ordering::Greater,
-                    (_, _) => std::cmp::Ordering::Equal,
-                }
-            });
+            // Without Factory7 there is no high-performance order, rank them
+            // the same way so ids mean the same thing on both paths.
+            let selector = DeviceSelector::new();
+            gpus.sort_by_key(|(_, info)| Reverse(selector.rank(info, DeviceRole::Primary)));
+
+            for (id, (_, info)) in gpus.iter_mut().enumerate() {
+                info.id = id;
+            }
         }
 
         let (adapters, adapter_infos): (Vec<_>, Vec<_>) = gpus.into_iter().unzip();
@@ -210,7 +212,10 @@ impl Api for DxBackend {
         )
     }
 
-    fn compile_shader<P: AsRef<Path>>(&self, desc: &ShaderDesc<'_, P>) -> CompiledShader {
+    fn compile_shader<P: AsRef<Path>>(
+        &self,
+        desc: &ShaderDesc<'_, P>,
+    ) -> Result<CompiledShader, RenderError> {
         let target = match desc.ty {
             ShaderType::Vertex => c"vs_5_1",
             ShaderType::Pixel => c"ps_5_1",
@@ -242,7 +247,10 @@ impl Api for DxBackend {
         let entry_point = CString::new(desc.entry_point.as_bytes()).expect("CString::new failed");
 
         let raw = dx::Blob::compile_from_file(&desc.path, &defines, &entry_point, target, flags, 0)
-            .expect("Failed to compile a shader");
+            .map_err(|e| RenderError::ShaderCompile {
+                path: desc.path.as_ref().to_path_buf(),
+                log: format!("{:?}", e),
+            })?;
 
         let mapped = raw.as_ptr();
 
@@ -251,6 +259,6 @@ impl Api for DxBackend {
         let mut raw = vec![0; raw.len()];
         raw.clone_from_slice(slice);
 
-        CompiledShader { raw, ty: desc.ty }
+        Ok(CompiledShader { raw, ty: desc.ty })
     }
 }
@@ -16,21 +16,27 @@ use crate::rhi::{
         RenderCommandQueue, RenderEncoder, RenderResourceUploader, Subresource, SyncPoint,
         TransferEncoder,
     },
+    error::RenderError,
     resources::{
         Buffer, BufferDesc, BufferUsages, MemoryLocation, QueryHeap, RenderResourceDevice,
+        TextureType, buffer_row_pitch,
+    },
+    types::{
+        ClearColor, GeomTopology, IndexType, Region, ResourceState, Scissor, Timings, Viewport,
     },
-    types::{ClearColor, GeomTopology, IndexType, ResourceState, Scissor, Timings, Viewport},
 };
 
 use super::{
-    conv::{map_command_buffer_type, map_geom_topology, map_resource_state},
-    device::DxDevice,
+    conv::{map_command_buffer_type, map_format, map_geom_topology, map_resource_state},
+    device::{DxDevice, map_device_error},
     resources::{DxBuffer, DxTexture, DxTimestampQuery, TextureFlavor},
     shader::{DxRasterPipeline, DxShaderArgument},
 };
 
 pub const QUERY_SIZE: usize = 64;
 
+const WAIT_TIMEOUT: u32 = 0x00000102;
+
 impl RenderCommandDevice for DxDevice {
     type ResourceUploader = DxResourceUploader;
     type CommandQueue = DxCommandQueue;
@@ -55,7 +61,9 @@ impl RenderCommandDevice for DxDevice {
                     .create_command_allocator(map_command_buffer_type(ty))
                     .expect("failed to create command allocator"),
                 sync_point: 0,
-                query: self.create_timestamp_query(ty, QUERY_SIZE),
+                query: self
+                    .create_timestamp_query(ty, QUERY_SIZE)
+                    .expect("failed to create timestamp query"),
                 range: None,
                 labels: vec![],
             })
@@ -68,6 +76,7 @@ impl RenderCommandDevice for DxDevice {
         cmd_list.close().expect("failed to close list");
 
         DxCommandQueue {
+            gpu: self.gpu.clone(),
             ty_raw: map_command_buffer_type(ty),
             ty,
             fence,
@@ -136,6 +145,7 @@ impl RenderCommandDevice for DxDevice {
 
 #[derive(Debug)]
 pub struct DxCommandQueue {
+    gpu: dx::Device,
     ty_raw: dx::CommandListType,
     ty: CommandType,
 
@@ -167,9 +177,12 @@ impl RenderCommandQueue for DxCommandQueue {
     }
 
     #[allow(unused_assignments)]
-    fn create_command_buffer(&self, device: &Self::Device) -> Self::CommandBuffer {
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
         if let Some(buffer) = self.in_record.lock().pop() {
-            return buffer;
+            return Ok(buffer);
         };
 
         let allocator = if let Some(allocator) =
@@ -180,16 +193,13 @@ impl RenderCommandQueue for DxCommandQueue {
                     None
                 }
             }) {
-            allocator
-                .raw
-                .reset()
-                .expect("failed to reset command allocator");
+            allocator.raw.reset().map_err(|e| device.map_error(e))?;
 
             allocator
         } else {
             if self.capacity.is_some() {
                 let entry = self.cmd_allocators.lock().pop_front().expect("unreachable");
-                self.fence.wait(entry.sync_point);
+                self.fence.wait(entry.sync_point)?;
 
                 entry
             } else {
@@ -197,9 +207,9 @@ impl RenderCommandQueue for DxCommandQueue {
                     raw: device
                         .gpu
                         .create_command_allocator(self.ty_raw)
-                        .expect("failed to create command allocator"),
+                        .map_err(|e| device.map_error(e))?,
                     sync_point: 0,
-                    query: device.create_timestamp_query(self.ty, QUERY_SIZE),
+                    query: device.create_timestamp_query(self.ty, QUERY_SIZE)?,
                     range: None,
                     labels: vec![],
                 }
@@ -208,23 +218,23 @@ impl RenderCommandQueue for DxCommandQueue {
 
         let list = if let Some(list) = self.cmd_lists.lock().pop() {
             list.reset(&allocator.raw, None)
-                .expect("Failed to reset list");
+                .map_err(|e| device.map_error(e))?;
             list
         } else {
             let list = device
                 .gpu
                 .create_command_list(0, self.ty_raw, &allocator.raw, None)
-                .expect("failed to create command list");
-            list.close().expect("failed to close list");
+                .map_err(|e| device.map_error(e))?;
+            list.close().map_err(|e| device.map_error(e))?;
             list
         };
 
-        DxCommandBuffer {
+        Ok(DxCommandBuffer {
             list,
             allocator,
             ty: self.ty,
             frequency: self.frequency,
-        }
+        })
     }
 
     fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
@@ -239,7 +249,7 @@ impl RenderCommandQueue for DxCommandQueue {
         self.pending.lock().push(cmd_buffer);
     }
 
-    fn submit(&self, _: &DxDevice) -> SyncPoint {
+    fn submit(&self, device: &DxDevice) -> Result<SyncPoint, RenderError> {
         let cmd_buffers = self.pending.lock().drain(..).collect::<Vec<_>>();
         let lists = cmd_buffers
             .iter()
@@ -247,7 +257,12 @@ impl RenderCommandQueue for DxCommandQueue {
             .collect::<SmallVec<[_; 16]>>();
 
         self.queue.lock().execute_command_lists(&lists);
-        let fence_value = self.signal_event(&self.fence);
+
+        let fence_value = self.fence.increment();
+        self.queue
+            .lock()
+            .signal(&self.fence.fence, fence_value)
+            .map_err(|e| device.map_error(e))?;
 
         let allocators = cmd_buffers.into_iter().map(|mut buffer| {
             buffer.allocator.sync_point = fence_value;
@@ -260,37 +275,38 @@ impl RenderCommandQueue for DxCommandQueue {
             .map(|list| unsafe { list.unwrap_unchecked() });
         self.cmd_lists.lock().extend(lists);
 
-        fence_value
+        Ok(fence_value)
     }
 
-    fn signal_event(&self, event: &Self::Event) -> SyncPoint {
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
         let value = event.increment();
         self.queue
             .lock()
             .signal(&event.fence, value)
-            .expect("Failed to signal");
+            .map_err(|e| map_device_error(&self.gpu, e))?;
 
-        value
+        Ok(value)
     }
 
-    fn wait_event(&self, event: &Self::Event) {
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError> {
         self.queue
             .lock()
             .wait(&event.fence, event.get_goal())
-            .expect("failed to wait event");
+            .map_err(|e| map_device_error(&self.gpu, e))
     }
 
-    fn wait_on_cpu(&self, value: SyncPoint) {
-        self.fence.wait(value);
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.fence.wait(value).map(|_| ())
     }
 
-    fn wait_until_complete(&self) {
-        self.wait_on_cpu(self.cmd_allocators.lock()[0].sync_point);
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        let sync_point = self.cmd_allocators.lock()[0].sync_point;
+        self.wait_on_cpu(sync_point)
     }
 
-    fn wait_idle(&self) {
-        let value = self.signal_event(&self.fence);
-        self.wait_on_cpu(value);
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        let value = self.signal_event(&self.fence)?;
+        self.wait_on_cpu(value)
     }
 
     fn is_ready(&self) -> bool {
@@ -431,6 +447,9 @@ impl RenderCommandBuffer for DxCommandBuffer {
                         }
                     }
                 },
+                Barrier::Alias(texture) => {
+                    Some(dx::ResourceBarrier::aliasing(None, Some(&texture.raw)))
+                }
             })
             .collect::<SmallVec<[_; 8]>>();
 
@@ -482,7 +501,7 @@ impl RenderCommandBuffer for DxCommandBuffer {
         range
     }
 
-    fn transfer<'a>(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_> {
+    fn transfer(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_> {
         self.write_timestamp();
         self.allocator.labels.push(label);
 
@@ -520,11 +539,14 @@ impl RenderCommandQueue for DxResourceUploader {
         self.queue.frequency
     }
 
-    fn create_command_buffer(&self, device: &Self::Device) -> Self::CommandBuffer {
-        let buffer = self.queue.create_command_buffer(device);
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
+        let buffer = self.queue.create_command_buffer(device)?;
         let temps = self.res_pool.lock().pop().unwrap_or_default();
 
-        DxIoCommandBuffer { buffer, temps }
+        Ok(DxIoCommandBuffer { buffer, temps })
     }
 
     fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
@@ -537,8 +559,8 @@ impl RenderCommandQueue for DxResourceUploader {
         self.queue.commit(cmd_buffer.buffer);
     }
 
-    fn submit(&self, device: &DxDevice) -> SyncPoint {
-        let value = self.queue.submit(device);
+    fn submit(&self, device: &DxDevice) -> Result<SyncPoint, RenderError> {
+        let value = self.queue.submit(device)?;
 
         {
             let mut guard = self.pending.lock();
@@ -569,27 +591,27 @@ impl RenderCommandQueue for DxResourceUploader {
             }
         }
 
-        value
+        Ok(value)
     }
 
-    fn signal_event(&self, event: &Self::Event) -> SyncPoint {
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
         self.queue.signal_event(event)
     }
 
-    fn wait_event(&self, event: &Self::Event) {
-        self.queue.wait_event(event);
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError> {
+        self.queue.wait_event(event)
     }
 
-    fn wait_on_cpu(&self, value: SyncPoint) {
-        self.queue.wait_on_cpu(value);
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.queue.wait_on_cpu(value)
     }
 
-    fn wait_until_complete(&self) {
-        self.queue.wait_until_complete();
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        self.queue.wait_until_complete()
     }
 
-    fn wait_idle(&self) {
-        self.queue.wait_idle();
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        self.queue.wait_idle()
     }
 
     fn is_ready(&self) -> bool {
@@ -602,12 +624,14 @@ impl RenderCommandQueue for DxResourceUploader {
 }
 
 impl RenderResourceUploader for DxResourceUploader {
-    fn flush(&self, device: &Self::Device) {
-        self.wait_idle();
+    fn flush(&self, device: &Self::Device) -> Result<(), RenderError> {
+        self.wait_idle()?;
 
         for buffer in self.staging.lock().drain(..) {
             device.destroy_buffer(buffer.res);
         }
+
+        Ok(())
     }
 }
 
@@ -620,13 +644,18 @@ pub struct DxIoCommandBuffer {
 impl IoCommandBuffer for DxIoCommandBuffer {
     type Device = DxDevice;
 
-    fn load_to_buffer(&mut self, device: &Self::Device, buffer: &mut DxBuffer, data: &'_ [u8]) {
+    fn load_to_buffer(
+        &mut self,
+        device: &Self::Device,
+        buffer: &mut DxBuffer,
+        data: &'_ [u8],
+    ) -> Result<(), RenderError> {
         if buffer.desc.memory_location == MemoryLocation::CpuToGpu {
             let map = buffer.map_mut();
             map.clone_from_slice(data);
         } else {
-            let mut staging =
-                device.create_buffer(BufferDesc::cpu_to_gpu(buffer.desc.size, BufferUsages::Copy));
+            let mut staging = device
+                .create_buffer(BufferDesc::cpu_to_gpu(buffer.desc.size, BufferUsages::Copy))?;
 
             {
                 let map = staging.map_mut();
@@ -637,13 +666,20 @@ impl IoCommandBuffer for DxIoCommandBuffer {
 
             self.temps.push(staging);
         }
+
+        Ok(())
     }
 
-    fn load_to_texture(&mut self, device: &Self::Device, texture: &DxTexture, data: &'_ [u8]) {
+    fn load_to_texture(
+        &mut self,
+        device: &Self::Device,
+        texture: &DxTexture,
+        data: &'_ [u8],
+    ) -> Result<(), RenderError> {
         debug_assert_eq!(data.len(), texture.size);
 
         let staging =
-            device.create_buffer(BufferDesc::cpu_to_gpu(texture.size, BufferUsages::Copy));
+            device.create_buffer(BufferDesc::cpu_to_gpu(texture.size, BufferUsages::Copy))?;
 
         self.buffer.set_barriers(
             [Barrier::Texture(
@@ -676,6 +712,8 @@ impl IoCommandBuffer for DxIoCommandBuffer {
         );
 
         self.temps.push(staging);
+
+        Ok(())
     }
 }
 
@@ -693,20 +731,23 @@ pub struct DxFence {
 }
 
 impl GpuEvent for DxFence {
-    fn wait(&self, value: SyncPoint) -> bool {
-        if self.get_completed_value() < value {
-            let event = dx::Event::create(false, false).expect("failed to create event");
-            self.fence
-                .set_event_on_completion(value, event)
-                .expect("failed to bind fence to event");
-            if event.wait(10_000_000) == 0x00000102 {
-                panic!("device lost")
-            }
+    // A wait that times out means the GPU hung or was removed.
+    fn wait(&self, value: SyncPoint) -> Result<bool, RenderError> {
+        if self.get_completed_value() >= value {
+            return Ok(false);
+        }
 
-            true
-        } else {
-            false
+        let event = dx::Event::create(false, false)
+            .map_err(|e| RenderError::Backend(format!("{:?}", e)))?;
+        self.fence
+            .set_event_on_completion(value, event)
+            .map_err(|e| RenderError::Backend(format!("{:?}", e)))?;
+
+        if event.wait(10_000_000) == WAIT_TIMEOUT {
+            return Err(RenderError::DeviceLost);
         }
+
+        Ok(true)
     }
 
     fn increment(&self) -> SyncPoint {
@@ -736,7 +777,7 @@ impl<'a> RenderEncoder for DxRenderEncoder<'a> {
     type RasterPipeline = DxRasterPipeline;
     type ShaderArgument = DxShaderArgument;
 
-    fn clear_rt(&self, texture: &Self::Texture, color: Option<[f32; 4]>) {
+    fn clear_rt(&self, texture: &Self::Texture, color: Option<[f32; 4]>, region: Option<Region>) {
         if let Some(descriptor) = &texture.descriptor {
             let color = match (texture.desc.clear_color, color) {
                 (Some(ClearColor::Color(c)), None) => c,
@@ -745,13 +786,15 @@ impl<'a> RenderEncoder for DxRenderEncoder<'a> {
                 (_, _) => Default::default(),
             };
 
-            self.cmd
-                .list
-                .clear_render_target_view(descriptor.cpu, color, &[]);
+            self.cmd.list.clear_render_target_view(
+                descriptor.cpu,
+                color,
+                region.map(map_region).as_slice(),
+            );
         }
     }
 
-    fn clear_depth(&self, texture: &Self::Texture, depth: Option<f32>) {
+    fn clear_depth(&self, texture: &Self::Texture, depth: Option<f32>, region: Option<Region>) {
         if let Some(descriptor) = &texture.descriptor {
             let depth = match (texture.desc.clear_color, depth) {
                 (Some(ClearColor::Depth(c)), None) => c,
@@ -765,7 +808,7 @@ impl<'a> RenderEncoder for DxRenderEncoder<'a> {
                 dx::ClearFlags::Depth,
                 depth,
                 0,
-                None,
+                region.map(map_region).as_ref().map(std::slice::from_ref),
             );
         }
     }
@@ -864,6 +907,30 @@ impl<'a> RenderEncoder for DxRenderEncoder<'a> {
             .list
             .draw_indexed_instanced(count, 1, start_index, base_vertex as i32, 0);
     }
+
+    fn draw_indexed_instanced(
+        &self,
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_vertex: u32,
+        start_instance: u32,
+    ) {
+        self.cmd.list.draw_indexed_instanced(
+            count,
+            instance_count,
+            start_index,
+            base_vertex as i32,
+            start_instance,
+        );
+    }
+}
+
+fn map_region(region: Region) -> dx::Rect {
+    dx::Rect::default()
+        .with_left(region.x as i32)
+        .with_top(region.y as i32)
+        .with_size((region.w as i32, region.h as i32))
 }
 
 impl Drop for DxRenderEncoder<'_> {
@@ -878,25 +945,90 @@ pub struct DxTransferEncoder<'a> {
 }
 
 impl<'a> TransferEncoder for DxTransferEncoder<'a> {
+    type Buffer = DxBuffer;
     type Texture = DxTexture;
 
-    fn pull_texture(&self, texture: &Self::Texture) {
+    fn pull_texture(&self, texture: &Self::Texture, region: Option<Region>) {
         match &texture.flavor {
             TextureFlavor::Binded { cross, .. } => {
-                self.cmd.list.copy_resource(&texture.raw, cross);
+                self.copy(cross, &texture.raw, region);
             }
             _ => { /* NOOP */ }
         }
     }
 
-    fn push_texture(&self, texture: &Self::Texture) {
+    fn push_texture(&self, texture: &Self::Texture, region: Option<Region>) {
         match &texture.flavor {
             TextureFlavor::Binded { cross, .. } => {
-                self.cmd.list.copy_resource(cross, &texture.raw);
+                self.copy(&texture.raw, cross, texture.desc.ty, region);
             }
             _ => { /* NOOP */ }
         }
     }
+
+    fn copy_texture(&self, src: &Self::Texture, dst: &Self::Texture, region: Option<Region>) {
+        self.copy(&src.raw, &dst.raw, dst.desc.ty, region);
+    }
+
+    fn copy_texture_to_buffer(&self, src: &Self::Texture, dst: &Self::Buffer) {
+        let footprint = dx::PlacedSubresourceFootprint::new(
+            0,
+            dx::SubresourceFootprint::new(
+                map_format(src.desc.format),
+                src.desc.extent[0],
+                src.desc.extent[1],
+                1,
+                buffer_row_pitch(&src.desc) as u32,
+            ),
+        );
+
+        self.cmd.list.copy_texture_region(
+            &dx::TextureCopyLocation::placed_footprint(&dst.raw, footprint),
+            0,
+            0,
+            0,
+            &dx::TextureCopyLocation::subresource(&src.raw, 0),
+            None,
+        );
+    }
+}
+
+impl DxTransferEncoder<'_> {
+    fn copy(
+        &self,
+        src: &dx::Resource,
+        dst: &dx::Resource,
+        ty: TextureType,
+        region: Option<Region>,
+    ) {
+        let Some(region) = region else {
+            self.cmd.list.copy_resource(dst, src);
+            return;
+        };
+
+        // Regions only address the first subresource, array slices are copied whole.
+        assert_eq!(
+            ty,
+            TextureType::D2,
+            "region copies need a single layer texture"
+        );
+
+        self.cmd.list.copy_texture_region(
+            &dx::TextureCopyLocation::subresource(dst, 0),
+            region.x,
+            region.y,
+            0,
+            &dx::TextureCopyLocation::subresource(src, 0),
+            Some(&dx::DxBox::new(
+                region.x,
+                region.y,
+                0,
+                region.x + region.w,
+                region.y + region.h,
+                1,
+            )),
+        );
+    }
 }
 
 impl Drop for DxTransferEncoder<'_> {
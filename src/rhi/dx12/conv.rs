@@ -5,8 +5,8 @@ use crate::rhi::{
     resources::{TextureDesc, TextureType, TextureUsages},
     shader::{SamplerType, StaticSampler},
     types::{
-        AddressMode, ClearColor, ComparisonFunc, CullMode, DepthOp, Filter, Format, GeomTopology,
-        ResourceState, VertexAttribute, VertexType,
+        AddressMode, BlendMode, ClearColor, ComparisonFunc, CullMode, DepthOp, Filter, Format,
+        GeomTopology, ResourceState, VertexAttribute, VertexType,
     },
 };
 
@@ -26,6 +26,7 @@ pub(super) fn map_format(format: Format) -> dx::Format {
         Format::Rgb32 => dx::Format::Rgb32Float,
         Format::Rgba32 => dx::Format::Rgba32Float,
         Format::Rgba8Unorm => dx::Format::Rgba8Unorm,
+        Format::Rgba8UnormSrgb => dx::Format::Rgba8UnormSrgb,
         Format::Rgba8 => dx::Format::Rgba8Uint,
         Format::D24S8 => dx::Format::D24UnormS8Uint,
         Format::D32 => dx::Format::D32Float,
@@ -171,6 +172,18 @@ pub(super) fn map_cull_mode(mode: CullMode) -> dx::CullMode {
     }
 }
 
+pub(super) fn map_blend(mode: BlendMode) -> dx::RenderTargetBlendDesc {
+    match mode {
+        BlendMode::None => dx::RenderTargetBlendDesc::default(),
+        BlendMode::Alpha => dx::RenderTargetBlendDesc::blend(
+            dx::Blend::SrcAlpha,
+            dx::Blend::InvSrcAlpha,
+            dx::BlendOp::Add,
+            dx::ColorWriteEnable::all(),
+        ),
+    }
+}
+
 pub(super) fn map_depth_op(op: DepthOp) -> dx::ComparisonFunc {
     match op {
         DepthOp::None => dx::ComparisonFunc::None,
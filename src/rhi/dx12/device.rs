@@ -1,10 +1,10 @@
-use std::ops::Range;
+use std::{fmt::Debug, ops::Range};
 
 use oxidx::dx;
 use parking_lot::Mutex;
 use tracing::info;
 
-use crate::rhi::backend::RenderDeviceInfo;
+use crate::rhi::{backend::RenderDeviceInfo, error::RenderError};
 
 #[derive(Debug)]
 pub struct DxDevice {
@@ -46,6 +46,20 @@ impl DxDevice {
             descriptors,
         }
     }
+
+    pub(super) fn map_error(&self, error: impl Debug) -> RenderError {
+        map_device_error(&self.gpu, error)
+    }
+}
+
+// Calls fail with DXGI_ERROR_DEVICE_REMOVED once the device is gone, the
+// removed reason tells it apart from an ordinary failure.
+pub(super) fn map_device_error(gpu: &dx::Device, error: impl Debug) -> RenderError {
+    if gpu.get_device_removed_reason().is_err() {
+        RenderError::DeviceLost
+    } else {
+        RenderError::Backend(format!("{:?}", error))
+    }
 }
 
 #[derive(Debug)]
@@ -72,7 +86,11 @@ impl DescriptorPool {
         }
     }
 
-    pub(super) fn allocate(&self, ty: dx::DescriptorHeapType, size: usize) -> Descriptor {
+    pub(super) fn allocate(
+        &self,
+        ty: dx::DescriptorHeapType,
+        size: usize,
+    ) -> Result<Descriptor, RenderError> {
         match ty {
             dx::DescriptorHeapType::Rtv => self.rtv_heap.lock().allocate(size),
             dx::DescriptorHeapType::Dsv => self.dsv_heap.lock().allocate(size),
@@ -129,11 +147,11 @@ impl DescriptorHeap {
         }
     }
 
-    pub(super) fn allocate(&mut self, size: usize) -> Descriptor {
+    pub(super) fn allocate(&mut self, size: usize) -> Result<Descriptor, RenderError> {
         let allocation = self
             .allocator
             .allocate_range(size)
-            .expect("out of memory in descriptor heap");
+            .map_err(|_| RenderError::OutOfDescriptors { requested: size })?;
 
         let cpu = self
             .heap
@@ -147,12 +165,12 @@ impl DescriptorHeap {
             dx::GpuDescriptorHandle::default()
         };
 
-        Descriptor {
+        Ok(Descriptor {
             ty: self.ty,
             allocation,
             cpu,
             gpu,
-        }
+        })
     }
 
     pub(super) fn free(&mut self, descriptor: Descriptor) {
@@ -6,6 +6,7 @@ use parking_lot::Mutex;
 use crate::rhi::{
     command::CommandType,
     dx12::conv::map_texture_desc,
+    error::RenderError,
     resources::{
         Buffer, BufferDesc, BufferUsages, MemoryLocation, QueryHeap, RenderResourceDevice,
         SamplerDesc, TextureDesc, TextureType, TextureUsages, TextureViewDesc, TextureViewType,
@@ -22,8 +23,9 @@ impl RenderResourceDevice for DxDevice {
     type Texture = DxTexture;
     type Sampler = DxSampler;
     type TimestampQuery = DxTimestampQuery;
+    type Memory = DxMemory;
 
-    fn create_buffer(&self, desc: BufferDesc) -> Self::Buffer {
+    fn create_buffer(&self, desc: BufferDesc) -> Result<Self::Buffer, RenderError> {
         let heap_props = match desc.memory_location {
             MemoryLocation::CpuToGpu => dx::HeapProperties::upload(),
             MemoryLocation::GpuToGpu => dx::HeapProperties::default(),
@@ -52,7 +54,7 @@ impl RenderResourceDevice for DxDevice {
                 initial_state,
                 None,
             )
-            .expect("Failed to create buffer");
+            .map_err(|e| self.map_error(e))?;
 
         if let Some(name) = &desc.name {
             let debug_name = CString::new(name.as_bytes()).expect("failed to create resource name");
@@ -60,16 +62,16 @@ impl RenderResourceDevice for DxDevice {
                 .expect("failed to set debug object name");
         }
 
-        DxBuffer {
+        Ok(DxBuffer {
             raw,
             desc,
             state: Mutex::new(initial_state),
-        }
+        })
     }
 
     fn destroy_buffer(&self, _buffer: Self::Buffer) {}
 
-    fn create_texture(&self, desc: TextureDesc) -> Self::Texture {
+    fn create_texture(&self, desc: TextureDesc) -> Result<Self::Texture, RenderError> {
         if desc.usage.contains(TextureUsages::Shared) {
             let raw_desc = map_texture_desc(&desc, self.desc.is_cross_adapter_texture_supported);
 
@@ -94,11 +96,11 @@ impl RenderResourceDevice for DxDevice {
                     &dx::HeapDesc::new(size * 2, dx::HeapProperties::default())
                         .with_flags(dx::HeapFlags::SharedCrossAdapter | dx::HeapFlags::Shared),
                 )
-                .expect("Failed to create shared heap");
+                .map_err(|e| self.map_error(e))?;
 
             self.create_shared_texture(desc, heap, None)
         } else {
-            self.create_local_texture(desc)
+            self.create_local_texture(desc, None)
         }
     }
 
@@ -108,10 +110,50 @@ impl RenderResourceDevice for DxDevice {
         }
     }
 
-    fn create_texture_view(&self, texture: &Self::Texture, desc: TextureViewDesc) -> Self::Texture {
+    fn create_memory(&self, descs: &[TextureDesc]) -> Result<Self::Memory, RenderError> {
+        let size = descs
+            .iter()
+            .map(|desc| {
+                let raw_desc = map_texture_desc(desc, self.desc.is_cross_adapter_texture_supported);
+
+                self.gpu
+                    .get_resource_allocation_info(0, &[raw_desc])
+                    .size_in_bytes()
+            })
+            .max()
+            .unwrap_or_default();
+
+        // Tier 1 heaps can only hold a single resource category, transient
+        // memory is reserved for render and depth targets.
+        let heap = self
+            .gpu
+            .create_heap(
+                &dx::HeapDesc::new(size, dx::HeapProperties::default())
+                    .with_flags(dx::HeapFlags::AllowOnlyRtDsTextures),
+            )
+            .map_err(|e| self.map_error(e))?;
+
+        Ok(DxMemory { heap })
+    }
+
+    fn destroy_memory(&self, _memory: Self::Memory) {}
+
+    fn create_placed_texture(
+        &self,
+        desc: TextureDesc,
+        memory: &Self::Memory,
+    ) -> Result<Self::Texture, RenderError> {
+        self.create_local_texture(desc, Some(&memory.heap))
+    }
+
+    fn create_texture_view(
+        &self,
+        texture: &Self::Texture,
+        desc: TextureViewDesc,
+    ) -> Result<Self::Texture, RenderError> {
         let descriptor = match desc.view_ty {
-            TextureViewType::RenderTarget => Some(self.descriptors.rtv_heap.lock().allocate(1)),
-            TextureViewType::DepthStencil => Some(self.descriptors.dsv_heap.lock().allocate(1)),
+            TextureViewType::RenderTarget => Some(self.descriptors.rtv_heap.lock().allocate(1)?),
+            TextureViewType::DepthStencil => Some(self.descriptors.dsv_heap.lock().allocate(1)?),
             _ => None,
         };
 
@@ -119,7 +161,7 @@ impl RenderResourceDevice for DxDevice {
             self.create_texture_view(descriptor.cpu, &texture.raw, &desc, &texture.desc);
         }
 
-        DxTexture {
+        Ok(DxTexture {
             raw: texture.raw.clone(),
             state: Arc::clone(&texture.state),
             desc: texture.desc.clone(),
@@ -138,7 +180,7 @@ impl RenderResourceDevice for DxDevice {
             descriptor,
             view: desc,
             _is_view: true,
-        }
+        })
     }
 
     fn open_texture(
@@ -146,9 +188,9 @@ impl RenderResourceDevice for DxDevice {
         texture: &Self::Texture,
         other_gpu: &Self,
         overrided_view: Option<TextureViewDesc>,
-    ) -> Self::Texture {
+    ) -> Result<Self::Texture, RenderError> {
         let heap = match &texture.flavor {
-            TextureFlavor::Local => panic!("Texture is local, can not open handle"),
+            TextureFlavor::Local => return Err(RenderError::NotShareable),
             TextureFlavor::CrossAdapter { heap } => heap,
             TextureFlavor::Binded { heap, .. } => heap,
         };
@@ -156,12 +198,12 @@ impl RenderResourceDevice for DxDevice {
         let handle = other_gpu
             .gpu
             .create_shared_handle(heap, None)
-            .expect("Failed to open handle");
+            .map_err(|e| other_gpu.map_error(e))?;
         let open_heap: dx::Heap = self
             .gpu
             .open_shared_handle(handle)
-            .expect("Failed to open heap");
-        handle.close().expect("Failed to close handle");
+            .map_err(|e| self.map_error(e))?;
+        handle.close().map_err(|e| self.map_error(e))?;
 
         self.create_shared_texture(
             texture
@@ -180,41 +222,45 @@ impl RenderResourceDevice for DxDevice {
         )
     }
 
-    fn create_sampler(&self, desc: SamplerDesc) -> Self::Sampler {
+    fn create_sampler(&self, desc: SamplerDesc) -> Result<Self::Sampler, RenderError> {
         let address = map_address_mode(desc.address_mode);
-        DxSampler {
+        Ok(DxSampler {
             desc: dx::SamplerDesc::new(map_filter(desc.filter))
                 .with_address_u(address)
                 .with_address_v(address)
                 .with_address_w(address),
-        }
+        })
     }
 
     fn destroy_sampler(&self, _sampler: Self::Sampler) {}
 
-    fn create_timestamp_query(&self, ty: CommandType, size: usize) -> Self::TimestampQuery {
+    fn create_timestamp_query(
+        &self,
+        ty: CommandType,
+        size: usize,
+    ) -> Result<Self::TimestampQuery, RenderError> {
         let raw = match ty {
             CommandType::Graphics | CommandType::Compute => self
                 .gpu
                 .create_query_heap(&dx::QueryHeapDesc::timestamp(2 * size as u32))
-                .expect("failed to create timestamp query"),
+                .map_err(|e| self.map_error(e))?,
             CommandType::Transfer => self
                 .gpu
                 .create_query_heap(&dx::QueryHeapDesc::copy_queue_timestamp(2 * size as u32))
-                .expect("failed to create timestamp query"),
+                .map_err(|e| self.map_error(e))?,
         };
 
         let buffer = self.create_buffer(BufferDesc::gpu_to_cpu(
             2 * size * size_of::<u64>(),
             BufferUsages::QueryResolve,
-        ));
+        ))?;
 
-        DxTimestampQuery {
+        Ok(DxTimestampQuery {
             raw,
             buffer,
             _size: size,
             cur_index: 0,
-        }
+        })
     }
 
     fn destroy_timestamp_query(&self, _query: Self::TimestampQuery) {}
@@ -267,6 +313,11 @@ pub struct DxTexture {
     pub(super) _is_view: bool,
 }
 
+#[derive(Debug)]
+pub struct DxMemory {
+    heap: dx::Heap,
+}
+
 #[derive(Debug)]
 pub enum TextureFlavor {
     Local,
@@ -281,7 +332,11 @@ pub enum TextureFlavor {
 }
 
 impl DxDevice {
-    fn create_local_texture(&self, desc: TextureDesc) -> DxTexture {
+    fn create_local_texture(
+        &self,
+        desc: TextureDesc,
+        heap: Option<&dx::Heap>,
+    ) -> Result<DxTexture, RenderError> {
         let raw_desc = map_texture_desc(&desc, self.desc.is_cross_adapter_texture_supported);
 
         let size = self.gpu.get_copyable_footprints(
@@ -305,25 +360,29 @@ impl DxDevice {
 
         let clear_color = desc.clear_color.map(|c| map_clear_color(desc.format, c));
 
-        let raw = self
-            .gpu
-            .create_committed_resource(
+        let raw = match heap {
+            Some(heap) => {
+                self.gpu
+                    .create_placed_resource(heap, 0, &raw_desc, state, clear_color.as_ref())
+            }
+            None => self.gpu.create_committed_resource(
                 &dx::HeapProperties::default(),
                 dx::HeapFlags::empty(),
                 &raw_desc,
                 state,
                 clear_color.as_ref(),
-            )
-            .expect("Failed to create buffer");
+            ),
+        }
+        .map_err(|e| self.map_error(e))?;
 
         let view = desc.to_default_view();
 
         let descriptor = match view.view_ty {
             TextureViewType::RenderTarget => {
-                Some(self.descriptors.allocate(dx::DescriptorHeapType::Rtv, 1))
+                Some(self.descriptors.allocate(dx::DescriptorHeapType::Rtv, 1)?)
             }
             TextureViewType::DepthStencil => {
-                Some(self.descriptors.allocate(dx::DescriptorHeapType::Dsv, 1))
+                Some(self.descriptors.allocate(dx::DescriptorHeapType::Dsv, 1)?)
             }
             _ => None,
         };
@@ -338,7 +397,7 @@ impl DxDevice {
                 .expect("failed to set debug object name");
         }
 
-        DxTexture {
+        Ok(DxTexture {
             raw,
             state: Arc::new(Mutex::new(state)),
             desc,
@@ -347,7 +406,7 @@ impl DxDevice {
             descriptor,
             view,
             _is_view: false,
-        }
+        })
     }
 
     fn create_shared_texture(
@@ -355,7 +414,7 @@ impl DxDevice {
         desc: TextureDesc,
         heap: dx::Heap,
         overrided_view: Option<TextureViewDesc>,
-    ) -> DxTexture {
+    ) -> Result<DxTexture, RenderError> {
         let raw_desc = map_texture_desc(&desc, self.desc.is_cross_adapter_texture_supported);
 
         let cross_adapter = raw_desc
@@ -380,16 +439,16 @@ impl DxDevice {
                     dx::ResourceStates::Common,
                     clear_color.as_ref(),
                 )
-                .expect("failed to create cross texture");
+                .map_err(|e| self.map_error(e))?;
 
             let view = overrided_view.unwrap_or_else(|| desc.to_default_view());
 
             let descriptor = match view.view_ty {
                 TextureViewType::RenderTarget => {
-                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Rtv, 1))
+                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Rtv, 1)?)
                 }
                 TextureViewType::DepthStencil => {
-                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Dsv, 1))
+                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Dsv, 1)?)
                 }
                 _ => None,
             };
@@ -406,7 +465,7 @@ impl DxDevice {
                     .expect("failed to set debug object name");
             }
 
-            DxTexture {
+            Ok(DxTexture {
                 raw: cross_res,
                 state: Arc::new(Mutex::new(dx::ResourceStates::Common)),
                 desc,
@@ -415,7 +474,7 @@ impl DxDevice {
                 descriptor,
                 view,
                 _is_view: false,
-            }
+            })
         } else {
             let clear_color = desc.clear_color.map(|c| map_clear_color(desc.format, c));
             let raw = self
@@ -427,16 +486,16 @@ impl DxDevice {
                     dx::ResourceStates::Common,
                     clear_color.as_ref(),
                 )
-                .expect("Failed to create buffer");
+                .map_err(|e| self.map_error(e))?;
 
             let view = overrided_view.unwrap_or_else(|| desc.to_default_view());
 
             let descriptor = match view.view_ty {
                 TextureViewType::RenderTarget => {
-                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Rtv, 1))
+                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Rtv, 1)?)
                 }
                 TextureViewType::DepthStencil => {
-                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Dsv, 1))
+                    Some(self.descriptors.allocate(dx::DescriptorHeapType::Dsv, 1)?)
                 }
                 _ => None,
             };
@@ -457,7 +516,7 @@ impl DxDevice {
             let cross_res = self
                 .gpu
                 .create_placed_resource(&heap, 0, &cross_desc, dx::ResourceStates::Common, None)
-                .expect("failed to create cross texture");
+                .map_err(|e| self.map_error(e))?;
 
             if let Some(name) = &desc.name {
                 let debug_name = CString::new(format!("{} Local", name).as_bytes())
@@ -472,7 +531,7 @@ impl DxDevice {
                     .expect("failed to set debug object name");
             }
 
-            DxTexture {
+            Ok(DxTexture {
                 raw,
                 state: Arc::new(Mutex::new(dx::ResourceStates::Common)),
                 desc,
@@ -485,7 +544,7 @@ impl DxDevice {
                 descriptor,
                 view,
                 _is_view: false,
-            }
+            })
         }
     }
 
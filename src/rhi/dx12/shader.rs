@@ -2,7 +2,10 @@ use oxidx::dx;
 use smallvec::SmallVec;
 
 use crate::rhi::{
-    dx12::conv::{map_cull_mode, map_depth_op, map_format, map_semantic, map_vertex_format},
+    dx12::conv::{
+        map_blend, map_cull_mode, map_depth_op, map_format, map_semantic, map_vertex_format,
+    },
+    error::RenderError,
     shader::{
         BindingType, PipelineLayoutDesc, RasterPipelineDesc, RenderShaderDevice,
         ShaderArgumentDesc, ShaderEntry,
@@ -20,7 +23,10 @@ impl RenderShaderDevice for DxDevice {
     type ShaderArgument = DxShaderArgument;
     type RasterPipeline = DxRasterPipeline;
 
-    fn create_pipeline_layout(&self, desc: PipelineLayoutDesc<'_>) -> Self::PipelineLayout {
+    fn create_pipeline_layout(
+        &self,
+        desc: PipelineLayoutDesc<'_>,
+    ) -> Result<Self::PipelineLayout, RenderError> {
         let mut ranges = SmallVec::<[_; 8]>::new();
         let mut sampler_ranges = SmallVec::<[_; 8]>::new();
         let mut dynamic_buffers = SmallVec::<[_; 4]>::new();
@@ -126,9 +132,9 @@ impl RenderShaderDevice for DxDevice {
         let raw = self
             .gpu
             .serialize_and_create_root_signature(&desc, dx::RootSignatureVersion::V1_0, 0)
-            .expect("failed to create pipeline layout");
+            .map_err(|e| self.map_error(e))?;
 
-        DxPipelineLayout { raw, offsets }
+        Ok(DxPipelineLayout { raw, offsets })
     }
 
     fn destroy_pipeline_layout(&self, _layout: Self::PipelineLayout) {}
@@ -140,7 +146,7 @@ impl RenderShaderDevice for DxDevice {
     >(
         &self,
         desc: ShaderArgumentDesc<'a, Self, V, S>,
-    ) -> Self::ShaderArgument {
+    ) -> Result<Self::ShaderArgument, RenderError> {
         let desc_views = desc.views.into_iter();
         let desc_samplers = desc.samplers.into_iter();
 
@@ -148,7 +154,7 @@ impl RenderShaderDevice for DxDevice {
             let size = self.descriptors.shader_heap.lock().inc_size;
             let views = self
                 .descriptors
-                .allocate(dx::DescriptorHeapType::CbvSrvUav, desc_views.size_hint().0);
+                .allocate(dx::DescriptorHeapType::CbvSrvUav, desc_views.size_hint().0)?;
 
             for (i, view) in desc_views.into_iter().enumerate() {
                 match view {
@@ -178,7 +184,7 @@ impl RenderShaderDevice for DxDevice {
             let size = self.descriptors.sampler_heap.lock().inc_size;
             let samplers = self
                 .descriptors
-                .allocate(dx::DescriptorHeapType::Sampler, desc_samplers.size_hint().0);
+                .allocate(dx::DescriptorHeapType::Sampler, desc_samplers.size_hint().0)?;
 
             for (i, sampler) in desc_samplers.into_iter().enumerate() {
                 self.gpu
@@ -192,11 +198,11 @@ impl RenderShaderDevice for DxDevice {
 
         let dynamic_address = desc.dynamic_buffer.map(|b| b.raw.get_gpu_virtual_address());
 
-        DxShaderArgument {
+        Ok(DxShaderArgument {
             views,
             samplers,
             dynamic_address,
-        }
+        })
     }
 
     fn destroy_shader_argument(&self, argument: Self::ShaderArgument) {
@@ -209,7 +215,10 @@ impl RenderShaderDevice for DxDevice {
         }
     }
 
-    fn create_raster_pipeline(&self, desc: RasterPipelineDesc<'_, Self>) -> Self::RasterPipeline {
+    fn create_raster_pipeline(
+        &self,
+        desc: RasterPipelineDesc<'_, Self>,
+    ) -> Result<Self::RasterPipeline, RenderError> {
         let input_element_desc = desc
             .input_elements
             .iter()
@@ -240,11 +249,8 @@ impl RenderShaderDevice for DxDevice {
         let raw_desc = dx::GraphicsPipelineDesc::new(&vs)
             .with_input_layout(&input_element_desc)
             .with_blend_desc(
-                dx::BlendDesc::default().with_render_targets(
-                    desc.render_targets
-                        .iter()
-                        .map(|_| dx::RenderTargetBlendDesc::default()),
-                ),
+                dx::BlendDesc::default()
+                    .with_render_targets(desc.render_targets.iter().map(|_| map_blend(desc.blend))),
             )
             .with_render_targets(desc.render_targets.iter().map(|f| map_format(*f)))
             .with_rasterizer_state(raster)
@@ -287,12 +293,12 @@ impl RenderShaderDevice for DxDevice {
         let raw = self
             .gpu
             .create_graphics_pipeline(&raw_desc)
-            .expect("failed to create pipeline");
+            .map_err(|e| self.map_error(e))?;
 
-        DxRasterPipeline {
+        Ok(DxRasterPipeline {
             raw,
             layout: desc.layout.cloned(),
-        }
+        })
     }
 
     fn destroy_raster_pipeline(&self, _pipeline: Self::RasterPipeline) {}
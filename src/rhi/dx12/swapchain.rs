@@ -6,6 +6,7 @@ use smallvec::SmallVec;
 use winit::raw_window_handle::RawWindowHandle;
 
 use crate::rhi::{
+    error::RenderError,
     resources::{TextureDesc, TextureType, TextureUsages, TextureViewDesc, TextureViewType},
     swapchain::{PresentMode, RenderSwapchainDevice, Surface, SwapchainDesc, SwapchainFrame},
     types::Format,
@@ -34,7 +35,7 @@ impl RenderSwapchainDevice for DxDevice {
         desc: SwapchainDesc,
         wnd: &RawWindowHandle,
         queue: &Self::Queue,
-    ) -> Self::Swapchain {
+    ) -> Result<Self::Swapchain, RenderError> {
         let width = desc.width;
         let height = desc.height;
 
@@ -54,7 +55,7 @@ impl RenderSwapchainDevice for DxDevice {
         let swapchain = self
             .factory
             .create_swapchain_for_hwnd(&*queue.queue.lock(), hwnd, &raw_desc, None, dx::OUTPUT_NONE)
-            .expect("failed to create swapchain");
+            .map_err(|e| self.map_error(e))?;
 
         let mut swapchain = Self::Swapchain {
             raw: swapchain.try_into().expect("failed to cast to Swapchain3"),
@@ -62,12 +63,12 @@ impl RenderSwapchainDevice for DxDevice {
             resources: SmallVec::new(),
             desc,
         };
-        self.resize(&mut swapchain, [width, height]);
+        self.resize(&mut swapchain, [width, height])?;
 
-        swapchain
+        Ok(swapchain)
     }
 
-    fn resize(&self, swapchain: &mut Self::Swapchain, extent: [u32; 2]) {
+    fn resize(&self, swapchain: &mut Self::Swapchain, extent: [u32; 2]) -> Result<(), RenderError> {
         {
             let resources = std::mem::take(&mut swapchain.resources);
 
@@ -89,15 +90,15 @@ impl RenderSwapchainDevice for DxDevice {
                 dx::Format::Unknown,
                 dx::SwapchainFlags::AllowTearing,
             )
-            .expect("Failed to resize swapchain");
+            .map_err(|e| self.map_error(e))?;
 
         for i in 0..swapchain.desc.frames {
             let res: dx::Resource = swapchain
                 .raw
                 .get_buffer(i as u32)
-                .expect("Failed to get swapchain buffer");
+                .map_err(|e| self.map_error(e))?;
 
-            let descriptor = self.descriptors.rtv_heap.lock().allocate(1);
+            let descriptor = self.descriptors.rtv_heap.lock().allocate(1)?;
             self.gpu
                 .create_render_target_view(Some(&res), None, descriptor.cpu);
             let descriptor = Some(descriptor);
@@ -126,6 +127,8 @@ impl RenderSwapchainDevice for DxDevice {
                 last_access: 0,
             });
         }
+
+        Ok(())
     }
 
     fn destroy_swapchain_image(&self, image: <Self::Swapchain as Surface>::Texture) {
@@ -0,0 +1,52 @@
+use std::{fmt, path::PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenderError {
+    HandleNotBound { kind: &'static str, index: u32 },
+    OutOfDescriptors { requested: usize },
+    ShaderCompile { path: PathBuf, log: String },
+    DeviceLost,
+    NotShareable,
+    DeviceNotFound { query: String },
+    NotEnoughDevices { required: usize, found: usize },
+    Io { path: PathBuf, message: String },
+    InvalidTrace(String),
+    Backend(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::HandleNotBound { kind, index } => {
+                write!(f, "{} handle {} is not bound", kind, index)
+            }
+            RenderError::OutOfDescriptors { requested } => write!(
+                f,
+                "out of memory in descriptor heap, requested {} descriptors",
+                requested
+            ),
+            RenderError::ShaderCompile { path, log } => {
+                write!(f, "failed to compile shader {}: {}", path.display(), log)
+            }
+            RenderError::DeviceLost => write!(f, "device lost"),
+            RenderError::NotShareable => {
+                write!(f, "texture is local, can not open handle")
+            }
+            RenderError::DeviceNotFound { query } => {
+                write!(f, "no adapter matches '{}'", query)
+            }
+            RenderError::NotEnoughDevices { required, found } => write!(
+                f,
+                "found {} suitable adapters, but {} are required (hardware adapters with cross-adapter texture support)",
+                found, required
+            ),
+            RenderError::Io { path, message } => {
+                write!(f, "failed to access {}: {}", path.display(), message)
+            }
+            RenderError::InvalidTrace(message) => write!(f, "invalid trace: {}", message),
+            RenderError::Backend(message) => write!(f, "backend error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
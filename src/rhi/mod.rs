@@ -1,9 +1,14 @@
 pub mod backend;
 pub mod command;
 pub mod device;
+pub mod error;
 pub mod resources;
 pub mod shader;
 pub mod swapchain;
 pub mod types;
 
+#[cfg(all(windows, feature = "dx12"))]
 pub mod dx12;
+pub mod null;
+pub mod recording;
+pub mod validation;
@@ -0,0 +1,140 @@
+use std::{path::Path, sync::Arc};
+
+use glam::Vec4;
+
+use tracing::info;
+
+use crate::rhi::{
+    backend::{Api, DeviceType, RenderDeviceId, RenderDeviceInfo},
+    error::RenderError,
+    shader::{CompiledShader, ShaderDesc},
+};
+
+use super::{
+    device::NullDevice,
+    raster::{PixelInput, ShaderLibrary, ShaderResources, VertexOutput, shader_key},
+};
+
+#[derive(Debug)]
+pub struct NullBackend {
+    adapter_infos: Vec<RenderDeviceInfo>,
+    shaders: Arc<ShaderLibrary>,
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        let adapter_infos = (0..2)
+            .map(|i| RenderDeviceInfo {
+                name: format!("Null Device {}", i),
+                id: i as RenderDeviceId,
+                is_cross_adapter_texture_supported: true,
+                is_uma: false,
+                ty: DeviceType::Discrete,
+                copy_timestamp_support: true,
+            })
+            .collect();
+
+        Self::with_adapters(adapter_infos)
+    }
+
+    pub fn with_adapters(adapter_infos: Vec<RenderDeviceInfo>) -> Self {
+        adapter_infos
+            .iter()
+            .for_each(|a| info!("Found null adapter: {:?}", a));
+
+        Self {
+            adapter_infos,
+            shaders: Default::default(),
+        }
+    }
+
+    pub fn with_vertex_shader(
+        self,
+        file: &str,
+        entry_point: &str,
+        shader: impl Fn(&ShaderResources<'_>, &[Vec4]) -> VertexOutput + Send + Sync + 'static,
+    ) -> Self {
+        self.with_vertex_shader_variant(file, entry_point, &[], shader)
+    }
+
+    pub fn with_pixel_shader(
+        self,
+        file: &str,
+        entry_point: &str,
+        shader: impl Fn(&ShaderResources<'_>, &PixelInput<'_>, &mut [Vec4]) -> bool
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.with_pixel_shader_variant(file, entry_point, &[], shader)
+    }
+
+    pub fn with_vertex_shader_variant(
+        mut self,
+        file: &str,
+        entry_point: &str,
+        defines: &[&str],
+        shader: impl Fn(&ShaderResources<'_>, &[Vec4]) -> VertexOutput + Send + Sync + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.shaders)
+            .vertex
+            .insert(shader_key(file, entry_point, defines), Arc::new(shader));
+        self
+    }
+
+    pub fn with_pixel_shader_variant(
+        mut self,
+        file: &str,
+        entry_point: &str,
+        defines: &[&str],
+        shader: impl Fn(&ShaderResources<'_>, &PixelInput<'_>, &mut [Vec4]) -> bool
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.shaders)
+            .pixel
+            .insert(shader_key(file, entry_point, defines), Arc::new(shader));
+        self
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Api for NullBackend {
+    type Device = NullDevice;
+
+    fn enumerate_devices(&self) -> impl Iterator<Item = &RenderDeviceInfo> + '_ {
+        self.adapter_infos.iter()
+    }
+
+    fn create_device(&self, index: RenderDeviceId) -> Self::Device {
+        NullDevice::new(self.adapter_infos[index].clone(), Arc::clone(&self.shaders))
+    }
+
+    fn compile_shader<P: AsRef<Path>>(
+        &self,
+        desc: &ShaderDesc<'_, P>,
+    ) -> Result<CompiledShader, RenderError> {
+        let file = desc
+            .path
+            .as_ref()
+            .file_name()
+            .map(|f| f.to_string_lossy())
+            .unwrap_or_default();
+        let defines = desc
+            .defines
+            .iter()
+            .map(|(name, _)| name.as_ref())
+            .collect::<Vec<_>>();
+
+        Ok(CompiledShader {
+            raw: shader_key(&file, &desc.entry_point, &defines),
+            ty: desc.ty,
+        })
+    }
+}
@@ -0,0 +1,591 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use glam::Vec4;
+use parking_lot::Mutex;
+
+use crate::rhi::{
+    command::{
+        Barrier, CommandType, GpuEvent, IoCommandBuffer, RenderCommandBuffer, RenderCommandDevice,
+        RenderCommandQueue, RenderEncoder, RenderResourceUploader, Subresource, SyncPoint,
+        TransferEncoder,
+    },
+    error::RenderError,
+    resources::{Buffer, RenderResourceDevice, TextureType, buffer_row_pitch},
+    types::{
+        ClearColor, GeomTopology, IndexType, Region, ResourceState, Scissor, Timings, Viewport,
+    },
+};
+
+use super::{
+    device::NullDevice,
+    raster::{BoundArgument, RasterState},
+    resources::{NullBuffer, NullTexture, TextureFlavor},
+    shader::{NullRasterPipeline, NullShaderArgument},
+};
+
+pub const SYNTHETIC_PASS_TIME: Duration = Duration::from_micros(100);
+
+impl RenderCommandDevice for NullDevice {
+    type ResourceUploader = NullResourceUploader;
+    type CommandQueue = NullCommandQueue;
+    type Event = NullFence;
+
+    fn create_command_queue(
+        &self,
+        ty: CommandType,
+        _capacity: Option<usize>,
+    ) -> Self::CommandQueue {
+        NullCommandQueue {
+            ty,
+            fence: self.create_event(false),
+            in_record: Default::default(),
+            pending: Default::default(),
+            labels: Default::default(),
+        }
+    }
+
+    fn create_resource_uploader(&self) -> Self::ResourceUploader {
+        NullResourceUploader {
+            queue: self.create_command_queue(CommandType::Graphics, None),
+        }
+    }
+
+    fn create_event(&self, shared: bool) -> Self::Event {
+        NullFence {
+            value: Default::default(),
+            completed: Default::default(),
+            shared,
+        }
+    }
+
+    fn open_event(&self, event: &Self::Event, _other_gpu: &Self) -> Self::Event {
+        NullFence {
+            value: Arc::clone(&event.value),
+            completed: Arc::clone(&event.completed),
+            shared: event.shared,
+        }
+    }
+
+    fn wait_idle(&self) {}
+}
+
+#[derive(Debug)]
+pub struct NullCommandQueue {
+    ty: CommandType,
+
+    fence: NullFence,
+
+    in_record: Mutex<Vec<NullCommandBuffer>>,
+    pending: Mutex<Vec<NullCommandBuffer>>,
+
+    labels: Mutex<Option<Vec<Cow<'static, str>>>>,
+}
+
+impl RenderCommandQueue for NullCommandQueue {
+    type Device = NullDevice;
+    type Event = NullFence;
+    type CommandBuffer = NullCommandBuffer;
+
+    fn ty(&self) -> CommandType {
+        self.ty
+    }
+
+    fn frequency(&self) -> f64 {
+        1_000_000_000.0
+    }
+
+    fn create_command_buffer(
+        &self,
+        _device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
+        if let Some(buffer) = self.in_record.lock().pop() {
+            return Ok(buffer);
+        };
+
+        Ok(NullCommandBuffer {
+            ty: self.ty,
+            labels: vec![],
+            previous: self.labels.lock().take(),
+        })
+    }
+
+    fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
+        self.in_record.lock().push(cmd_buffer);
+    }
+
+    fn commit(&self, cmd_buffer: Self::CommandBuffer) {
+        self.pending.lock().push(cmd_buffer);
+    }
+
+    fn submit(&self, _: &NullDevice) -> Result<SyncPoint, RenderError> {
+        let labels = self
+            .pending
+            .lock()
+            .drain(..)
+            .flat_map(|b| b.labels)
+            .collect::<Vec<_>>();
+
+        *self.labels.lock() = Some(labels);
+
+        self.signal_event(&self.fence)
+    }
+
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
+        let value = event.increment();
+        event.completed.fetch_max(value, Ordering::Relaxed);
+
+        Ok(value)
+    }
+
+    fn wait_event(&self, _event: &Self::Event) -> Result<(), RenderError> {
+        Ok(())
+    }
+
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.fence.wait(value).map(|_| ())
+    }
+
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        self.wait_on_cpu(self.fence.get_goal())
+    }
+
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        let value = self.signal_event(&self.fence)?;
+        self.wait_on_cpu(value)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.is_ready_for(self.fence.get_goal())
+    }
+
+    fn is_ready_for(&self, v: u64) -> bool {
+        self.fence.get_completed_value() >= v
+    }
+}
+
+#[derive(Debug)]
+pub struct NullCommandBuffer {
+    ty: CommandType,
+    labels: Vec<Cow<'static, str>>,
+    previous: Option<Vec<Cow<'static, str>>>,
+}
+
+impl RenderCommandBuffer for NullCommandBuffer {
+    type Device = NullDevice;
+    type RenderEncoder<'a> = NullRenderEncoder<'a>;
+    type TransferEncoder<'a> = NullTransferEncoder<'a>;
+
+    fn ty(&self) -> CommandType {
+        self.ty
+    }
+
+    fn begin(&mut self, _device: &Self::Device) -> Option<Timings> {
+        self.previous.take().map(|labels| {
+            let timings = labels
+                .into_iter()
+                .map(|label| (label, SYNTHETIC_PASS_TIME))
+                .collect::<Vec<_>>();
+
+            let total = SYNTHETIC_PASS_TIME * timings.len() as u32;
+
+            Timings { timings, total }
+        })
+    }
+
+    fn set_barriers<'a>(&self, barriers: impl IntoIterator<Item = Barrier<'a, NullDevice>>) {
+        for barrier in barriers {
+            match barrier {
+                Barrier::Buffer(buffer, state) => *buffer.state.lock() = state,
+                Barrier::Texture(texture, state, Subresource::Local(_)) => {
+                    *texture.state.lock() = state
+                }
+                Barrier::Texture(texture, state, Subresource::Shared) => {
+                    if let TextureFlavor::Binded { cross_state, .. } = &texture.flavor {
+                        *cross_state.lock() = state;
+                    }
+                }
+                Barrier::Alias(_) => {}
+            }
+        }
+    }
+
+    fn render<'a>(
+        &mut self,
+        label: Cow<'static, str>,
+        targets: impl IntoIterator<Item = &'a <Self::Device as RenderResourceDevice>::Texture>,
+        depth: Option<&<Self::Device as RenderResourceDevice>::Texture>,
+    ) -> Self::RenderEncoder<'_> {
+        self.labels.push(label);
+
+        let targets = targets.into_iter().map(|t| t.target()).collect();
+        let depth = depth.map(|t| t.target());
+
+        NullRenderEncoder {
+            _cmd: self,
+            state: RefCell::new(RasterState::new(targets, depth)),
+        }
+    }
+
+    fn transfer(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_> {
+        self.labels.push(label);
+
+        NullTransferEncoder { _cmd: self }
+    }
+
+    fn resolve_timestamp_data(&mut self) -> Range<usize> {
+        0..0
+    }
+}
+
+#[derive(Debug)]
+pub struct NullResourceUploader {
+    queue: NullCommandQueue,
+}
+
+impl RenderCommandQueue for NullResourceUploader {
+    type Device = NullDevice;
+    type Event = NullFence;
+    type CommandBuffer = NullIoCommandBuffer;
+
+    fn ty(&self) -> CommandType {
+        CommandType::Transfer
+    }
+
+    fn frequency(&self) -> f64 {
+        self.queue.frequency()
+    }
+
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
+        Ok(NullIoCommandBuffer {
+            buffer: self.queue.create_command_buffer(device)?,
+        })
+    }
+
+    fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
+        self.queue.enqueue(cmd_buffer.buffer);
+    }
+
+    fn commit(&self, cmd_buffer: Self::CommandBuffer) {
+        self.queue.commit(cmd_buffer.buffer);
+    }
+
+    fn submit(&self, device: &NullDevice) -> Result<SyncPoint, RenderError> {
+        self.queue.submit(device)
+    }
+
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
+        self.queue.signal_event(event)
+    }
+
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError> {
+        self.queue.wait_event(event)
+    }
+
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.queue.wait_on_cpu(value)
+    }
+
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        self.queue.wait_until_complete()
+    }
+
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        self.queue.wait_idle()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.queue.is_ready()
+    }
+
+    fn is_ready_for(&self, v: u64) -> bool {
+        self.queue.is_ready_for(v)
+    }
+}
+
+impl RenderResourceUploader for NullResourceUploader {
+    fn flush(&self, _device: &Self::Device) -> Result<(), RenderError> {
+        self.wait_idle()
+    }
+}
+
+#[derive(Debug)]
+pub struct NullIoCommandBuffer {
+    buffer: NullCommandBuffer,
+}
+
+impl IoCommandBuffer for NullIoCommandBuffer {
+    type Device = NullDevice;
+
+    fn load_to_buffer(
+        &mut self,
+        _device: &Self::Device,
+        buffer: &mut NullBuffer,
+        data: &'_ [u8],
+    ) -> Result<(), RenderError> {
+        let map = buffer.map_mut();
+        map.clone_from_slice(data);
+
+        Ok(())
+    }
+
+    fn load_to_texture(
+        &mut self,
+        _device: &Self::Device,
+        texture: &NullTexture,
+        data: &'_ [u8],
+    ) -> Result<(), RenderError> {
+        debug_assert_eq!(data.len(), texture.size);
+
+        texture.memory.lock()[..data.len()].copy_from_slice(data);
+
+        self.buffer.set_barriers([Barrier::Texture(
+            texture,
+            ResourceState::Shader,
+            Subresource::Local(None),
+        )]);
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct NullFence {
+    pub(super) value: Arc<AtomicU64>,
+    pub(super) completed: Arc<AtomicU64>,
+    pub(super) shared: bool,
+}
+
+impl GpuEvent for NullFence {
+    // Work completes on submit, a value that was never signaled would block
+    // forever.
+    fn wait(&self, value: SyncPoint) -> Result<bool, RenderError> {
+        if self.get_completed_value() < value {
+            return Err(RenderError::DeviceLost);
+        }
+
+        Ok(false)
+    }
+
+    fn increment(&self) -> SyncPoint {
+        self.value.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn get_completed_value(&self) -> SyncPoint {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    fn get_goal(&self) -> SyncPoint {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct NullRenderEncoder<'a> {
+    _cmd: &'a mut NullCommandBuffer,
+    state: RefCell<RasterState>,
+}
+
+impl RenderEncoder for NullRenderEncoder<'_> {
+    type Buffer = NullBuffer;
+    type Texture = NullTexture;
+    type RasterPipeline = NullRasterPipeline;
+    type ShaderArgument = NullShaderArgument;
+
+    fn clear_rt(&self, texture: &Self::Texture, color: Option<[f32; 4]>, region: Option<Region>) {
+        let color = match (color, &texture.desc.clear_color) {
+            (Some(color), _) => color,
+            (None, Some(ClearColor::Color(color))) => *color,
+            _ => [0.0; 4],
+        };
+
+        texture.target().clear(Vec4::from_array(color), region);
+    }
+
+    fn clear_depth(&self, texture: &Self::Texture, depth: Option<f32>, region: Option<Region>) {
+        let depth = match (depth, &texture.desc.clear_color) {
+            (Some(depth), _) => depth,
+            (None, Some(ClearColor::Depth(depth))) => *depth,
+            _ => 1.0,
+        };
+
+        texture.target().clear(Vec4::splat(depth), region);
+    }
+
+    fn set_viewport(&self, viewport: Viewport) {
+        self.state.borrow_mut().viewport = Some(viewport);
+    }
+
+    fn set_scissor(&self, scissor: Scissor) {
+        self.state.borrow_mut().scissor = Some(scissor);
+    }
+
+    fn set_topology(&self, topology: GeomTopology) {
+        self.state.borrow_mut().topology = topology;
+    }
+
+    fn set_raster_pipeline(&mut self, pipeline: &Self::RasterPipeline) {
+        self.state.get_mut().pipeline = Some(Arc::clone(&pipeline.state));
+    }
+
+    fn bind_shader_argument(
+        &self,
+        space: u32,
+        argument: &Self::ShaderArgument,
+        dynamic_offset: usize,
+    ) {
+        let mut state = self.state.borrow_mut();
+        let space = space as usize;
+
+        if state.arguments.len() <= space {
+            state.arguments.resize(space + 1, None);
+        }
+
+        state.arguments[space] = Some(BoundArgument {
+            argument: Arc::clone(&argument.state),
+            offset: dynamic_offset,
+        });
+    }
+
+    fn bind_vertex_buffer(&self, buffer: &Self::Buffer, slot: usize) {
+        let mut state = self.state.borrow_mut();
+
+        if state.vertex_buffers.len() <= slot {
+            state.vertex_buffers.resize(slot + 1, None);
+        }
+
+        state.vertex_buffers[slot] = Some((Arc::clone(&buffer.memory), buffer.desc.stride));
+    }
+
+    fn bind_index_buffer(&self, buffer: &Self::Buffer, ty: IndexType) {
+        self.state.borrow_mut().index_buffer = Some((Arc::clone(&buffer.memory), ty));
+    }
+
+    fn draw(&self, count: u32, start_vertex: u32) {
+        self.state
+            .borrow()
+            .draw(start_vertex..start_vertex + count, 0);
+    }
+
+    fn draw_indexed(&self, count: u32, start_index: u32, base_vertex: u32) {
+        self.draw_indexed_instanced(count, 1, start_index, base_vertex, 0);
+    }
+
+    // Like SV_InstanceID, the instance index seen by shaders does not include
+    // the start instance.
+    fn draw_indexed_instanced(
+        &self,
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_vertex: u32,
+        _start_instance: u32,
+    ) {
+        let state = self.state.borrow();
+        let indices = state.indices(count, start_index);
+
+        for instance in 0..instance_count {
+            state.draw(
+                indices.iter().map(|i| i.wrapping_add(base_vertex)),
+                instance,
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NullTransferEncoder<'a> {
+    _cmd: &'a mut NullCommandBuffer,
+}
+
+impl TransferEncoder for NullTransferEncoder<'_> {
+    type Buffer = NullBuffer;
+    type Texture = NullTexture;
+
+    fn pull_texture(&self, texture: &Self::Texture, region: Option<Region>) {
+        if let TextureFlavor::Binded { cross, .. } = &texture.flavor {
+            copy_region(&cross.lock(), &mut texture.memory.lock(), texture, region);
+        }
+    }
+
+    fn push_texture(&self, texture: &Self::Texture, region: Option<Region>) {
+        if let TextureFlavor::Binded { cross, .. } = &texture.flavor {
+            copy_region(&texture.memory.lock(), &mut cross.lock(), texture, region);
+        }
+    }
+
+    fn copy_texture(&self, src: &Self::Texture, dst: &Self::Texture, region: Option<Region>) {
+        if Arc::ptr_eq(&src.memory, &dst.memory) {
+            return;
+        }
+
+        copy_region(&src.memory.lock(), &mut dst.memory.lock(), dst, region);
+    }
+
+    fn copy_texture_to_buffer(&self, src: &Self::Texture, dst: &Self::Buffer) {
+        let target = src.target();
+        let row = target.extent[0] as usize * src.desc.format.bytes_per_pixel();
+        let pitch = buffer_row_pitch(&src.desc);
+
+        let memory = src.memory.lock();
+        for y in 0..target.extent[1] as usize {
+            let start = target.base + y * row;
+            dst.write(y * pitch, &memory[start..start + row]);
+        }
+    }
+}
+
+fn copy_region(src: &[u8], dst: &mut [u8], texture: &NullTexture, region: Option<Region>) {
+    let Some(region) = region else {
+        dst.copy_from_slice(src);
+        return;
+    };
+
+    // Regions only address the first subresource, array slices are copied whole.
+    assert_eq!(
+        texture.desc.ty,
+        TextureType::D2,
+        "region copies need a single layer texture"
+    );
+
+    let bpp = texture.desc.format.bytes_per_pixel();
+    let pitch = texture.desc.extent[0] as usize * bpp;
+
+    for y in region.y..region.y + region.h {
+        let start = y as usize * pitch + region.x as usize * bpp;
+        let end = start + region.w as usize * bpp;
+
+        dst[start..end].copy_from_slice(&src[start..end]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rhi::{backend::Api, null::backend::NullBackend};
+
+    use super::*;
+
+    #[test]
+    fn waiting_on_unsignaled_value_reports_device_lost() {
+        let device = NullBackend::new().create_device(0);
+        let queue = device.create_command_queue(CommandType::Graphics, None);
+
+        let sync_point = queue.submit(&device).expect("failed to submit");
+        assert_eq!(queue.wait_on_cpu(sync_point), Ok(()));
+        assert_eq!(
+            queue.wait_on_cpu(sync_point + 1),
+            Err(RenderError::DeviceLost)
+        );
+    }
+}
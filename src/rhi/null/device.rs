@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use tracing::info;
+
+use crate::rhi::backend::RenderDeviceInfo;
+
+use super::raster::ShaderLibrary;
+
+#[derive(Debug)]
+pub struct NullDevice {
+    pub(super) desc: RenderDeviceInfo,
+    pub(super) shaders: Arc<ShaderLibrary>,
+}
+
+impl NullDevice {
+    pub(super) fn new(desc: RenderDeviceInfo, shaders: Arc<ShaderLibrary>) -> Self {
+        info!(
+            "Creating null device with adapter {} and id {}",
+            desc.name, desc.id
+        );
+
+        Self { desc, shaders }
+    }
+}
@@ -0,0 +1,357 @@
+use std::path::Path;
+
+use glam::Vec4;
+use image::{DynamicImage, ImageBuffer, Luma, Rgba};
+
+use crate::rhi::types::Format;
+
+use super::resources::NullTexture;
+
+impl NullTexture {
+    pub fn texels(&self) -> Vec<Vec4> {
+        let target = self.target();
+        let [width, height] = target.extent;
+
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| [x, y]))
+            .map(|texel| target.load(texel))
+            .collect()
+    }
+
+    pub fn to_image(&self) -> DynamicImage {
+        let extent = [self.desc.extent[0], self.desc.extent[1]];
+
+        texels_to_image(self.desc.format, extent, &self.texels())
+    }
+
+    pub fn save_image(&self, path: impl AsRef<Path>) {
+        self.to_image()
+            .save(path)
+            .expect("failed to save texture image");
+    }
+}
+
+// Depth and single channel formats become 16 bit grayscale, so readbacks
+// through a buffer compare against the same references as null textures.
+pub fn texels_to_image(format: Format, extent: [u32; 2], texels: &[Vec4]) -> DynamicImage {
+    let [width, height] = extent.map(|e| e.max(1));
+
+    match format {
+        Format::R32 | Format::D32 | Format::D24S8 => {
+            DynamicImage::ImageLuma16(ImageBuffer::from_fn(width, height, |x, y| {
+                let texel = texels[(y * width + x) as usize];
+                Luma([(texel.x.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16])
+            }))
+        }
+        Format::Rgba8Unorm | Format::Rgba8 => {
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |x, y| {
+                let texel = texels[(y * width + x) as usize];
+                Rgba(
+                    (texel.clamp(Vec4::ZERO, Vec4::ONE) * 255.0)
+                        .round()
+                        .to_array()
+                        .map(|c| c as u8),
+                )
+            }))
+        }
+        _ => DynamicImage::ImageRgba16(ImageBuffer::from_fn(width, height, |x, y| {
+            let texel = texels[(y * width + x) as usize];
+            Rgba(
+                (texel.clamp(Vec4::ZERO, Vec4::ONE) * u16::MAX as f32)
+                    .round()
+                    .to_array()
+                    .map(|c| c as u16),
+            )
+        })),
+    }
+}
+
+pub fn image_difference(a: &DynamicImage, b: &DynamicImage) -> Option<f32> {
+    if a.width() != b.width() || a.height() != b.height() {
+        return None;
+    }
+
+    let a = a.to_rgba32f();
+    let b = b.to_rgba32f();
+
+    let diff = a
+        .pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| a.0.into_iter().zip(b.0).map(|(a, b)| (a - b).abs()))
+        .fold(0.0, f32::max);
+
+    Some(diff)
+}
+
+pub fn compare_with_reference(
+    texture: &NullTexture,
+    reference: impl AsRef<Path>,
+    tolerance: f32,
+) -> bool {
+    matches_reference(&texture.to_image(), reference, tolerance)
+}
+
+pub fn matches_reference(
+    image: &DynamicImage,
+    reference: impl AsRef<Path>,
+    tolerance: f32,
+) -> bool {
+    let reference = image::open(reference).expect("failed to open reference image");
+
+    image_difference(image, &reference).is_some_and(|diff| diff <= tolerance)
+}
+
+// Set FOTIA_UPDATE_GOLDEN to rewrite the references instead of comparing.
+#[cfg(test)]
+pub fn assert_golden(image: &DynamicImage, name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets/golden")
+        .join(format!("{}.png", name));
+
+    if std::env::var_os("FOTIA_UPDATE_GOLDEN").is_some() {
+        image.save(&path).expect("failed to save golden image");
+    }
+
+    assert!(
+        matches_reference(image, &path, 1.0 / 255.0),
+        "{} differs from its reference",
+        name
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rhi::{
+        backend::Api,
+        command::{
+            CommandType, RenderCommandBuffer, RenderCommandDevice, RenderCommandQueue,
+            RenderEncoder,
+        },
+        null::{
+            backend::NullBackend,
+            device::NullDevice,
+            raster::{PixelInput, ShaderResources, VertexOutput, shader_key},
+        },
+        resources::{
+            Buffer, BufferDesc, BufferUsages, RenderResourceDevice, TextureDesc, TextureUsages,
+        },
+        shader::{CompiledShader, RasterPipelineDesc, RenderShaderDevice},
+        types::{
+            BlendMode, CullMode, DepthOp, DepthStateDesc, InputElementDesc, ShaderType,
+            VertexAttribute, VertexType,
+        },
+    };
+
+    use super::*;
+
+    const EXTENT: [u32; 2] = [16, 16];
+
+    fn color_vs(_: &ShaderResources<'_>, input: &[Vec4]) -> VertexOutput {
+        VertexOutput::new(input[0].truncate().extend(1.0)).with_varyings(&input[1].to_array()[..3])
+    }
+
+    fn color_ps(_: &ShaderResources<'_>, input: &PixelInput<'_>, output: &mut [Vec4]) -> bool {
+        output[0] = Vec4::new(input.varyings[0], input.varyings[1], input.varyings[2], 1.0);
+        true
+    }
+
+    // Discards the left half of the target.
+    fn masked_ps(_: &ShaderResources<'_>, input: &PixelInput<'_>, _: &mut [Vec4]) -> bool {
+        input.position.x >= EXTENT[0] as f32 / 2.0
+    }
+
+    fn device() -> NullDevice {
+        NullBackend::new()
+            .with_vertex_shader("Golden.hlsl", "VSMain", color_vs)
+            .with_pixel_shader("Golden.hlsl", "PSMain", color_ps)
+            .with_pixel_shader("Golden.hlsl", "PSMasked", masked_ps)
+            .create_device(0)
+    }
+
+    fn shader(entry_point: &str, ty: ShaderType) -> CompiledShader {
+        CompiledShader {
+            raw: shader_key("Golden.hlsl", entry_point, &[]),
+            ty,
+        }
+    }
+
+    fn vertex_buffer(
+        device: &NullDevice,
+        data: &[[f32; 3]],
+    ) -> <NullDevice as RenderResourceDevice>::Buffer {
+        let mut buffer = device
+            .create_buffer(
+                BufferDesc::cpu_to_gpu(size_of_val(data), BufferUsages::Vertex).with_stride(12),
+            )
+            .expect("failed to create vertex buffer");
+        buffer.map_mut::<[f32; 3]>().copy_from_slice(data);
+
+        buffer
+    }
+
+    // A full screen red triangle at 0.5, a green one in front of it and a blue
+    // one behind it.
+    const POSITIONS: [[f32; 3]; 9] = [
+        [-1.0, -1.0, 0.5],
+        [3.0, -1.0, 0.5],
+        [-1.0, 3.0, 0.5],
+        [-0.5, -0.5, 0.25],
+        [0.5, -0.5, 0.25],
+        [0.0, 0.5, 0.25],
+        [-1.0, -1.0, 0.75],
+        [1.0, -1.0, 0.75],
+        [1.0, 1.0, 0.75],
+    ];
+
+    const COLORS: [[f32; 3]; 9] = [
+        [1.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0],
+    ];
+
+    fn render(ps: &str, with_color: bool) -> (NullTexture, NullTexture) {
+        let device = device();
+        let queue = device.create_command_queue(CommandType::Graphics, None);
+
+        let color = device
+            .create_texture(TextureDesc::new_2d(
+                EXTENT,
+                Format::Rgba8Unorm,
+                TextureUsages::RenderTarget,
+            ))
+            .expect("failed to create color target");
+        let depth = device
+            .create_texture(TextureDesc::new_2d(
+                EXTENT,
+                Format::D32,
+                TextureUsages::DepthTarget,
+            ))
+            .expect("failed to create depth target");
+
+        let vs = shader("VSMain", ShaderType::Vertex);
+        let ps = shader(ps, ShaderType::Pixel);
+        let render_targets = if with_color {
+            &[Format::Rgba8Unorm][..]
+        } else {
+            &[]
+        };
+        let pipeline = device
+            .create_raster_pipeline(RasterPipelineDesc {
+                layout: None,
+                input_elements: &[
+                    InputElementDesc {
+                        semantic: VertexAttribute::Position(0),
+                        format: VertexType::Float3,
+                    },
+                    InputElementDesc {
+                        semantic: VertexAttribute::Color(0),
+                        format: VertexType::Float3,
+                    },
+                ],
+                depth_bias: 0,
+                slope_bias: 0.0,
+                depth_clip: true,
+                depth: Some(DepthStateDesc {
+                    op: DepthOp::Less,
+                    format: Format::D32,
+                    read_only: false,
+                }),
+                render_targets,
+                cull_mode: CullMode::None,
+                blend: BlendMode::None,
+                vs: &vs,
+                shaders: &[&ps],
+            })
+            .expect("failed to create pipeline");
+
+        let positions = vertex_buffer(&device, &POSITIONS);
+        let colors = vertex_buffer(&device, &COLORS);
+
+        let mut cmd = queue
+            .create_command_buffer(&device)
+            .expect("failed to create command buffer");
+        cmd.begin(&device);
+        {
+            let targets = if with_color { vec![&color] } else { vec![] };
+            let mut encoder = cmd.render("Golden".into(), targets, Some(&depth));
+            encoder.clear_rt(&color, Some([0.0, 0.0, 0.0, 1.0]), None);
+            encoder.clear_depth(&depth, Some(1.0), None);
+            encoder.set_raster_pipeline(&pipeline);
+            encoder.bind_vertex_buffer(&positions, 0);
+            encoder.bind_vertex_buffer(&colors, 1);
+            encoder.draw(POSITIONS.len() as u32, 0);
+        }
+
+        (color, depth)
+    }
+
+    #[test]
+    fn depth_test_keeps_nearest_triangle() {
+        let (color, depth) = render("PSMain", true);
+        let texels = color.texels();
+
+        let [width, _] = EXTENT;
+        let at = |x: u32, y: u32| texels[(y * width + x) as usize];
+        assert_eq!(at(8, 8), Vec4::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(at(14, 14), Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(at(1, 1), Vec4::new(1.0, 0.0, 0.0, 1.0));
+
+        assert_golden(&color.to_image(), "depth_test_color");
+        assert_golden(&depth.to_image(), "depth_test_depth");
+    }
+
+    #[test]
+    fn discarded_fragments_leave_depth_untouched() {
+        let (_, depth) = render("PSMasked", false);
+        let texels = depth.texels();
+
+        let [width, height] = EXTENT;
+        for y in 0..height {
+            for x in 0..width {
+                let stored = texels[(y * width + x) as usize].x;
+
+                if x < width / 2 {
+                    assert_eq!(stored, 1.0, "texel {} {} was written", x, y);
+                } else {
+                    assert!(stored < 1.0, "texel {} {} was not written", x, y);
+                }
+            }
+        }
+
+        assert_golden(&depth.to_image(), "discard_depth");
+    }
+
+    #[test]
+    fn srgb_targets_store_encoded_colors() {
+        let device = device();
+        let queue = device.create_command_queue(CommandType::Graphics, None);
+        let texture = device
+            .create_texture(TextureDesc::new_2d(
+                [1, 1],
+                Format::Rgba8UnormSrgb,
+                TextureUsages::RenderTarget,
+            ))
+            .expect("failed to create srgb target");
+
+        let mut cmd = queue
+            .create_command_buffer(&device)
+            .expect("failed to create command buffer");
+        cmd.begin(&device);
+        cmd.render("Srgb".into(), vec![&texture], None).clear_rt(
+            &texture,
+            Some([0.5, 0.0, 1.0, 0.5]),
+            None,
+        );
+
+        assert_eq!(*texture.memory.lock(), [188, 0, 255, 128]);
+
+        let texel = texture.texels()[0];
+        assert!((texel - Vec4::new(0.5, 0.0, 1.0, 0.5)).abs().max_element() < 0.01);
+    }
+}
@@ -0,0 +1,8 @@
+pub mod backend;
+pub mod command;
+pub mod device;
+pub mod golden;
+pub mod raster;
+pub mod resources;
+pub mod shader;
+pub mod swapchain;
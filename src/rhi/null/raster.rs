@@ -0,0 +1,834 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use bytemuck::AnyBitPattern;
+use glam::{Vec2, Vec4};
+use parking_lot::{Mutex, MutexGuard};
+use smallvec::SmallVec;
+
+use crate::rhi::{
+    resources::TextureDesc,
+    types::{
+        BlendMode, CullMode, DepthOp, DepthStateDesc, Format, GeomTopology, IndexType, Region,
+        Scissor, VertexType, Viewport,
+    },
+};
+
+use super::resources::HeapMemory;
+
+pub type VertexShader =
+    Arc<dyn Fn(&ShaderResources<'_>, &[Vec4]) -> VertexOutput + Send + Sync + 'static>;
+// Returns false to discard the fragment.
+pub type PixelShader =
+    Arc<dyn Fn(&ShaderResources<'_>, &PixelInput<'_>, &mut [Vec4]) -> bool + Send + Sync + 'static>;
+
+type VertexBinding = (Arc<HeapMemory>, usize);
+type LockedTarget<'a> = (&'a TextureTarget, MutexGuard<'a, Vec<u8>>);
+type ClipPlane = fn(Vec4) -> f32;
+
+// Variants compiled with other defines are separate shaders, only the names
+// of the defines take part in the key.
+pub(super) fn shader_key(file: &str, entry_point: &str, defines: &[&str]) -> Vec<u8> {
+    let mut defines = defines.to_vec();
+    defines.sort_unstable();
+
+    let mut key = format!("{}:{}", file, entry_point);
+    for define in defines {
+        key.push(':');
+        key.push_str(define);
+    }
+
+    key.into_bytes()
+}
+
+#[derive(Clone, Default)]
+pub struct ShaderLibrary {
+    pub(super) vertex: HashMap<Vec<u8>, VertexShader>,
+    pub(super) pixel: HashMap<Vec<u8>, PixelShader>,
+}
+
+impl fmt::Debug for ShaderLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShaderLibrary")
+            .field("vertex", &self.vertex.len())
+            .field("pixel", &self.pixel.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VertexOutput {
+    pub position: Vec4,
+    pub varyings: SmallVec<[f32; 16]>,
+}
+
+impl VertexOutput {
+    pub fn new(position: Vec4) -> Self {
+        Self {
+            position,
+            varyings: SmallVec::new(),
+        }
+    }
+
+    pub fn with_varyings(mut self, varyings: &[f32]) -> Self {
+        self.varyings.extend_from_slice(varyings);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PixelInput<'a> {
+    pub position: Vec4,
+    pub varyings: &'a [f32],
+    pub front_face: bool,
+}
+
+#[derive(Debug)]
+pub(super) enum ArgumentView {
+    Cbv(Arc<HeapMemory>),
+    Texture(TextureTarget),
+}
+
+#[derive(Debug)]
+pub(super) struct ArgumentState {
+    pub(super) views: Vec<ArgumentView>,
+    pub(super) dynamic_buffer: Option<Arc<HeapMemory>>,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct BoundArgument {
+    pub(super) argument: Arc<ArgumentState>,
+    pub(super) offset: usize,
+}
+
+pub struct ShaderResources<'a> {
+    arguments: &'a [Option<BoundArgument>],
+    instance: u32,
+}
+
+impl ShaderResources<'_> {
+    pub fn instance(&self) -> u32 {
+        self.instance
+    }
+
+    fn argument(&self, set: u32) -> &BoundArgument {
+        self.arguments
+            .get(set as usize)
+            .and_then(|a| a.as_ref())
+            .unwrap_or_else(|| panic!("shader argument for set {} is not bound", set))
+    }
+
+    pub fn read<T: AnyBitPattern>(&self, set: u32, offset: usize) -> T {
+        let argument = self.argument(set);
+        let buffer = argument
+            .argument
+            .dynamic_buffer
+            .as_ref()
+            .expect("shader argument has no dynamic buffer");
+
+        let offset = argument.offset + offset;
+        bytemuck::pod_read_unaligned(&buffer.as_slice()[offset..offset + size_of::<T>()])
+    }
+
+    pub fn read_cbv<T: AnyBitPattern>(&self, set: u32, index: usize, offset: usize) -> T {
+        match &self.argument(set).argument.views[index] {
+            ArgumentView::Cbv(buffer) => {
+                bytemuck::pod_read_unaligned(&buffer.as_slice()[offset..offset + size_of::<T>()])
+            }
+            ArgumentView::Texture(_) => panic!("view {} in set {} is not a buffer", index, set),
+        }
+    }
+
+    pub fn load(&self, set: u32, index: usize, texel: [u32; 2]) -> Vec4 {
+        match &self.argument(set).argument.views[index] {
+            ArgumentView::Texture(texture) => texture.load(texel),
+            ArgumentView::Cbv(_) => panic!("view {} in set {} is not a texture", index, set),
+        }
+    }
+
+    // Point sampling with wrapping, the samplers of the pipeline layout are
+    // not emulated.
+    pub fn sample(&self, set: u32, index: usize, uv: Vec2) -> Vec4 {
+        match &self.argument(set).argument.views[index] {
+            ArgumentView::Texture(texture) => {
+                let [w, h] = texture.extent.map(|e| e as f32);
+                let x = (uv.x * w).floor().rem_euclid(w);
+                let y = (uv.y * h).floor().rem_euclid(h);
+
+                texture.load([x as u32, y as u32])
+            }
+            ArgumentView::Cbv(_) => panic!("view {} in set {} is not a texture", index, set),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct TextureTarget {
+    pub(super) memory: Arc<Mutex<Vec<u8>>>,
+    pub(super) format: Format,
+    pub(super) extent: [u32; 2],
+    pub(super) base: usize,
+}
+
+impl TextureTarget {
+    pub(super) fn new(memory: &Arc<Mutex<Vec<u8>>>, desc: &TextureDesc) -> Self {
+        Self {
+            memory: Arc::clone(memory),
+            format: desc.format,
+            extent: [desc.extent[0].max(1), desc.extent[1].max(1)],
+            base: 0,
+        }
+    }
+
+    pub(super) fn with_layer(mut self, layer: u32) -> Self {
+        self.base = layer as usize * self.layer_size();
+        self
+    }
+
+    fn layer_size(&self) -> usize {
+        (self.extent[0] * self.extent[1]) as usize * self.format.bytes_per_pixel()
+    }
+
+    fn offset(&self, [x, y]: [u32; 2]) -> usize {
+        self.base
+            + (y as usize * self.extent[0] as usize + x as usize) * self.format.bytes_per_pixel()
+    }
+
+    pub(super) fn load(&self, texel: [u32; 2]) -> Vec4 {
+        let texel = [
+            texel[0].min(self.extent[0] - 1),
+            texel[1].min(self.extent[1] - 1),
+        ];
+        let offset = self.offset(texel);
+
+        decode(self.format, &self.memory.lock()[offset..])
+    }
+
+    pub(super) fn clear(&self, value: Vec4, region: Option<Region>) {
+        let bpp = self.format.bytes_per_pixel();
+        if bpp == 0 {
+            return;
+        }
+
+        let mut texel = [0; 16];
+        encode(self.format, &mut texel[..bpp], value);
+
+        let region = region.unwrap_or(Region {
+            x: 0,
+            y: 0,
+            w: self.extent[0],
+            h: self.extent[1],
+        });
+        let x_end = (region.x + region.w).min(self.extent[0]);
+        let y_end = (region.y + region.h).min(self.extent[1]);
+        if region.x >= x_end {
+            return;
+        }
+
+        let mut memory = self.memory.lock();
+        for y in region.y..y_end {
+            let start = self.offset([region.x, y]);
+            let end = self.offset([x_end - 1, y]) + bpp;
+
+            for chunk in memory[start..end].chunks_exact_mut(bpp) {
+                chunk.copy_from_slice(&texel[..bpp]);
+            }
+        }
+    }
+}
+
+pub(super) fn decode(format: Format, bytes: &[u8]) -> Vec4 {
+    let float = |i: usize| f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+
+    match format {
+        Format::Unknown => Vec4::ZERO,
+        Format::Rgba8Unorm | Format::Rgba8 => {
+            Vec4::new(
+                bytes[0] as f32,
+                bytes[1] as f32,
+                bytes[2] as f32,
+                bytes[3] as f32,
+            ) / 255.0
+        }
+        Format::Rgba8UnormSrgb => {
+            let color = decode(Format::Rgba8Unorm, bytes);
+            color.truncate().map(srgb_to_linear).extend(color.w)
+        }
+        Format::R32 | Format::D32 => Vec4::new(float(0), 0.0, 0.0, 1.0),
+        Format::Rg32 => Vec4::new(float(0), float(1), 0.0, 1.0),
+        Format::Rgb32 => Vec4::new(float(0), float(1), float(2), 1.0),
+        Format::Rgba32 => Vec4::new(float(0), float(1), float(2), float(3)),
+        Format::D24S8 => {
+            let raw = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+            Vec4::new((raw & 0xFFFFFF) as f32 / 0xFFFFFF as f32, 0.0, 0.0, 1.0)
+        }
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub(super) fn encode(format: Format, bytes: &mut [u8], value: Vec4) {
+    let mut float = |i: usize, v: f32| bytes[4 * i..4 * i + 4].copy_from_slice(&v.to_le_bytes());
+
+    match format {
+        Format::Unknown => {}
+        Format::Rgba8Unorm | Format::Rgba8 => {
+            let value = (value.clamp(Vec4::ZERO, Vec4::ONE) * 255.0).round();
+            bytes[..4].copy_from_slice(&value.to_array().map(|c| c as u8));
+        }
+        Format::Rgba8UnormSrgb => {
+            let color = value.clamp(Vec4::ZERO, Vec4::ONE);
+            encode(
+                Format::Rgba8Unorm,
+                bytes,
+                color.truncate().map(linear_to_srgb).extend(color.w),
+            );
+        }
+        Format::R32 | Format::D32 => float(0, value.x),
+        Format::Rg32 => {
+            float(0, value.x);
+            float(1, value.y);
+        }
+        Format::Rgb32 => {
+            float(0, value.x);
+            float(1, value.y);
+            float(2, value.z);
+        }
+        Format::Rgba32 => {
+            float(0, value.x);
+            float(1, value.y);
+            float(2, value.z);
+            float(3, value.w);
+        }
+        Format::D24S8 => {
+            let depth = (value.x.clamp(0.0, 1.0) * 0xFFFFFF as f32).round() as u32;
+            let raw = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+            bytes[..4].copy_from_slice(&((raw & 0xFF000000) | depth).to_le_bytes());
+        }
+    }
+}
+
+pub(super) struct PipelineState {
+    pub(super) vs: Option<VertexShader>,
+    pub(super) ps: Option<PixelShader>,
+    pub(super) input_elements: Vec<VertexType>,
+    pub(super) cull_mode: CullMode,
+    pub(super) blend: BlendMode,
+    pub(super) depth: Option<DepthStateDesc>,
+    pub(super) depth_bias: i32,
+    pub(super) slope_bias: f32,
+    pub(super) depth_clip: bool,
+}
+
+impl fmt::Debug for PipelineState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PipelineState")
+            .field("vs", &self.vs.is_some())
+            .field("ps", &self.ps.is_some())
+            .field("input_elements", &self.input_elements)
+            .field("cull_mode", &self.cull_mode)
+            .field("blend", &self.blend)
+            .field("depth", &self.depth)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct RasterState {
+    pub(super) targets: SmallVec<[TextureTarget; 8]>,
+    pub(super) depth: Option<TextureTarget>,
+    pub(super) viewport: Option<Viewport>,
+    pub(super) scissor: Option<Scissor>,
+    pub(super) topology: GeomTopology,
+    pub(super) pipeline: Option<Arc<PipelineState>>,
+    pub(super) arguments: SmallVec<[Option<BoundArgument>; 4]>,
+    pub(super) vertex_buffers: SmallVec<[Option<VertexBinding>; 4]>,
+    pub(super) index_buffer: Option<(Arc<HeapMemory>, IndexType)>,
+}
+
+impl RasterState {
+    pub(super) fn new(targets: SmallVec<[TextureTarget; 8]>, depth: Option<TextureTarget>) -> Self {
+        Self {
+            targets,
+            depth,
+            viewport: None,
+            scissor: None,
+            topology: GeomTopology::Triangles,
+            pipeline: None,
+            arguments: SmallVec::new(),
+            vertex_buffers: SmallVec::new(),
+            index_buffer: None,
+        }
+    }
+
+    pub(super) fn draw(&self, indices: impl Iterator<Item = u32>, instance: u32) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+
+        let Some(vs) = &pipeline.vs else {
+            return;
+        };
+
+        let resources = ShaderResources {
+            arguments: &self.arguments,
+            instance,
+        };
+
+        let vertices = indices
+            .map(|index| vs(&resources, &self.fetch(pipeline, index)))
+            .collect::<Vec<_>>();
+
+        let mut raster = Rasterizer::new(self, pipeline, &resources);
+
+        match self.topology {
+            GeomTopology::Triangles => {
+                for triangle in vertices.chunks_exact(3) {
+                    raster.triangle(triangle);
+                }
+            }
+            GeomTopology::Lines => {
+                for line in vertices.chunks_exact(2) {
+                    raster.line(line);
+                }
+            }
+        }
+    }
+
+    pub(super) fn indices(&self, count: u32, start_index: u32) -> Vec<u32> {
+        let Some((buffer, ty)) = &self.index_buffer else {
+            return vec![];
+        };
+
+        let data = buffer.as_slice();
+        let range = start_index as usize..(start_index + count) as usize;
+
+        match ty {
+            IndexType::U16 => range
+                .map(|i| u16::from_le_bytes([data[2 * i], data[2 * i + 1]]) as u32)
+                .collect(),
+            IndexType::U32 => range
+                .map(|i| bytemuck::pod_read_unaligned(&data[4 * i..4 * i + 4]))
+                .collect(),
+        }
+    }
+
+    fn fetch(&self, pipeline: &PipelineState, index: u32) -> SmallVec<[Vec4; 8]> {
+        pipeline
+            .input_elements
+            .iter()
+            .enumerate()
+            .map(|(slot, ty)| {
+                let Some(Some((buffer, stride))) = self.vertex_buffers.get(slot) else {
+                    return Vec4::W;
+                };
+
+                let components = match ty {
+                    VertexType::Float => 1,
+                    VertexType::Float2 => 2,
+                    VertexType::Float3 => 3,
+                    VertexType::Float4 => 4,
+                };
+
+                let offset = index as usize * stride;
+                let data = &buffer.as_slice()[offset..offset + 4 * components];
+
+                let mut value = Vec4::W;
+                for c in 0..components {
+                    value[c] = bytemuck::pod_read_unaligned(&data[4 * c..4 * c + 4]);
+                }
+
+                value
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ClipVertex {
+    position: Vec4,
+    varyings: SmallVec<[f32; 16]>,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(other.position, t),
+            varyings: self
+                .varyings
+                .iter()
+                .zip(&other.varyings)
+                .map(|(a, b)| a + (b - a) * t)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ScreenVertex {
+    pos: Vec2,
+    z: f32,
+    inv_w: f32,
+}
+
+struct Rasterizer<'a> {
+    pipeline: &'a PipelineState,
+    resources: &'a ShaderResources<'a>,
+
+    targets: SmallVec<[LockedTarget<'a>; 8]>,
+    depth: Option<LockedTarget<'a>>,
+
+    viewport: Viewport,
+    bounds: [i32; 4],
+}
+
+impl<'a> Rasterizer<'a> {
+    fn new(
+        state: &'a RasterState,
+        pipeline: &'a PipelineState,
+        resources: &'a ShaderResources<'a>,
+    ) -> Self {
+        let targets = state
+            .targets
+            .iter()
+            .map(|t| (t, t.memory.lock()))
+            .collect::<SmallVec<[_; 8]>>();
+
+        let depth = state
+            .depth
+            .as_ref()
+            .filter(|_| pipeline.depth.is_some())
+            .map(|t| (t, t.memory.lock()));
+
+        let extent = state
+            .targets
+            .first()
+            .or(state.depth.as_ref())
+            .map(|t| t.extent)
+            .unwrap_or([0, 0]);
+
+        let viewport = state.viewport.unwrap_or(Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: extent[0] as f32,
+            h: extent[1] as f32,
+        });
+
+        let mut bounds = [
+            viewport.x.max(0.0) as i32,
+            viewport.y.max(0.0) as i32,
+            ((viewport.x + viewport.w).ceil() as i32).min(extent[0] as i32),
+            ((viewport.y + viewport.h).ceil() as i32).min(extent[1] as i32),
+        ];
+
+        if let Some(scissor) = &state.scissor {
+            bounds[0] = bounds[0].max(scissor.x);
+            bounds[1] = bounds[1].max(scissor.y);
+            bounds[2] = bounds[2].min(scissor.x + scissor.w as i32);
+            bounds[3] = bounds[3].min(scissor.y + scissor.h as i32);
+        }
+
+        Self {
+            pipeline,
+            resources,
+            targets,
+            depth,
+            viewport,
+            bounds,
+        }
+    }
+
+    fn to_screen(&self, vertex: &ClipVertex) -> ScreenVertex {
+        let inv_w = 1.0 / vertex.position.w;
+        let ndc = vertex.position.truncate() * inv_w;
+
+        ScreenVertex {
+            pos: Vec2::new(
+                self.viewport.x + (ndc.x + 1.0) * 0.5 * self.viewport.w,
+                self.viewport.y + (1.0 - ndc.y) * 0.5 * self.viewport.h,
+            ),
+            z: ndc.z,
+            inv_w,
+        }
+    }
+
+    fn clip(&self, vertices: &[VertexOutput]) -> SmallVec<[ClipVertex; 8]> {
+        let mut polygon = vertices
+            .iter()
+            .map(|v| ClipVertex {
+                position: v.position,
+                varyings: v.varyings.clone(),
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        let mut planes: SmallVec<[ClipPlane; 3]> = SmallVec::new();
+        planes.push(|p| p.w - 1e-5);
+        if self.pipeline.depth_clip {
+            planes.push(|p| p.z);
+            planes.push(|p| p.w - p.z);
+        }
+
+        for plane in planes {
+            if polygon.is_empty() {
+                break;
+            }
+
+            let mut clipped = SmallVec::new();
+            for i in 0..polygon.len() {
+                let a = &polygon[i];
+                let b = &polygon[(i + 1) % polygon.len()];
+                let (da, db) = (plane(a.position), plane(b.position));
+
+                if da >= 0.0 {
+                    clipped.push(a.clone());
+                }
+
+                if (da >= 0.0) != (db >= 0.0) {
+                    clipped.push(a.lerp(b, da / (da - db)));
+                }
+            }
+
+            polygon = clipped;
+        }
+
+        polygon
+    }
+
+    fn triangle(&mut self, vertices: &[VertexOutput]) {
+        let polygon = self.clip(vertices);
+
+        for i in 1..polygon.len().saturating_sub(1) {
+            self.clipped_triangle([&polygon[0], &polygon[i], &polygon[i + 1]]);
+        }
+    }
+
+    fn clipped_triangle(&mut self, vertices: [&ClipVertex; 3]) {
+        let screen = vertices.map(|v| self.to_screen(v));
+
+        let area = edge(screen[0].pos, screen[1].pos, screen[2].pos);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+
+        let front_face = area > 0.0;
+        match self.pipeline.cull_mode {
+            CullMode::Back if !front_face => return,
+            CullMode::Front if front_face => return,
+            _ => {}
+        }
+
+        let order = if front_face { [0, 1, 2] } else { [0, 2, 1] };
+        let [v0, v1, v2] = order.map(|i| screen[i]);
+        let varyings = order.map(|i| vertices[i].varyings.as_slice());
+        let area = area.abs();
+
+        let bias = self.depth_bias(&screen, area);
+
+        let min = v0.pos.min(v1.pos).min(v2.pos).floor();
+        let max = v0.pos.max(v1.pos).max(v2.pos).ceil();
+
+        let x0 = (min.x as i32).max(self.bounds[0]);
+        let y0 = (min.y as i32).max(self.bounds[1]);
+        let x1 = (max.x as i32).min(self.bounds[2]);
+        let y1 = (max.y as i32).min(self.bounds[3]);
+
+        let edges = [(v1.pos, v2.pos), (v2.pos, v0.pos), (v0.pos, v1.pos)];
+        let top_left = edges.map(|(a, b)| is_top_left(a, b));
+
+        let mut interpolated: SmallVec<[f32; 16]> = SmallVec::new();
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+                let w = edges.map(|(a, b)| edge(a, b, p));
+                let inside = (0..3).all(|i| w[i] > 0.0 || (w[i] == 0.0 && top_left[i]));
+                if !inside {
+                    continue;
+                }
+
+                let b = w.map(|w| w / area);
+                let z = b[0] * v0.z + b[1] * v1.z + b[2] * v2.z + bias;
+                let inv_w = b[0] * v0.inv_w + b[1] * v1.inv_w + b[2] * v2.inv_w;
+
+                interpolated.clear();
+                interpolated.extend((0..varyings[0].len()).map(|i| {
+                    (b[0] * varyings[0][i] * v0.inv_w
+                        + b[1] * varyings[1][i] * v1.inv_w
+                        + b[2] * varyings[2][i] * v2.inv_w)
+                        / inv_w
+                }));
+
+                self.fragment(
+                    [x as u32, y as u32],
+                    Vec4::new(p.x, p.y, z.clamp(0.0, 1.0), inv_w),
+                    &interpolated,
+                    front_face,
+                );
+            }
+        }
+    }
+
+    fn line(&mut self, vertices: &[VertexOutput]) {
+        let polygon = self.clip(vertices);
+        if polygon.len() < 2 {
+            return;
+        }
+
+        let a = self.to_screen(&polygon[0]);
+        let b = self.to_screen(&polygon[1]);
+
+        let steps = (b.pos - a.pos).abs().max_element().ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let p = a.pos.lerp(b.pos, t).floor();
+
+            if (p.x as i32) < self.bounds[0]
+                || (p.y as i32) < self.bounds[1]
+                || (p.x as i32) >= self.bounds[2]
+                || (p.y as i32) >= self.bounds[3]
+            {
+                continue;
+            }
+
+            let varyings = polygon[0]
+                .lerp(&polygon[1], t)
+                .varyings
+                .into_iter()
+                .collect::<SmallVec<[f32; 16]>>();
+
+            self.fragment(
+                [p.x as u32, p.y as u32],
+                Vec4::new(
+                    p.x + 0.5,
+                    p.y + 0.5,
+                    (a.z + (b.z - a.z) * t).clamp(0.0, 1.0),
+                    a.inv_w + (b.inv_w - a.inv_w) * t,
+                ),
+                &varyings,
+                true,
+            );
+        }
+    }
+
+    fn depth_bias(&self, screen: &[ScreenVertex; 3], area: f32) -> f32 {
+        let Some((target, _)) = &self.depth else {
+            return 0.0;
+        };
+
+        let [v0, v1, v2] = screen;
+        let dzdx =
+            ((v1.z - v0.z) * (v2.pos.y - v0.pos.y) - (v2.z - v0.z) * (v1.pos.y - v0.pos.y)) / area;
+        let dzdy =
+            ((v2.z - v0.z) * (v1.pos.x - v0.pos.x) - (v1.z - v0.z) * (v2.pos.x - v0.pos.x)) / area;
+        let slope = dzdx.abs().max(dzdy.abs());
+
+        let r = match target.format {
+            Format::D24S8 => 1.0 / (1 << 24) as f32,
+            _ => {
+                let max_z =
+                    v0.z.abs()
+                        .max(v1.z.abs())
+                        .max(v2.z.abs())
+                        .max(f32::MIN_POSITIVE);
+                2f32.powi(max_z.log2().floor() as i32 - 23)
+            }
+        };
+
+        self.pipeline.depth_bias as f32 * r + self.pipeline.slope_bias * slope
+    }
+
+    // Like a pixel shader with discard, the shader runs before the depth test
+    // and can reject the fragment, including in depth only passes.
+    fn fragment(&mut self, texel: [u32; 2], position: Vec4, varyings: &[f32], front_face: bool) {
+        let mut outputs: SmallVec<[Vec4; 8]> = SmallVec::from_elem(Vec4::ZERO, self.targets.len());
+
+        if let Some(ps) = &self.pipeline.ps {
+            let keep = ps(
+                self.resources,
+                &PixelInput {
+                    position,
+                    varyings,
+                    front_face,
+                },
+                &mut outputs,
+            );
+
+            if !keep {
+                return;
+            }
+        }
+
+        if let Some((target, memory)) = &mut self.depth {
+            let depth = self
+                .pipeline
+                .depth
+                .as_ref()
+                .expect("depth state is missing");
+            let offset = target.offset(texel);
+            let bpp = target.format.bytes_per_pixel();
+
+            let stored = decode(target.format, &memory[offset..offset + bpp]).x;
+            if !depth_test(depth.op, position.z, stored) {
+                return;
+            }
+
+            if !depth.read_only {
+                encode(
+                    target.format,
+                    &mut memory[offset..offset + bpp],
+                    Vec4::splat(position.z),
+                );
+            }
+        }
+
+        if self.pipeline.ps.is_none() {
+            return;
+        }
+
+        for ((target, memory), value) in self.targets.iter_mut().zip(outputs) {
+            let offset = target.offset(texel);
+            let bpp = target.format.bytes_per_pixel();
+            let texel = &mut memory[offset..offset + bpp];
+
+            let value = match self.pipeline.blend {
+                BlendMode::None => value,
+                BlendMode::Alpha => decode(target.format, texel).lerp(value, value.w),
+            };
+
+            encode(target.format, texel, value);
+        }
+    }
+}
+
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+fn is_top_left(a: Vec2, b: Vec2) -> bool {
+    let d = b - a;
+    (d.y == 0.0 && d.x > 0.0) || d.y < 0.0
+}
+
+fn depth_test(op: DepthOp, value: f32, stored: f32) -> bool {
+    match op {
+        DepthOp::None => true,
+        DepthOp::Less => value < stored,
+        DepthOp::Equal => value == stored,
+        DepthOp::LessEqual => value <= stored,
+        DepthOp::Greater => value > stored,
+    }
+}
@@ -0,0 +1,301 @@
+use std::{alloc::Layout, ptr::NonNull, sync::Arc};
+
+use parking_lot::Mutex;
+
+use crate::rhi::{
+    command::CommandType,
+    error::RenderError,
+    resources::{
+        Buffer, BufferDesc, QueryHeap, RenderResourceDevice, SamplerDesc, TextureDesc,
+        TextureUsages, TextureViewDesc, texture_bytes,
+    },
+    types::ResourceState,
+};
+
+use super::{device::NullDevice, raster::TextureTarget};
+
+const BUFFER_ALIGNMENT: usize = 256;
+
+impl RenderResourceDevice for NullDevice {
+    type Buffer = NullBuffer;
+    type Texture = NullTexture;
+    type Sampler = NullSampler;
+    type TimestampQuery = NullTimestampQuery;
+    type Memory = NullMemory;
+
+    fn create_buffer(&self, desc: BufferDesc) -> Result<Self::Buffer, RenderError> {
+        Ok(NullBuffer {
+            memory: Arc::new(HeapMemory::new(desc.size)),
+            state: Mutex::new(ResourceState::Common),
+            desc,
+        })
+    }
+
+    fn destroy_buffer(&self, _buffer: Self::Buffer) {}
+
+    fn create_texture(&self, desc: TextureDesc) -> Result<Self::Texture, RenderError> {
+        let size = texture_bytes(&desc);
+        let state = initial_state(&desc);
+
+        let flavor = if !desc.usage.contains(TextureUsages::Shared) {
+            TextureFlavor::Local
+        } else if self.desc.is_cross_adapter_texture_supported
+            && !desc.usage.contains(TextureUsages::DepthTarget)
+        {
+            TextureFlavor::CrossAdapter
+        } else {
+            TextureFlavor::Binded {
+                cross: Arc::new(Mutex::new(vec![0; size])),
+                cross_state: Mutex::new(ResourceState::Common),
+            }
+        };
+
+        Ok(NullTexture {
+            memory: Arc::new(Mutex::new(vec![0; size])),
+            state: Arc::new(Mutex::new(state)),
+            desc,
+            flavor,
+            size,
+            layer: 0,
+        })
+    }
+
+    fn destroy_texture(&self, _texture: Self::Texture) {}
+
+    fn create_memory(&self, descs: &[TextureDesc]) -> Result<Self::Memory, RenderError> {
+        let size = descs.iter().map(texture_bytes).max().unwrap_or_default();
+
+        Ok(NullMemory {
+            memory: Arc::new(Mutex::new(vec![0; size])),
+        })
+    }
+
+    fn destroy_memory(&self, _memory: Self::Memory) {}
+
+    fn create_placed_texture(
+        &self,
+        desc: TextureDesc,
+        memory: &Self::Memory,
+    ) -> Result<Self::Texture, RenderError> {
+        let size = texture_bytes(&desc);
+        if size > memory.memory.lock().len() {
+            return Err(RenderError::Backend(format!(
+                "texture of {} bytes does not fit into its memory",
+                size
+            )));
+        }
+
+        Ok(NullTexture {
+            memory: Arc::clone(&memory.memory),
+            state: Arc::new(Mutex::new(initial_state(&desc))),
+            desc,
+            flavor: TextureFlavor::Local,
+            size,
+            layer: 0,
+        })
+    }
+
+    fn create_texture_view(
+        &self,
+        texture: &Self::Texture,
+        desc: TextureViewDesc,
+    ) -> Result<Self::Texture, RenderError> {
+        Ok(NullTexture {
+            memory: Arc::clone(&texture.memory),
+            state: Arc::clone(&texture.state),
+            desc: texture.desc.clone(),
+            flavor: match &texture.flavor {
+                TextureFlavor::Local => TextureFlavor::Local,
+                TextureFlavor::CrossAdapter => TextureFlavor::CrossAdapter,
+                TextureFlavor::Binded { cross, .. } => TextureFlavor::Binded {
+                    cross: Arc::clone(cross),
+                    cross_state: Mutex::new(ResourceState::Common),
+                },
+            },
+            size: texture.size,
+            layer: texture.layer + desc.array.map_or(0, |array| array.start),
+        })
+    }
+
+    fn open_texture(
+        &self,
+        texture: &Self::Texture,
+        _other_gpu: &Self,
+        _overrided_view: Option<TextureViewDesc>,
+    ) -> Result<Self::Texture, RenderError> {
+        let memory = match &texture.flavor {
+            TextureFlavor::Local => return Err(RenderError::NotShareable),
+            TextureFlavor::CrossAdapter => &texture.memory,
+            TextureFlavor::Binded { cross, .. } => cross,
+        };
+
+        let desc = texture
+            .desc
+            .clone()
+            .with_name(std::borrow::Cow::Owned(format!(
+                "{} Opened",
+                texture
+                    .desc
+                    .name
+                    .as_ref()
+                    .unwrap_or(&std::borrow::Cow::Borrowed("Unnamed"))
+            )));
+
+        Ok(NullTexture {
+            memory: Arc::clone(memory),
+            state: Arc::new(Mutex::new(ResourceState::Common)),
+            desc,
+            flavor: TextureFlavor::CrossAdapter,
+            size: texture.size,
+            layer: 0,
+        })
+    }
+
+    fn create_sampler(&self, _desc: SamplerDesc) -> Result<Self::Sampler, RenderError> {
+        Ok(NullSampler)
+    }
+
+    fn destroy_sampler(&self, _sampler: Self::Sampler) {}
+
+    fn create_timestamp_query(
+        &self,
+        _ty: CommandType,
+        size: usize,
+    ) -> Result<Self::TimestampQuery, RenderError> {
+        Ok(NullTimestampQuery {
+            data: vec![0; 2 * size * size_of::<u64>()],
+        })
+    }
+
+    fn destroy_timestamp_query(&self, _query: Self::TimestampQuery) {}
+}
+
+#[derive(Debug)]
+pub struct NullBuffer {
+    pub(super) memory: Arc<HeapMemory>,
+    pub(super) desc: BufferDesc,
+    pub(super) state: Mutex<ResourceState>,
+}
+
+impl NullBuffer {
+    pub(super) fn write(&self, offset: usize, data: &[u8]) {
+        let end = (offset + data.len()).min(self.desc.size);
+        if offset >= end {
+            return;
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.memory.ptr.as_ptr().add(offset),
+                end - offset,
+            );
+        }
+    }
+}
+
+impl Buffer for NullBuffer {
+    fn map<T>(&self) -> &'_ [T] {
+        debug_assert!(align_of::<T>() <= BUFFER_ALIGNMENT);
+        let size = self.desc.size / size_of::<T>();
+
+        unsafe { std::slice::from_raw_parts(self.memory.ptr.as_ptr() as *const T, size) }
+    }
+
+    fn map_mut<T>(&mut self) -> &'_ mut [T] {
+        debug_assert!(align_of::<T>() <= BUFFER_ALIGNMENT);
+        let size = self.desc.size / size_of::<T>();
+
+        unsafe { std::slice::from_raw_parts_mut(self.memory.ptr.as_ptr() as *mut T, size) }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct HeapMemory {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+unsafe impl Send for HeapMemory {}
+unsafe impl Sync for HeapMemory {}
+
+impl HeapMemory {
+    fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size.max(1), BUFFER_ALIGNMENT)
+            .expect("failed to create buffer layout");
+
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+
+        Self { ptr, layout }
+    }
+
+    pub(super) fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Drop for HeapMemory {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+#[derive(Debug)]
+pub struct NullTexture {
+    pub(super) memory: Arc<Mutex<Vec<u8>>>,
+    pub(super) state: Arc<Mutex<ResourceState>>,
+    pub(super) desc: TextureDesc,
+    pub(super) flavor: TextureFlavor,
+
+    pub(super) size: usize,
+
+    pub(super) layer: u32,
+}
+
+impl NullTexture {
+    pub(super) fn target(&self) -> TextureTarget {
+        TextureTarget::new(&self.memory, &self.desc).with_layer(self.layer)
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureFlavor {
+    Local,
+    CrossAdapter,
+    Binded {
+        cross: Arc<Mutex<Vec<u8>>>,
+        cross_state: Mutex<ResourceState>,
+    },
+}
+
+#[derive(Debug)]
+pub struct NullMemory {
+    memory: Arc<Mutex<Vec<u8>>>,
+}
+
+#[derive(Debug)]
+pub struct NullSampler;
+
+#[derive(Debug)]
+pub struct NullTimestampQuery {
+    data: Vec<u8>,
+}
+
+impl QueryHeap for NullTimestampQuery {
+    fn read_buffer(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+fn initial_state(desc: &TextureDesc) -> ResourceState {
+    if desc.usage.contains(TextureUsages::RenderTarget) {
+        ResourceState::RenderTarget
+    } else if desc.usage.contains(TextureUsages::DepthTarget) {
+        ResourceState::DepthWrite
+    } else if desc.usage.contains(TextureUsages::Resource) {
+        ResourceState::Shader
+    } else {
+        ResourceState::Common
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use crate::rhi::{
+    error::RenderError,
+    shader::{
+        PipelineLayoutDesc, RasterPipelineDesc, RenderShaderDevice, ShaderArgumentDesc, ShaderEntry,
+    },
+};
+
+use super::{
+    device::NullDevice,
+    raster::{ArgumentState, ArgumentView, PipelineState},
+};
+
+impl RenderShaderDevice for NullDevice {
+    type PipelineLayout = NullPipelineLayout;
+    type ShaderArgument = NullShaderArgument;
+    type RasterPipeline = NullRasterPipeline;
+
+    fn create_pipeline_layout(
+        &self,
+        _desc: PipelineLayoutDesc<'_>,
+    ) -> Result<Self::PipelineLayout, RenderError> {
+        Ok(NullPipelineLayout)
+    }
+
+    fn destroy_pipeline_layout(&self, _layout: Self::PipelineLayout) {}
+
+    fn create_shader_argument<
+        'a,
+        V: IntoIterator<Item = ShaderEntry<'a, Self>>,
+        S: IntoIterator<Item = &'a Self::Sampler>,
+    >(
+        &self,
+        desc: ShaderArgumentDesc<'a, Self, V, S>,
+    ) -> Result<Self::ShaderArgument, RenderError> {
+        let views = desc
+            .views
+            .into_iter()
+            .map(|entry| match entry {
+                ShaderEntry::Cbv(buffer, _) => ArgumentView::Cbv(Arc::clone(&buffer.memory)),
+                ShaderEntry::Srv(texture) | ShaderEntry::Uav(texture) => {
+                    ArgumentView::Texture(texture.target())
+                }
+            })
+            .collect();
+
+        Ok(NullShaderArgument {
+            state: Arc::new(ArgumentState {
+                views,
+                dynamic_buffer: desc.dynamic_buffer.map(|b| Arc::clone(&b.memory)),
+            }),
+        })
+    }
+
+    fn destroy_shader_argument(&self, _argument: Self::ShaderArgument) {}
+
+    fn create_raster_pipeline(
+        &self,
+        desc: RasterPipelineDesc<'_, Self>,
+    ) -> Result<Self::RasterPipeline, RenderError> {
+        let vs = self.shaders.vertex.get(&desc.vs.raw).cloned();
+        let ps = desc
+            .shaders
+            .iter()
+            .find_map(|shader| self.shaders.pixel.get(&shader.raw).cloned());
+
+        Ok(NullRasterPipeline {
+            state: Arc::new(PipelineState {
+                vs,
+                ps,
+                input_elements: desc.input_elements.iter().map(|el| el.format).collect(),
+                cull_mode: desc.cull_mode,
+                blend: desc.blend,
+                depth: desc.depth,
+                depth_bias: desc.depth_bias,
+                slope_bias: desc.slope_bias,
+                depth_clip: desc.depth_clip,
+            }),
+        })
+    }
+
+    fn destroy_raster_pipeline(&self, _pipeline: Self::RasterPipeline) {}
+}
+
+#[derive(Debug)]
+pub struct NullPipelineLayout;
+
+#[derive(Debug)]
+pub struct NullShaderArgument {
+    pub(super) state: Arc<ArgumentState>,
+}
+
+#[derive(Debug)]
+pub struct NullRasterPipeline {
+    pub(super) state: Arc<PipelineState>,
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smallvec::SmallVec;
+use winit::raw_window_handle::RawWindowHandle;
+
+use crate::rhi::{
+    error::RenderError,
+    resources::{RenderResourceDevice, TextureDesc, TextureUsages},
+    swapchain::{RenderSwapchainDevice, Surface, SwapchainDesc, SwapchainFrame},
+    types::Format,
+};
+
+use super::{command::NullCommandQueue, device::NullDevice, resources::NullTexture};
+
+#[derive(Debug)]
+pub struct NullSwapchain {
+    resources: SmallVec<[SwapchainFrame<NullTexture>; 4]>,
+    current: AtomicUsize,
+    desc: SwapchainDesc,
+}
+
+impl RenderSwapchainDevice for NullDevice {
+    type Swapchain = NullSwapchain;
+    type Queue = NullCommandQueue;
+
+    fn create_swapchain(
+        &self,
+        desc: SwapchainDesc,
+        _wnd: &RawWindowHandle,
+        _queue: &Self::Queue,
+    ) -> Result<Self::Swapchain, RenderError> {
+        let width = desc.width;
+        let height = desc.height;
+
+        let mut swapchain = NullSwapchain {
+            resources: SmallVec::new(),
+            current: AtomicUsize::new(0),
+            desc,
+        };
+        self.resize(&mut swapchain, [width, height])?;
+
+        Ok(swapchain)
+    }
+
+    fn resize(&self, swapchain: &mut Self::Swapchain, extent: [u32; 2]) -> Result<(), RenderError> {
+        swapchain.resources.clear();
+        swapchain.current.store(0, Ordering::Relaxed);
+
+        for _ in 0..swapchain.desc.frames {
+            let texture = self.create_texture(TextureDesc::new_2d(
+                extent,
+                Format::Rgba8Unorm,
+                TextureUsages::RenderTarget,
+            ))?;
+
+            swapchain.resources.push(SwapchainFrame {
+                texture,
+                last_access: 0,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn destroy_swapchain_image(&self, image: <Self::Swapchain as Surface>::Texture) {
+        self.destroy_texture(image);
+    }
+
+    fn destroy_swapchain(&self, mut swapchain: Self::Swapchain) {
+        for frame in swapchain.drain_frames() {
+            self.destroy_texture(frame.texture);
+        }
+    }
+}
+
+impl Surface for NullSwapchain {
+    type Texture = NullTexture;
+
+    fn drain_frames(&mut self) -> impl Iterator<Item = SwapchainFrame<Self::Texture>> {
+        self.resources.drain(..)
+    }
+
+    fn next_frame_index(&mut self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    fn next_frame(&mut self) -> &mut SwapchainFrame<Self::Texture> {
+        let next_idx = self.current.load(Ordering::Relaxed);
+        &mut self.resources[next_idx]
+    }
+
+    fn present(&self) {
+        let next_idx = (self.current.load(Ordering::Relaxed) + 1) % self.resources.len().max(1);
+        self.current.store(next_idx, Ordering::Relaxed);
+    }
+}
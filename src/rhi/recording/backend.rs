@@ -0,0 +1,40 @@
+use std::{path::Path, sync::Arc};
+
+use crate::rhi::{
+    backend::{Api, RenderDeviceId, RenderDeviceInfo},
+    error::RenderError,
+    shader::{CompiledShader, ShaderDesc},
+};
+
+use super::{device::RecordingDevice, trace::TraceSink};
+
+#[derive(Debug)]
+pub struct RecordingBackend<A: Api> {
+    api: A,
+    sink: Arc<dyn TraceSink>,
+}
+
+impl<A: Api> RecordingBackend<A> {
+    pub fn new(api: A, sink: Arc<dyn TraceSink>) -> Self {
+        Self { api, sink }
+    }
+}
+
+impl<A: Api> Api for RecordingBackend<A> {
+    type Device = RecordingDevice<A::Device>;
+
+    fn enumerate_devices(&self) -> impl Iterator<Item = &RenderDeviceInfo> + '_ {
+        self.api.enumerate_devices()
+    }
+
+    fn create_device(&self, index: RenderDeviceId) -> Self::Device {
+        RecordingDevice::new(self.api.create_device(index), index, Arc::clone(&self.sink))
+    }
+
+    fn compile_shader<P: AsRef<Path>>(
+        &self,
+        desc: &ShaderDesc<'_, P>,
+    ) -> Result<CompiledShader, RenderError> {
+        self.api.compile_shader(desc)
+    }
+}
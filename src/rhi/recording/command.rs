@@ -0,0 +1,679 @@
+use std::{borrow::Cow, ops::Range, sync::Arc};
+
+use parking_lot::Mutex;
+use smallvec::SmallVec;
+
+use crate::{
+    ra::context::RenderDevice,
+    rhi::{
+        backend::RenderDeviceId,
+        command::{
+            Barrier, CommandType, GpuEvent, IoCommandBuffer, RenderCommandBuffer,
+            RenderCommandDevice, RenderCommandQueue, RenderEncoder, RenderResourceUploader,
+            SyncPoint, TransferEncoder,
+        },
+        error::RenderError,
+        resources::RenderResourceDevice,
+        types::{GeomTopology, IndexType, Region, Scissor, Timings, Viewport},
+    },
+};
+
+use super::{
+    device::RecordingDevice,
+    resources::Recorded,
+    trace::{EncoderCommand, TraceBarrier, TraceCommand, TraceId, TraceSink},
+};
+
+type CmdBuf<D> = <<D as RenderCommandDevice>::CommandQueue as RenderCommandQueue>::CommandBuffer;
+type IoCmdBuf<D> =
+    <<D as RenderCommandDevice>::ResourceUploader as RenderCommandQueue>::CommandBuffer;
+type UploaderEvent<D> = <<D as RenderCommandDevice>::ResourceUploader as RenderCommandQueue>::Event;
+
+impl<D: RenderDevice> RenderCommandDevice for RecordingDevice<D> {
+    type ResourceUploader = RecordingUploader<D>;
+    type CommandQueue = RecordingQueue<D>;
+    type Event = Recorded<D::Event>;
+
+    fn create_command_queue(&self, ty: CommandType, capacity: Option<usize>) -> Self::CommandQueue {
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateCommandQueue { queue: id, ty });
+
+        RecordingQueue {
+            inner: self.inner.create_command_queue(ty, capacity),
+            id,
+            device: self.id,
+            sink: Arc::clone(&self.sink),
+            in_record: Default::default(),
+        }
+    }
+
+    fn create_resource_uploader(&self) -> Self::ResourceUploader {
+        let inner = self.inner.create_resource_uploader();
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateCommandQueue {
+            queue: id,
+            ty: inner.ty(),
+        });
+
+        RecordingUploader {
+            inner,
+            id,
+            device: self.id,
+            sink: Arc::clone(&self.sink),
+            in_record: Default::default(),
+        }
+    }
+
+    fn create_event(&self, shared: bool) -> Self::Event {
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateEvent { id, shared });
+
+        Recorded::new(self.inner.create_event(shared), id)
+    }
+
+    fn open_event(&self, event: &Self::Event, other_gpu: &Self) -> Self::Event {
+        let id = self.sink.next_id();
+        self.record(TraceCommand::OpenEvent {
+            id,
+            event: event.id,
+        });
+
+        Recorded::new(self.inner.open_event(&event.inner, &other_gpu.inner), id)
+    }
+
+    fn wait_idle(&self) {
+        self.record(TraceCommand::DeviceWaitIdle);
+        self.inner.wait_idle();
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordingQueue<D: RenderDevice> {
+    pub(super) inner: D::CommandQueue,
+    id: TraceId,
+    device: RenderDeviceId,
+    sink: Arc<dyn TraceSink>,
+
+    in_record: Mutex<Vec<Vec<EncoderCommand>>>,
+}
+
+impl<D: RenderDevice> RecordingQueue<D> {
+    fn record(&self, command: TraceCommand) {
+        self.sink.record(self.device, command);
+    }
+}
+
+impl<D: RenderDevice> RenderCommandQueue for RecordingQueue<D> {
+    type Device = RecordingDevice<D>;
+    type Event = Recorded<D::Event>;
+    type CommandBuffer = RecordingCommandBuffer<D>;
+
+    fn ty(&self) -> CommandType {
+        self.inner.ty()
+    }
+
+    fn frequency(&self) -> f64 {
+        self.inner.frequency()
+    }
+
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
+        Ok(RecordingCommandBuffer {
+            inner: self.inner.create_command_buffer(&device.inner)?,
+            commands: Mutex::new(self.in_record.lock().pop().unwrap_or_default()),
+        })
+    }
+
+    fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
+        self.in_record.lock().push(cmd_buffer.commands.into_inner());
+        self.inner.enqueue(cmd_buffer.inner);
+    }
+
+    fn commit(&self, cmd_buffer: Self::CommandBuffer) {
+        self.record(TraceCommand::Commit {
+            queue: self.id,
+            commands: cmd_buffer.commands.into_inner(),
+        });
+        self.inner.commit(cmd_buffer.inner);
+    }
+
+    fn submit(&self, device: &Self::Device) -> Result<SyncPoint, RenderError> {
+        let sync_point = self.inner.submit(&device.inner)?;
+        self.record(TraceCommand::Submit {
+            queue: self.id,
+            sync_point,
+        });
+
+        Ok(sync_point)
+    }
+
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
+        let sync_point = self.inner.signal_event(&event.inner)?;
+        self.record(TraceCommand::SignalEvent {
+            queue: self.id,
+            event: event.id,
+            sync_point,
+        });
+
+        Ok(sync_point)
+    }
+
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError> {
+        self.record(TraceCommand::WaitEvent {
+            queue: self.id,
+            event: event.id,
+        });
+        self.inner.wait_event(&event.inner)
+    }
+
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.record(TraceCommand::WaitOnCpu {
+            queue: self.id,
+            sync_point: value,
+        });
+        self.inner.wait_on_cpu(value)?;
+        self.sink.complete(self.id, value);
+
+        Ok(())
+    }
+
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        self.inner.wait_until_complete()?;
+        self.sink.complete(self.id, SyncPoint::MAX);
+
+        Ok(())
+    }
+
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        self.record(TraceCommand::WaitIdle { queue: self.id });
+        self.inner.wait_idle()?;
+        self.sink.complete(self.id, SyncPoint::MAX);
+
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        let ready = self.inner.is_ready();
+        if ready {
+            self.sink.complete(self.id, SyncPoint::MAX);
+        }
+
+        ready
+    }
+
+    fn is_ready_for(&self, v: u64) -> bool {
+        let ready = self.inner.is_ready_for(v);
+        if ready {
+            self.sink.complete(self.id, v);
+        }
+
+        ready
+    }
+}
+
+pub struct RecordingCommandBuffer<D: RenderDevice> {
+    inner: CmdBuf<D>,
+    commands: Mutex<Vec<EncoderCommand>>,
+}
+
+impl<D: RenderDevice> RenderCommandBuffer for RecordingCommandBuffer<D> {
+    type Device = RecordingDevice<D>;
+    type RenderEncoder<'a>
+        = RecordingRenderEncoder<'a, D>
+    where
+        Self: 'a;
+    type TransferEncoder<'a>
+        = RecordingTransferEncoder<'a, D>
+    where
+        Self: 'a;
+
+    fn ty(&self) -> CommandType {
+        self.inner.ty()
+    }
+
+    fn begin(&mut self, device: &Self::Device) -> Option<Timings> {
+        self.commands.get_mut().push(EncoderCommand::Begin);
+        self.inner.begin(&device.inner)
+    }
+
+    fn set_barriers<'a>(&self, barriers: impl IntoIterator<Item = Barrier<'a, Self::Device>>) {
+        let barriers = barriers.into_iter().collect::<SmallVec<[_; 8]>>();
+
+        self.commands.lock().push(EncoderCommand::SetBarriers {
+            barriers: barriers
+                .iter()
+                .map(|barrier| match barrier {
+                    Barrier::Buffer(buffer, state) => TraceBarrier::Buffer {
+                        buffer: buffer.id,
+                        state: *state,
+                    },
+                    Barrier::Texture(texture, state, subresource) => TraceBarrier::Texture {
+                        texture: texture.id,
+                        state: *state,
+                        subresource: *subresource,
+                    },
+                    Barrier::Alias(texture) => TraceBarrier::Alias {
+                        texture: texture.id,
+                    },
+                })
+                .collect(),
+        });
+
+        self.inner
+            .set_barriers(barriers.into_iter().map(|barrier| match barrier {
+                Barrier::Buffer(buffer, state) => Barrier::Buffer(&buffer.inner, state),
+                Barrier::Texture(texture, state, subresource) => {
+                    Barrier::Texture(&texture.inner, state, subresource)
+                }
+                Barrier::Alias(texture) => Barrier::Alias(&texture.inner),
+            }));
+    }
+
+    fn render<'a>(
+        &mut self,
+        label: Cow<'static, str>,
+        targets: impl IntoIterator<Item = &'a <Self::Device as RenderResourceDevice>::Texture>,
+        depth: Option<&<Self::Device as RenderResourceDevice>::Texture>,
+    ) -> Self::RenderEncoder<'_> {
+        let targets = targets.into_iter().collect::<SmallVec<[_; 8]>>();
+
+        self.commands.get_mut().push(EncoderCommand::Render {
+            label: label.to_string(),
+            targets: targets.iter().map(|texture| texture.id).collect(),
+            depth: depth.map(|texture| texture.id),
+        });
+
+        RecordingRenderEncoder {
+            inner: self.inner.render(
+                label,
+                targets.into_iter().map(|texture| &texture.inner),
+                depth.map(|texture| &texture.inner),
+            ),
+            commands: &self.commands,
+        }
+    }
+
+    fn transfer(&mut self, label: Cow<'static, str>) -> Self::TransferEncoder<'_> {
+        self.commands.get_mut().push(EncoderCommand::Transfer {
+            label: label.to_string(),
+        });
+
+        RecordingTransferEncoder {
+            inner: self.inner.transfer(label),
+            commands: &self.commands,
+        }
+    }
+
+    fn resolve_timestamp_data(&mut self) -> Range<usize> {
+        self.inner.resolve_timestamp_data()
+    }
+}
+
+pub struct RecordingRenderEncoder<'a, D: RenderDevice + 'a> {
+    inner: <CmdBuf<D> as RenderCommandBuffer>::RenderEncoder<'a>,
+    commands: &'a Mutex<Vec<EncoderCommand>>,
+}
+
+impl<D: RenderDevice> RecordingRenderEncoder<'_, D> {
+    fn record(&self, command: EncoderCommand) {
+        self.commands.lock().push(command);
+    }
+}
+
+impl<D: RenderDevice> RenderEncoder for RecordingRenderEncoder<'_, D> {
+    type Buffer = Recorded<D::Buffer>;
+    type Texture = Recorded<D::Texture>;
+    type RasterPipeline = Recorded<D::RasterPipeline>;
+    type ShaderArgument = Recorded<D::ShaderArgument>;
+
+    fn clear_rt(&self, texture: &Self::Texture, color: Option<[f32; 4]>, region: Option<Region>) {
+        self.record(EncoderCommand::ClearRt {
+            texture: texture.id,
+            color,
+            region,
+        });
+        self.inner.clear_rt(&texture.inner, color, region);
+    }
+
+    fn clear_depth(&self, texture: &Self::Texture, depth: Option<f32>, region: Option<Region>) {
+        self.record(EncoderCommand::ClearDepth {
+            texture: texture.id,
+            depth,
+            region,
+        });
+        self.inner.clear_depth(&texture.inner, depth, region);
+    }
+
+    fn set_viewport(&self, viewport: Viewport) {
+        self.record(EncoderCommand::SetViewport(viewport));
+        self.inner.set_viewport(viewport);
+    }
+
+    fn set_scissor(&self, scissor: Scissor) {
+        self.record(EncoderCommand::SetScissor(scissor.clone()));
+        self.inner.set_scissor(scissor);
+    }
+
+    fn set_topology(&self, topology: GeomTopology) {
+        self.record(EncoderCommand::SetTopology(topology));
+        self.inner.set_topology(topology);
+    }
+
+    fn set_raster_pipeline(&mut self, pipeline: &Self::RasterPipeline) {
+        self.record(EncoderCommand::SetRasterPipeline {
+            pipeline: pipeline.id,
+        });
+        self.inner.set_raster_pipeline(&pipeline.inner);
+    }
+
+    fn bind_shader_argument(
+        &self,
+        set: u32,
+        argument: &Self::ShaderArgument,
+        dynamic_offset: usize,
+    ) {
+        self.record(EncoderCommand::BindShaderArgument {
+            set,
+            argument: argument.id,
+            dynamic_offset,
+        });
+        self.inner
+            .bind_shader_argument(set, &argument.inner, dynamic_offset);
+    }
+
+    fn bind_vertex_buffer(&self, buffer: &Self::Buffer, slot: usize) {
+        self.record(EncoderCommand::BindVertexBuffer {
+            buffer: buffer.id,
+            slot,
+        });
+        self.inner.bind_vertex_buffer(&buffer.inner, slot);
+    }
+
+    fn bind_index_buffer(&self, buffer: &Self::Buffer, ty: IndexType) {
+        self.record(EncoderCommand::BindIndexBuffer {
+            buffer: buffer.id,
+            ty: ty.clone(),
+        });
+        self.inner.bind_index_buffer(&buffer.inner, ty);
+    }
+
+    fn draw(&self, count: u32, start_vertex: u32) {
+        self.record(EncoderCommand::Draw {
+            count,
+            start_vertex,
+        });
+        self.inner.draw(count, start_vertex);
+    }
+
+    fn draw_indexed(&self, count: u32, start_index: u32, base_vertex: u32) {
+        self.record(EncoderCommand::DrawIndexed {
+            count,
+            start_index,
+            base_vertex,
+        });
+        self.inner.draw_indexed(count, start_index, base_vertex);
+    }
+
+    fn draw_indexed_instanced(
+        &self,
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_vertex: u32,
+        start_instance: u32,
+    ) {
+        self.record(EncoderCommand::DrawIndexedInstanced {
+            count,
+            instance_count,
+            start_index,
+            base_vertex,
+            start_instance,
+        });
+        self.inner.draw_indexed_instanced(
+            count,
+            instance_count,
+            start_index,
+            base_vertex,
+            start_instance,
+        );
+    }
+}
+
+pub struct RecordingTransferEncoder<'a, D: RenderDevice + 'a> {
+    inner: <CmdBuf<D> as RenderCommandBuffer>::TransferEncoder<'a>,
+    commands: &'a Mutex<Vec<EncoderCommand>>,
+}
+
+impl<D: RenderDevice> TransferEncoder for RecordingTransferEncoder<'_, D> {
+    type Buffer = Recorded<D::Buffer>;
+    type Texture = Recorded<D::Texture>;
+
+    fn pull_texture(&self, texture: &Self::Texture, region: Option<Region>) {
+        self.commands.lock().push(EncoderCommand::PullTexture {
+            texture: texture.id,
+            region,
+        });
+        self.inner.pull_texture(&texture.inner, region);
+    }
+
+    fn push_texture(&self, texture: &Self::Texture, region: Option<Region>) {
+        self.commands.lock().push(EncoderCommand::PushTexture {
+            texture: texture.id,
+            region,
+        });
+        self.inner.push_texture(&texture.inner, region);
+    }
+
+    fn copy_texture(&self, src: &Self::Texture, dst: &Self::Texture, region: Option<Region>) {
+        self.commands.lock().push(EncoderCommand::CopyTexture {
+            src: src.id,
+            dst: dst.id,
+            region,
+        });
+        self.inner.copy_texture(&src.inner, &dst.inner, region);
+    }
+
+    fn copy_texture_to_buffer(&self, src: &Self::Texture, dst: &Self::Buffer) {
+        self.commands
+            .lock()
+            .push(EncoderCommand::CopyTextureToBuffer {
+                texture: src.id,
+                buffer: dst.id,
+            });
+        self.inner.copy_texture_to_buffer(&src.inner, &dst.inner);
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordingUploader<D: RenderDevice> {
+    inner: D::ResourceUploader,
+    id: TraceId,
+    device: RenderDeviceId,
+    sink: Arc<dyn TraceSink>,
+
+    in_record: Mutex<Vec<Vec<EncoderCommand>>>,
+}
+
+impl<D: RenderDevice> RecordingUploader<D> {
+    fn record(&self, command: TraceCommand) {
+        self.sink.record(self.device, command);
+    }
+}
+
+impl<D: RenderDevice> RenderCommandQueue for RecordingUploader<D> {
+    type Device = RecordingDevice<D>;
+    type Event = Recorded<UploaderEvent<D>>;
+    type CommandBuffer = RecordingIoCommandBuffer<D>;
+
+    fn ty(&self) -> CommandType {
+        self.inner.ty()
+    }
+
+    fn frequency(&self) -> f64 {
+        self.inner.frequency()
+    }
+
+    fn create_command_buffer(
+        &self,
+        device: &Self::Device,
+    ) -> Result<Self::CommandBuffer, RenderError> {
+        Ok(RecordingIoCommandBuffer {
+            inner: self.inner.create_command_buffer(&device.inner)?,
+            commands: self.in_record.lock().pop().unwrap_or_default(),
+        })
+    }
+
+    fn enqueue(&self, cmd_buffer: Self::CommandBuffer) {
+        self.in_record.lock().push(cmd_buffer.commands);
+        self.inner.enqueue(cmd_buffer.inner);
+    }
+
+    fn commit(&self, cmd_buffer: Self::CommandBuffer) {
+        self.record(TraceCommand::Commit {
+            queue: self.id,
+            commands: cmd_buffer.commands,
+        });
+        self.inner.commit(cmd_buffer.inner);
+    }
+
+    fn submit(&self, device: &Self::Device) -> Result<SyncPoint, RenderError> {
+        let sync_point = self.inner.submit(&device.inner)?;
+        self.record(TraceCommand::Submit {
+            queue: self.id,
+            sync_point,
+        });
+
+        Ok(sync_point)
+    }
+
+    fn signal_event(&self, event: &Self::Event) -> Result<SyncPoint, RenderError> {
+        let sync_point = self.inner.signal_event(&event.inner)?;
+        self.record(TraceCommand::SignalEvent {
+            queue: self.id,
+            event: event.id,
+            sync_point,
+        });
+
+        Ok(sync_point)
+    }
+
+    fn wait_event(&self, event: &Self::Event) -> Result<(), RenderError> {
+        self.record(TraceCommand::WaitEvent {
+            queue: self.id,
+            event: event.id,
+        });
+        self.inner.wait_event(&event.inner)
+    }
+
+    fn wait_on_cpu(&self, value: SyncPoint) -> Result<(), RenderError> {
+        self.record(TraceCommand::WaitOnCpu {
+            queue: self.id,
+            sync_point: value,
+        });
+        self.inner.wait_on_cpu(value)?;
+        self.sink.complete(self.id, value);
+
+        Ok(())
+    }
+
+    fn wait_until_complete(&self) -> Result<(), RenderError> {
+        self.inner.wait_until_complete()?;
+        self.sink.complete(self.id, SyncPoint::MAX);
+
+        Ok(())
+    }
+
+    fn wait_idle(&self) -> Result<(), RenderError> {
+        self.record(TraceCommand::WaitIdle { queue: self.id });
+        self.inner.wait_idle()?;
+        self.sink.complete(self.id, SyncPoint::MAX);
+
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        let ready = self.inner.is_ready();
+        if ready {
+            self.sink.complete(self.id, SyncPoint::MAX);
+        }
+
+        ready
+    }
+
+    fn is_ready_for(&self, v: u64) -> bool {
+        let ready = self.inner.is_ready_for(v);
+        if ready {
+            self.sink.complete(self.id, v);
+        }
+
+        ready
+    }
+}
+
+impl<D: RenderDevice> RenderResourceUploader for RecordingUploader<D> {
+    fn flush(&self, device: &Self::Device) -> Result<(), RenderError> {
+        self.record(TraceCommand::Flush { queue: self.id });
+        self.inner.flush(&device.inner)?;
+        self.sink.complete(self.id, SyncPoint::MAX);
+
+        Ok(())
+    }
+}
+
+pub struct RecordingIoCommandBuffer<D: RenderDevice> {
+    inner: IoCmdBuf<D>,
+    commands: Vec<EncoderCommand>,
+}
+
+impl<D: RenderDevice> IoCommandBuffer for RecordingIoCommandBuffer<D> {
+    type Device = RecordingDevice<D>;
+
+    fn load_to_buffer(
+        &mut self,
+        device: &Self::Device,
+        buffer: &mut <Self::Device as RenderResourceDevice>::Buffer,
+        data: &'_ [u8],
+    ) -> Result<(), RenderError> {
+        self.commands.push(EncoderCommand::LoadToBuffer {
+            buffer: buffer.id,
+            size: data.len(),
+        });
+        self.inner
+            .load_to_buffer(&device.inner, &mut buffer.inner, data)
+    }
+
+    fn load_to_texture(
+        &mut self,
+        device: &Self::Device,
+        texture: &<Self::Device as RenderResourceDevice>::Texture,
+        data: &'_ [u8],
+    ) -> Result<(), RenderError> {
+        self.commands.push(EncoderCommand::LoadToTexture {
+            texture: texture.id,
+            size: data.len(),
+        });
+        self.inner
+            .load_to_texture(&device.inner, &texture.inner, data)
+    }
+}
+
+impl<T: GpuEvent> GpuEvent for Recorded<T> {
+    fn wait(&self, value: SyncPoint) -> Result<bool, RenderError> {
+        self.inner.wait(value)
+    }
+
+    fn increment(&self) -> SyncPoint {
+        self.inner.increment()
+    }
+
+    fn get_completed_value(&self) -> SyncPoint {
+        self.inner.get_completed_value()
+    }
+
+    fn get_goal(&self) -> SyncPoint {
+        self.inner.get_goal()
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use crate::rhi::backend::RenderDeviceId;
+
+use super::trace::{TraceCommand, TraceSink};
+
+#[derive(Debug)]
+pub struct RecordingDevice<D> {
+    pub(super) inner: D,
+    pub(super) id: RenderDeviceId,
+    pub(super) sink: Arc<dyn TraceSink>,
+}
+
+impl<D> RecordingDevice<D> {
+    pub fn new(inner: D, id: RenderDeviceId, sink: Arc<dyn TraceSink>) -> Self {
+        Self { inner, id, sink }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn sink(&self) -> &Arc<dyn TraceSink> {
+        &self.sink
+    }
+
+    pub(super) fn record(&self, command: TraceCommand) {
+        self.sink.record(self.id, command);
+    }
+}
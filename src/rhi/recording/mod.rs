@@ -0,0 +1,27 @@
+pub mod backend;
+pub mod command;
+pub mod device;
+pub mod resources;
+pub mod shader;
+pub mod swapchain;
+pub mod trace;
+
+#[cfg(test)]
+use std::sync::Arc;
+
+#[cfg(test)]
+use crate::{
+    ra::{backend::Backend, context::Context},
+    rhi::null::{backend::NullBackend, device::NullDevice},
+};
+
+// Single null device recording into `sink`, for tests that inspect the
+// commands a frame produced.
+#[cfg(test)]
+pub fn null_device(
+    sink: Arc<dyn trace::TraceSink>,
+) -> Arc<Context<device::RecordingDevice<NullDevice>>> {
+    let backend = Backend::new(backend::RecordingBackend::new(NullBackend::new(), sink));
+
+    Arc::clone(backend.create_group(&[0]).primary())
+}
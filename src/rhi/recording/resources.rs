@@ -0,0 +1,189 @@
+use crate::{
+    ra::context::RenderDevice,
+    rhi::{
+        command::CommandType,
+        error::RenderError,
+        resources::{
+            Buffer, BufferDesc, QueryHeap, RenderResourceDevice, SamplerDesc, TextureDesc,
+            TextureViewDesc,
+        },
+    },
+};
+
+use super::{
+    device::RecordingDevice,
+    trace::{TraceCommand, TraceId},
+};
+
+impl<D: RenderDevice> RenderResourceDevice for RecordingDevice<D> {
+    type Buffer = Recorded<D::Buffer>;
+    type Texture = Recorded<D::Texture>;
+    type Sampler = Recorded<D::Sampler>;
+    type TimestampQuery = D::TimestampQuery;
+    type Memory = Recorded<D::Memory>;
+
+    fn create_buffer(&self, desc: BufferDesc) -> Result<Self::Buffer, RenderError> {
+        let buffer = self.inner.create_buffer(desc.clone())?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateBuffer { id, desc });
+
+        Ok(Recorded::new(buffer, id))
+    }
+
+    fn destroy_buffer(&self, buffer: Self::Buffer) {
+        self.record(TraceCommand::DestroyBuffer { id: buffer.id });
+        self.inner.destroy_buffer(buffer.inner);
+    }
+
+    fn create_texture(&self, desc: TextureDesc) -> Result<Self::Texture, RenderError> {
+        let texture = self.inner.create_texture(desc.clone())?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateTexture { id, desc });
+
+        Ok(Recorded::new(texture, id))
+    }
+
+    fn destroy_texture(&self, texture: Self::Texture) {
+        self.record(TraceCommand::DestroyTexture { id: texture.id });
+        self.inner.destroy_texture(texture.inner);
+    }
+
+    fn create_memory(&self, descs: &[TextureDesc]) -> Result<Self::Memory, RenderError> {
+        let memory = self.inner.create_memory(descs)?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateMemory {
+            id,
+            descs: descs.to_vec(),
+        });
+
+        Ok(Recorded::new(memory, id))
+    }
+
+    fn destroy_memory(&self, memory: Self::Memory) {
+        self.record(TraceCommand::DestroyMemory { id: memory.id });
+        self.inner.destroy_memory(memory.inner);
+    }
+
+    fn create_placed_texture(
+        &self,
+        desc: TextureDesc,
+        memory: &Self::Memory,
+    ) -> Result<Self::Texture, RenderError> {
+        let texture = self
+            .inner
+            .create_placed_texture(desc.clone(), &memory.inner)?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreatePlacedTexture {
+            id,
+            memory: memory.id,
+            desc,
+        });
+
+        Ok(Recorded::new(texture, id))
+    }
+
+    fn create_texture_view(
+        &self,
+        texture: &Self::Texture,
+        desc: TextureViewDesc,
+    ) -> Result<Self::Texture, RenderError> {
+        let view = self
+            .inner
+            .create_texture_view(&texture.inner, desc.clone())?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateTextureView {
+            id,
+            texture: texture.id,
+            desc,
+        });
+
+        Ok(Recorded::new(view, id))
+    }
+
+    fn open_texture(
+        &self,
+        texture: &Self::Texture,
+        other_gpu: &Self,
+        overrided_view: Option<TextureViewDesc>,
+    ) -> Result<Self::Texture, RenderError> {
+        let opened =
+            self.inner
+                .open_texture(&texture.inner, &other_gpu.inner, overrided_view.clone())?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::OpenTexture {
+            id,
+            texture: texture.id,
+            view: overrided_view,
+        });
+
+        Ok(Recorded::new(opened, id))
+    }
+
+    fn create_sampler(&self, desc: SamplerDesc) -> Result<Self::Sampler, RenderError> {
+        let sampler = self.inner.create_sampler(desc.clone())?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateSampler { id, desc });
+
+        Ok(Recorded::new(sampler, id))
+    }
+
+    fn destroy_sampler(&self, sampler: Self::Sampler) {
+        self.record(TraceCommand::DestroySampler { id: sampler.id });
+        self.inner.destroy_sampler(sampler.inner);
+    }
+
+    fn create_timestamp_query(
+        &self,
+        ty: CommandType,
+        size: usize,
+    ) -> Result<Self::TimestampQuery, RenderError> {
+        self.inner.create_timestamp_query(ty, size)
+    }
+
+    fn destroy_timestamp_query(&self, query: Self::TimestampQuery) {
+        self.inner.destroy_timestamp_query(query);
+    }
+}
+
+#[derive(Debug)]
+pub struct Recorded<T> {
+    pub(super) inner: T,
+    pub(super) id: TraceId,
+}
+
+impl<T> Recorded<T> {
+    pub(super) fn new(inner: T, id: TraceId) -> Self {
+        Self { inner, id }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn id(&self) -> TraceId {
+        self.id
+    }
+}
+
+impl<T: Buffer> Buffer for Recorded<T> {
+    fn map<U>(&self) -> &'_ [U] {
+        self.inner.map()
+    }
+
+    fn map_mut<U>(&mut self) -> &'_ mut [U] {
+        self.inner.map_mut()
+    }
+}
+
+impl<T: QueryHeap> QueryHeap for Recorded<T> {
+    fn read_buffer(&self) -> &[u8] {
+        self.inner.read_buffer()
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    ra::context::RenderDevice,
+    rhi::{
+        error::RenderError,
+        shader::{
+            PipelineLayoutDesc, RasterPipelineDesc, RenderShaderDevice, ShaderArgumentDesc,
+            ShaderEntry,
+        },
+    },
+};
+
+use super::{
+    device::RecordingDevice,
+    resources::Recorded,
+    trace::{TraceBindingSet, TraceCommand, TraceShaderEntry},
+};
+
+impl<D: RenderDevice> RenderShaderDevice for RecordingDevice<D> {
+    type PipelineLayout = Recorded<D::PipelineLayout>;
+    type ShaderArgument = Recorded<D::ShaderArgument>;
+    type RasterPipeline = Recorded<D::RasterPipeline>;
+
+    fn create_pipeline_layout(
+        &self,
+        desc: PipelineLayoutDesc<'_>,
+    ) -> Result<Self::PipelineLayout, RenderError> {
+        let sets = desc
+            .sets
+            .iter()
+            .map(|set| TraceBindingSet {
+                entries: set.entries.to_vec(),
+                use_dynamic_buffer: set.use_dynamic_buffer,
+            })
+            .collect();
+
+        let layout = self.inner.create_pipeline_layout(desc)?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreatePipelineLayout { id, sets });
+
+        Ok(Recorded::new(layout, id))
+    }
+
+    fn destroy_pipeline_layout(&self, layout: Self::PipelineLayout) {
+        self.record(TraceCommand::DestroyPipelineLayout { id: layout.id });
+        self.inner.destroy_pipeline_layout(layout.inner);
+    }
+
+    fn create_shader_argument<
+        'a,
+        V: IntoIterator<Item = ShaderEntry<'a, Self>>,
+        S: IntoIterator<Item = &'a Self::Sampler>,
+    >(
+        &self,
+        desc: ShaderArgumentDesc<'a, Self, V, S>,
+    ) -> Result<Self::ShaderArgument, RenderError> {
+        let views = desc.views.into_iter().collect::<Vec<_>>();
+        let samplers = desc.samplers.into_iter().collect::<Vec<_>>();
+
+        let trace_views = views
+            .iter()
+            .map(|entry| match entry {
+                ShaderEntry::Cbv(buffer, size) => TraceShaderEntry::Cbv {
+                    buffer: buffer.id,
+                    size: *size,
+                },
+                ShaderEntry::Srv(texture) => TraceShaderEntry::Srv {
+                    texture: texture.id,
+                },
+                ShaderEntry::Uav(texture) => TraceShaderEntry::Uav {
+                    texture: texture.id,
+                },
+            })
+            .collect();
+        let trace_samplers = samplers.iter().map(|sampler| sampler.id).collect();
+
+        let argument = self.inner.create_shader_argument(ShaderArgumentDesc {
+            views: views.into_iter().map(|entry| match entry {
+                ShaderEntry::Cbv(buffer, size) => ShaderEntry::Cbv(&buffer.inner, size),
+                ShaderEntry::Srv(texture) => ShaderEntry::Srv(&texture.inner),
+                ShaderEntry::Uav(texture) => ShaderEntry::Uav(&texture.inner),
+            }),
+            samplers: samplers.into_iter().map(|sampler| &sampler.inner),
+            dynamic_buffer: desc.dynamic_buffer.map(|buffer| &buffer.inner),
+        })?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateShaderArgument {
+            id,
+            views: trace_views,
+            samplers: trace_samplers,
+            dynamic_buffer: desc.dynamic_buffer.map(|buffer| buffer.id),
+        });
+
+        Ok(Recorded::new(argument, id))
+    }
+
+    fn destroy_shader_argument(&self, argument: Self::ShaderArgument) {
+        self.record(TraceCommand::DestroyShaderArgument { id: argument.id });
+        self.inner.destroy_shader_argument(argument.inner);
+    }
+
+    fn create_raster_pipeline(
+        &self,
+        desc: RasterPipelineDesc<'_, Self>,
+    ) -> Result<Self::RasterPipeline, RenderError> {
+        let layout = desc.layout.map(|layout| layout.id);
+        let depth = desc.depth.clone();
+
+        let pipeline = self.inner.create_raster_pipeline(RasterPipelineDesc {
+            layout: desc.layout.map(|layout| &layout.inner),
+            input_elements: desc.input_elements,
+            depth_bias: desc.depth_bias,
+            slope_bias: desc.slope_bias,
+            depth_clip: desc.depth_clip,
+            depth: desc.depth,
+            render_targets: desc.render_targets,
+            cull_mode: desc.cull_mode,
+            blend: desc.blend,
+            vs: desc.vs,
+            shaders: desc.shaders,
+        })?;
+
+        let id = self.sink.next_id();
+        self.record(TraceCommand::CreateRasterPipeline {
+            id,
+            layout,
+            render_targets: desc.render_targets.to_vec(),
+            depth,
+            cull_mode: desc.cull_mode,
+            blend: desc.blend,
+        });
+
+        Ok(Recorded::new(pipeline, id))
+    }
+
+    fn destroy_raster_pipeline(&self, pipeline: Self::RasterPipeline) {
+        self.record(TraceCommand::DestroyRasterPipeline { id: pipeline.id });
+        self.inner.destroy_raster_pipeline(pipeline.inner);
+    }
+}
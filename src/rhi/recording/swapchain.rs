@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+use winit::raw_window_handle::RawWindowHandle;
+
+use crate::{
+    ra::context::RenderDevice,
+    rhi::{
+        backend::RenderDeviceId,
+        error::RenderError,
+        swapchain::{RenderSwapchainDevice, Surface, SwapchainDesc, SwapchainFrame},
+    },
+};
+
+use super::{
+    command::RecordingQueue,
+    device::RecordingDevice,
+    resources::Recorded,
+    trace::{TraceCommand, TraceId, TraceSink},
+};
+
+#[derive(Debug)]
+pub struct RecordingSwapchain<D: RenderDevice> {
+    inner: D::Swapchain,
+    id: TraceId,
+    frames: SmallVec<[SwapchainFrame<Recorded<D::Texture>>; 4]>,
+    device: RenderDeviceId,
+    sink: Arc<dyn TraceSink>,
+}
+
+impl<D: RenderDevice> RecordingDevice<D> {
+    fn wrap_frames(&self, swapchain: &mut RecordingSwapchain<D>) -> Vec<TraceId> {
+        let frames = swapchain
+            .inner
+            .drain_frames()
+            .map(|frame| SwapchainFrame {
+                texture: Recorded::new(frame.texture, self.sink.next_id()),
+                last_access: frame.last_access,
+            })
+            .collect::<SmallVec<[_; 4]>>();
+
+        swapchain.frames = frames;
+        swapchain
+            .frames
+            .iter()
+            .map(|frame| frame.texture.id)
+            .collect()
+    }
+}
+
+impl<D: RenderDevice> RenderSwapchainDevice for RecordingDevice<D> {
+    type Swapchain = RecordingSwapchain<D>;
+    type Queue = RecordingQueue<D>;
+
+    fn create_swapchain(
+        &self,
+        desc: SwapchainDesc,
+        wnd: &RawWindowHandle,
+        queue: &Self::Queue,
+    ) -> Result<Self::Swapchain, RenderError> {
+        let extent = [desc.width, desc.height];
+
+        let mut swapchain = RecordingSwapchain {
+            inner: self.inner.create_swapchain(desc, wnd, &queue.inner)?,
+            id: self.sink.next_id(),
+            frames: SmallVec::new(),
+            device: self.id,
+            sink: Arc::clone(&self.sink),
+        };
+
+        let images = self.wrap_frames(&mut swapchain);
+        self.record(TraceCommand::CreateSwapchain {
+            id: swapchain.id,
+            extent,
+            images,
+        });
+
+        Ok(swapchain)
+    }
+
+    fn resize(&self, swapchain: &mut Self::Swapchain, extent: [u32; 2]) -> Result<(), RenderError> {
+        for frame in swapchain.frames.drain(..) {
+            self.destroy_swapchain_image(frame.texture);
+        }
+
+        self.inner.resize(&mut swapchain.inner, extent)?;
+
+        let images = self.wrap_frames(swapchain);
+        self.record(TraceCommand::ResizeSwapchain {
+            id: swapchain.id,
+            extent,
+            images,
+        });
+
+        Ok(())
+    }
+
+    fn destroy_swapchain_image(&self, image: <Self::Swapchain as Surface>::Texture) {
+        self.record(TraceCommand::DestroyTexture { id: image.id });
+        self.inner.destroy_swapchain_image(image.inner);
+    }
+
+    fn destroy_swapchain(&self, mut swapchain: Self::Swapchain) {
+        for frame in swapchain.frames.drain(..) {
+            self.destroy_swapchain_image(frame.texture);
+        }
+
+        self.record(TraceCommand::DestroySwapchain { id: swapchain.id });
+        self.inner.destroy_swapchain(swapchain.inner);
+    }
+}
+
+impl<D: RenderDevice> Surface for RecordingSwapchain<D> {
+    type Texture = Recorded<D::Texture>;
+
+    fn drain_frames(&mut self) -> impl Iterator<Item = SwapchainFrame<Self::Texture>> {
+        self.frames.drain(..)
+    }
+
+    fn next_frame_index(&mut self) -> usize {
+        self.inner.next_frame_index()
+    }
+
+    fn next_frame(&mut self) -> &mut SwapchainFrame<Self::Texture> {
+        let index = self.inner.next_frame_index();
+        &mut self.frames[index]
+    }
+
+    fn present(&self) {
+        self.sink
+            .record(self.device, TraceCommand::Present { id: self.id });
+        self.inner.present();
+    }
+}
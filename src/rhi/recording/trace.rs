@@ -0,0 +1,573 @@
+use std::{
+    fmt::Debug,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::rhi::{
+    backend::RenderDeviceId,
+    command::{CommandType, Subresource, SyncPoint},
+    error::RenderError,
+    resources::{BufferDesc, SamplerDesc, TextureDesc, TextureViewDesc},
+    shader::BindingEntry,
+    types::{
+        BlendMode, CullMode, DepthStateDesc, Format, GeomTopology, IndexType, Region,
+        ResourceState, Scissor, Viewport,
+    },
+};
+
+pub type TraceId = u64;
+
+pub trait TraceSink: Debug + Send + Sync {
+    fn next_id(&self) -> TraceId;
+    fn record(&self, device: RenderDeviceId, command: TraceCommand);
+
+    fn complete(&self, _queue: TraceId, _sync_point: SyncPoint) {}
+}
+
+#[derive(Debug, Default)]
+pub struct Recorder {
+    next_id: AtomicU64,
+    frame: AtomicUsize,
+    entries: Mutex<Vec<TraceEntry>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn end_frame(&self) -> FrameTrace {
+        FrameTrace {
+            frame: self.frame.fetch_add(1, Ordering::Relaxed),
+            entries: std::mem::take(&mut *self.entries.lock()),
+        }
+    }
+}
+
+impl TraceSink for Recorder {
+    fn next_id(&self) -> TraceId {
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn record(&self, device: RenderDeviceId, command: TraceCommand) {
+        self.entries.lock().push(TraceEntry { device, command });
+    }
+}
+
+// Forwards every command to several sinks, so a recorder and a validator can
+// watch the same device. Ids are handed out here, the sinks' own counters are
+// never used.
+#[derive(Debug, Default)]
+pub struct TraceSinks {
+    next_id: AtomicU64,
+    sinks: Vec<Arc<dyn TraceSink>>,
+}
+
+impl TraceSinks {
+    pub fn new(sinks: impl IntoIterator<Item = Arc<dyn TraceSink>>) -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            sinks: sinks.into_iter().collect(),
+        }
+    }
+}
+
+impl TraceSink for TraceSinks {
+    fn next_id(&self) -> TraceId {
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn record(&self, device: RenderDeviceId, command: TraceCommand) {
+        if let Some((last, rest)) = self.sinks.split_last() {
+            for sink in rest {
+                sink.record(device, command.clone());
+            }
+
+            last.record(device, command);
+        }
+    }
+
+    fn complete(&self, queue: TraceId, sync_point: SyncPoint) {
+        for sink in &self.sinks {
+            sink.complete(queue, sync_point);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameTrace {
+    pub frame: usize,
+    pub entries: Vec<TraceEntry>,
+}
+
+impl FrameTrace {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("failed to serialize trace")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RenderError> {
+        serde_json::from_str(json).map_err(|err| RenderError::InvalidTrace(err.to_string()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RenderError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_json()).map_err(|err| io_error(path, err))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RenderError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|err| io_error(path, err))?;
+        Self::from_json(&json)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &EncoderCommand> + '_ {
+        self.entries.iter().flat_map(|e| match &e.command {
+            TraceCommand::Commit { commands, .. } => commands.as_slice(),
+            _ => &[],
+        })
+    }
+
+    // Render and transfer pass labels in commit order with the device they
+    // ran on, which is what stays comparable between graphs.
+    pub fn passes(&self) -> impl Iterator<Item = (RenderDeviceId, &str)> + '_ {
+        self.entries
+            .iter()
+            .flat_map(|e| {
+                let commands = match &e.command {
+                    TraceCommand::Commit { commands, .. } => commands.as_slice(),
+                    _ => &[],
+                };
+
+                commands.iter().map(move |command| (e.device, command))
+            })
+            .filter_map(|(device, command)| match command {
+                EncoderCommand::Render { label, .. } | EncoderCommand::Transfer { label } => {
+                    Some((device, label.as_str()))
+                }
+                _ => None,
+            })
+    }
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> RenderError {
+    RenderError::Io {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub device: RenderDeviceId,
+    pub command: TraceCommand,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TraceCommand {
+    CreateBuffer {
+        id: TraceId,
+        desc: BufferDesc,
+    },
+    DestroyBuffer {
+        id: TraceId,
+    },
+    CreateTexture {
+        id: TraceId,
+        desc: TextureDesc,
+    },
+    DestroyTexture {
+        id: TraceId,
+    },
+    CreateMemory {
+        id: TraceId,
+        descs: Vec<TextureDesc>,
+    },
+    DestroyMemory {
+        id: TraceId,
+    },
+    CreatePlacedTexture {
+        id: TraceId,
+        memory: TraceId,
+        desc: TextureDesc,
+    },
+    CreateTextureView {
+        id: TraceId,
+        texture: TraceId,
+        desc: TextureViewDesc,
+    },
+    OpenTexture {
+        id: TraceId,
+        texture: TraceId,
+        view: Option<TextureViewDesc>,
+    },
+    CreateSampler {
+        id: TraceId,
+        desc: SamplerDesc,
+    },
+    DestroySampler {
+        id: TraceId,
+    },
+    CreatePipelineLayout {
+        id: TraceId,
+        sets: Vec<TraceBindingSet>,
+    },
+    DestroyPipelineLayout {
+        id: TraceId,
+    },
+    CreateShaderArgument {
+        id: TraceId,
+        views: Vec<TraceShaderEntry>,
+        samplers: Vec<TraceId>,
+        dynamic_buffer: Option<TraceId>,
+    },
+    DestroyShaderArgument {
+        id: TraceId,
+    },
+    CreateRasterPipeline {
+        id: TraceId,
+        layout: Option<TraceId>,
+        render_targets: Vec<Format>,
+        depth: Option<DepthStateDesc>,
+        cull_mode: CullMode,
+        blend: BlendMode,
+    },
+    DestroyRasterPipeline {
+        id: TraceId,
+    },
+    CreateCommandQueue {
+        queue: TraceId,
+        ty: CommandType,
+    },
+    CreateEvent {
+        id: TraceId,
+        shared: bool,
+    },
+    OpenEvent {
+        id: TraceId,
+        event: TraceId,
+    },
+    Commit {
+        queue: TraceId,
+        commands: Vec<EncoderCommand>,
+    },
+    Submit {
+        queue: TraceId,
+        sync_point: SyncPoint,
+    },
+    Flush {
+        queue: TraceId,
+    },
+    SignalEvent {
+        queue: TraceId,
+        event: TraceId,
+        sync_point: SyncPoint,
+    },
+    WaitEvent {
+        queue: TraceId,
+        event: TraceId,
+    },
+    WaitOnCpu {
+        queue: TraceId,
+        sync_point: SyncPoint,
+    },
+    WaitIdle {
+        queue: TraceId,
+    },
+    DeviceWaitIdle,
+    CreateSwapchain {
+        id: TraceId,
+        extent: [u32; 2],
+        images: Vec<TraceId>,
+    },
+    ResizeSwapchain {
+        id: TraceId,
+        extent: [u32; 2],
+        images: Vec<TraceId>,
+    },
+    Present {
+        id: TraceId,
+    },
+    DestroySwapchain {
+        id: TraceId,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EncoderCommand {
+    Begin,
+    SetBarriers {
+        barriers: Vec<TraceBarrier>,
+    },
+    Render {
+        label: String,
+        targets: Vec<TraceId>,
+        depth: Option<TraceId>,
+    },
+    Transfer {
+        label: String,
+    },
+    ClearRt {
+        texture: TraceId,
+        color: Option<[f32; 4]>,
+        region: Option<Region>,
+    },
+    ClearDepth {
+        texture: TraceId,
+        depth: Option<f32>,
+        region: Option<Region>,
+    },
+    SetViewport(Viewport),
+    SetScissor(Scissor),
+    SetTopology(GeomTopology),
+    SetRasterPipeline {
+        pipeline: TraceId,
+    },
+    BindShaderArgument {
+        set: u32,
+        argument: TraceId,
+        dynamic_offset: usize,
+    },
+    BindVertexBuffer {
+        buffer: TraceId,
+        slot: usize,
+    },
+    BindIndexBuffer {
+        buffer: TraceId,
+        ty: IndexType,
+    },
+    Draw {
+        count: u32,
+        start_vertex: u32,
+    },
+    DrawIndexed {
+        count: u32,
+        start_index: u32,
+        base_vertex: u32,
+    },
+    DrawIndexedInstanced {
+        count: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_vertex: u32,
+        start_instance: u32,
+    },
+    PullTexture {
+        texture: TraceId,
+        region: Option<Region>,
+    },
+    PushTexture {
+        texture: TraceId,
+        region: Option<Region>,
+    },
+    CopyTexture {
+        src: TraceId,
+        dst: TraceId,
+        region: Option<Region>,
+    },
+    CopyTextureToBuffer {
+        texture: TraceId,
+        buffer: TraceId,
+    },
+    LoadToBuffer {
+        buffer: TraceId,
+        size: usize,
+    },
+    LoadToTexture {
+        texture: TraceId,
+        size: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TraceBarrier {
+    Buffer {
+        buffer: TraceId,
+        state: ResourceState,
+    },
+    Texture {
+        texture: TraceId,
+        state: ResourceState,
+        subresource: Subresource,
+    },
+    Alias {
+        texture: TraceId,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TraceShaderEntry {
+    Cbv { buffer: TraceId, size: usize },
+    Srv { texture: TraceId },
+    Uav { texture: TraceId },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceBindingSet {
+    pub entries: Vec<BindingEntry>,
+    pub use_dynamic_buffer: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        ra::{
+            command::{RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+            context::Context,
+            resources::RenderResourceContext,
+            system::RenderSystem,
+        },
+        rhi::{
+            null::device::NullDevice,
+            recording::{device::RecordingDevice, null_device},
+            resources::{TextureDesc, TextureUsages},
+            types::ClearColor,
+        },
+    };
+
+    use super::*;
+
+    const REGION: Region = Region {
+        x: 1,
+        y: 1,
+        w: 2,
+        h: 2,
+    };
+
+    fn clear_frame(rs: &RenderSystem, ctx: &Context<RecordingDevice<NullDevice>>) {
+        let texture = rs.create_texture_handle();
+        ctx.bind_texture(
+            texture,
+            TextureDesc::new_2d([4, 4], Format::Rgba8Unorm, TextureUsages::RenderTarget)
+                .with_color(ClearColor::Color([0.0; 4])),
+            None,
+        )
+        .expect("failed to bind texture");
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(ctx);
+        {
+            let mut encoder = cmd
+                .render("Clear".into(), &[texture], None)
+                .expect("failed to begin render pass");
+            encoder
+                .clear_rt(texture, Some([1.0, 0.0, 0.0, 1.0]))
+                .expect("failed to clear");
+            encoder
+                .clear_rt_region(texture, None, REGION)
+                .expect("failed to clear");
+        }
+        ctx.commit(cmd);
+        ctx.submit(CommandType::Graphics).expect("failed to submit");
+    }
+
+    #[test]
+    fn records_calls_in_order() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&recorder) as _);
+
+        clear_frame(&rs, &ctx);
+        let trace = recorder.end_frame();
+
+        let create = trace
+            .entries
+            .iter()
+            .position(|e| matches!(e.command, TraceCommand::CreateTexture { .. }))
+            .expect("missing texture creation");
+        let commit = trace
+            .entries
+            .iter()
+            .position(|e| matches!(e.command, TraceCommand::Commit { .. }))
+            .expect("missing commit");
+        let submit = trace
+            .entries
+            .iter()
+            .position(|e| matches!(e.command, TraceCommand::Submit { .. }))
+            .expect("missing submit");
+
+        assert!(create < commit && commit < submit);
+        assert_eq!(trace.passes().collect::<Vec<_>>(), [(0, "Clear")]);
+        assert!(trace.commands().any(|c| matches!(
+            c,
+            EncoderCommand::ClearRt {
+                color: Some([1.0, 0.0, 0.0, 1.0]),
+                region: None,
+                ..
+            }
+        )));
+        assert!(trace.commands().any(|c| matches!(
+            c,
+            EncoderCommand::ClearRt {
+                color: None,
+                region: Some(REGION),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn trace_round_trips_through_json() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&recorder) as _);
+
+        clear_frame(&rs, &ctx);
+        let trace = recorder.end_frame();
+
+        assert_eq!(FrameTrace::from_json(&trace.to_json()), Ok(trace));
+    }
+
+    #[test]
+    fn malformed_traces_are_rejected() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&recorder) as _);
+
+        clear_frame(&rs, &ctx);
+        let json = recorder.end_frame().to_json();
+
+        let truncated = &json[..json.len() / 2];
+        assert!(matches!(
+            FrameTrace::from_json(truncated),
+            Err(RenderError::InvalidTrace(_))
+        ));
+
+        let edited = json.replace("\"frame\"", "\"frames\"");
+        assert!(matches!(
+            FrameTrace::from_json(&edited),
+            Err(RenderError::InvalidTrace(_))
+        ));
+
+        let missing = std::env::temp_dir().join("fotia_missing_trace.json");
+        assert!(matches!(
+            FrameTrace::load(&missing),
+            Err(RenderError::Io { path, .. }) if path == missing
+        ));
+    }
+
+    #[test]
+    fn end_frame_starts_a_new_trace() {
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&recorder) as _);
+
+        clear_frame(&rs, &ctx);
+        let first = recorder.end_frame();
+        let second = recorder.end_frame();
+
+        assert_eq!(first.frame, 0);
+        assert_eq!(second.frame, 1);
+        assert!(second.entries.is_empty());
+    }
+}
@@ -1,7 +1,10 @@
 use std::{borrow::Cow, fmt::Debug, ops::Range};
 
+use serde::{Deserialize, Serialize};
+
 use super::{
     command::CommandType,
+    error::RenderError,
     types::{AddressMode, ClearColor, Filter, Format},
 };
 
@@ -19,37 +22,83 @@ pub trait RenderResourceDevice: Sized {
     type Texture: Send + Sync + Debug + 'static;
     type Sampler: Send + Sync + Debug + 'static;
     type TimestampQuery: QueryHeap + Send + Sync + Debug + 'static;
+    type Memory: Send + Sync + Debug + 'static;
 
-    fn create_buffer(&self, desc: BufferDesc) -> Self::Buffer;
+    fn create_buffer(&self, desc: BufferDesc) -> Result<Self::Buffer, RenderError>;
     fn destroy_buffer(&self, buffer: Self::Buffer);
 
-    fn create_texture(&self, desc: TextureDesc) -> Self::Texture;
+    fn create_texture(&self, desc: TextureDesc) -> Result<Self::Texture, RenderError>;
     fn destroy_texture(&self, texture: Self::Texture);
 
-    fn create_texture_view(&self, texture: &Self::Texture, desc: TextureViewDesc) -> Self::Texture;
+    // Memory large enough to hold any one of the given render or depth
+    // targets. Textures placed in the same memory alias each other, only the
+    // last one activated with an alias barrier holds defined contents.
+    fn create_memory(&self, descs: &[TextureDesc]) -> Result<Self::Memory, RenderError>;
+    fn destroy_memory(&self, memory: Self::Memory);
+    fn create_placed_texture(
+        &self,
+        desc: TextureDesc,
+        memory: &Self::Memory,
+    ) -> Result<Self::Texture, RenderError>;
+
+    fn create_texture_view(
+        &self,
+        texture: &Self::Texture,
+        desc: TextureViewDesc,
+    ) -> Result<Self::Texture, RenderError>;
 
     fn open_texture(
         &self,
         texture: &Self::Texture,
         other_gpu: &Self,
         overrided_view: Option<TextureViewDesc>,
-    ) -> Self::Texture;
+    ) -> Result<Self::Texture, RenderError>;
 
-    fn create_sampler(&self, desc: SamplerDesc) -> Self::Sampler;
+    fn create_sampler(&self, desc: SamplerDesc) -> Result<Self::Sampler, RenderError>;
     fn destroy_sampler(&self, sampler: Self::Sampler);
 
-    fn create_timestamp_query(&self, ty: CommandType, size: usize) -> Self::TimestampQuery;
+    fn create_timestamp_query(
+        &self,
+        ty: CommandType,
+        size: usize,
+    ) -> Result<Self::TimestampQuery, RenderError>;
     fn destroy_timestamp_query(&self, query: Self::TimestampQuery);
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub const TEXTURE_PITCH_ALIGNMENT: usize = 256;
+
+// Row pitch of a texture copied into a buffer, rows are padded to the
+// alignment required for placed footprints.
+pub fn buffer_row_pitch(desc: &TextureDesc) -> usize {
+    (desc.extent[0] as usize * desc.format.bytes_per_pixel())
+        .next_multiple_of(TEXTURE_PITCH_ALIGNMENT)
+}
+
+// Tightly packed size of all subresources of a texture.
+pub fn texture_bytes(desc: &TextureDesc) -> usize {
+    let [width, height, depth] = desc.extent.map(|e| e.max(1) as usize);
+
+    let (depth, layers) = match desc.ty {
+        TextureType::D1 | TextureType::D2 => (1, 1),
+        TextureType::D1Array | TextureType::D2Array => (1, depth),
+        TextureType::D3 => (depth, 1),
+    };
+
+    let texels = (0..desc.mip_levels.max(1) as usize)
+        .map(|mip| (width >> mip).max(1) * (height >> mip).max(1) * (depth >> mip).max(1))
+        .sum::<usize>();
+
+    texels * layers * desc.format.bytes_per_pixel()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MemoryLocation {
     CpuToGpu,
     GpuToGpu,
     GpuToCpu,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BufferDesc {
     pub name: Option<Cow<'static, str>>,
     pub size: usize,
@@ -101,7 +150,7 @@ impl BufferDesc {
 }
 
 bitflags::bitflags! {
-    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct BufferUsages: u32 {
         const Copy = 1 << 0;
         const Uniform = 1 << 1;
@@ -112,7 +161,7 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TextureDesc {
     pub name: Option<Cow<'static, str>>,
     pub ty: TextureType,
@@ -232,7 +281,7 @@ impl TextureDesc {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TextureType {
     D1,
     D1Array,
@@ -242,7 +291,7 @@ pub enum TextureType {
 }
 
 bitflags::bitflags! {
-    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct TextureUsages: u32 {
         const Copy = 1 << 0;
         const Resource = 1 << 1;
@@ -253,7 +302,7 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TextureViewType {
     RenderTarget,
     DepthStencil,
@@ -262,7 +311,7 @@ pub enum TextureViewType {
     Storage,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TextureViewDesc {
     pub view_ty: TextureViewType,
     pub format: Option<Format>,
@@ -298,7 +347,7 @@ impl TextureViewDesc {
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SamplerDesc {
     pub filter: Filter,
     pub address_mode: AddressMode,
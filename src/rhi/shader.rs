@@ -1,10 +1,13 @@
 use std::{borrow::Cow, fmt::Debug, path::Path};
 
+use serde::{Deserialize, Serialize};
+
 use super::{
+    error::RenderError,
     resources::RenderResourceDevice,
     types::{
-        AddressMode, ComparisonFunc, CullMode, DepthStateDesc, Filter, Format, InputElementDesc,
-        ShaderType,
+        AddressMode, BlendMode, ComparisonFunc, CullMode, DepthStateDesc, Filter, Format,
+        InputElementDesc, ShaderType,
     },
 };
 
@@ -20,7 +23,10 @@ pub trait RenderShaderDevice: RenderResourceDevice {
 
     type RasterPipeline: Send + Sync + Debug + 'static;
 
-    fn create_pipeline_layout(&self, desc: PipelineLayoutDesc<'_>) -> Self::PipelineLayout;
+    fn create_pipeline_layout(
+        &self,
+        desc: PipelineLayoutDesc<'_>,
+    ) -> Result<Self::PipelineLayout, RenderError>;
     fn destroy_pipeline_layout(&self, layout: Self::PipelineLayout);
 
     fn create_shader_argument<
@@ -30,15 +36,18 @@ pub trait RenderShaderDevice: RenderResourceDevice {
     >(
         &self,
         desc: ShaderArgumentDesc<'a, Self, V, S>,
-    ) -> Self::ShaderArgument;
+    ) -> Result<Self::ShaderArgument, RenderError>;
 
     fn destroy_shader_argument(&self, argument: Self::ShaderArgument);
 
-    fn create_raster_pipeline(&self, desc: RasterPipelineDesc<'_, Self>) -> Self::RasterPipeline;
+    fn create_raster_pipeline(
+        &self,
+        desc: RasterPipelineDesc<'_, Self>,
+    ) -> Result<Self::RasterPipeline, RenderError>;
     fn destroy_raster_pipeline(&self, pipeline: Self::RasterPipeline);
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BindingType {
     Cbv,
     Uav,
@@ -46,7 +55,7 @@ pub enum BindingType {
     Sampler,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BindingEntry {
     pub ty: BindingType,
     pub nums: u32,
@@ -120,6 +129,7 @@ pub struct RasterPipelineDesc<'a, D: RenderShaderDevice> {
     pub depth: Option<DepthStateDesc>,
     pub render_targets: &'a [Format],
     pub cull_mode: CullMode,
+    pub blend: BlendMode,
 
     pub vs: &'a CompiledShader,
     pub shaders: &'a [&'a CompiledShader],
@@ -1,6 +1,6 @@
 use winit::raw_window_handle::RawWindowHandle;
 
-use super::command::SyncPoint;
+use super::{command::SyncPoint, error::RenderError};
 
 #[derive(Clone, Debug)]
 pub enum PresentMode {
@@ -26,9 +26,9 @@ pub trait RenderSwapchainDevice {
         desc: SwapchainDesc,
         wnd: &RawWindowHandle,
         queue: &Self::Queue,
-    ) -> Self::Swapchain;
+    ) -> Result<Self::Swapchain, RenderError>;
 
-    fn resize(&self, swapchain: &mut Self::Swapchain, extent: [u32; 2]);
+    fn resize(&self, swapchain: &mut Self::Swapchain, extent: [u32; 2]) -> Result<(), RenderError>;
 
     fn destroy_swapchain_image(&self, image: <Self::Swapchain as Surface>::Texture);
 
@@ -2,11 +2,12 @@ use std::{borrow::Cow, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Format {
     Unknown,
 
     Rgba8Unorm,
+    Rgba8UnormSrgb,
 
     R32,
     Rg32,
@@ -24,6 +25,7 @@ impl Format {
         match self {
             Format::Unknown => 0,
             Format::Rgba8Unorm => 4,
+            Format::Rgba8UnormSrgb => 4,
             Format::R32 => 4,
             Format::Rg32 => 8,
             Format::Rgb32 => 12,
@@ -35,7 +37,7 @@ impl Format {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Filter {
     #[default]
     Point,
@@ -52,7 +54,7 @@ pub enum ComparisonFunc {
     GreaterEqual,
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AddressMode {
     #[default]
     Wrap,
@@ -60,14 +62,20 @@ pub enum AddressMode {
     Clamp,
 }
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CullMode {
     None,
     Back,
     Front,
 }
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    None,
+    Alpha,
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DepthOp {
     None,
     Less,
@@ -105,14 +113,14 @@ pub struct InputElementDesc {
     pub format: VertexType,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DepthStateDesc {
     pub op: DepthOp,
     pub format: Format,
     pub read_only: bool,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Viewport {
     pub x: f32,
     pub y: f32,
@@ -120,7 +128,7 @@ pub struct Viewport {
     pub h: f32,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Scissor {
     pub x: i32,
     pub y: i32,
@@ -128,13 +136,21 @@ pub struct Scissor {
     pub h: u32,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IndexType {
     U16,
     U32,
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ResourceState {
     Common,
     RenderTarget,
@@ -146,7 +162,7 @@ pub enum ResourceState {
     CopyDst,
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GeomTopology {
     Triangles,
     Lines,
@@ -158,7 +174,7 @@ pub struct Timings {
     pub total: Duration,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ClearColor {
     Color([f32; 4]),
     Depth(f32),
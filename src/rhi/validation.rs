@@ -0,0 +1,876 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use parking_lot::Mutex;
+use tracing::error;
+
+use super::{
+    backend::RenderDeviceId,
+    command::{Subresource, SyncPoint},
+    recording::trace::{
+        EncoderCommand, TraceBarrier, TraceCommand, TraceId, TraceShaderEntry, TraceSink,
+    },
+    resources::TextureUsages,
+    types::ResourceState,
+};
+
+#[derive(Debug, Default)]
+pub struct Validator {
+    next_id: AtomicU64,
+    state: Mutex<ValidationState>,
+    messages: Mutex<Vec<ValidationMessage>>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> Vec<ValidationMessage> {
+        self.messages.lock().clone()
+    }
+
+    pub fn take_messages(&self) -> Vec<ValidationMessage> {
+        std::mem::take(&mut *self.messages.lock())
+    }
+
+    fn report(&self, messages: Vec<ValidationMessage>) {
+        for message in &messages {
+            error!("[Validation] {}", message);
+        }
+
+        self.messages.lock().extend(messages);
+    }
+}
+
+impl TraceSink for Validator {
+    fn next_id(&self) -> TraceId {
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn record(&self, device: RenderDeviceId, command: TraceCommand) {
+        let messages = self.state.lock().apply(device, command);
+
+        if !messages.is_empty() {
+            self.report(messages);
+        }
+    }
+
+    fn complete(&self, queue: TraceId, sync_point: SyncPoint) {
+        self.state
+            .lock()
+            .in_flight
+            .retain(|s| s.queue != queue || s.sync_point > sync_point);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationMessage {
+    pub device: RenderDeviceId,
+    pub pass: Option<String>,
+    pub kind: ValidationKind,
+}
+
+impl fmt::Display for ValidationMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "device {}", self.device)?;
+
+        if let Some(pass) = &self.pass {
+            write!(f, ", pass '{}'", pass)?;
+        }
+
+        match &self.kind {
+            ValidationKind::SampledRenderTarget { texture } => write!(
+                f,
+                ": texture {} is sampled while its last barrier is RenderTarget",
+                texture
+            ),
+            ValidationKind::DestroyedInFlight {
+                resource,
+                queue,
+                sync_point,
+            } => match sync_point {
+                Some(sync_point) => write!(
+                    f,
+                    ": resource {} is destroyed while queue {} has not reached {}",
+                    resource, queue, sync_point
+                ),
+                None => write!(
+                    f,
+                    ": resource {} is destroyed while committed to queue {} and not submitted",
+                    resource, queue
+                ),
+            },
+            ValidationKind::ClearUnboundTarget { texture } => write!(
+                f,
+                ": texture {} is cleared but not bound to the render pass",
+                texture
+            ),
+            ValidationKind::SetOutOfRange { set, sets } => write!(
+                f,
+                ": shader argument bound to set {} but layout has {} sets",
+                set, sets
+            ),
+            ValidationKind::SharedBarrierOnLocal { texture } => {
+                write!(f, ": shared barrier on local texture {}", texture)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationKind {
+    SampledRenderTarget {
+        texture: TraceId,
+    },
+    DestroyedInFlight {
+        resource: TraceId,
+        queue: TraceId,
+        sync_point: Option<SyncPoint>,
+    },
+    ClearUnboundTarget {
+        texture: TraceId,
+    },
+    SetOutOfRange {
+        set: u32,
+        sets: usize,
+    },
+    SharedBarrierOnLocal {
+        texture: TraceId,
+    },
+}
+
+#[derive(Debug, Default)]
+struct ValidationState {
+    textures: HashMap<TraceId, TextureInfo>,
+    states: HashMap<TraceId, ResourceState>,
+    layouts: HashMap<TraceId, usize>,
+    pipelines: HashMap<TraceId, Option<TraceId>>,
+    arguments: HashMap<TraceId, ArgumentInfo>,
+
+    pending: HashMap<TraceId, Usages>,
+    in_flight: Vec<Submission>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TextureInfo {
+    root: TraceId,
+    shared: bool,
+}
+
+#[derive(Debug)]
+struct ArgumentInfo {
+    srvs: Vec<TraceId>,
+    resources: Vec<TraceId>,
+}
+
+type Usages = HashMap<TraceId, Option<String>>;
+
+#[derive(Debug)]
+struct Submission {
+    device: RenderDeviceId,
+    queue: TraceId,
+    sync_point: SyncPoint,
+    usages: Usages,
+}
+
+impl ValidationState {
+    fn apply(&mut self, device: RenderDeviceId, command: TraceCommand) -> Vec<ValidationMessage> {
+        let mut messages = vec![];
+
+        match command {
+            TraceCommand::CreateTexture { id, desc }
+            | TraceCommand::CreatePlacedTexture { id, desc, .. } => {
+                self.textures.insert(
+                    id,
+                    TextureInfo {
+                        root: id,
+                        shared: desc.usage.contains(TextureUsages::Shared),
+                    },
+                );
+            }
+            TraceCommand::CreateTextureView { id, texture, .. } => {
+                let info = self.texture(texture);
+                self.textures.insert(id, info);
+            }
+            TraceCommand::OpenTexture { id, .. } => {
+                self.textures.insert(
+                    id,
+                    TextureInfo {
+                        root: id,
+                        shared: true,
+                    },
+                );
+            }
+            TraceCommand::CreateSwapchain { images, .. }
+            | TraceCommand::ResizeSwapchain { images, .. } => {
+                for id in images {
+                    self.textures.insert(
+                        id,
+                        TextureInfo {
+                            root: id,
+                            shared: false,
+                        },
+                    );
+                }
+            }
+            TraceCommand::DestroyBuffer { id } => {
+                messages.extend(self.check_destroy(device, id));
+            }
+            TraceCommand::DestroyTexture { id } => {
+                messages.extend(self.check_destroy(device, id));
+
+                self.textures.remove(&id);
+                self.states.remove(&id);
+            }
+            TraceCommand::CreatePipelineLayout { id, sets } => {
+                self.layouts.insert(id, sets.len());
+            }
+            TraceCommand::DestroyPipelineLayout { id } => {
+                self.layouts.remove(&id);
+            }
+            TraceCommand::CreateRasterPipeline { id, layout, .. } => {
+                self.pipelines.insert(id, layout);
+            }
+            TraceCommand::DestroyRasterPipeline { id } => {
+                self.pipelines.remove(&id);
+            }
+            TraceCommand::CreateShaderArgument {
+                id,
+                views,
+                dynamic_buffer,
+                ..
+            } => {
+                let srvs = views
+                    .iter()
+                    .filter_map(|entry| match entry {
+                        TraceShaderEntry::Srv { texture } => Some(*texture),
+                        _ => None,
+                    })
+                    .collect();
+
+                let resources = views
+                    .iter()
+                    .map(|entry| match entry {
+                        TraceShaderEntry::Cbv { buffer, .. } => *buffer,
+                        TraceShaderEntry::Srv { texture } | TraceShaderEntry::Uav { texture } => {
+                            *texture
+                        }
+                    })
+                    .chain(dynamic_buffer)
+                    .collect();
+
+                self.arguments.insert(id, ArgumentInfo { srvs, resources });
+            }
+            TraceCommand::DestroyShaderArgument { id } => {
+                self.arguments.remove(&id);
+            }
+            TraceCommand::Commit { queue, commands } => {
+                let usages = self.validate_commit(device, &commands, &mut messages);
+
+                let pending = self.pending.entry(queue).or_default();
+                for (resource, pass) in usages {
+                    pending.entry(resource).or_insert(pass);
+                }
+            }
+            TraceCommand::Submit { queue, sync_point } => {
+                if let Some(usages) = self.pending.remove(&queue) {
+                    self.in_flight.push(Submission {
+                        device,
+                        queue,
+                        sync_point,
+                        usages,
+                    });
+                }
+            }
+            TraceCommand::DeviceWaitIdle => {
+                self.in_flight.retain(|s| s.device != device);
+            }
+            _ => {}
+        }
+
+        messages
+    }
+
+    fn texture(&self, id: TraceId) -> TextureInfo {
+        self.textures.get(&id).copied().unwrap_or(TextureInfo {
+            root: id,
+            shared: false,
+        })
+    }
+
+    fn check_destroy(&self, device: RenderDeviceId, id: TraceId) -> Option<ValidationMessage> {
+        let in_flight = self
+            .in_flight
+            .iter()
+            .find_map(|s| {
+                s.usages
+                    .get(&id)
+                    .map(|pass| (s.queue, Some(s.sync_point), pass))
+            })
+            .or_else(|| {
+                self.pending
+                    .iter()
+                    .find_map(|(queue, usages)| usages.get(&id).map(|pass| (*queue, None, pass)))
+            });
+
+        in_flight.map(|(queue, sync_point, pass)| ValidationMessage {
+            device,
+            pass: pass.clone(),
+            kind: ValidationKind::DestroyedInFlight {
+                resource: id,
+                queue,
+                sync_point,
+            },
+        })
+    }
+
+    fn validate_commit(
+        &mut self,
+        device: RenderDeviceId,
+        commands: &[EncoderCommand],
+        messages: &mut Vec<ValidationMessage>,
+    ) -> Usages {
+        let mut usages = Usages::new();
+
+        let mut pass: Option<String> = None;
+        let mut targets: Vec<TraceId> = vec![];
+        let mut sets: Option<usize> = None;
+        let mut bound: HashMap<u32, TraceId> = HashMap::new();
+
+        let mut report = |pass: &Option<String>, kind: ValidationKind| {
+            let message = ValidationMessage {
+                device,
+                pass: pass.clone(),
+                kind,
+            };
+
+            if !messages.contains(&message) {
+                messages.push(message);
+            }
+        };
+
+        for command in commands {
+            match command {
+                EncoderCommand::SetBarriers { barriers } => {
+                    for barrier in barriers {
+                        match barrier {
+                            TraceBarrier::Buffer { buffer, .. } => {
+                                usages.entry(*buffer).or_insert(pass.clone());
+                            }
+                            TraceBarrier::Alias { texture } => {
+                                usages.entry(*texture).or_insert(pass.clone());
+                            }
+                            TraceBarrier::Texture {
+                                texture,
+                                state,
+                                subresource,
+                            } => {
+                                usages.entry(*texture).or_insert(pass.clone());
+
+                                let info = self.texture(*texture);
+
+                                match subresource {
+                                    Subresource::Local(_) => {
+                                        self.states.insert(info.root, *state);
+                                    }
+                                    Subresource::Shared if info.shared => {}
+                                    Subresource::Shared => report(
+                                        &pass,
+                                        ValidationKind::SharedBarrierOnLocal { texture: *texture },
+                                    ),
+                                }
+                            }
+                        }
+                    }
+                }
+                EncoderCommand::Render {
+                    label,
+                    targets: render_targets,
+                    depth,
+                } => {
+                    pass = Some(label.clone());
+                    sets = None;
+                    bound.clear();
+
+                    targets = render_targets
+                        .iter()
+                        .chain(depth)
+                        .map(|texture| {
+                            usages.entry(*texture).or_insert(pass.clone());
+                            self.texture(*texture).root
+                        })
+                        .collect();
+                }
+                EncoderCommand::Transfer { label } => {
+                    pass = Some(label.clone());
+                    targets.clear();
+                }
+                EncoderCommand::ClearRt { texture, .. }
+                | EncoderCommand::ClearDepth { texture, .. } => {
+                    usages.entry(*texture).or_insert(pass.clone());
+
+                    if !targets.contains(&self.texture(*texture).root) {
+                        report(
+                            &pass,
+                            ValidationKind::ClearUnboundTarget { texture: *texture },
+                        );
+                    }
+                }
+                EncoderCommand::SetRasterPipeline { pipeline } => {
+                    sets = self.pipelines.get(pipeline).map(|layout| {
+                        layout
+                            .and_then(|layout| self.layouts.get(&layout).copied())
+                            .unwrap_or(0)
+                    });
+                }
+                EncoderCommand::BindShaderArgument { set, argument, .. } => {
+                    if let Some(sets) = sets
+                        && *set as usize >= sets
+                    {
+                        report(&pass, ValidationKind::SetOutOfRange { set: *set, sets });
+                    }
+
+                    if let Some(info) = self.arguments.get(argument) {
+                        for resource in &info.resources {
+                            usages.entry(*resource).or_insert(pass.clone());
+                        }
+                    }
+
+                    bound.insert(*set, *argument);
+                }
+                EncoderCommand::Draw { .. }
+                | EncoderCommand::DrawIndexed { .. }
+                | EncoderCommand::DrawIndexedInstanced { .. } => {
+                    let sampled = bound
+                        .values()
+                        .filter_map(|argument| self.arguments.get(argument))
+                        .flat_map(|info| info.srvs.iter().copied());
+
+                    for texture in sampled {
+                        let state = self.states.get(&self.texture(texture).root);
+
+                        if state == Some(&ResourceState::RenderTarget) {
+                            report(&pass, ValidationKind::SampledRenderTarget { texture });
+                        }
+                    }
+                }
+                EncoderCommand::BindVertexBuffer { buffer, .. }
+                | EncoderCommand::BindIndexBuffer { buffer, .. }
+                | EncoderCommand::LoadToBuffer { buffer, .. } => {
+                    usages.entry(*buffer).or_insert(pass.clone());
+                }
+                EncoderCommand::PullTexture { texture, .. }
+                | EncoderCommand::PushTexture { texture, .. }
+                | EncoderCommand::LoadToTexture { texture, .. } => {
+                    usages.entry(*texture).or_insert(pass.clone());
+                }
+                EncoderCommand::CopyTextureToBuffer { texture, buffer } => {
+                    usages.entry(*texture).or_insert(pass.clone());
+                    usages.entry(*buffer).or_insert(pass.clone());
+                }
+                EncoderCommand::CopyTexture { src, dst, .. } => {
+                    usages.entry(*src).or_insert(pass.clone());
+                    usages.entry(*dst).or_insert(pass.clone());
+                }
+                _ => {}
+            }
+        }
+
+        usages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        collections::handle::Handle,
+        ra::{
+            command::{Barrier, RenderCommandContext, RenderCommandEncoder, RenderEncoder},
+            context::Context,
+            resources::{RenderResourceContext, Texture},
+            shader::{
+                RasterPipeline, RasterPipelineDesc, RenderShaderContext, ShaderArgument,
+                ShaderArgumentDesc, ShaderEntry,
+            },
+            system::RenderSystem,
+        },
+        rhi::{
+            command::CommandType,
+            null::device::NullDevice,
+            recording::{
+                device::RecordingDevice,
+                null_device,
+                trace::{Recorder, TraceSinks},
+            },
+            resources::{BufferDesc, BufferUsages, TextureDesc},
+            shader::{BindingEntry, BindingSet, BindingType, CompiledShader, PipelineLayoutDesc},
+            types::{BlendMode, CullMode, Format, ShaderType},
+        },
+    };
+
+    use super::*;
+
+    type Ctx = Arc<Context<RecordingDevice<NullDevice>>>;
+
+    fn texture(rs: &RenderSystem, ctx: &Ctx, usage: TextureUsages) -> Handle<Texture> {
+        let texture = rs.create_texture_handle();
+        ctx.bind_texture(
+            texture,
+            TextureDesc::new_2d([4, 4], Format::Rgba8Unorm, usage),
+            None,
+        )
+        .expect("failed to bind texture");
+
+        texture
+    }
+
+    fn kinds(validator: &Validator) -> Vec<ValidationKind> {
+        validator
+            .take_messages()
+            .into_iter()
+            .map(|message| message.kind)
+            .collect()
+    }
+
+    // A pipeline whose layout has a single set holding one srv, with an
+    // argument for it sampling `sampled`.
+    fn pipeline(
+        rs: &RenderSystem,
+        ctx: &Ctx,
+        sampled: Handle<Texture>,
+    ) -> (Handle<RasterPipeline>, Handle<ShaderArgument>) {
+        let layout = rs.create_pipeline_layout_handle();
+        ctx.bind_pipeline_layout(
+            layout,
+            PipelineLayoutDesc {
+                sets: &[BindingSet {
+                    entries: &[BindingEntry::new(BindingType::Srv, 1)],
+                    use_dynamic_buffer: false,
+                }],
+                static_samplers: &[],
+            },
+        )
+        .expect("failed to bind layout");
+
+        let vs = CompiledShader {
+            raw: vec![],
+            ty: ShaderType::Vertex,
+        };
+        let pipeline = rs.create_raster_pipeline_handle();
+        ctx.bind_raster_pipeline(
+            pipeline,
+            RasterPipelineDesc {
+                layout: Some(layout),
+                input_elements: &[],
+                depth_bias: 0,
+                slope_bias: 0.0,
+                depth_clip: false,
+                depth: None,
+                render_targets: &[Format::Rgba8Unorm],
+                cull_mode: CullMode::None,
+                blend: BlendMode::None,
+                vs: &vs,
+                shaders: &[],
+            },
+        )
+        .expect("failed to bind pipeline");
+
+        let argument = rs.create_shader_argument_handle();
+        ctx.bind_shader_argument(
+            argument,
+            ShaderArgumentDesc {
+                views: &[ShaderEntry::Srv(sampled)],
+                samplers: &[],
+                dynamic_buffer: None,
+            },
+        )
+        .expect("failed to bind argument");
+
+        (pipeline, argument)
+    }
+
+    #[test]
+    fn sampling_a_render_target_is_reported() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let target = texture(&rs, &ctx, TextureUsages::RenderTarget);
+        let sampled = texture(
+            &rs,
+            &ctx,
+            TextureUsages::RenderTarget | TextureUsages::Resource,
+        );
+        let (pipeline, argument) = pipeline(&rs, &ctx, sampled);
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        cmd.set_barriers(&[Barrier::Texture(
+            sampled,
+            ResourceState::RenderTarget,
+            Subresource::Local(None),
+        )])
+        .expect("failed to set barriers");
+        {
+            let mut encoder = cmd
+                .render("Sample".into(), &[target], None)
+                .expect("failed to begin render pass");
+            encoder
+                .set_render_pipeline(pipeline)
+                .expect("failed to set pipeline");
+            encoder
+                .bind_shader_argument(0, argument, 0)
+                .expect("failed to bind argument");
+            encoder.draw(3, 0);
+        }
+        ctx.commit(cmd);
+
+        let messages = validator.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].pass.as_deref(), Some("Sample"));
+        assert!(matches!(
+            messages[0].kind,
+            ValidationKind::SampledRenderTarget { .. }
+        ));
+    }
+
+    #[test]
+    fn sampling_after_shader_barrier_is_clean() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let target = texture(&rs, &ctx, TextureUsages::RenderTarget);
+        let sampled = texture(
+            &rs,
+            &ctx,
+            TextureUsages::RenderTarget | TextureUsages::Resource,
+        );
+        let (pipeline, argument) = pipeline(&rs, &ctx, sampled);
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        cmd.set_barriers(&[Barrier::Texture(
+            sampled,
+            ResourceState::Shader,
+            Subresource::Local(None),
+        )])
+        .expect("failed to set barriers");
+        {
+            let mut encoder = cmd
+                .render("Sample".into(), &[target], None)
+                .expect("failed to begin render pass");
+            encoder
+                .set_render_pipeline(pipeline)
+                .expect("failed to set pipeline");
+            encoder
+                .bind_shader_argument(0, argument, 0)
+                .expect("failed to bind argument");
+            encoder.draw(3, 0);
+        }
+        ctx.commit(cmd);
+
+        assert!(validator.messages().is_empty());
+    }
+
+    #[test]
+    fn binding_past_the_layout_is_reported() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let target = texture(&rs, &ctx, TextureUsages::RenderTarget);
+        let sampled = texture(&rs, &ctx, TextureUsages::Resource);
+        let (pipeline, argument) = pipeline(&rs, &ctx, sampled);
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        {
+            let mut encoder = cmd
+                .render("Bind".into(), &[target], None)
+                .expect("failed to begin render pass");
+            encoder
+                .set_render_pipeline(pipeline)
+                .expect("failed to set pipeline");
+            encoder
+                .bind_shader_argument(1, argument, 0)
+                .expect("failed to bind argument");
+        }
+        ctx.commit(cmd);
+
+        assert_eq!(
+            kinds(&validator),
+            [ValidationKind::SetOutOfRange { set: 1, sets: 1 }]
+        );
+    }
+
+    #[test]
+    fn clearing_an_unbound_target_is_reported() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let target = texture(&rs, &ctx, TextureUsages::RenderTarget);
+        let other = texture(&rs, &ctx, TextureUsages::RenderTarget);
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        {
+            let mut encoder = cmd
+                .render("Clear".into(), &[target], None)
+                .expect("failed to begin render pass");
+            encoder
+                .clear_rt(target, None)
+                .expect("failed to clear bound target");
+            encoder
+                .clear_rt(other, None)
+                .expect("failed to clear unbound target");
+        }
+        ctx.commit(cmd);
+
+        let messages = validator.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].pass.as_deref(), Some("Clear"));
+        assert!(matches!(
+            messages[0].kind,
+            ValidationKind::ClearUnboundTarget { .. }
+        ));
+    }
+
+    #[test]
+    fn shared_barrier_on_local_texture_is_reported() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let local = texture(&rs, &ctx, TextureUsages::Resource);
+        let shared = texture(&rs, &ctx, TextureUsages::Resource | TextureUsages::Shared);
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        cmd.set_barriers(&[
+            Barrier::Texture(shared, ResourceState::CopySrc, Subresource::Shared),
+            Barrier::Texture(local, ResourceState::CopySrc, Subresource::Shared),
+        ])
+        .expect("failed to set barriers");
+        ctx.commit(cmd);
+
+        let kinds = kinds(&validator);
+        assert_eq!(kinds.len(), 1);
+        assert!(matches!(
+            kinds[0],
+            ValidationKind::SharedBarrierOnLocal { .. }
+        ));
+    }
+
+    #[test]
+    fn destroying_an_in_flight_buffer_is_reported() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let buffer = rs.create_buffer_handle();
+        ctx.bind_buffer(
+            buffer,
+            BufferDesc::gpu_to_gpu(64, BufferUsages::Uniform),
+            None,
+        )
+        .expect("failed to bind buffer");
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        cmd.set_barriers(&[Barrier::Buffer(buffer, ResourceState::Shader)])
+            .expect("failed to set barriers");
+        ctx.commit(cmd);
+        let sync_point = ctx.submit(CommandType::Graphics).expect("failed to submit");
+
+        ctx.unbind_buffer(buffer);
+
+        let kinds = kinds(&validator);
+        assert_eq!(kinds.len(), 1);
+        assert!(matches!(
+            kinds[0],
+            ValidationKind::DestroyedInFlight { sync_point: Some(s), .. } if s == sync_point
+        ));
+    }
+
+    #[test]
+    fn destroying_after_completion_is_clean() {
+        let validator = Arc::new(Validator::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::clone(&validator) as _);
+
+        let buffer = rs.create_buffer_handle();
+        ctx.bind_buffer(
+            buffer,
+            BufferDesc::gpu_to_gpu(64, BufferUsages::Uniform),
+            None,
+        )
+        .expect("failed to bind buffer");
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        cmd.set_barriers(&[Barrier::Buffer(buffer, ResourceState::Shader)])
+            .expect("failed to set barriers");
+        ctx.commit(cmd);
+        let sync_point = ctx.submit(CommandType::Graphics).expect("failed to submit");
+        ctx.wait_on_cpu(CommandType::Graphics, sync_point)
+            .expect("failed to wait");
+
+        ctx.unbind_buffer(buffer);
+
+        assert!(validator.messages().is_empty());
+    }
+
+    #[test]
+    fn validator_runs_next_to_a_recorder() {
+        let validator = Arc::new(Validator::new());
+        let recorder = Arc::new(Recorder::new());
+        let rs = RenderSystem::new(&[]);
+        let ctx = null_device(Arc::new(TraceSinks::new([
+            Arc::clone(&recorder) as Arc<dyn TraceSink>,
+            Arc::clone(&validator) as _,
+        ])));
+
+        let target = texture(&rs, &ctx, TextureUsages::RenderTarget);
+        let other = texture(&rs, &ctx, TextureUsages::RenderTarget);
+
+        let mut cmd = ctx
+            .create_encoder(CommandType::Graphics)
+            .expect("failed to create encoder");
+        cmd.begin(&ctx);
+        {
+            let mut encoder = cmd
+                .render("Clear".into(), &[target], None)
+                .expect("failed to begin render pass");
+            encoder
+                .clear_rt(other, None)
+                .expect("failed to clear unbound target");
+        }
+        ctx.commit(cmd);
+
+        let trace = recorder.end_frame();
+        assert_eq!(trace.passes().collect::<Vec<_>>(), [(0, "Clear")]);
+        assert_eq!(validator.messages().len(), 1);
+    }
+}
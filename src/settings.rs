@@ -1,8 +1,16 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CascadeLayout {
+    #[default]
+    Atlas,
+    Array,
+}
+
 #[derive(Clone, Debug, Parser)]
 #[command(version, about, long_about = None)]
 pub struct CliRenderSettings {
@@ -44,6 +52,42 @@ pub struct CliRenderSettings {
 
     #[arg(long)]
     pub cascades_lambda: Option<f32>,
+
+    #[arg(long)]
+    pub primary_device: Option<String>,
+
+    #[arg(long)]
+    pub secondary_device: Option<String>,
+
+    #[arg(long)]
+    pub gpass_device: Option<usize>,
+
+    #[arg(long)]
+    pub csm_device: Option<usize>,
+
+    #[arg(long)]
+    pub light_device: Option<usize>,
+
+    #[arg(long)]
+    pub camera_prediction: Option<bool>,
+
+    #[arg(long)]
+    pub stable_cascades: Option<bool>,
+
+    #[arg(long)]
+    pub cascade_layout: Option<CascadeLayout>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub cascade_intervals: Option<Vec<u32>>,
+
+    #[arg(long)]
+    pub sdsm: Option<bool>,
+
+    #[arg(long)]
+    pub record_trace: Option<String>,
+
+    #[arg(long)]
+    pub validate: Option<bool>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -82,6 +126,39 @@ pub struct TomlRenderSettings {
 
     #[serde(default = "default_cascades_lambda")]
     pub cascades_lambda: f32,
+
+    pub primary_device: Option<String>,
+
+    pub secondary_device: Option<String>,
+
+    #[serde(default = "default_gpass_device")]
+    pub gpass_device: usize,
+
+    #[serde(default = "default_csm_device")]
+    pub csm_device: usize,
+
+    #[serde(default = "default_light_device")]
+    pub light_device: usize,
+
+    #[serde(default = "default_camera_prediction")]
+    pub camera_prediction: bool,
+
+    #[serde(default)]
+    pub stable_cascades: bool,
+
+    #[serde(default)]
+    pub cascade_layout: CascadeLayout,
+
+    #[serde(default)]
+    pub cascade_intervals: Vec<u32>,
+
+    #[serde(default)]
+    pub sdsm: bool,
+
+    pub record_trace: Option<String>,
+
+    #[serde(default)]
+    pub validate: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -99,15 +176,29 @@ pub struct RenderSettings {
     pub camera_far: f32,
     pub shadows_far: Option<f32>,
     pub cascades_lambda: f32,
+    pub primary_device: Option<String>,
+    pub secondary_device: Option<String>,
+    pub gpass_device: usize,
+    pub csm_device: usize,
+    pub light_device: usize,
+    pub camera_prediction: bool,
+    pub stable_cascades: bool,
+    pub cascade_layout: CascadeLayout,
+    pub cascade_intervals: Vec<u32>,
+    pub sdsm: bool,
+    // Directory the per-frame command traces are written to.
+    pub record_trace: Option<PathBuf>,
+    // Runs the rhi validation layer over the recording backend.
+    pub validate: bool,
 }
 
 pub fn read_settings() -> RenderSettings {
     let cli = CliRenderSettings::parse();
 
-    if let Ok(content) = std::fs::read_to_string("config.toml") {
-        if let Ok(toml) = toml::from_str(&content) {
-            return merge_settings(cli, toml);
-        }
+    if let Ok(content) = std::fs::read_to_string("config.toml")
+        && let Ok(toml) = toml::from_str(&content)
+    {
+        return merge_settings(cli, toml);
     }
 
     RenderSettings {
@@ -126,6 +217,20 @@ pub fn read_settings() -> RenderSettings {
         shadows_far: cli.shadows_far,
         cascades_lambda: cli.cascades_lambda.unwrap_or_else(default_cascades_lambda),
         bench_frames: cli.bench_frames.unwrap_or_else(default_bench_frames),
+        primary_device: cli.primary_device,
+        secondary_device: cli.secondary_device,
+        gpass_device: cli.gpass_device.unwrap_or_else(default_gpass_device),
+        csm_device: cli.csm_device.unwrap_or_else(default_csm_device),
+        light_device: cli.light_device.unwrap_or_else(default_light_device),
+        camera_prediction: cli
+            .camera_prediction
+            .unwrap_or_else(default_camera_prediction),
+        stable_cascades: cli.stable_cascades.unwrap_or_default(),
+        cascade_layout: cli.cascade_layout.unwrap_or_default(),
+        cascade_intervals: cli.cascade_intervals.unwrap_or_default(),
+        sdsm: cli.sdsm.unwrap_or_default(),
+        record_trace: cli.record_trace.map(PathBuf::from),
+        validate: cli.validate.unwrap_or_default(),
     }
 }
 
@@ -152,6 +257,18 @@ pub fn merge_settings(cli: CliRenderSettings, toml: TomlRenderSettings) -> Rende
         shadows_far: cli.shadows_far.or(toml.shadows_far),
         cascades_lambda: cli.cascades_lambda.unwrap_or(toml.cascades_lambda),
         bench_frames: cli.bench_frames.unwrap_or(toml.bench_frames),
+        primary_device: cli.primary_device.or(toml.primary_device),
+        secondary_device: cli.secondary_device.or(toml.secondary_device),
+        gpass_device: cli.gpass_device.unwrap_or(toml.gpass_device),
+        csm_device: cli.csm_device.unwrap_or(toml.csm_device),
+        light_device: cli.light_device.unwrap_or(toml.light_device),
+        camera_prediction: cli.camera_prediction.unwrap_or(toml.camera_prediction),
+        stable_cascades: cli.stable_cascades.unwrap_or(toml.stable_cascades),
+        cascade_layout: cli.cascade_layout.unwrap_or(toml.cascade_layout),
+        cascade_intervals: cli.cascade_intervals.unwrap_or(toml.cascade_intervals),
+        sdsm: cli.sdsm.unwrap_or(toml.sdsm),
+        record_trace: cli.record_trace.or(toml.record_trace).map(PathBuf::from),
+        validate: cli.validate.unwrap_or(toml.validate),
     }
 }
 
@@ -190,3 +307,19 @@ fn default_cascades_lambda() -> f32 {
 fn default_bench_frames() -> usize {
     5000
 }
+
+fn default_gpass_device() -> usize {
+    0
+}
+
+fn default_csm_device() -> usize {
+    1
+}
+
+fn default_light_device() -> usize {
+    0
+}
+
+fn default_camera_prediction() -> bool {
+    true
+}